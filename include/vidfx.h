@@ -0,0 +1,44 @@
+/* Embedding API, available when libvidfx is built with `--features vidfx-ffi`:
+ *
+ *   cargo rustc --lib --release --features vidfx-ffi --crate-type cdylib
+ *
+ * or `--crate-type staticlib` for a static library. */
+#ifndef VIDFX_H
+#define VIDFX_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#include "vidfx_plugin.h"
+
+typedef struct VidfxSource VidfxSource;
+typedef struct VidfxChain VidfxChain;
+
+/* Message for the last failed call on this thread, NULL if there was none.
+ * Valid until the next vidfx call on the same thread. */
+const char *vidfx_last_error(void);
+
+/* Opens a video file or url for decoding. NULL on failure. */
+VidfxSource *vidfx_source_open(const char *path);
+void vidfx_source_size(const VidfxSource *source, uint32_t *width, uint32_t *height);
+double vidfx_source_frame_rate(const VidfxSource *source);
+void vidfx_source_free(VidfxSource *source);
+
+/* Parses an effect chain in the same JSON format as the Rust EffectChain.
+ * NULL on failure. */
+VidfxChain *vidfx_chain_from_json(const char *json);
+void vidfx_chain_free(VidfxChain *chain);
+
+/* Runs `chain` over `frame` in place. `time` is in seconds, `frame_rate` is
+ * the clip's (frame based lengths like a 1f strobe need it) and
+ * `scale_factor` is 1.0 when unmodulated. Returns 0 on success. */
+int32_t vidfx_chain_process(const VidfxChain *chain, VidfxFrame *frame, uint64_t index,
+                            double time, double frame_rate, double scale_factor);
+
+/* Decodes the next frame of `source`, runs `chain` (may be NULL) over it and
+ * writes RGBA8 pixels to `frame`, which must match the source size. Returns 1
+ * when a frame was written, 0 at the end of the source and -1 on error. */
+int32_t vidfx_source_next_frame(VidfxSource *source, const VidfxChain *chain, VidfxFrame *frame,
+                                double scale_factor);
+
+#endif
@@ -0,0 +1,37 @@
+/* Interface for vidfx effect plugins, loaded with `vidfx --plugin libmyfx.so`. */
+#ifndef VIDFX_PLUGIN_H
+#define VIDFX_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#define VIDFX_PLUGIN_ABI_VERSION 1
+
+/* Tightly packed RGBA8 pixels, `stride` bytes per row. Edit in place. */
+typedef struct {
+    uint8_t *data;
+    uint32_t width;
+    uint32_t height;
+    uint32_t stride;
+} VidfxFrame;
+
+/* One `--plugin-param key=value`. */
+typedef struct {
+    const char *key;
+    const char *value;
+} VidfxParam;
+
+typedef struct {
+    const VidfxParam *params;
+    size_t len;
+    /* Modulation from --visualization, 1.0 when unmodulated */
+    double scale_factor;
+} VidfxParams;
+
+/* Optional. Plugins built against a different ABI version are rejected. */
+uint32_t vidfx_plugin_abi_version(void);
+
+/* Required. Return 0 on success, anything else aborts the render. */
+int32_t vidfx_plugin_process(VidfxFrame *frame, const VidfxParams *params);
+
+#endif
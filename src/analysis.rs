@@ -0,0 +1,224 @@
+//! `--analyze-first-pass`: one pass over the input before rendering, as far
+//! as `--duration` or `--loop-to` reach, measuring statistics of the clip
+//! that any flag can take as its value by name, e.g. `sort --min
+//! black_global` or `bloom --min p95_global*255`.
+//!
+//! Every statistic runs from 0 to 1:
+//!
+//! - `p<N>_global`: the luma N percent of the clip falls below, e.g.
+//!   `p95_global` or `p99.5_global`
+//! - `black_global` and `white_global`: `p0.5_global` and `p99.5_global`,
+//!   the points `normalize` stretches between by default
+//! - `mean_global`: the average luma
+//! - `red_global`, `green_global` and `blue_global`: the average color
+//! - `motion_global`: how much luma changes from one frame to the next on
+//!   average
+//!
+//! `*<N>` after a name scales it, rounded when N is whole so flags taking
+//! 0-255 levels accept it.
+
+use std::path::Path;
+use std::sync::OnceLock;
+
+use vidfx::source::Source;
+
+use crate::units::parse_duration;
+use crate::ytdlp;
+
+const FLAG: &str = "--analyze-first-pass";
+
+/// Every this many pixels are sampled, as for `normalize --global`.
+const SAMPLE_STEP: usize = 4;
+
+/// The first pass, run once however many times arguments are resolved.
+static STATS: OnceLock<Stats> = OnceLock::new();
+
+struct Stats {
+    histogram: [u64; 256],
+    mean: f64,
+    color: [f64; 3],
+    motion: f64,
+}
+
+impl Stats {
+    /// The statistics of the first `window` seconds of `path`, or all of it.
+    fn measure(path: &Path, window: Option<f64>) -> Result<Stats, video_rs::Error> {
+        let mut source = Source::open(path)?;
+        let count = window.map_or(usize::MAX, |window| {
+            (window * source.frame_rate()).ceil() as usize
+        });
+        let mut histogram = [0u64; 256];
+        let mut sums = [0u64; 3];
+        let (mut change, mut compared) = (0u64, 0u64);
+        let mut previous: Vec<u8> = vec![];
+        for frame in std::iter::from_fn(|| source.next_frame()).take(count) {
+            let luma: Vec<u8> = frame
+                .as_raw()
+                .chunks_exact(3)
+                .step_by(SAMPLE_STEP)
+                .map(|pixel| {
+                    for (sum, &v) in sums.iter_mut().zip(pixel) {
+                        *sum += v as u64;
+                    }
+                    ((pixel[0] as u32 * 77 + pixel[1] as u32 * 150 + pixel[2] as u32 * 29) >> 8)
+                        as u8
+                })
+                .collect();
+            for &level in &luma {
+                histogram[level as usize] += 1;
+            }
+            if previous.len() == luma.len() {
+                change += previous
+                    .iter()
+                    .zip(&luma)
+                    .map(|(a, b)| a.abs_diff(*b) as u64)
+                    .sum::<u64>();
+                compared += luma.len() as u64;
+            }
+            previous = luma;
+        }
+
+        let samples = histogram.iter().sum::<u64>().max(1) as f64;
+        let mean = histogram
+            .iter()
+            .enumerate()
+            .map(|(level, &count)| level as f64 * count as f64)
+            .sum::<f64>()
+            / samples
+            / 255.0;
+        Ok(Stats {
+            histogram,
+            mean,
+            color: sums.map(|sum| sum as f64 / samples / 255.0),
+            motion: change as f64 / compared.max(1) as f64 / 255.0,
+        })
+    }
+
+    fn percentile(&self, percent: f64) -> f64 {
+        let total: u64 = self.histogram.iter().sum();
+        let limit = (total as f64 * percent / 100.0) as u64;
+        let mut seen = 0;
+        let level = self
+            .histogram
+            .iter()
+            .position(|&count| {
+                seen += count;
+                seen > limit
+            })
+            .unwrap_or(255);
+        level as f64 / 255.0
+    }
+
+    /// The statistic `name`, without its `_global`.
+    fn get(&self, name: &str) -> f64 {
+        match name {
+            "black" => self.percentile(0.5),
+            "white" => self.percentile(99.5),
+            "mean" => self.mean,
+            "red" => self.color[0],
+            "green" => self.color[1],
+            "blue" => self.color[2],
+            "motion" => self.motion,
+            _ => self.percentile(percent(name).expect("Statistic names are checked")),
+        }
+    }
+}
+
+/// The percentile a `p<N>` name stands for.
+fn percent(name: &str) -> Option<f64> {
+    name.strip_prefix('p')?
+        .parse()
+        .ok()
+        .filter(|percent| (0.0..=100.0).contains(percent))
+}
+
+/// The statistic's name and scale if `word` names one, so other values that
+/// happen to end in `_global` are left alone.
+fn reference(word: &str) -> Option<(&str, Option<f64>)> {
+    let (name, scale) = match word.split_once('*') {
+        Some((name, scale)) => (name, Some(scale.parse().ok()?)),
+        None => (word, None),
+    };
+    let name = name.strip_suffix("_global")?;
+    let known = matches!(
+        name,
+        "black" | "white" | "mean" | "red" | "green" | "blue" | "motion"
+    ) || percent(name).is_some();
+    known.then_some((name, scale))
+}
+
+/// The value of the last `long` or `short` flag in `argv`.
+fn flag_value<'a>(argv: &'a [String], long: &str, short: Option<&str>) -> Option<&'a str> {
+    argv.iter().enumerate().rev().find_map(|(i, arg)| {
+        if arg == long || Some(arg.as_str()) == short {
+            return argv.get(i + 1).map(String::as_str);
+        }
+        arg.strip_prefix(long)?.strip_prefix('=')
+    })
+}
+
+/// How much of the input the render shows, the shorter of `--duration` and
+/// `--loop-to`, as for `normalize --global`.
+fn window(argv: &[String]) -> Option<f64> {
+    ["--duration", "--loop-to"]
+        .into_iter()
+        .filter_map(|flag| flag_value(argv, flag, None))
+        .filter_map(|duration| parse_duration(duration).ok())
+        .reduce(f64::min)
+}
+
+/// `argv` with every statistic it names replaced by its value, measuring the
+/// input the first time one turns up. Without `--analyze-first-pass` it is
+/// left as it is, so values that happen to read as a name, such as a preset
+/// called `mean_global`, keep working.
+pub fn resolve(argv: Vec<String>) -> Vec<String> {
+    if !argv.iter().any(|arg| arg == FLAG) {
+        return argv;
+    }
+    let stats = || {
+        STATS.get_or_init(|| {
+            let input = flag_value(&argv, "--input", Some("-i")).expect("No --input provided!");
+            if input.starts_with("generate:")
+                || input.starts_with(ytdlp::PREFIX)
+                || input.contains("://")
+            {
+                panic!("Only files can be analyzed for {}", FLAG);
+            }
+            eprintln!("Analyzing {}", input);
+            let stats =
+                Stats::measure(Path::new(input), window(&argv)).expect("Failed to analyze input");
+            eprintln!(
+                "Black {:.3}, white {:.3}, mean {:.3}, motion {:.3}",
+                stats.percentile(0.5),
+                stats.percentile(99.5),
+                stats.mean,
+                stats.motion
+            );
+            stats
+        })
+    };
+
+    argv.iter()
+        .map(|arg| {
+            // Names can stand alone or be one part of a value, as in
+            // --uniform amount=mean_global or --grain-channels red_global,1,1
+            arg.split_inclusive([',', '=', ':', ';', ' '])
+                .map(|piece| {
+                    let word = piece.trim_end_matches([',', '=', ':', ';', ' ']);
+                    let Some((name, scale)) = reference(word) else {
+                        return piece.to_string();
+                    };
+                    let value = stats().get(name);
+                    let value = match scale {
+                        Some(scale) if scale.fract() == 0.0 => {
+                            format!("{}", (value * scale).round())
+                        }
+                        Some(scale) => format!("{:.4}", value * scale),
+                        None => format!("{:.4}", value),
+                    };
+                    format!("{}{}", value, &piece[word.len()..])
+                })
+                .collect()
+        })
+        .collect()
+}
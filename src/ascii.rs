@@ -0,0 +1,78 @@
+//! Character mosaics for the `ascii` effect: each cell of the frame is
+//! redrawn as the character whose ink best matches its brightness, in the
+//! 3x5 font used for burned-in text.
+
+use image::RgbaImage;
+
+use crate::generate::glyph;
+
+/// Characters of a named charset, or `name` itself as the characters.
+pub fn charset(name: &str) -> String {
+    match name {
+        "dense" => " .'-:=+*#%@",
+        "simple" => " .:-=+*#@",
+        "digits" => " 0123456789",
+        characters => characters,
+    }
+    .to_string()
+}
+
+/// How many of a glyph's 15 pixels are lit.
+fn ink(c: char) -> u32 {
+    glyph(c).iter().map(|row| row.count_ones()).sum()
+}
+
+/// `img` redrawn as characters from `charset` on `cell` sized cells, in
+/// each cell's color or white on black.
+pub fn apply(
+    img: RgbaImage,
+    charset: &str,
+    (cell_width, cell_height): (u32, u32),
+    color: bool,
+) -> RgbaImage {
+    let mut glyphs: Vec<char> = charset.chars().collect();
+    if glyphs.is_empty() {
+        return img;
+    }
+    // Darkest to brightest, keeping the given order between equals
+    glyphs.sort_by_key(|&c| ink(c));
+    let (cell_width, cell_height) = (cell_width.max(1), cell_height.max(1));
+
+    let (width, height) = img.dimensions();
+    let mut out = RgbaImage::new(width, height);
+    for top in (0..height).step_by(cell_height as usize) {
+        for left in (0..width).step_by(cell_width as usize) {
+            let (w, h) = (cell_width.min(width - left), cell_height.min(height - top));
+            let mut sum = [0u64; 4];
+            for y in top..top + h {
+                for x in left..left + w {
+                    for (total, &v) in sum.iter_mut().zip(&img.get_pixel(x, y).0) {
+                        *total += v as u64;
+                    }
+                }
+            }
+            let count = (w * h) as u64;
+            let average = sum.map(|total| (total / count) as u8);
+            let luma =
+                (average[0] as u32 * 77 + average[1] as u32 * 150 + average[2] as u32 * 29) >> 8;
+            let pick = glyphs[(luma as usize * glyphs.len() / 256).min(glyphs.len() - 1)];
+            let rows = glyph(pick);
+            let ink = if color {
+                [average[0], average[1], average[2]]
+            } else {
+                [255, 255, 255]
+            };
+
+            for y in 0..h {
+                for x in 0..w {
+                    // The 3x5 glyph with a pixel of spacing, stretched over the cell
+                    let (gx, gy) = (x * 4 / cell_width, y * 6 / cell_height);
+                    let lit = gx < 3 && gy < 5 && (rows[gy as usize] >> (2 - gx)) & 1 == 1;
+                    let [r, g, b] = if lit { ink } else { [0, 0, 0] };
+                    out.put_pixel(left + x, top + y, image::Rgba([r, g, b, average[3]]));
+                }
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,167 @@
+//! Audio decoding and spectrum analysis for audio driven frames.
+
+use std::f32::consts::PI;
+use std::path::Path;
+
+use ffmpeg_next::{
+    format::{self, sample::Type as SampleType, Sample},
+    frame, media,
+    software::resampling,
+    ChannelLayout,
+};
+
+/// Mono samples in -1..1 at `rate` Hz.
+pub struct Audio {
+    pub samples: Vec<f32>,
+    pub rate: u32,
+}
+
+impl Audio {
+    /// Decodes the first audio stream of `path`, downmixed to mono.
+    pub fn load(path: &Path) -> Result<Audio, ffmpeg_next::Error> {
+        let mut input = format::input(&path)?;
+        let stream = input
+            .streams()
+            .best(media::Type::Audio)
+            .ok_or(ffmpeg_next::Error::StreamNotFound)?;
+        let stream_index = stream.index();
+
+        let mut decoder = ffmpeg_next::codec::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .audio()?;
+        let rate = decoder.rate();
+
+        let mut resampler = resampling::Context::get(
+            decoder.format(),
+            decoder.channel_layout(),
+            rate,
+            Sample::F32(SampleType::Packed),
+            ChannelLayout::MONO,
+            rate,
+        )?;
+
+        let mut samples = vec![];
+        let mut decoded = frame::Audio::empty();
+        let mut resampled = frame::Audio::empty();
+        let mut drain = |decoder: &mut ffmpeg_next::decoder::Audio,
+                         samples: &mut Vec<f32>|
+         -> Result<(), ffmpeg_next::Error> {
+            while decoder.receive_frame(&mut decoded).is_ok() {
+                resampler.run(&decoded, &mut resampled)?;
+                samples.extend_from_slice(&resampled.plane::<f32>(0)[..resampled.samples()]);
+            }
+            Ok(())
+        };
+
+        for (stream, packet) in input.packets() {
+            if stream.index() == stream_index {
+                decoder.send_packet(&packet)?;
+                drain(&mut decoder, &mut samples)?;
+            }
+        }
+        decoder.send_eof()?;
+        drain(&mut decoder, &mut samples)?;
+
+        Ok(Audio { samples, rate })
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.samples.len() as f64 / self.rate as f64
+    }
+
+    /// `len` samples centered on `time`, zero padded past either end.
+    pub fn window(&self, time: f64, len: usize) -> Vec<f32> {
+        let center = (time * self.rate as f64) as isize;
+        (0..len as isize)
+            .map(|i| {
+                let at = center - len as isize / 2 + i;
+                if at < 0 {
+                    0.0
+                } else {
+                    self.samples.get(at as usize).copied().unwrap_or(0.0)
+                }
+            })
+            .collect()
+    }
+
+    /// RMS level of the `seconds` of audio centered on `time`, in 0..1.
+    pub fn level(&self, time: f64, seconds: f64) -> f32 {
+        let len = ((seconds * self.rate as f64) as usize).max(1);
+        let window = self.window(time, len);
+        (window.iter().map(|s| s * s).sum::<f32>() / len as f32).sqrt()
+    }
+}
+
+/// In place radix-2 FFT of (re, im) pairs. `buf.len()` must be a power of two.
+fn fft(buf: &mut [(f32, f32)]) {
+    let n = buf.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            buf.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (re, im) = buf[start + k + len / 2];
+                let odd = (re * cos - im * sin, re * sin + im * cos);
+                let even = buf[start + k];
+                buf[start + k] = (even.0 + odd.0, even.1 + odd.1);
+                buf[start + k + len / 2] = (even.0 - odd.0, even.1 - odd.1);
+            }
+        }
+        len <<= 1;
+    }
+}
+
+/// Magnitudes of the first half of the Hann windowed spectrum of `samples`,
+/// scaled to 0..1 over an 80dB range.
+pub fn spectrum(samples: &[f32]) -> Vec<f32> {
+    let n = samples.len();
+    let mut buf: Vec<(f32, f32)> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let hann = 0.5 - 0.5 * (2.0 * PI * i as f32 / n as f32).cos();
+            (s * hann, 0.0)
+        })
+        .collect();
+    fft(&mut buf);
+
+    buf[..n / 2]
+        .iter()
+        .map(|(re, im)| {
+            let magnitude = (re * re + im * im).sqrt() / n as f32 * 4.0;
+            let db = 20.0 * magnitude.max(1e-6).log10();
+            ((db + 80.0) / 80.0).clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+/// Averages `spectrum` into `bands` logarithmically spaced bands between
+/// 30Hz and 16kHz.
+pub fn bands(spectrum: &[f32], rate: u32, bands: usize) -> Vec<f32> {
+    let bin_hz = rate as f32 / 2.0 / spectrum.len() as f32;
+    let (low, high) = (30.0f32, 16_000.0f32.min(rate as f32 / 2.0));
+
+    (0..bands)
+        .map(|band| {
+            let edge = |b: usize| low * (high / low).powf(b as f32 / bands as f32);
+            let from = (edge(band) / bin_hz) as usize;
+            let to = ((edge(band + 1) / bin_hz) as usize).max(from + 1);
+            let bins = &spectrum[from.min(spectrum.len() - 1)..to.min(spectrum.len())];
+            bins.iter().sum::<f32>() / bins.len().max(1) as f32
+        })
+        .collect()
+}
@@ -0,0 +1,68 @@
+//! Finding black letterbox and pillarbox bars, for `--autocrop` to cut off
+//! before effects that threshold on brightness see them.
+
+use std::path::Path;
+
+use image::RgbImage;
+
+use vidfx::source::Source;
+
+/// Frames sampled across the input.
+const SAMPLES: usize = 12;
+
+/// Lines whose brightest pixel stays under this luma count as bar.
+const BLACK: u32 = 24;
+
+fn luma(img: &RgbImage, x: u32, y: u32) -> u32 {
+    let [r, g, b] = img.get_pixel(x, y).0;
+    (r as u32 * 77 + g as u32 * 150 + b as u32 * 29) >> 8
+}
+
+/// The part of `img` inside its bars as `(left, top, right, bottom)`
+/// exclusive, `None` if the whole frame is black.
+fn content(img: &RgbImage) -> Option<(u32, u32, u32, u32)> {
+    let (width, height) = img.dimensions();
+    let row_lit = |y: u32| (0..width).any(|x| luma(img, x, y) > BLACK);
+    let column_lit = |x: u32| (0..height).any(|y| luma(img, x, y) > BLACK);
+
+    let top = (0..height).find(|&y| row_lit(y))?;
+    let bottom = (0..height).rev().find(|&y| row_lit(y))? + 1;
+    let left = (0..width).find(|&x| column_lit(x))?;
+    let right = (0..width).rev().find(|&x| column_lit(x))? + 1;
+    Some((left, top, right, bottom))
+}
+
+/// The crop `x,y,width,height` that removes the bars from `path`, `None`
+/// when there are none. Each edge keeps the most of the frame any sample
+/// shows, so a dark scene doesn't eat into the picture.
+pub fn detect(path: &Path) -> Option<(u32, u32, u32, u32)> {
+    let mut source = Source::open(path).ok()?;
+    let (width, height) = source.size();
+    let duration = source.duration().unwrap_or(0.0);
+
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for i in 0..SAMPLES {
+        // Away from the very start and end, which are often black
+        let time = duration * (i as f64 + 0.5) / SAMPLES as f64;
+        let Some(frame) = source.frame_at_time(time) else {
+            continue;
+        };
+        let Some((left, top, right, bottom)) = content(&frame) else {
+            continue;
+        };
+        bounds = Some(match bounds {
+            Some((l, t, r, b)) => (l.min(left), t.min(top), r.max(right), b.max(bottom)),
+            None => (left, top, right, bottom),
+        });
+    }
+
+    // Even edges keep 4:2:0 chroma lined up
+    let (left, top, right, bottom) = bounds?;
+    let (left, top) = (left & !1, top & !1);
+    let (right, bottom) = ((right + 1) & !1, (bottom + 1) & !1);
+    let (right, bottom) = (right.min(width), bottom.min(height));
+    if (left, top, right, bottom) == (0, 0, width, height) || right <= left || bottom <= top {
+        return None;
+    }
+    Some((left, top, right - left, bottom - top))
+}
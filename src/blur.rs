@@ -0,0 +1,57 @@
+//! Directional blurs for the `zoomblur` and `motionblur` effects, averaging
+//! each pixel with samples along a line through it.
+
+use image::RgbaImage;
+
+use crate::displace::sample;
+
+/// Most samples taken along a blur, past which longer blurs just space them
+/// out.
+const MAX_SAMPLES: usize = 32;
+
+/// `img` with every pixel averaged along the line `line(x, y)` gives for it,
+/// `reach` pixels long at most.
+fn along(img: RgbaImage, reach: f64, line: impl Fn(f64, f64) -> [(f64, f64); 2]) -> RgbaImage {
+    if reach < 0.5 {
+        return img;
+    }
+    let samples = (reach.ceil() as usize).clamp(1, MAX_SAMPLES);
+    RgbaImage::from_fn(img.width(), img.height(), |x, y| {
+        let [(sx, sy), (ex, ey)] = line(x as f64, y as f64);
+        let mut sum = [0.0; 4];
+        for i in 0..=samples {
+            let t = i as f64 / samples as f64;
+            let pixel = sample(&img, sx + (ex - sx) * t, sy + (ey - sy) * t);
+            for (total, &v) in sum.iter_mut().zip(&pixel.0) {
+                *total += v as f64;
+            }
+        }
+        image::Rgba(sum.map(|total| (total / (samples + 1) as f64).round() as u8))
+    })
+}
+
+/// `img` blurred towards `center`, in fractions of the frame, over `amount`
+/// of each pixel's distance from it.
+pub fn zoom(img: RgbaImage, center: [f32; 2], amount: f32) -> RgbaImage {
+    let (width, height) = (img.width() as f64, img.height() as f64);
+    let (cx, cy) = (center[0] as f64 * width, center[1] as f64 * height);
+    let amount = (amount as f64).clamp(0.0, 1.0);
+    let reach = amount * width.hypot(height);
+    along(img, reach, |x, y| {
+        [(x, y), (x + (cx - x) * amount, y + (cy - y) * amount)]
+    })
+}
+
+/// `img` blurred over `length` pixels at `angle` degrees, 0 being
+/// horizontal and 90 vertical.
+pub fn motion(img: RgbaImage, angle: f32, length: f32) -> RgbaImage {
+    let (sin, cos) = (angle as f64).to_radians().sin_cos();
+    let half = length.max(0.0) as f64 / 2.0;
+    // Centered on each pixel, so the blur doesn't shift the frame
+    along(img, half * 2.0, |x, y| {
+        [
+            (x - cos * half, y - sin * half),
+            (x + cos * half, y + sin * half),
+        ]
+    })
+}
@@ -0,0 +1,165 @@
+//! Frame queues that spill to disk past a memory budget, for modes that have
+//! to hold on to many frames at once.
+
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use image::RgbImage;
+
+static SPILL_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Fixed size frame slots in a file under the temp dir, deleted on drop.
+struct Spill {
+    file: File,
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    /// Slots `start..end` hold queued frames
+    start: u64,
+    end: u64,
+}
+
+impl Spill {
+    fn create(width: u32, height: u32) -> Spill {
+        let path = std::env::temp_dir().join(format!(
+            "vidfx-{}-{}.frames",
+            std::process::id(),
+            SPILL_FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .expect("Failed to create frame spill file");
+
+        Spill {
+            file,
+            path,
+            width,
+            height,
+            start: 0,
+            end: 0,
+        }
+    }
+
+    fn frame_bytes(&self) -> u64 {
+        self.width as u64 * self.height as u64 * 3
+    }
+
+    fn len(&self) -> usize {
+        (self.end - self.start) as usize
+    }
+
+    fn push(&mut self, frame: &RgbImage) {
+        assert_eq!(
+            frame.dimensions(),
+            (self.width, self.height),
+            "Spilled frames must all have the same size"
+        );
+        self.file
+            .seek(SeekFrom::Start(self.end * self.frame_bytes()))
+            .and_then(|_| self.file.write_all(frame.as_raw()))
+            .expect("Failed to write frame spill file");
+        self.end += 1;
+    }
+
+    fn read(&mut self, slot: u64) -> RgbImage {
+        let mut data = vec![0; self.frame_bytes() as usize];
+        self.file
+            .seek(SeekFrom::Start(slot * self.frame_bytes()))
+            .and_then(|_| self.file.read_exact(&mut data))
+            .expect("Failed to read frame spill file");
+        RgbImage::from_raw(self.width, self.height, data).expect("Spill slot has frame size")
+    }
+
+    fn pop(&mut self) -> Option<RgbImage> {
+        if self.start == self.end {
+            return None;
+        }
+        let frame = self.read(self.start);
+        self.start += 1;
+        if self.start == self.end {
+            // Reuse the file from the top instead of letting it grow
+            self.start = 0;
+            self.end = 0;
+        }
+        Some(frame)
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A FIFO of frames that keeps at most `max_memory` bytes in memory and
+/// writes the rest to a temporary file. Without a budget it is a plain
+/// in-memory queue.
+pub struct FrameQueue {
+    memory: VecDeque<RgbImage>,
+    memory_bytes: u64,
+    max_memory: Option<u64>,
+    spill: Option<Spill>,
+}
+
+impl FrameQueue {
+    pub fn new(max_memory: Option<u64>) -> Self {
+        FrameQueue {
+            memory: VecDeque::new(),
+            memory_bytes: 0,
+            max_memory,
+            spill: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.memory.len() + self.spill.as_ref().map_or(0, Spill::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push_back(&mut self, frame: RgbImage) {
+        let bytes = frame.as_raw().len() as u64;
+        let spilling = self.spill.as_ref().is_some_and(|spill| spill.len() > 0);
+        let over_budget = self
+            .max_memory
+            .is_some_and(|max| self.memory_bytes + bytes > max);
+
+        // Once frames are on disk, later ones have to follow them to keep order
+        if spilling || (over_budget && !self.memory.is_empty()) {
+            let (width, height) = frame.dimensions();
+            self.spill
+                .get_or_insert_with(|| Spill::create(width, height))
+                .push(&frame);
+        } else {
+            self.memory_bytes += bytes;
+            self.memory.push_back(frame);
+        }
+    }
+
+    pub fn pop_front(&mut self) -> Option<RgbImage> {
+        if let Some(frame) = self.memory.pop_front() {
+            self.memory_bytes -= frame.as_raw().len() as u64;
+            return Some(frame);
+        }
+        self.spill.as_mut()?.pop()
+    }
+
+    /// A copy of the frame `index` places from the front.
+    pub fn get(&mut self, index: usize) -> Option<RgbImage> {
+        if let Some(frame) = self.memory.get(index) {
+            return Some(frame.clone());
+        }
+        let spill = self.spill.as_mut()?;
+        let slot = (index - self.memory.len()) as u64;
+        (slot < spill.len() as u64).then(|| spill.read(spill.start + slot))
+    }
+}
@@ -0,0 +1,52 @@
+use std::path::{Path, PathBuf};
+
+use image::RgbImage;
+use vidfx::hash::fnv1a;
+
+/// Processed frames of one render, under `<cache dir>/<hash of everything
+/// that affects the output>/`. A re-render with the same input and settings
+/// reuses each frame that was already written, so tweaking the end of a long
+/// render only reprocesses the frames that changed.
+pub struct FrameCache {
+    dir: PathBuf,
+}
+
+impl FrameCache {
+    /// `key` must describe everything that affects processed frames: the
+    /// input and the files it depends on as they stand, the effect chain,
+    /// modulation and so on.
+    pub fn open(cache_dir: &str, key: &str) -> FrameCache {
+        let hash = fnv1a(key.as_bytes());
+
+        let dir = Path::new(cache_dir).join(format!("{:016x}", hash));
+        std::fs::create_dir_all(&dir).expect("Failed to create --cache-dir");
+        FrameCache { dir }
+    }
+
+    fn path(&self, index: usize) -> PathBuf {
+        self.dir.join(format!("{:08}.rgb", index))
+    }
+
+    pub fn get(&self, index: usize) -> Option<RgbImage> {
+        let data = std::fs::read(self.path(index)).ok()?;
+        let (header, pixels) = data.split_at_checked(8)?;
+        let width = u32::from_le_bytes(header[0..4].try_into().ok()?);
+        let height = u32::from_le_bytes(header[4..8].try_into().ok()?);
+        RgbImage::from_raw(width, height, pixels.to_vec())
+    }
+
+    /// Writes through a temporary name so an interrupted render never leaves
+    /// a truncated frame behind.
+    pub fn put(&self, index: usize, frame: &RgbImage) {
+        let mut data = Vec::with_capacity(8 + frame.as_raw().len());
+        data.extend_from_slice(&frame.width().to_le_bytes());
+        data.extend_from_slice(&frame.height().to_le_bytes());
+        data.extend_from_slice(frame.as_raw());
+
+        let path = self.path(index);
+        let tmp = path.with_extension("tmp");
+        if std::fs::write(&tmp, data).is_ok() {
+            let _ = std::fs::rename(tmp, path);
+        }
+    }
+}
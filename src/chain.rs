@@ -0,0 +1,1391 @@
+//! A serializable list of effects, shared by the CLI, presets and library users.
+//!
+//! ```no_run
+//! use clap::ValueEnum;
+//! use imgfx::sort::{Direction, SortBy};
+//! use vidfx::chain::{Color, EffectChain};
+//!
+//! let direction = Direction::from_str("horizontal", true).unwrap();
+//! let sort_by = SortBy::from_str("luma", true).unwrap();
+//! let chain = EffectChain::new()
+//!     .xor(Color::hex("ff0000"))
+//!     .bloom(1.5, 8.0, 100, None)
+//!     .sort(direction, sort_by, 0.2, 0.8);
+//!
+//! let json = serde_json::to_string(&chain).unwrap();
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use clap::builder::styling::RgbColor;
+use image::{DynamicImage, RgbaImage};
+use imgfx::*;
+use serde::{Deserialize, Serialize};
+
+use crate::curves::{self, Curve};
+use crate::{
+    ascii, blur, displace, gradient, halftone, isf, levels, linear, matching, mixer, replace,
+    ripple, schema, shader, shatter, FrameContext,
+};
+
+/// An RGB color, written as a hex string (`ff0000`) when serialized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Color(pub u8, pub u8, pub u8);
+
+impl Color {
+    /// Parses `rrggbb`, with or without a leading `#`.
+    ///
+    /// # Panics
+    ///
+    /// If `hex` isn't a valid color, use `str::parse` to handle that instead.
+    pub fn hex(hex: &str) -> Color {
+        hex.parse().expect("Could not convert color to rgb")
+    }
+
+    pub(crate) fn scaled(&self, scale_factor: f64, scaling: &Scaling) -> RgbColor {
+        let channel = |value: u8, i: usize| {
+            if scaling.channels[i] {
+                (value as f64 * scale_factor) as u8
+            } else {
+                value
+            }
+        };
+        RgbColor(channel(self.0, 0), channel(self.1, 1), channel(self.2, 2))
+    }
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                .ok_or_else(|| format!("invalid color '{}'", s))
+        };
+
+        if hex.len() != 6 {
+            return Err(format!("invalid color '{}'", s));
+        }
+
+        Ok(Color(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> String {
+        color.to_string()
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+/// Serializes clap value enums from imgfx by their command line name.
+mod value_enum {
+    use clap::ValueEnum;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T: ValueEnum, S: Serializer>(value: &T, s: S) -> Result<S::Ok, S::Error> {
+        let name = value
+            .to_possible_value()
+            .expect("imgfx value enums have no skipped variants");
+        s.serialize_str(name.get_name())
+    }
+
+    pub fn deserialize<'de, T: ValueEnum, D: Deserializer<'de>>(d: D) -> Result<T, D::Error> {
+        let name = String::deserialize(d)?;
+        T::from_str(&name, true).map_err(D::Error::custom)
+    }
+}
+
+/// A remapping of the scale factor before it reaches any effect.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScaleCurve {
+    #[default]
+    Linear,
+    /// Full strength at 0, nothing at 1
+    Invert,
+    /// Slow to start, then rising steeply
+    Exp,
+    /// Rising steeply, then leveling off
+    Log,
+}
+
+/// Steepness of the `exp` and `log` curves.
+const CURVE_STEEPNESS: f64 = 4.0;
+
+impl ScaleCurve {
+    pub fn apply(self, scale_factor: f64) -> f64 {
+        let k = CURVE_STEEPNESS;
+        match self {
+            ScaleCurve::Linear => scale_factor,
+            ScaleCurve::Invert => 1.0 - scale_factor,
+            ScaleCurve::Exp => ((k * scale_factor).exp() - 1.0) / (k.exp() - 1.0),
+            ScaleCurve::Log => (1.0 + (k.exp() - 1.0) * scale_factor.max(0.0)).ln() / k,
+        }
+    }
+}
+
+/// How a chain's colors follow the scale factor: which of red, green and
+/// blue it scales, the rest staying at full value, and the curve it goes
+/// through first.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scaling {
+    pub channels: [bool; 3],
+    pub curve: ScaleCurve,
+}
+
+impl Default for Scaling {
+    fn default() -> Self {
+        Scaling {
+            channels: [true; 3],
+            curve: ScaleCurve::Linear,
+        }
+    }
+}
+
+impl Scaling {
+    /// Parses the channels to scale, e.g. `r,b` or `rb`.
+    pub fn parse_channels(s: &str) -> Result<[bool; 3], String> {
+        let mut channels = [false; 3];
+        for c in s.chars().filter(|c| !matches!(c, ',' | ' ')) {
+            match c.to_ascii_lowercase() {
+                'r' => channels[0] = true,
+                'g' => channels[1] = true,
+                'b' => channels[2] = true,
+                _ => return Err(format!("invalid channels '{}', expected e.g. r,b", s)),
+            }
+        }
+        Ok(channels)
+    }
+
+    fn is_default(&self) -> bool {
+        *self == Scaling::default()
+    }
+}
+
+/// A musical step length, for effects and tools that act on the beat grid.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Per {
+    Beat,
+    /// Four beats
+    Bar,
+}
+
+impl Per {
+    pub fn beats(self) -> f64 {
+        match self {
+            Per::Beat => 1.0,
+            Per::Bar => 4.0,
+        }
+    }
+
+    /// Which step is playing at `time`.
+    pub fn step(self, time: f64, bpm: u32) -> usize {
+        self.step_at(time * bpm as f64 / 60.0)
+    }
+
+    /// Which step is playing once `beats` beats have gone by.
+    pub fn step_at(self, beats: f64) -> usize {
+        (beats / self.beats()).floor() as usize
+    }
+}
+
+/// What moves `shatter` cell centers from their resting places.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Jitter {
+    /// Cells stay put
+    None,
+    /// Thrown on every beat, settling before the next. Needs a bpm in the
+    /// frame context
+    Beat,
+    /// Thrown as far as the scale factor
+    Modulate,
+}
+
+/// A `falsecolor` palette.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Palette {
+    /// Black through purple and orange to pale yellow
+    Inferno,
+    /// Dark purple through teal to yellow
+    Viridis,
+    /// The iron palette of thermal cameras
+    Ir,
+    /// Bands for judging exposure: purple and blue crushed, green at mid
+    /// grey, pink a stop over, yellow and red near and at clipping
+    Exposure,
+}
+
+/// How `match-color` moves a frame's colors onto its reference's.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMethod {
+    /// Every channel's levels spread exactly as the reference's
+    Histogram,
+    /// The reference's average color and contrast, keeping the frame's own
+    /// tonal shape
+    Reinhard,
+}
+
+/// What fills each `shatter` cell.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShatterFill {
+    /// The cell's average color, for stained glass
+    Average,
+    /// The cell's part of the frame, moving with its center
+    Shards,
+}
+
+/// How long a strobe flash lasts, frames (`1f`) or a duration (`50ms`,
+/// `0.1s`, `0.1`) when serialized.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum FlashLength {
+    Frames(u32),
+    Seconds(f64),
+}
+
+impl FlashLength {
+    fn seconds(self, frame_rate: f64) -> f64 {
+        match self {
+            FlashLength::Frames(frames) => frames as f64 / frame_rate,
+            FlashLength::Seconds(seconds) => seconds,
+        }
+    }
+}
+
+impl FromStr for FlashLength {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("invalid flash length '{}', e.g. 1f or 50ms", s);
+
+        if let Some(frames) = s.strip_suffix('f') {
+            return frames
+                .trim()
+                .parse()
+                .map(FlashLength::Frames)
+                .map_err(|_| invalid());
+        }
+        let (number, scale) = if let Some(n) = s.strip_suffix("ms") {
+            (n, 0.001)
+        } else {
+            (s.strip_suffix('s').unwrap_or(s), 1.0)
+        };
+        let seconds = number.trim().parse::<f64>().map_err(|_| invalid())? * scale;
+        if seconds < 0.0 {
+            return Err(invalid());
+        }
+        Ok(FlashLength::Seconds(seconds))
+    }
+}
+
+impl TryFrom<String> for FlashLength {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<FlashLength> for String {
+    fn from(length: FlashLength) -> String {
+        match length {
+            FlashLength::Frames(frames) => format!("{}f", frames),
+            FlashLength::Seconds(seconds) => format!("{}s", seconds),
+        }
+    }
+}
+
+/// Flashes per second a strobe stays under unless told otherwise, for
+/// photosensitive viewers.
+pub const DEFAULT_MAX_FLASH_RATE: f64 = 3.0;
+
+fn default_max_flash_rate() -> f64 {
+    DEFAULT_MAX_FLASH_RATE
+}
+
+/// Whether a strobe stepping every `per` is lit at this frame. Steps are
+/// skipped as needed to stay under `max_flash_rate` flashes per second.
+fn strobe_lit(duration: FlashLength, per: Per, max_flash_rate: f64, frame: &FrameContext) -> bool {
+    let Some(bpm) = frame.bpm else {
+        return false;
+    };
+    let step = 60.0 / bpm as f64 * per.beats();
+    let every = if max_flash_rate > 0.0 {
+        (1.0 / step / max_flash_rate).ceil().max(1.0)
+    } else {
+        1.0
+    };
+
+    let beats = frame.beats.unwrap_or(frame.time * bpm as f64 / 60.0);
+    let elapsed = (beats % (per.beats() * every)) * 60.0 / bpm as f64;
+    // Frames sitting exactly on the end of the flash are already dark
+    elapsed < duration.seconds(frame.frame_rate) - 1e-9
+}
+
+/// Largest useful shift for 8 bit channels.
+pub const MAX_SHIFT: u8 = 8;
+
+fn shift_bits(bits: u8, modulate: bool, scale_factor: f64) -> u8 {
+    let bits = if modulate {
+        (bits as f64 * scale_factor)
+            .round()
+            .clamp(0.0, MAX_SHIFT as f64) as u8
+    } else {
+        bits
+    };
+    bits.min(MAX_SHIFT)
+}
+
+/// A color along a gradient map, at a luma from 0 for black to 1 for white.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stop {
+    pub at: f32,
+    pub color: Color,
+}
+
+impl FromStr for Stop {
+    type Err = String;
+
+    /// Parses `at:color`, e.g. `0.5:ff7a18`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid stop '{}', expected e.g. 0.5:ff7a18", s);
+        let (at, color) = s.split_once(':').ok_or_else(invalid)?;
+        let at = at.trim().parse::<f32>().map_err(|_| invalid())?;
+        if !(0.0..=1.0).contains(&at) {
+            return Err(format!("stop '{}' is outside 0..1", s));
+        }
+        Ok(Stop {
+            at,
+            color: color.parse()?,
+        })
+    }
+}
+
+/// Which channels an arithmetic or logic op reads, e.g. `lhs: ["b", "g", "r"]`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Operands {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lhs: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rhs: Option<Vec<String>>,
+}
+
+/// One step of an [`EffectChain`]. Mirrors the CLI subcommands.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "lowercase")]
+pub enum Effect {
+    Or {
+        color: Color,
+        #[serde(flatten)]
+        operands: Operands,
+        #[serde(default)]
+        negate: bool,
+    },
+    And {
+        color: Color,
+        #[serde(flatten)]
+        operands: Operands,
+        #[serde(default)]
+        negate: bool,
+    },
+    Xor {
+        color: Color,
+        #[serde(flatten)]
+        operands: Operands,
+        #[serde(default)]
+        negate: bool,
+    },
+    Left {
+        bits: u8,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lhs: Option<Vec<String>>,
+        #[serde(default)]
+        raw: bool,
+        /// Scale `bits` with the frame's scale factor
+        #[serde(default)]
+        modulate: bool,
+    },
+    Right {
+        bits: u8,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        lhs: Option<Vec<String>>,
+        #[serde(default)]
+        raw: bool,
+        /// Scale `bits` with the frame's scale factor
+        #[serde(default)]
+        modulate: bool,
+    },
+    Add {
+        color: Color,
+        #[serde(flatten)]
+        operands: Operands,
+    },
+    Sub {
+        color: Color,
+        #[serde(flatten)]
+        operands: Operands,
+        #[serde(default)]
+        raw: bool,
+    },
+    Mult {
+        color: Color,
+        #[serde(flatten)]
+        operands: Operands,
+    },
+    Pow {
+        color: Color,
+        #[serde(flatten)]
+        operands: Operands,
+    },
+    Div {
+        color: Color,
+        #[serde(flatten)]
+        operands: Operands,
+    },
+    Average {
+        color: Color,
+        #[serde(flatten)]
+        operands: Operands,
+    },
+    Screen {
+        color: Color,
+        #[serde(flatten)]
+        operands: Operands,
+    },
+    Overlay {
+        color: Color,
+        #[serde(flatten)]
+        operands: Operands,
+    },
+    Bloom {
+        intensity: f32,
+        radius: f32,
+        min_threshold: u8,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_threshold: Option<u8>,
+    },
+    Sort {
+        #[serde(with = "value_enum")]
+        direction: imgfx::sort::Direction,
+        #[serde(with = "value_enum")]
+        sort_by: imgfx::sort::SortBy,
+        min_threshold: f32,
+        max_threshold: f32,
+    },
+    Shader {
+        file: String,
+        #[serde(default)]
+        uniforms: Vec<String>,
+    },
+    Isf {
+        file: String,
+        #[serde(default)]
+        params: Vec<String>,
+        #[serde(default)]
+        modulate: Vec<String>,
+    },
+    /// Flashes a color over the frame, or inverts it, at the start of every
+    /// beat or bar. Needs a bpm in the frame context
+    Strobe {
+        color: Color,
+        #[serde(default)]
+        invert: bool,
+        duration: FlashLength,
+        per: Per,
+        /// How much of the flash shows over the frame, 0-1
+        mix: f32,
+        /// Photosensitivity limit in flashes per second, none when 0
+        #[serde(default = "default_max_flash_rate")]
+        max_flash_rate: f64,
+    },
+    /// Stretches levels so the darkest and brightest pixels reach black and
+    /// white
+    Normalize {
+        /// Percent of samples given up at each end
+        clip: f32,
+        /// Stretch every frame by levels from the whole input instead of its own
+        #[serde(default)]
+        global: bool,
+        /// The whole input's levels, filled in by a first pass before rendering
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        levels: Option<[u8; 2]>,
+    },
+    /// Prints the frame as rotated CMYK halftone screens
+    Halftone {
+        /// Distance between dots in pixels
+        dot_size: f32,
+        /// Screen angles in degrees for cyan, magenta, yellow and black
+        angles: [f32; 4],
+        /// Scale `dot_size` with the frame's scale factor
+        #[serde(default)]
+        modulate: bool,
+    },
+    /// Redraws the frame as a mosaic of characters
+    Ascii {
+        /// `dense`, `simple`, `digits` or the characters themselves
+        charset: String,
+        /// Cell width and height in pixels
+        cell: [u32; 2],
+        /// Draw each character in its cell's color rather than white
+        #[serde(default)]
+        color: bool,
+    },
+    /// Breaks the frame into Voronoi cells
+    Shatter {
+        /// About how many cells the frame is broken into
+        cells: u32,
+        jitter: Jitter,
+        /// How far centers are thrown, in cell sizes up to 1
+        amount: f32,
+        fill: ShatterFill,
+        /// Width of the dark lines between cells in pixels, none when 0
+        #[serde(default)]
+        edges: f32,
+    },
+    /// Pushes pixels around by a displacement map
+    Displace {
+        /// `noise:perlin`, an image, or a video whose luma is the map
+        map: String,
+        /// Furthest a pixel is pushed, in pixels
+        amount: f32,
+        /// Size of noise features in pixels
+        scale: f32,
+        /// How many times a second noise changes
+        evolve: f32,
+        /// Direction image and video maps push in, in degrees
+        #[serde(default)]
+        angle: f32,
+        /// Scale `amount` with the frame's scale factor
+        #[serde(default)]
+        modulate: bool,
+    },
+    /// Distorts the frame with sine waves spreading from a point
+    Ripple {
+        /// How far waves push pixels, in pixels
+        amplitude: f32,
+        /// Distance between waves in pixels
+        wavelength: f32,
+        /// Wavelengths the waves travel a second, inwards when negative
+        speed: f32,
+        /// Where the waves start from, in fractions of the frame
+        origin: [f32; 2],
+        /// Scale `amplitude` with the frame's scale factor
+        #[serde(default)]
+        modulate: bool,
+    },
+    /// Blurs the frame towards a point, as if zooming during the exposure
+    Zoomblur {
+        /// The point blurred towards, in fractions of the frame
+        center: [f32; 2],
+        /// How much of each pixel's distance to the center is blurred over,
+        /// 0 to 1
+        amount: f32,
+        /// Scale `amount` with the frame's scale factor
+        #[serde(default)]
+        modulate: bool,
+    },
+    /// Blurs the frame along a direction, as if moving during the exposure
+    Motionblur {
+        /// Direction in degrees, 0 being horizontal and 90 vertical
+        angle: f32,
+        /// Length of the blur in pixels
+        length: f32,
+        /// Scale `length` with the frame's scale factor
+        #[serde(default)]
+        modulate: bool,
+    },
+    /// Tone curves over every channel and then each on its own
+    Curves {
+        #[serde(default, skip_serializing_if = "Curve::is_identity")]
+        master: Curve,
+        #[serde(default, skip_serializing_if = "Curve::is_identity")]
+        r: Curve,
+        #[serde(default, skip_serializing_if = "Curve::is_identity")]
+        g: Curve,
+        #[serde(default, skip_serializing_if = "Curve::is_identity")]
+        b: Curve,
+    },
+    /// White balance, then a mix of each output channel from the inputs
+    Mixer {
+        /// Rows for red, green and blue out, columns for red, green and blue in
+        matrix: [[f32; 3]; 3],
+        /// Color temperature in kelvin the footage is corrected from
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        temperature: Option<f32>,
+        /// Shift towards magenta, or green when negative, -100 to 100
+        #[serde(default)]
+        tint: f32,
+    },
+    /// Moves pixels near one hue to another color
+    #[serde(rename = "replace-color")]
+    ReplaceColor {
+        from: Color,
+        to: Color,
+        /// Hue distance still replaced in full, as a fraction of half the
+        /// hue wheel
+        tolerance: f32,
+        /// Hue distance past `tolerance` the replacement fades out over
+        #[serde(default)]
+        feather: f32,
+        /// Scale both colors with the frame's scale factor, on the channels
+        /// chosen by the chain's scaling
+        #[serde(default)]
+        modulate: bool,
+    },
+    /// Maps each pixel's luma to a color along a ramp of stops
+    #[serde(rename = "gradient-map")]
+    GradientMap {
+        stops: Vec<Stop>,
+        /// Scale every stop's color with the frame's scale factor, on the
+        /// channels chosen by the chain's scaling
+        #[serde(default)]
+        modulate: bool,
+    },
+    /// Maps each pixel's luma through a scientific or thermal palette
+    Falsecolor { palette: Palette },
+    /// Moves the frame's colors onto those of a reference image or clip
+    #[serde(rename = "match-color")]
+    MatchColor {
+        /// An image, a video sampled across its length, or a `generate:`
+        /// test pattern
+        reference: String,
+        method: MatchMethod,
+        /// How far colors move onto the reference's, 0 to 1
+        amount: f32,
+        /// Scale `amount` with the frame's scale factor
+        #[serde(default)]
+        modulate: bool,
+    },
+}
+
+impl Effect {
+    pub fn apply(&self, img: DynamicImage, frame: &FrameContext) -> RgbaImage {
+        self.apply_scaled(img, frame, &Scaling::default())
+    }
+
+    fn apply_scaled(
+        &self,
+        img: DynamicImage,
+        frame: &FrameContext,
+        scaling: &Scaling,
+    ) -> RgbaImage {
+        let scale_factor = scaling.curve.apply(frame.scale_factor);
+        match self {
+            Effect::Or {
+                color,
+                operands,
+                negate,
+            } => or(
+                img,
+                operands.lhs.clone(),
+                operands.rhs.clone(),
+                color.scaled(scale_factor, scaling),
+                *negate,
+            ),
+            Effect::And {
+                color,
+                operands,
+                negate,
+            } => and(
+                img,
+                operands.lhs.clone(),
+                operands.rhs.clone(),
+                color.scaled(scale_factor, scaling),
+                *negate,
+            ),
+            Effect::Xor {
+                color,
+                operands,
+                negate,
+            } => xor(
+                img,
+                operands.lhs.clone(),
+                operands.rhs.clone(),
+                color.scaled(scale_factor, scaling),
+                *negate,
+            ),
+            Effect::Left {
+                bits,
+                lhs,
+                raw,
+                modulate,
+            } => bitshift(
+                img,
+                BitshiftDirection::LEFT,
+                lhs.clone(),
+                shift_bits(*bits, *modulate, scale_factor),
+                *raw,
+            ),
+            Effect::Right {
+                bits,
+                lhs,
+                raw,
+                modulate,
+            } => bitshift(
+                img,
+                BitshiftDirection::RIGHT,
+                lhs.clone(),
+                shift_bits(*bits, *modulate, scale_factor),
+                *raw,
+            ),
+            Effect::Add { color, operands } => add(
+                img,
+                operands.lhs.clone(),
+                operands.rhs.clone(),
+                color.scaled(scale_factor, scaling),
+            ),
+            Effect::Sub {
+                color,
+                operands,
+                raw,
+            } => sub(
+                img,
+                operands.lhs.clone(),
+                operands.rhs.clone(),
+                color.scaled(scale_factor, scaling),
+                *raw,
+            ),
+            Effect::Mult { color, operands } => mult(
+                img,
+                operands.lhs.clone(),
+                operands.rhs.clone(),
+                color.scaled(scale_factor, scaling),
+            ),
+            Effect::Pow { color, operands } => pow(
+                img,
+                operands.lhs.clone(),
+                operands.rhs.clone(),
+                color.scaled(scale_factor, scaling),
+            ),
+            Effect::Div { color, operands } => div(
+                img,
+                operands.lhs.clone(),
+                operands.rhs.clone(),
+                color.scaled(scale_factor, scaling),
+            ),
+            Effect::Average { color, operands } => average(
+                img,
+                operands.lhs.clone(),
+                operands.rhs.clone(),
+                color.scaled(scale_factor, scaling),
+            ),
+            Effect::Screen { color, operands } => screen(
+                img,
+                operands.lhs.clone(),
+                operands.rhs.clone(),
+                color.scaled(scale_factor, scaling),
+            ),
+            Effect::Overlay { color, operands } => overlay(
+                img,
+                operands.lhs.clone(),
+                operands.rhs.clone(),
+                color.scaled(scale_factor, scaling),
+            ),
+            Effect::Bloom {
+                intensity,
+                radius,
+                min_threshold,
+                max_threshold,
+            } => imgfx::bloom(img, *intensity, *radius, *min_threshold, *max_threshold),
+            Effect::Sort {
+                direction,
+                sort_by,
+                min_threshold,
+                max_threshold,
+            } => sort(
+                Into::into(img),
+                *direction,
+                *sort_by,
+                *min_threshold * scale_factor as f32,
+                *max_threshold * scale_factor as f32,
+            ),
+            Effect::Shader { file, uniforms } => {
+                shader::apply(file, uniforms, img.into_rgba8(), frame)
+            }
+            Effect::Isf {
+                file,
+                params,
+                modulate,
+            } => isf::apply(file, params, modulate, img.into_rgba8(), frame),
+            Effect::Strobe {
+                color,
+                invert,
+                duration,
+                per,
+                mix,
+                max_flash_rate,
+            } => {
+                let mut img = img.into_rgba8();
+                if strobe_lit(*duration, *per, *max_flash_rate, frame) {
+                    let mix = mix.clamp(0.0, 1.0);
+                    let flash = [color.0, color.1, color.2];
+                    for pixel in img.pixels_mut() {
+                        for (c, channel) in pixel.0.iter_mut().take(3).enumerate() {
+                            let target = if *invert { 255 - *channel } else { flash[c] };
+                            *channel = (*channel as f32 + (target as f32 - *channel as f32) * mix)
+                                .round() as u8;
+                        }
+                    }
+                }
+                img
+            }
+            Effect::Normalize { clip, levels, .. } => {
+                let mut img = img.into_rgba8();
+                let levels = levels.unwrap_or_else(|| levels::frame_levels(&img, *clip));
+                levels::stretch(&mut img, levels);
+                img
+            }
+            Effect::Halftone {
+                dot_size,
+                angles,
+                modulate,
+            } => {
+                let dot_size = if *modulate {
+                    *dot_size * scale_factor as f32
+                } else {
+                    *dot_size
+                };
+                halftone::apply(img.into_rgba8(), dot_size, *angles)
+            }
+            Effect::Ascii {
+                charset,
+                cell,
+                color,
+            } => ascii::apply(
+                img.into_rgba8(),
+                &ascii::charset(charset),
+                (cell[0], cell[1]),
+                *color,
+            ),
+            Effect::Shatter {
+                cells,
+                jitter,
+                amount,
+                fill,
+                edges,
+            } => shatter::apply(
+                img.into_rgba8(),
+                *cells,
+                *jitter,
+                *amount,
+                *fill,
+                *edges,
+                frame,
+                scale_factor,
+            ),
+            Effect::Displace {
+                map,
+                amount,
+                scale,
+                evolve,
+                angle,
+                modulate,
+            } => {
+                let amount = if *modulate {
+                    *amount * scale_factor as f32
+                } else {
+                    *amount
+                };
+                displace::apply(
+                    img.into_rgba8(),
+                    map,
+                    amount,
+                    *scale,
+                    *evolve,
+                    *angle,
+                    frame,
+                )
+            }
+            Effect::Ripple {
+                amplitude,
+                wavelength,
+                speed,
+                origin,
+                modulate,
+            } => {
+                let amplitude = if *modulate {
+                    *amplitude * scale_factor as f32
+                } else {
+                    *amplitude
+                };
+                ripple::apply(
+                    img.into_rgba8(),
+                    amplitude,
+                    *wavelength,
+                    *speed,
+                    *origin,
+                    frame,
+                )
+            }
+            Effect::Zoomblur {
+                center,
+                amount,
+                modulate,
+            } => {
+                let amount = if *modulate {
+                    *amount * scale_factor as f32
+                } else {
+                    *amount
+                };
+                blur::zoom(img.into_rgba8(), *center, amount)
+            }
+            Effect::Motionblur {
+                angle,
+                length,
+                modulate,
+            } => {
+                let length = if *modulate {
+                    *length * scale_factor as f32
+                } else {
+                    *length
+                };
+                blur::motion(img.into_rgba8(), *angle, length)
+            }
+            Effect::Curves { master, r, g, b } => {
+                curves::apply(img.into_rgba8(), master, [r, g, b])
+            }
+            Effect::Mixer {
+                matrix,
+                temperature,
+                tint,
+            } => mixer::apply(img.into_rgba8(), matrix, *temperature, *tint),
+            Effect::ReplaceColor {
+                from,
+                to,
+                tolerance,
+                feather,
+                modulate,
+            } => {
+                let rgb = |color: &Color| {
+                    if *modulate {
+                        let RgbColor(r, g, b) = color.scaled(scale_factor, scaling);
+                        [r, g, b]
+                    } else {
+                        [color.0, color.1, color.2]
+                    }
+                };
+                replace::apply(img.into_rgba8(), rgb(from), rgb(to), *tolerance, *feather)
+            }
+            Effect::GradientMap { stops, modulate } => {
+                let stops: Vec<(f32, [u8; 3])> = stops
+                    .iter()
+                    .map(|stop| {
+                        let color = &stop.color;
+                        let rgb = if *modulate {
+                            let RgbColor(r, g, b) = color.scaled(scale_factor, scaling);
+                            [r, g, b]
+                        } else {
+                            [color.0, color.1, color.2]
+                        };
+                        (stop.at, rgb)
+                    })
+                    .collect();
+                gradient::apply(img.into_rgba8(), &stops)
+            }
+            Effect::Falsecolor { palette } => {
+                gradient::apply(img.into_rgba8(), &gradient::palette(*palette))
+            }
+            Effect::MatchColor {
+                reference,
+                method,
+                amount,
+                modulate,
+            } => {
+                let amount = if *modulate {
+                    *amount * scale_factor as f32
+                } else {
+                    *amount
+                };
+                matching::apply(img.into_rgba8(), reference, *method, amount)
+            }
+        }
+    }
+}
+
+/// Effects applied one after the other to every frame.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct EffectChain {
+    pub effects: Vec<Effect>,
+    /// Run add, mult, average, screen and bloom on linear light rather than
+    /// sRGB values, so blends and glows fall off like light does
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub linear: bool,
+    /// Which channels the scale factor drives and through what curve
+    #[serde(default, skip_serializing_if = "Scaling::is_default")]
+    pub scaling: Scaling,
+}
+
+impl EffectChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a saved chain, migrating it from older versions of the format.
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+        serde_json::from_value(schema::migrate(value)?).map_err(|e| e.to_string())
+    }
+
+    /// The chain as JSON stamped with the current format version.
+    pub fn to_json(&self) -> String {
+        let mut value = serde_json::to_value(self).expect("Effect chains serialize");
+        value["version"] = schema::VERSION.into();
+        value.to_string()
+    }
+
+    /// Appends any effect, for options the shorthand methods don't cover.
+    pub fn then(mut self, effect: Effect) -> Self {
+        self.effects.push(effect);
+        self
+    }
+
+    pub fn or(self, color: Color) -> Self {
+        self.then(Effect::Or {
+            color,
+            operands: Operands::default(),
+            negate: false,
+        })
+    }
+
+    pub fn and(self, color: Color) -> Self {
+        self.then(Effect::And {
+            color,
+            operands: Operands::default(),
+            negate: false,
+        })
+    }
+
+    pub fn xor(self, color: Color) -> Self {
+        self.then(Effect::Xor {
+            color,
+            operands: Operands::default(),
+            negate: false,
+        })
+    }
+
+    pub fn left(self, bits: u8) -> Self {
+        self.then(Effect::Left {
+            bits,
+            lhs: None,
+            raw: false,
+            modulate: false,
+        })
+    }
+
+    pub fn right(self, bits: u8) -> Self {
+        self.then(Effect::Right {
+            bits,
+            lhs: None,
+            raw: false,
+            modulate: false,
+        })
+    }
+
+    pub fn add(self, color: Color) -> Self {
+        self.then(Effect::Add {
+            color,
+            operands: Operands::default(),
+        })
+    }
+
+    pub fn sub(self, color: Color) -> Self {
+        self.then(Effect::Sub {
+            color,
+            operands: Operands::default(),
+            raw: false,
+        })
+    }
+
+    pub fn mult(self, color: Color) -> Self {
+        self.then(Effect::Mult {
+            color,
+            operands: Operands::default(),
+        })
+    }
+
+    pub fn pow(self, color: Color) -> Self {
+        self.then(Effect::Pow {
+            color,
+            operands: Operands::default(),
+        })
+    }
+
+    pub fn div(self, color: Color) -> Self {
+        self.then(Effect::Div {
+            color,
+            operands: Operands::default(),
+        })
+    }
+
+    pub fn average(self, color: Color) -> Self {
+        self.then(Effect::Average {
+            color,
+            operands: Operands::default(),
+        })
+    }
+
+    pub fn screen(self, color: Color) -> Self {
+        self.then(Effect::Screen {
+            color,
+            operands: Operands::default(),
+        })
+    }
+
+    pub fn overlay(self, color: Color) -> Self {
+        self.then(Effect::Overlay {
+            color,
+            operands: Operands::default(),
+        })
+    }
+
+    pub fn bloom(
+        self,
+        intensity: f32,
+        radius: f32,
+        min_threshold: u8,
+        max_threshold: Option<u8>,
+    ) -> Self {
+        self.then(Effect::Bloom {
+            intensity,
+            radius,
+            min_threshold,
+            max_threshold,
+        })
+    }
+
+    pub fn sort(
+        self,
+        direction: imgfx::sort::Direction,
+        sort_by: imgfx::sort::SortBy,
+        min_threshold: f32,
+        max_threshold: f32,
+    ) -> Self {
+        self.then(Effect::Sort {
+            direction,
+            sort_by,
+            min_threshold,
+            max_threshold,
+        })
+    }
+
+    pub fn shader(self, file: impl Into<String>) -> Self {
+        self.then(Effect::Shader {
+            file: file.into(),
+            uniforms: vec![],
+        })
+    }
+
+    pub fn isf(self, file: impl Into<String>) -> Self {
+        self.then(Effect::Isf {
+            file: file.into(),
+            params: vec![],
+            modulate: vec![],
+        })
+    }
+
+    /// A flash of `color` for the first frame of every beat, limited to
+    /// three flashes a second.
+    pub fn strobe(self, color: Color) -> Self {
+        self.then(Effect::Strobe {
+            color,
+            invert: false,
+            duration: FlashLength::Frames(1),
+            per: Per::Beat,
+            mix: 1.0,
+            max_flash_rate: DEFAULT_MAX_FLASH_RATE,
+        })
+    }
+
+    /// Switches linear light processing on or off, see [`EffectChain::linear`].
+    pub fn linear(mut self, linear: bool) -> Self {
+        self.linear = linear;
+        self
+    }
+
+    /// Sets which channels follow the scale factor and how, see
+    /// [`EffectChain::scaling`].
+    pub fn scaling(mut self, scaling: Scaling) -> Self {
+        self.scaling = scaling;
+        self
+    }
+
+    /// Per frame level stretching, clipping `clip` percent at each end.
+    pub fn normalize(self, clip: f32) -> Self {
+        self.then(Effect::Normalize {
+            clip,
+            global: false,
+            levels: None,
+        })
+    }
+
+    /// Halftone screens `dot_size` pixels apart at the usual print angles.
+    pub fn halftone(self, dot_size: f32) -> Self {
+        self.then(Effect::Halftone {
+            dot_size,
+            angles: [15.0, 75.0, 0.0, 45.0],
+            modulate: false,
+        })
+    }
+
+    /// Colored `dense` characters on 8x12 cells.
+    pub fn ascii(self) -> Self {
+        self.then(Effect::Ascii {
+            charset: "dense".to_string(),
+            cell: [8, 12],
+            color: true,
+        })
+    }
+
+    /// Stained glass of about `cells` still cells with thin lead lines.
+    pub fn shatter(self, cells: u32) -> Self {
+        self.then(Effect::Shatter {
+            cells,
+            jitter: Jitter::None,
+            amount: 0.5,
+            fill: ShatterFill::Average,
+            edges: 1.0,
+        })
+    }
+
+    /// Pixels pushed up to `amount` pixels by slowly evolving Perlin noise.
+    pub fn displace(self, amount: f32) -> Self {
+        self.then(Effect::Displace {
+            map: "noise:perlin".to_string(),
+            amount,
+            scale: 64.0,
+            evolve: 0.5,
+            angle: 0.0,
+            modulate: false,
+        })
+    }
+
+    /// Waves of `amplitude` pixels spreading from the center once a second.
+    pub fn ripple(self, amplitude: f32, wavelength: f32) -> Self {
+        self.then(Effect::Ripple {
+            amplitude,
+            wavelength,
+            speed: 1.0,
+            origin: [0.5, 0.5],
+            modulate: false,
+        })
+    }
+
+    /// A blur towards the center over `amount` of each pixel's distance.
+    pub fn zoomblur(self, amount: f32) -> Self {
+        self.then(Effect::Zoomblur {
+            center: [0.5, 0.5],
+            amount,
+            modulate: false,
+        })
+    }
+
+    pub fn motionblur(self, angle: f32, length: f32) -> Self {
+        self.then(Effect::Motionblur {
+            angle,
+            length,
+            modulate: false,
+        })
+    }
+
+    /// The same tone curve over every channel.
+    pub fn curves(self, master: Curve) -> Self {
+        self.then(Effect::Curves {
+            master,
+            r: Curve::default(),
+            g: Curve::default(),
+            b: Curve::default(),
+        })
+    }
+
+    /// White balance for footage lit at `temperature` kelvin, shifted
+    /// towards magenta by `tint`.
+    pub fn white_balance(self, temperature: f32, tint: f32) -> Self {
+        self.then(Effect::Mixer {
+            matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            temperature: Some(temperature),
+            tint,
+        })
+    }
+
+    /// Hues within `tolerance` of `from` moved to `to`.
+    pub fn replace_color(self, from: Color, to: Color, tolerance: f32) -> Self {
+        self.then(Effect::ReplaceColor {
+            from,
+            to,
+            tolerance,
+            feather: 0.05,
+            modulate: false,
+        })
+    }
+
+    /// Shadows in `shadow` running to highlights in `highlight`.
+    pub fn duotone(self, shadow: Color, highlight: Color) -> Self {
+        self.gradient_map(vec![
+            Stop {
+                at: 0.0,
+                color: shadow,
+            },
+            Stop {
+                at: 1.0,
+                color: highlight,
+            },
+        ])
+    }
+
+    pub fn gradient_map(self, stops: Vec<Stop>) -> Self {
+        self.then(Effect::GradientMap {
+            stops,
+            modulate: false,
+        })
+    }
+
+    pub fn falsecolor(self, palette: Palette) -> Self {
+        self.then(Effect::Falsecolor { palette })
+    }
+
+    pub fn match_color(self, reference: &str, method: MatchMethod) -> Self {
+        self.then(Effect::MatchColor {
+            reference: reference.to_string(),
+            method,
+            amount: 1.0,
+            modulate: false,
+        })
+    }
+
+    pub fn apply(&self, img: DynamicImage, frame: &FrameContext) -> RgbaImage {
+        self.effects.iter().fold(img.into_rgba8(), |img, effect| {
+            if self.linear {
+                let scale_factor = self.scaling.curve.apply(frame.scale_factor);
+                if let Some(out) = linear::apply(effect, &img, scale_factor, &self.scaling) {
+                    return out;
+                }
+            }
+            effect.apply_scaled(DynamicImage::ImageRgba8(img), frame, &self.scaling)
+        })
+    }
+}
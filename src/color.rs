@@ -0,0 +1,236 @@
+//! Color matrix and range handling around the RGB the effects work in.
+//!
+//! Effects see full range BT.709 RGB. The decoder converts with swscale's
+//! defaults, BT.601 with the range implied by the pixel format, so anything
+//! else comes out subtly shifted until a [`Correction`] is applied. Outputs
+//! are converted with the BT.709 matrix and tagged to match.
+
+use std::path::Path;
+
+use ffmpeg_next::{codec, color, format::Pixel, media};
+use image::RgbImage;
+
+/// Quantization range of an encoded output.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorRange {
+    /// 16-235 luma, what broadcast and most players expect
+    #[default]
+    Limited,
+    /// 0-255
+    Full,
+}
+
+impl ColorRange {
+    pub fn to_ffmpeg(self) -> color::Range {
+        match self {
+            ColorRange::Limited => color::Range::MPEG,
+            ColorRange::Full => color::Range::JPEG,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Matrix {
+    Bt601,
+    Bt709,
+    Bt2020,
+}
+
+impl Matrix {
+    /// Untagged video is read the way players do: BT.709 from 720 lines up.
+    fn from_ffmpeg(space: color::Space, height: u32) -> Matrix {
+        match space {
+            color::Space::BT709 => Matrix::Bt709,
+            color::Space::BT2020NCL | color::Space::BT2020CL => Matrix::Bt2020,
+            color::Space::BT470BG | color::Space::SMPTE170M | color::Space::FCC => Matrix::Bt601,
+            _ if height >= 720 => Matrix::Bt709,
+            _ => Matrix::Bt601,
+        }
+    }
+
+    /// The red and blue luma weights.
+    fn weights(self) -> (f32, f32) {
+        match self {
+            Matrix::Bt601 => (0.299, 0.114),
+            Matrix::Bt709 => (0.2126, 0.0722),
+            Matrix::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+}
+
+/// `x -> matrix * x + offset` on values in 0..1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Affine {
+    matrix: [[f32; 3]; 3],
+    offset: [f32; 3],
+}
+
+impl Affine {
+    /// YCbCr code values to RGB for `matrix` at `range`.
+    fn decode(matrix: Matrix, range: ColorRange) -> Affine {
+        let (kr, kb) = matrix.weights();
+        let kg = 1.0 - kr - kb;
+        let (y, c, black) = match range {
+            ColorRange::Limited => (255.0 / 219.0, 255.0 / 224.0, 16.0 / 255.0),
+            ColorRange::Full => (1.0, 1.0, 0.0),
+        };
+        let matrix = [
+            [y, 0.0, 2.0 * (1.0 - kr) * c],
+            [
+                y,
+                -2.0 * kb * (1.0 - kb) / kg * c,
+                -2.0 * kr * (1.0 - kr) / kg * c,
+            ],
+            [y, 2.0 * (1.0 - kb) * c, 0.0],
+        ];
+        let origin = [black, 0.5, 0.5];
+        Affine {
+            matrix,
+            offset: matrix.map(|row| -dot(row, origin)),
+        }
+    }
+
+    fn apply(&self, x: [f32; 3]) -> [f32; 3] {
+        let mut out = self.offset;
+        for (o, row) in out.iter_mut().zip(self.matrix) {
+            *o += dot(row, x);
+        }
+        out
+    }
+
+    fn inverse(&self) -> Affine {
+        let m = self.matrix;
+        let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+        let cofactor = |r: usize, c: usize| {
+            let (r0, r1) = ((r + 1) % 3, (r + 2) % 3);
+            let (c0, c1) = ((c + 1) % 3, (c + 2) % 3);
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+        let mut matrix = [[0.0; 3]; 3];
+        for (r, row) in matrix.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = cofactor(c, r) / det;
+            }
+        }
+        Affine {
+            matrix,
+            offset: matrix.map(|row| -dot(row, self.offset)),
+        }
+    }
+
+    /// `self` after `first`.
+    fn after(&self, first: &Affine) -> Affine {
+        let mut matrix = [[0.0; 3]; 3];
+        for (r, row) in matrix.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| self.matrix[r][k] * first.matrix[k][c]).sum();
+            }
+        }
+        Affine {
+            matrix,
+            offset: self.apply(first.offset),
+        }
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+/// Linear light BT.2020 to BT.709 primaries.
+const BT2020_TO_BT709: [[f32; 3]; 3] = [
+    [1.6605, -0.5876, -0.0728],
+    [-0.1246, 1.1329, -0.0083],
+    [-0.0182, -0.1006, 1.1187],
+];
+
+/// Display gamma used to go to and from linear light for gamut conversion.
+const GAMMA: f32 = 2.4;
+
+/// Fixes decoded frames whose matrix, range or primaries differ from what
+/// the decoder assumed.
+pub struct Correction {
+    /// Undoes the decoder's matrix and range and applies the source's
+    matrix: Affine,
+    /// Whether to bring BT.2020 primaries into BT.709
+    gamut: bool,
+}
+
+impl Correction {
+    /// The correction `path`'s first video stream needs, `None` if it
+    /// decodes right as is or can't be probed.
+    pub fn probe(path: &Path) -> Option<Correction> {
+        let input = ffmpeg_next::format::input(&path).ok()?;
+        let stream = input.streams().best(media::Type::Video)?;
+        let decoder = codec::context::Context::from_parameters(stream.parameters())
+            .ok()?
+            .decoder()
+            .video()
+            .ok()?;
+
+        let format = decoder.format();
+        if decoder.color_space() == color::Space::RGB
+            || matches!(
+                format,
+                Pixel::RGB24
+                    | Pixel::BGR24
+                    | Pixel::RGBA
+                    | Pixel::BGRA
+                    | Pixel::GBRP
+                    | Pixel::GRAY8
+            )
+        {
+            return None;
+        }
+
+        let assumed_range = match format {
+            Pixel::YUVJ420P | Pixel::YUVJ422P | Pixel::YUVJ444P | Pixel::YUVJ440P => {
+                ColorRange::Full
+            }
+            _ => ColorRange::Limited,
+        };
+        let range = match decoder.color_range() {
+            color::Range::JPEG => ColorRange::Full,
+            color::Range::MPEG => ColorRange::Limited,
+            _ => assumed_range,
+        };
+        let matrix = Matrix::from_ffmpeg(decoder.color_space(), decoder.height());
+
+        let gamut = decoder.color_primaries() == color::Primaries::BT2020
+            && match decoder.color_transfer_characteristic() {
+                color::TransferCharacteristic::SMPTE2084
+                | color::TransferCharacteristic::ARIB_STD_B67 => {
+                    eprintln!(
+                        "{} is HDR, which isn't tone mapped; colors will look flat",
+                        path.display()
+                    );
+                    false
+                }
+                _ => true,
+            };
+
+        let assumed = Affine::decode(Matrix::Bt601, assumed_range);
+        let actual = Affine::decode(matrix, range);
+        if matrix == Matrix::Bt601 && range == assumed_range && !gamut {
+            return None;
+        }
+        Some(Correction {
+            matrix: actual.after(&assumed.inverse()),
+            gamut,
+        })
+    }
+
+    pub fn apply(&self, img: &mut RgbImage) {
+        for pixel in img.pixels_mut() {
+            let rgb = pixel.0.map(|v| v as f32 / 255.0);
+            let mut rgb = self.matrix.apply(rgb).map(|v| v.clamp(0.0, 1.0));
+            if self.gamut {
+                let linear = rgb.map(|v| v.powf(GAMMA));
+                rgb = BT2020_TO_BT709.map(|row| dot(row, linear).clamp(0.0, 1.0).powf(1.0 / GAMMA));
+            }
+            pixel.0 = rgb.map(|v| (v * 255.0).round() as u8);
+        }
+    }
+}
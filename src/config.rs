@@ -0,0 +1,84 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use clap::{ArgAction, Command};
+use serde_json::Value;
+
+use crate::project::settings_args;
+
+/// Per-folder defaults, read from the working directory.
+const CONFIG_FILE: &str = ".vidfx.toml";
+
+/// Flags from a `.vidfx.toml` in the working directory, keyed by long flag
+/// name like project settings:
+///
+/// ```toml
+/// bpm = 120
+/// codec = "prores"
+/// preset-dir = "looks"
+/// ```
+///
+/// They come before the command line's own flags, which override them, even
+/// repeatable ones: an `output` here is replaced by `--output`, not added to.
+/// They take precedence over `VIDFX_*` environment variables.
+pub fn default_args() -> Vec<String> {
+    let path = Path::new(CONFIG_FILE);
+    if !path.exists() {
+        return vec![];
+    }
+
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", CONFIG_FILE, e));
+    let settings: serde_json::Map<String, Value> =
+        toml::from_str(&text).unwrap_or_else(|e| panic!("Failed to parse {}: {}", CONFIG_FILE, e));
+    settings_args(&settings, Path::new(""))
+}
+
+/// `layers` of flags joined in order, each overriding the ones before it.
+/// clap keeps the last value of a flag that takes one, but repeatable flags
+/// like `--output` or `--layer` would pile up, so a layer giving one drops
+/// every earlier layer's occurrences of it.
+pub fn layered(mut command: Command, layers: Vec<Vec<String>>) -> Vec<String> {
+    command.build();
+    let repeatable: HashMap<&str, usize> = command
+        .get_arguments()
+        .filter(|arg| matches!(arg.get_action(), ArgAction::Append))
+        .filter_map(|arg| {
+            let values = arg.get_num_args().map_or(1, |count| count.min_values());
+            Some((arg.get_long()?, values))
+        })
+        .collect();
+
+    let mut given: HashSet<&str> = HashSet::new();
+    let mut kept = vec![];
+    for layer in layers.iter().rev() {
+        let mut flags = vec![];
+        let mut tokens = vec![];
+        let mut i = 0;
+        while i < layer.len() {
+            let token = &layer[i];
+            let name = token
+                .strip_prefix("--")
+                .map(|name| name.split_once('=').map_or(name, |(name, _)| name));
+            let Some((name, values)) = name.and_then(|name| repeatable.get_key_value(name)) else {
+                tokens.push(token.clone());
+                i += 1;
+                continue;
+            };
+            let end = if token.contains('=') {
+                i + 1
+            } else {
+                i + 1 + values
+            }
+            .min(layer.len());
+            if !given.contains(name) {
+                tokens.extend_from_slice(&layer[i..end]);
+            }
+            flags.push(*name);
+            i = end;
+        }
+        given.extend(flags);
+        kept.push(tokens);
+    }
+    kept.into_iter().rev().flatten().collect()
+}
@@ -0,0 +1,163 @@
+//! Tone curves for the `curves` effect: a master curve and one per channel,
+//! each a Catmull-Rom spline through control points from 0,0 to 1,1.
+//!
+//! Curves are written as space separated `x,y` points, e.g.
+//! `0,0 0.5,0.7 1,1`. Curve files hold one curve per line, named `master`,
+//! `r`, `g` or `b`:
+//!
+//! ```text
+//! # Lift the shadows, warm the mids
+//! master 0,0.05 0.5,0.55 1,1
+//! r 0,0 0.5,0.56 1,1
+//! ```
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+
+/// Control points sorted by input, leaving tones alone when empty.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Curve(Vec<[f32; 2]>);
+
+impl Curve {
+    pub fn is_identity(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The curve at `x`, held flat past its first and last points.
+    fn value(&self, x: f32) -> f32 {
+        let points = &self.0;
+        match points.len() {
+            0 => return x,
+            1 => return points[0][1],
+            _ => {}
+        }
+        if x <= points[0][0] {
+            return points[0][1];
+        }
+        let last = points.len() - 1;
+        if x >= points[last][0] {
+            return points[last][1];
+        }
+
+        let i = points.partition_point(|p| p[0] <= x) - 1;
+        let ([x0, y0], [x1, y1]) = (points[i], points[i + 1]);
+        // Catmull-Rom tangents, one sided at the ends
+        let slope = |i: usize| {
+            let (a, b) = (points[i.saturating_sub(1)], points[(i + 1).min(last)]);
+            (b[1] - a[1]) / (b[0] - a[0]).max(f32::EPSILON)
+        };
+        let (m0, m1) = (slope(i), slope(i + 1));
+        let h = (x1 - x0).max(f32::EPSILON);
+        let t = (x - x0) / h;
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+            + (t3 - 2.0 * t2 + t) * h * m0
+            + (-2.0 * t3 + 3.0 * t2) * y1
+            + (t3 - t2) * h * m1
+    }
+
+    /// Every 8 bit level through the curve.
+    fn table(&self) -> [u8; 256] {
+        std::array::from_fn(|level| {
+            (self.value(level as f32 / 255.0) * 255.0)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        })
+    }
+}
+
+impl FromStr for Curve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid curve '{}', expected e.g. \"0,0 0.5,0.7 1,1\"", s);
+        let mut points = s
+            .split_whitespace()
+            .map(|point| {
+                let (x, y) = point.split_once(',').ok_or_else(invalid)?;
+                let x = x.trim().parse::<f32>().map_err(|_| invalid())?;
+                let y = y.trim().parse::<f32>().map_err(|_| invalid())?;
+                if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+                    return Err(format!("curve point '{}' is outside 0..1", point));
+                }
+                Ok([x, y])
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        points.sort_by(|a, b| a[0].total_cmp(&b[0]));
+        if points.windows(2).any(|pair| pair[0][0] == pair[1][0]) {
+            return Err(format!("curve '{}' has two points at the same input", s));
+        }
+        Ok(Curve(points))
+    }
+}
+
+impl TryFrom<String> for Curve {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Curve> for String {
+    fn from(curve: Curve) -> String {
+        curve.to_string()
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let points: Vec<String> = self.0.iter().map(|[x, y]| format!("{},{}", x, y)).collect();
+        write!(f, "{}", points.join(" "))
+    }
+}
+
+/// The master, red, green and blue curves in a curve file, identity for any
+/// it leaves out.
+pub fn load(path: &Path) -> Result<[Curve; 4], String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read curves {}: {}", path.display(), e))?;
+    let mut curves: [Curve; 4] = Default::default();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, points) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let channel = match name.trim_end_matches(':') {
+            "master" => 0,
+            "r" => 1,
+            "g" => 2,
+            "b" => 3,
+            other => {
+                return Err(format!(
+                    "{}:{}: unknown curve '{}', expected master, r, g or b",
+                    path.display(),
+                    number + 1,
+                    other
+                ))
+            }
+        };
+        curves[channel] = points
+            .parse()
+            .map_err(|e| format!("{}:{}: {}", path.display(), number + 1, e))?;
+    }
+    Ok(curves)
+}
+
+/// `img` through the master curve, then each channel's own.
+pub fn apply(mut img: RgbaImage, master: &Curve, channels: [&Curve; 3]) -> RgbaImage {
+    let master = master.table();
+    let tables = channels.map(|curve| curve.table());
+    for pixel in img.pixels_mut() {
+        for (c, table) in tables.iter().enumerate() {
+            pixel.0[c] = table[master[pixel.0[c] as usize] as usize];
+        }
+    }
+    img
+}
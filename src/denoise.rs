@@ -0,0 +1,123 @@
+//! hqdn3d-style noise reduction before any effect sees the frame, so sensor
+//! noise doesn't make thresholds sparkle from frame to frame.
+//!
+//! Each pixel is pulled towards its neighbours, and towards the same pixel
+//! of the last frame, by how alike they are: flat noisy areas are smoothed
+//! while edges and movement, which differ by more than the noise, stay.
+
+use image::RgbImage;
+
+/// How much of the way a pixel moves to a neighbour `difference` levels away
+/// at `strength`. 0 turns the filter off.
+fn coefficients(strength: f32) -> [f32; 256] {
+    if strength <= 0.0 {
+        return [0.0; 256];
+    }
+    let pull = strength / (strength + 1.0);
+    let spread = 4.0 * strength;
+    std::array::from_fn(|difference| {
+        let d = difference as f32 / spread;
+        pull * (-0.5 * d * d).exp()
+    })
+}
+
+fn towards(value: f32, other: f32, coefficients: &[f32; 256]) -> f32 {
+    let difference = ((other - value).abs() as usize).min(255);
+    value + (other - value) * coefficients[difference]
+}
+
+/// Runs the filter along each row and column both ways.
+fn spatial(pixels: &mut [f32], width: usize, height: usize, coefficients: &[f32; 256]) {
+    let index = |x: usize, y: usize, c: usize| (y * width + x) * 3 + c;
+    for c in 0..3 {
+        for y in 0..height {
+            for x in 1..width {
+                pixels[index(x, y, c)] = towards(
+                    pixels[index(x, y, c)],
+                    pixels[index(x - 1, y, c)],
+                    coefficients,
+                );
+            }
+            for x in (0..width.saturating_sub(1)).rev() {
+                pixels[index(x, y, c)] = towards(
+                    pixels[index(x, y, c)],
+                    pixels[index(x + 1, y, c)],
+                    coefficients,
+                );
+            }
+        }
+        for x in 0..width {
+            for y in 1..height {
+                pixels[index(x, y, c)] = towards(
+                    pixels[index(x, y, c)],
+                    pixels[index(x, y - 1, c)],
+                    coefficients,
+                );
+            }
+            for y in (0..height.saturating_sub(1)).rev() {
+                pixels[index(x, y, c)] = towards(
+                    pixels[index(x, y, c)],
+                    pixels[index(x, y + 1, c)],
+                    coefficients,
+                );
+            }
+        }
+    }
+}
+
+/// `frames` with noise reduced at `spatial` and `temporal` strength, roughly
+/// the noise's size in levels. Either at 0 turns that half off.
+pub fn denoised<'a>(
+    frames: Box<dyn Iterator<Item = RgbImage> + 'a>,
+    spatial: f32,
+    temporal: f32,
+) -> Box<dyn Iterator<Item = RgbImage> + 'a> {
+    if spatial <= 0.0 && temporal <= 0.0 {
+        return frames;
+    }
+    Box::new(Denoise {
+        frames,
+        spatial: coefficients(spatial),
+        temporal: coefficients(temporal),
+        previous: None,
+    })
+}
+
+struct Denoise<'a> {
+    frames: Box<dyn Iterator<Item = RgbImage> + 'a>,
+    spatial: [f32; 256],
+    temporal: [f32; 256],
+    /// The last frame as filtered, unrounded so the filter doesn't drift
+    previous: Option<Vec<f32>>,
+}
+
+impl Iterator for Denoise<'_> {
+    type Item = RgbImage;
+
+    fn next(&mut self) -> Option<RgbImage> {
+        let frame = self.frames.next()?;
+        let (width, height) = frame.dimensions();
+        let mut pixels: Vec<f32> = frame.as_raw().iter().map(|&v| v as f32).collect();
+
+        spatial(&mut pixels, width as usize, height as usize, &self.spatial);
+        if let Some(previous) = &self.previous {
+            if previous.len() == pixels.len() {
+                for (value, &before) in pixels.iter_mut().zip(previous) {
+                    *value = towards(*value, before, &self.temporal);
+                }
+            }
+        }
+
+        let out = RgbImage::from_raw(
+            width,
+            height,
+            pixels
+                .iter()
+                .map(|&v| v.round().clamp(0.0, 255.0) as u8)
+                .collect(),
+        )
+        .expect("Denoising keeps the frame size");
+        self.previous = Some(pixels);
+        Some(out)
+    }
+}
@@ -0,0 +1,215 @@
+//! Displacement maps for the `displace` effect: pixels are pushed around by
+//! procedural noise, a still image or the frames of a second video.
+//!
+//! Noise pushes in two directions from two fields of Perlin noise. Image and
+//! video maps push along one angle by their luma, mid grey leaving pixels in
+//! place.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, Rgba, RgbaImage};
+
+use crate::source::Source;
+use crate::FrameContext;
+
+/// Where the second noise field sits, far enough not to repeat the first.
+const SECOND_FIELD: f64 = 71.3;
+
+/// What a displacement map spec names.
+pub enum MapSpec<'a> {
+    Noise,
+    Image(&'a str),
+    Video(&'a str),
+}
+
+impl MapSpec<'_> {
+    /// `noise:perlin`, `image:<path>`, `video:<path>`, or a path that is an
+    /// image or a video by its extension.
+    pub fn parse(spec: &str) -> Result<MapSpec<'_>, String> {
+        if spec.starts_with("noise:") {
+            if spec != "noise:perlin" {
+                return Err(format!(
+                    "unknown displacement noise {}, expected noise:perlin",
+                    spec
+                ));
+            }
+            return Ok(MapSpec::Noise);
+        }
+        Ok(match spec.split_once(':') {
+            Some(("image", path)) => MapSpec::Image(path),
+            Some(("video", path)) => MapSpec::Video(path),
+            _ if image::ImageFormat::from_path(spec).is_ok() => MapSpec::Image(spec),
+            _ => MapSpec::Video(spec),
+        })
+    }
+}
+
+enum Map {
+    Noise,
+    Image(GrayImage),
+    Video(Source),
+}
+
+impl Map {
+    fn open(spec: &str) -> Map {
+        match MapSpec::parse(spec).unwrap_or_else(|e| panic!("{}", e)) {
+            MapSpec::Noise => Map::Noise,
+            MapSpec::Image(path) => Map::Image(
+                image::open(path)
+                    .unwrap_or_else(|e| panic!("Failed to open displacement map {}: {}", path, e))
+                    .into_luma8(),
+            ),
+            MapSpec::Video(path) => {
+                Map::Video(Source::open(Path::new(path)).unwrap_or_else(|e| {
+                    panic!("Failed to open displacement video {}: {}", path, e)
+                }))
+            }
+        }
+    }
+
+    /// The map's luma at `frame`, the size of the frame, `None` for noise.
+    fn luma(&mut self, frame: &FrameContext, width: u32, height: u32) -> Option<GrayImage> {
+        let luma = match self {
+            Map::Noise => return None,
+            Map::Image(luma) => luma.clone(),
+            // Past its end a video map leaves pixels in place
+            Map::Video(source) => match source.frame_at_time(frame.time) {
+                Some(img) => DynamicImage::ImageRgb8(img).into_luma8(),
+                None => GrayImage::from_pixel(width, height, image::Luma([128])),
+            },
+        };
+        Some(if luma.dimensions() == (width, height) {
+            luma
+        } else {
+            imageops::resize(&luma, width, height, FilterType::Triangle)
+        })
+    }
+}
+
+thread_local! {
+    /// Every map opened on this thread by its spec, so chains with several
+    /// maps don't reopen them on every frame.
+    static LOADED: RefCell<HashMap<String, Map>> = RefCell::new(HashMap::new());
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+/// A pseudo random gradient for the lattice point `(x, y, z)`, dotted with
+/// `(dx, dy, dz)`.
+fn gradient(x: i64, y: i64, z: i64, dx: f64, dy: f64, dz: f64) -> f64 {
+    let mut h = (x as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        ^ (y as u64).wrapping_mul(0xbf58_476d_1ce4_e5b9)
+        ^ (z as u64).wrapping_mul(0x94d0_49bb_1331_11eb);
+    h = (h ^ (h >> 31)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    // The twelve edge directions of a cube, as in improved Perlin noise, each
+    // picked as often by scaling the top 32 bits onto 0..12
+    match ((h >> 32) * 12) >> 32 {
+        0 => dx + dy,
+        1 => -dx + dy,
+        2 => dx - dy,
+        3 => -dx - dy,
+        4 => dx + dz,
+        5 => -dx + dz,
+        6 => dx - dz,
+        7 => -dx - dz,
+        8 => dy + dz,
+        9 => -dy + dz,
+        10 => dy - dz,
+        _ => -dy - dz,
+    }
+}
+
+/// 3D Perlin noise, roughly -1 to 1.
+fn perlin(x: f64, y: f64, z: f64) -> f64 {
+    let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+    let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+    let (x0, y0, z0) = (x0 as i64, y0 as i64, z0 as i64);
+    let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+    let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+
+    let corner = |i: i64, j: i64, k: i64| {
+        gradient(
+            x0 + i,
+            y0 + j,
+            z0 + k,
+            fx - i as f64,
+            fy - j as f64,
+            fz - k as f64,
+        )
+    };
+    let near = lerp(
+        lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+        lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+        v,
+    );
+    let far = lerp(
+        lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+        lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+        v,
+    );
+    lerp(near, far, w)
+}
+
+/// `img` sampled between pixels at `(x, y)`, edges stretched outwards.
+pub(crate) fn sample(img: &RgbaImage, x: f64, y: f64) -> Rgba<u8> {
+    let (width, height) = img.dimensions();
+    let x = x.clamp(0.0, width as f64 - 1.0);
+    let y = y.clamp(0.0, height as f64 - 1.0);
+    let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (tx, ty) = (x - x0 as f64, y - y0 as f64);
+    let corners = [(x0, y0), (x1, y0), (x0, y1), (x1, y1)];
+    let [a, b, c, d] = corners.map(|(x, y)| img.get_pixel(x, y).0);
+    Rgba(std::array::from_fn(|i| {
+        let top = a[i] as f64 * (1.0 - tx) + b[i] as f64 * tx;
+        let bottom = c[i] as f64 * (1.0 - tx) + d[i] as f64 * tx;
+        (top * (1.0 - ty) + bottom * ty).round() as u8
+    }))
+}
+
+/// `img` with every pixel taken from up to `amount` pixels away, as the map
+/// in `spec` says. Noise features are `scale` pixels across and change
+/// `evolve` times a second, image and video maps push along `angle` degrees.
+pub fn apply(
+    img: RgbaImage,
+    spec: &str,
+    amount: f32,
+    scale: f32,
+    evolve: f32,
+    angle: f32,
+    frame: &FrameContext,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let luma = LOADED.with_borrow_mut(|loaded| {
+        loaded
+            .entry(spec.to_string())
+            .or_insert_with(|| Map::open(spec))
+            .luma(frame, width, height)
+    });
+
+    let amount = amount as f64;
+    let scale = (scale as f64).max(1.0);
+    let z = frame.time * evolve as f64;
+    let (sin, cos) = (angle as f64).to_radians().sin_cos();
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (dx, dy) = match &luma {
+            None => {
+                let (u, v) = (x as f64 / scale, y as f64 / scale);
+                (
+                    perlin(u, v, z) * amount,
+                    perlin(u + SECOND_FIELD, v + SECOND_FIELD, z) * amount,
+                )
+            }
+            Some(luma) => {
+                let push = (luma.get_pixel(x, y).0[0] as f64 / 255.0 * 2.0 - 1.0) * amount;
+                (push * cos, push * sin)
+            }
+        };
+        sample(&img, x as f64 + dx, y as f64 + dy)
+    })
+}
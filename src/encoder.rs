@@ -0,0 +1,575 @@
+use std::path::Path;
+
+use ffmpeg_next::{
+    codec, color, encoder,
+    format::{self, context::Output, Pixel},
+    frame,
+    software::scaling,
+    Dictionary, Packet, Rational,
+};
+use image::RgbImage;
+use ndarray::{Array, Array3};
+use video_rs::time::Time;
+
+use crate::color::ColorRange;
+
+/// Video codec for an output. Defaults to whatever fits the container implied
+/// by the output extension. `ffv1` is lossless, `prores` and `dnxhr` are
+/// intermediate codecs meant for further editing.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+pub enum Codec {
+    H264,
+    Vp9,
+    Av1,
+    Ffv1,
+    Prores,
+    Dnxhr,
+}
+
+impl Codec {
+    pub fn default_for(path: &Path) -> Codec {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("webm") => Codec::Vp9,
+            Some("mxf") => Codec::Dnxhr,
+            _ => Codec::H264,
+        }
+    }
+
+    /// Encoder implementations to try, most preferred first.
+    fn encoder_names(&self) -> &'static [&'static str] {
+        match self {
+            Codec::H264 => &["libx264", "h264"],
+            Codec::Vp9 => &["libvpx-vp9", "vp9"],
+            Codec::Av1 => &["libsvtav1", "libaom-av1", "av1"],
+            Codec::Ffv1 => &["ffv1"],
+            Codec::Prores => &["prores_ks", "prores"],
+            Codec::Dnxhr => &["dnxhd"],
+        }
+    }
+
+    fn pixel_format(&self) -> Pixel {
+        match self {
+            Codec::H264 | Codec::Vp9 | Codec::Av1 => Pixel::YUV420P,
+            Codec::Ffv1 => Pixel::YUV444P,
+            Codec::Prores => Pixel::YUV422P10LE,
+            Codec::Dnxhr => Pixel::YUV422P,
+        }
+    }
+
+    fn options(&self, encoder_name: &str, realtime: bool) -> Dictionary<'static> {
+        let mut options = Dictionary::new();
+        match (self, encoder_name) {
+            (Codec::H264, _) => {
+                options.set("preset", if realtime { "ultrafast" } else { "medium" });
+                if realtime {
+                    options.set("tune", "zerolatency");
+                }
+            }
+            (Codec::Vp9, _) => {
+                options.set("crf", "32");
+                options.set("row-mt", "1");
+                options.set("deadline", if realtime { "realtime" } else { "good" });
+            }
+            (Codec::Av1, "libsvtav1") => {
+                options.set("crf", "35");
+                options.set("preset", if realtime { "12" } else { "8" });
+            }
+            (Codec::Av1, _) => {
+                options.set("crf", "35");
+                options.set("cpu-used", if realtime { "8" } else { "6" });
+            }
+            (Codec::Ffv1, _) => {
+                options.set("level", "3");
+                options.set("slicecrc", "1");
+                options.set("g", "1");
+            }
+            (Codec::Prores, _) => {
+                // HQ profile
+                options.set("profile", "3");
+                options.set("vendor", "apl0");
+            }
+            (Codec::Dnxhr, _) => {
+                options.set("profile", "dnxhr_hq");
+            }
+        }
+        options
+    }
+
+    /// Rough average bit rate at 1080p30 with the default options, in bits
+    /// per second. The quality based codecs vary a lot with the content.
+    fn typical_bit_rate(&self) -> f64 {
+        match self {
+            Codec::H264 => 8e6,
+            Codec::Vp9 => 4e6,
+            Codec::Av1 => 3e6,
+            Codec::Ffv1 => 250e6,
+            Codec::Prores => 220e6,
+            Codec::Dnxhr => 180e6,
+        }
+    }
+
+    /// Whether the container can hold this codec.
+    pub fn fits(&self, format: &str) -> bool {
+        match self {
+            Codec::H264 => !matches!(format, "webm" | "mxf"),
+            Codec::Vp9 | Codec::Av1 => !matches!(format, "mxf" | "hls"),
+            Codec::Ffv1 => format == "matroska",
+            Codec::Prores => matches!(format, "mov" | "matroska"),
+            Codec::Dnxhr => matches!(format, "mov" | "matroska" | "mxf"),
+        }
+    }
+}
+
+/// Where a render is headed, `--target`: each sets up h264 the way the
+/// platform likes it so it isn't washed out or mangled on re-encode.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Delivery {
+    /// Instagram, TikTok and the like: high profile 4:2:0 in limited range
+    /// with a capped bit rate and the index up front
+    Social,
+    /// Constant bit rate with one second closed GOPs
+    Broadcast,
+    /// YouTube's upload recommendations: closed GOPs of half a second, two
+    /// B-frames and the index up front
+    Web,
+}
+
+impl Delivery {
+    /// Average bit rate at 1080p up to 30fps, in bits per second.
+    fn base_bit_rate(self) -> f64 {
+        match self {
+            Delivery::Social => 12e6,
+            Delivery::Broadcast => 25e6,
+            Delivery::Web => 16e6,
+        }
+    }
+
+    /// The base bit rate scaled to the frame size and rate.
+    fn bit_rate(self, settings: &EncodeSettings) -> usize {
+        let pixels = (settings.width * settings.height) as f64 / (1920.0 * 1080.0);
+        let motion = if settings.frame_rate > 30.5 { 1.5 } else { 1.0 };
+        (self.base_bit_rate() * pixels.max(0.25) * motion) as usize
+    }
+
+    fn options(self, settings: &EncodeSettings, bit_rate: usize, options: &mut Dictionary) {
+        let gop = |seconds: f64| ((settings.frame_rate * seconds).round() as usize).max(1);
+        options.set("profile", "high");
+        match self {
+            Delivery::Social => {
+                options.set("g", &gop(2.0).to_string());
+                options.set("maxrate", &(bit_rate * 3 / 2).to_string());
+                options.set("bufsize", &(bit_rate * 2).to_string());
+            }
+            Delivery::Broadcast => {
+                options.set("g", &gop(1.0).to_string());
+                options.set("maxrate", &bit_rate.to_string());
+                options.set("minrate", &bit_rate.to_string());
+                options.set("bufsize", &bit_rate.to_string());
+                options.set("x264-params", "nal-hrd=cbr:force-cfr=1");
+            }
+            Delivery::Web => {
+                options.set("g", &gop(0.5).to_string());
+                options.set("bf", "2");
+                options.set("maxrate", &(bit_rate * 2).to_string());
+                options.set("bufsize", &(bit_rate * 2).to_string());
+            }
+        }
+    }
+
+    /// Whether mp4 and mov outputs move their index to the front, so
+    /// playback can start before the whole file is loaded.
+    fn faststart(self) -> bool {
+        matches!(self, Delivery::Social | Delivery::Web)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct EncodeSettings {
+    pub width: u32,
+    pub height: u32,
+    pub frame_rate: f64,
+    pub codec: Option<Codec>,
+    /// Average bit rate in bits per second, instead of the codec's quality preset
+    pub bit_rate: Option<usize>,
+    /// Encoder threads, left to ffmpeg when `None`
+    pub threads: Option<usize>,
+    pub color_range: ColorRange,
+    /// Platform specific h264 settings, see [`Delivery`]
+    pub delivery: Option<Delivery>,
+    /// Put the mp4/mov index at the front of the file
+    pub faststart: bool,
+    /// Write mp4/mov as self-contained fragments, so a file cut short still
+    /// plays up to where it stops
+    pub fragmented: bool,
+    /// Seconds per segment of HLS and DASH outputs
+    pub segment_duration: f64,
+}
+
+impl EncodeSettings {
+    /// About how many bits per second `codec` will write with these settings,
+    /// for estimating output sizes.
+    pub fn estimated_bit_rate(&self, codec: Codec) -> usize {
+        self.bit_rate
+            .or(self.delivery.map(|delivery| delivery.bit_rate(self)))
+            .unwrap_or_else(|| self.typical_bit_rate(codec))
+    }
+
+    /// About how many bits per second `codec` writes at this size and frame
+    /// rate when left to itself, as the lossless files beside outputs are.
+    pub fn typical_bit_rate(&self, codec: Codec) -> usize {
+        let pixels = (self.width * self.height) as f64 / (1920.0 * 1080.0);
+        let frames = self.frame_rate / 30.0;
+        (codec.typical_bit_rate() * pixels * frames) as usize
+    }
+}
+
+/// Which half of a two-pass encode to run, with the stats file they share.
+/// Only libx264 is driven this way.
+pub enum Pass<'a> {
+    First(&'a Path),
+    Second(&'a Path),
+}
+
+/// A file stored inside the output, such as the chain a render used. Only
+/// matroska keeps attachments, other containers go without.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Attachment {
+    pub filename: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+/// What a render records about how it was made, in its container.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Provenance {
+    /// Written to the `comment` tag
+    pub comment: Option<String>,
+    pub attachments: Vec<Attachment>,
+}
+
+/// A named stretch of the output, in seconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chapter {
+    pub start: f64,
+    pub end: f64,
+    pub title: String,
+}
+
+/// Encodes RGB frames into a container through ffmpeg.
+pub struct VideoEncoder {
+    output: Output,
+    encoder: encoder::Video,
+    scaler: scaling::Context,
+    rgb: frame::Video,
+    converted: frame::Video,
+    stream_index: usize,
+    time_base: Rational,
+}
+
+impl VideoEncoder {
+    /// Opens `destination` (a path or url) with the muxer `format`.
+    pub fn new(
+        destination: &str,
+        format: &str,
+        codec: Codec,
+        settings: &EncodeSettings,
+        realtime: bool,
+        pass: Option<Pass>,
+        provenance: &Provenance,
+    ) -> Result<Self, ffmpeg_next::Error> {
+        let mut output = format::output_as(&destination, format)?;
+
+        let (encoder_name, av_codec) = codec
+            .encoder_names()
+            .iter()
+            .find_map(|name| encoder::find_by_name(name).map(|c| (*name, c)))
+            .ok_or(ffmpeg_next::Error::EncoderNotFound)?;
+
+        let global_header = output
+            .format()
+            .flags()
+            .contains(format::Flags::GLOBAL_HEADER);
+
+        let time_base = Rational::from(settings.frame_rate).invert();
+
+        let mut stream = output.add_stream(av_codec)?;
+        let stream_index = stream.index();
+
+        let mut video = codec::context::Context::new_with_codec(av_codec)
+            .encoder()
+            .video()?;
+        video.set_width(settings.width);
+        video.set_height(settings.height);
+        video.set_format(codec.pixel_format());
+        video.set_time_base(time_base);
+        video.set_frame_rate(Some(Rational::from(settings.frame_rate)));
+        let delivery = settings.delivery.filter(|_| codec == Codec::H264);
+        let bit_rate = settings
+            .bit_rate
+            .or(delivery.map(|delivery| delivery.bit_rate(settings)));
+        if let Some(bit_rate) = bit_rate {
+            video.set_bit_rate(bit_rate);
+        }
+        // Frames are BT.709 RGB, say so rather than leave players guessing
+        video.set_colorspace(color::Space::BT709);
+        video.set_color_range(settings.color_range.to_ffmpeg());
+        unsafe {
+            let context = video.as_mut_ptr();
+            (*context).color_primaries = color::Primaries::BT709.into();
+            (*context).color_trc = color::TransferCharacteristic::BT709.into();
+        }
+
+        let mut options = codec.options(encoder_name, realtime);
+        if let (Some(delivery), Some(bit_rate)) = (delivery, bit_rate) {
+            delivery.options(settings, bit_rate, &mut options);
+        }
+        if is_segmented(format) {
+            // A keyframe at the start of every segment and nowhere else
+            let gop = (settings.frame_rate * settings.segment_duration)
+                .round()
+                .max(1.0);
+            options.set("g", &gop.to_string());
+            options.set("keyint_min", &gop.to_string());
+            options.set("sc_threshold", "0");
+        }
+        if let Some(threads) = settings.threads {
+            options.set("threads", &threads.to_string());
+        }
+        let mut flags = codec::Flags::empty();
+        if global_header {
+            flags |= codec::Flags::GLOBAL_HEADER;
+        }
+        match pass {
+            Some(Pass::First(stats)) => {
+                flags |= codec::Flags::PASS1;
+                options.set("stats", &stats.to_string_lossy());
+            }
+            Some(Pass::Second(stats)) => {
+                flags |= codec::Flags::PASS2;
+                options.set("stats", &stats.to_string_lossy());
+            }
+            None => {}
+        }
+        video.set_flags(flags);
+
+        let encoder = video.open_with(options)?;
+        stream.set_parameters(&encoder);
+        stream.set_time_base(time_base);
+
+        if let Some(comment) = &provenance.comment {
+            let mut metadata = Dictionary::new();
+            metadata.set("comment", comment);
+            output.set_metadata(metadata);
+        }
+        if format == "matroska" {
+            for attachment in &provenance.attachments {
+                attach(&mut output, attachment)?;
+            }
+        }
+        let mut movflags = String::new();
+        if settings.fragmented {
+            movflags.push_str("+frag_keyframe+empty_moov+default_base_moof");
+        } else if settings.faststart || delivery.is_some_and(Delivery::faststart) {
+            movflags.push_str("+faststart");
+        }
+        if !movflags.is_empty() && matches!(format, "mp4" | "mov") {
+            let mut options = Dictionary::new();
+            options.set("movflags", &movflags);
+            output.write_header_with(options)?;
+        } else if is_segmented(format) {
+            output.write_header_with(segment_options(destination, format, settings))?;
+        } else {
+            output.write_header()?;
+        }
+
+        let mut scaler = scaling::Context::get(
+            Pixel::RGB24,
+            settings.width,
+            settings.height,
+            codec.pixel_format(),
+            settings.width,
+            settings.height,
+            scaling::Flags::BILINEAR,
+        )?;
+        // swscale converts with BT.601 to limited range unless told otherwise
+        unsafe {
+            let coefficients = ffmpeg_next::ffi::sws_getCoefficients(
+                ffmpeg_next::ffi::SWS_CS_ITU709 as std::os::raw::c_int,
+            );
+            ffmpeg_next::ffi::sws_setColorspaceDetails(
+                scaler.as_mut_ptr(),
+                coefficients,
+                1,
+                coefficients,
+                (settings.color_range == ColorRange::Full) as std::os::raw::c_int,
+                0,
+                1 << 16,
+                1 << 16,
+            );
+        }
+
+        let mut converted =
+            frame::Video::new(codec.pixel_format(), settings.width, settings.height);
+        converted.set_color_space(color::Space::BT709);
+        converted.set_color_range(settings.color_range.to_ffmpeg());
+        converted.set_color_primaries(color::Primaries::BT709);
+        converted.set_color_transfer_characteristic(color::TransferCharacteristic::BT709);
+
+        Ok(Self {
+            output,
+            encoder,
+            scaler,
+            rgb: frame::Video::new(Pixel::RGB24, settings.width, settings.height),
+            converted,
+            stream_index,
+            time_base,
+        })
+    }
+
+    pub fn encode(&mut self, frame: &Array3<u8>, position: Time) -> Result<(), ffmpeg_next::Error> {
+        let (_, width, _) = frame.dim();
+        let stride = self.rgb.stride(0);
+        let data = self.rgb.data_mut(0);
+        for (y, row) in frame.outer_iter().enumerate() {
+            let row = row.as_slice().expect("Frame rows must be contiguous");
+            data[y * stride..y * stride + width * 3].copy_from_slice(row);
+        }
+
+        self.scaler.run(&self.rgb, &mut self.converted)?;
+
+        let pts = (position.as_secs_f64() * f64::from(self.time_base.invert())).round() as i64;
+        self.converted.set_pts(Some(pts));
+
+        self.encoder.send_frame(&self.converted)?;
+        self.write_packets()
+    }
+
+    /// Marks `chapters` in the container for players and editors to
+    /// navigate by. The mp4 and matroska muxers write chapters with the
+    /// trailer, so they can be added any time before [`VideoEncoder::finish`].
+    pub fn add_chapters(&mut self, chapters: &[Chapter]) -> Result<(), ffmpeg_next::Error> {
+        let millis = |seconds: f64| (seconds * 1000.0).round() as i64;
+        for (id, chapter) in chapters.iter().enumerate() {
+            self.output.add_chapter(
+                id as i64 + 1,
+                Rational(1, 1000),
+                millis(chapter.start),
+                millis(chapter.end),
+                &chapter.title,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<(), ffmpeg_next::Error> {
+        self.encoder.send_eof()?;
+        self.write_packets()?;
+        self.output.write_trailer()
+    }
+
+    fn write_packets(&mut self) -> Result<(), ffmpeg_next::Error> {
+        let stream_time_base = self
+            .output
+            .stream(self.stream_index)
+            .expect("Output stream disappeared")
+            .time_base();
+
+        let mut packet = Packet::empty();
+        while self.encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(self.stream_index);
+            packet.rescale_ts(self.time_base, stream_time_base);
+            packet.write_interleaved(&mut self.output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds `attachment` to `output` as a stream of its own, before the header
+/// is written.
+fn attach(output: &mut Output, attachment: &Attachment) -> Result<(), ffmpeg_next::Error> {
+    use ffmpeg_next::ffi;
+
+    // ffmpeg-next only adds streams for codecs, attachments have none
+    unsafe {
+        let stream = ffi::avformat_new_stream(output.as_mut_ptr(), std::ptr::null());
+        if stream.is_null() {
+            return Err(ffmpeg_next::Error::Unknown);
+        }
+        let parameters = (*stream).codecpar;
+        (*parameters).codec_type = ffi::AVMediaType::AVMEDIA_TYPE_ATTACHMENT;
+
+        let size = attachment.data.len();
+        let extradata =
+            ffi::av_mallocz(size + ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize) as *mut u8;
+        if extradata.is_null() {
+            return Err(ffmpeg_next::Error::Unknown);
+        }
+        std::ptr::copy_nonoverlapping(attachment.data.as_ptr(), extradata, size);
+        (*parameters).extradata = extradata;
+        (*parameters).extradata_size = size as std::os::raw::c_int;
+    }
+
+    let mut metadata = Dictionary::new();
+    metadata.set("filename", &attachment.filename);
+    metadata.set("mimetype", &attachment.mime_type);
+    let index = output.nb_streams() as usize - 1;
+    output
+        .stream_mut(index)
+        .expect("The attachment stream was just added")
+        .set_metadata(metadata);
+    Ok(())
+}
+
+/// Whether `format` writes a playlist of segments rather than one file.
+pub fn is_segmented(format: &str) -> bool {
+    matches!(format, "hls" | "dash")
+}
+
+/// Muxer options for a playlist at `destination` that grows a segment at a
+/// time, so players can start while the render is still going.
+fn segment_options(
+    destination: &str,
+    format: &str,
+    settings: &EncodeSettings,
+) -> Dictionary<'static> {
+    let duration = settings.segment_duration.to_string();
+    let mut options = Dictionary::new();
+    if format == "hls" {
+        let stem = Path::new(destination).with_extension("");
+        options.set("hls_time", &duration);
+        options.set("hls_playlist_type", "event");
+        options.set(
+            "hls_segment_filename",
+            &format!("{}_%05d.ts", stem.to_string_lossy()),
+        );
+    } else {
+        options.set("seg_duration", &duration);
+        options.set("use_template", "1");
+        options.set("use_timeline", "1");
+    }
+    options
+}
+
+/// Muxer name for the container implied by the output extension. Needed because
+/// the encoder writes to a `.part` file, from which ffmpeg can't guess a format.
+pub fn container_format(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("mov") => "mov",
+        Some("mkv") => "matroska",
+        Some("webm") => "webm",
+        Some("mxf") => "mxf",
+        Some("m3u8") => "hls",
+        Some("mpd") => "dash",
+        _ => "mp4",
+    }
+}
+
+/// Converts a frame to the height x width x 3 layout the encoder expects.
+pub fn image_to_ndarray(image: &RgbImage) -> Array3<u8> {
+    let (width, height) = image.dimensions();
+    Array::from_shape_vec(
+        (height as usize, width as usize, 3),
+        image.clone().into_raw(),
+    )
+    .expect("Failed to convert image to ndarray")
+}
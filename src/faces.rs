@@ -0,0 +1,220 @@
+//! `--region faces`: effects only on faces, or everywhere but, found in each
+//! frame by an ONNX face detector.
+//!
+//! The detector is expected to be UltraFace-style: a 320x240 RGB input
+//! normalized to -1..1, and per anchor scores `[1, n, 2]` and corner boxes
+//! `[1, n, 4]` in fractions of the frame.
+
+use std::sync::Mutex;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, Luma, RgbaImage};
+use serde::Serialize;
+
+use crate::onnx::Plan;
+
+/// Input size of the detector.
+const INPUT: (usize, usize) = (320, 240);
+
+/// Boxes overlapping a better one by more than this are the same face.
+const NMS_OVERLAP: f32 = 0.3;
+
+/// How much of the way a tracked box moves to its new detection each frame.
+const TRACK_SPEED: f32 = 0.4;
+
+/// Frames a face is kept through after its detector stops finding it, so a
+/// missed frame doesn't flicker the effect off.
+const HOLD_FRAMES: usize = 5;
+
+/// Boxes are grown by this much so the mask covers hair and chin.
+const GROW: f32 = 0.2;
+
+/// Where `--region` puts the effect.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum Region {
+    /// Only on faces
+    Faces,
+    /// Everywhere but faces
+    Background,
+}
+
+/// A face as `[x1, y1, x2, y2]` in fractions of the frame.
+type Face = [f32; 4];
+
+fn overlap(a: &Face, b: &Face) -> f32 {
+    let width = (a[2].min(b[2]) - a[0].max(b[0])).max(0.0);
+    let height = (a[3].min(b[3]) - a[1].max(b[1])).max(0.0);
+    let area = |f: &Face| (f[2] - f[0]) * (f[3] - f[1]);
+    let shared = width * height;
+    shared / (area(a) + area(b) - shared).max(f32::EPSILON)
+}
+
+struct Tracked {
+    face: Face,
+    /// Frames since it was last detected
+    missed: usize,
+}
+
+/// Faces smoothed over the frames rendered so far. Starts over whenever
+/// frames come out of order.
+#[derive(Default)]
+struct Tracker {
+    faces: Vec<Tracked>,
+    last_index: Option<usize>,
+}
+
+impl Tracker {
+    fn update(&mut self, index: usize, detected: Vec<Face>) -> Vec<Face> {
+        if self.last_index.map(|last| last + 1) != Some(index) {
+            self.faces.clear();
+        }
+        self.last_index = Some(index);
+
+        for tracked in &mut self.faces {
+            tracked.missed += 1;
+        }
+        for face in detected {
+            let matched = self
+                .faces
+                .iter_mut()
+                .filter(|tracked| overlap(&tracked.face, &face) > NMS_OVERLAP)
+                .max_by(|a, b| overlap(&a.face, &face).total_cmp(&overlap(&b.face, &face)));
+            match matched {
+                Some(tracked) => {
+                    for (old, new) in tracked.face.iter_mut().zip(face) {
+                        *old += (new - *old) * TRACK_SPEED;
+                    }
+                    tracked.missed = 0;
+                }
+                None => self.faces.push(Tracked { face, missed: 0 }),
+            }
+        }
+        self.faces.retain(|tracked| tracked.missed <= HOLD_FRAMES);
+        self.faces.iter().map(|tracked| tracked.face).collect()
+    }
+}
+
+/// The detector with the settings of `--region`.
+pub struct FaceRegion {
+    plan: Plan,
+    region: Region,
+    /// Pixels the mask edge fades over
+    feather: f32,
+    /// Scores below this aren't faces
+    confidence: f32,
+    tracker: Mutex<Tracker>,
+}
+
+impl FaceRegion {
+    pub fn load(model: &str, region: Region, feather: f32, confidence: f32) -> FaceRegion {
+        FaceRegion {
+            plan: Plan::load(model, [1, 3, INPUT.1, INPUT.0]),
+            region,
+            feather,
+            confidence,
+            tracker: Mutex::new(Tracker::default()),
+        }
+    }
+
+    fn detect(&self, img: &DynamicImage) -> Vec<Face> {
+        let (w, h) = INPUT;
+        let input = imageops::resize(&img.to_rgb8(), w as u32, h as u32, FilterType::Triangle);
+        let outputs = self
+            .plan
+            .run(|[_, c, y, x]| (input.get_pixel(x as u32, y as u32).0[c] as f32 - 127.0) / 128.0)
+            .unwrap_or_else(|e| panic!("Failed to run face detector: {}", e));
+        let output = |channels: usize| {
+            outputs
+                .iter()
+                .find(|output| output.shape.last() == Some(&channels))
+                .map(|output| output.values.clone())
+                .unwrap_or_else(|| panic!("The face detector gives no [1, n, {}] output", channels))
+        };
+        let (scores, boxes) = (output(2), output(4));
+
+        let mut candidates: Vec<(f32, Face)> = scores
+            .chunks_exact(2)
+            .zip(boxes.chunks_exact(4))
+            .filter(|(score, _)| score[1] >= self.confidence)
+            .map(|(score, b)| (score[1], [b[0], b[1], b[2], b[3]]))
+            .collect();
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        let mut faces: Vec<Face> = vec![];
+        for (_, face) in candidates {
+            if faces.iter().all(|kept| overlap(kept, &face) <= NMS_OVERLAP) {
+                faces.push(face);
+            }
+        }
+        faces
+    }
+
+    /// How much of the effect each pixel of a `width`x`height` frame takes.
+    fn mask(&self, faces: &[Face], width: u32, height: u32) -> GrayImage {
+        let mut mask = GrayImage::new(width, height);
+        let feather = self.feather.max(f32::EPSILON);
+        for face in faces {
+            let (cx, cy) = ((face[0] + face[2]) / 2.0, (face[1] + face[3]) / 2.0);
+            let rx = (face[2] - face[0]) / 2.0 * (1.0 + GROW) * width as f32;
+            let ry = (face[3] - face[1]) / 2.0 * (1.0 + GROW) * height as f32;
+            if rx <= 0.0 || ry <= 0.0 {
+                continue;
+            }
+            let (cx, cy) = (cx * width as f32, cy * height as f32);
+            let reach_x = rx + feather;
+            let reach_y = ry + feather;
+            let x0 = (cx - reach_x).max(0.0) as u32;
+            let y0 = (cy - reach_y).max(0.0) as u32;
+            let x1 = ((cx + reach_x).ceil() as u32).min(width);
+            let y1 = ((cy + reach_y).ceil() as u32).min(height);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let (dx, dy) = ((x as f32 - cx) / rx, (y as f32 - cy) / ry);
+                    // Roughly how many pixels inside the ellipse's edge
+                    let inside = (1.0 - (dx * dx + dy * dy).sqrt()) * rx.min(ry);
+                    let alpha = (0.5 + inside / feather).clamp(0.0, 1.0);
+                    let pixel = mask.get_pixel_mut(x, y);
+                    pixel.0[0] = pixel.0[0].max((alpha * 255.0).round() as u8);
+                }
+            }
+        }
+        if self.region == Region::Background {
+            for pixel in mask.pixels_mut() {
+                pixel.0[0] = 255 - pixel.0[0];
+            }
+        }
+        mask
+    }
+
+    /// `effect` run on `img` and kept only where the region is, frame
+    /// `index` of the render.
+    pub fn apply(
+        &self,
+        img: DynamicImage,
+        index: usize,
+        effect: impl FnOnce(DynamicImage) -> RgbaImage,
+    ) -> RgbaImage {
+        let detected = self.detect(&img);
+        let faces = self
+            .tracker
+            .lock()
+            .expect("Face tracker lock poisoned")
+            .update(index, detected);
+        let mask = self.mask(&faces, img.width(), img.height());
+
+        let original = img.to_rgba8();
+        let mut out = effect(img);
+        for ((processed, original), Luma([alpha])) in out
+            .pixels_mut()
+            .zip(original.pixels())
+            .zip(mask.pixels().copied())
+        {
+            let t = alpha as f32 / 255.0;
+            for c in 0..4 {
+                processed.0[c] =
+                    (original.0[c] as f32 * (1.0 - t) + processed.0[c] as f32 * t).round() as u8;
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,246 @@
+//! C functions declared in `include/vidfx.h`, for hosts like OBS plugins or
+//! Max/MSP externals. Build the library for them with `cargo rustc --lib
+//! --release --features vidfx-ffi --crate-type cdylib` (or `staticlib`).
+//!
+//! Effects report errors by panicking, so every entry point catches unwinds
+//! and turns them into a return code plus [`vidfx_last_error`].
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+use image::{DynamicImage, RgbaImage};
+
+use crate::source::Source;
+use crate::{EffectChain, FrameContext};
+
+/// Same layout as `VidfxFrame` in `include/vidfx_plugin.h`.
+#[repr(C)]
+pub struct VidfxFrame {
+    data: *mut u8,
+    width: u32,
+    height: u32,
+    stride: u32,
+}
+
+pub struct VidfxSource {
+    source: Source,
+    index: usize,
+}
+
+pub struct VidfxChain {
+    chain: EffectChain,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).expect("nul bytes were removed");
+    LAST_ERROR.with_borrow_mut(|e| *e = Some(message));
+}
+
+/// Runs `f`, recording an `Err` or a panic as the last error.
+fn guard<T>(fallback: T, f: impl FnOnce() -> Result<T, String>) -> T {
+    LAST_ERROR.with_borrow_mut(|e| *e = None);
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(message)) => {
+            set_error(message);
+            fallback
+        }
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "vidfx panicked".to_string());
+            set_error(message);
+            fallback
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(s: *const c_char, name: &str) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err(format!("{} is NULL", name));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| format!("{} is not valid UTF-8", name))
+}
+
+/// Copies the frame out of host memory.
+unsafe fn read_frame(frame: &VidfxFrame) -> Result<RgbaImage, String> {
+    if frame.data.is_null() {
+        return Err("frame data is NULL".to_string());
+    }
+    let row = frame.width as usize * 4;
+    let mut pixels = Vec::with_capacity(row * frame.height as usize);
+    for y in 0..frame.height as usize {
+        let start = frame.data.add(y * frame.stride as usize);
+        pixels.extend_from_slice(std::slice::from_raw_parts(start, row));
+    }
+    RgbaImage::from_raw(frame.width, frame.height, pixels)
+        .ok_or_else(|| "frame is larger than its data".to_string())
+}
+
+unsafe fn write_frame(img: &RgbaImage, frame: &mut VidfxFrame) -> Result<(), String> {
+    if frame.data.is_null() {
+        return Err("frame data is NULL".to_string());
+    }
+    if img.dimensions() != (frame.width, frame.height) {
+        return Err(format!(
+            "frame is {}x{}, expected {}x{}",
+            frame.width,
+            frame.height,
+            img.width(),
+            img.height()
+        ));
+    }
+    let row = frame.width as usize * 4;
+    for (y, pixels) in img.as_raw().chunks(row).enumerate() {
+        let start = frame.data.add(y * frame.stride as usize);
+        ptr::copy_nonoverlapping(pixels.as_ptr(), start, row);
+    }
+    Ok(())
+}
+
+#[no_mangle]
+pub extern "C" fn vidfx_last_error() -> *const c_char {
+    LAST_ERROR.with_borrow(|e| e.as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// # Safety
+///
+/// `path` must be a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vidfx_source_open(path: *const c_char) -> *mut VidfxSource {
+    guard(ptr::null_mut(), || {
+        let path = str_arg(path, "path")?;
+        video_rs::init().map_err(|e| e.to_string())?;
+        let source =
+            Source::open(Path::new(path)).map_err(|e| format!("could not open {}: {}", path, e))?;
+        Ok(Box::into_raw(Box::new(VidfxSource { source, index: 0 })))
+    })
+}
+
+/// # Safety
+///
+/// `source` must come from [`vidfx_source_open`], the out pointers may be NULL.
+#[no_mangle]
+pub unsafe extern "C" fn vidfx_source_size(
+    source: *const VidfxSource,
+    width: *mut u32,
+    height: *mut u32,
+) {
+    let (w, h) = (*source).source.size();
+    if !width.is_null() {
+        *width = w;
+    }
+    if !height.is_null() {
+        *height = h;
+    }
+}
+
+/// # Safety
+///
+/// `source` must come from [`vidfx_source_open`].
+#[no_mangle]
+pub unsafe extern "C" fn vidfx_source_frame_rate(source: *const VidfxSource) -> f64 {
+    (*source).source.frame_rate()
+}
+
+/// # Safety
+///
+/// `source` must come from [`vidfx_source_open`] or be NULL.
+#[no_mangle]
+pub unsafe extern "C" fn vidfx_source_free(source: *mut VidfxSource) {
+    if !source.is_null() {
+        drop(Box::from_raw(source));
+    }
+}
+
+/// # Safety
+///
+/// `json` must be a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn vidfx_chain_from_json(json: *const c_char) -> *mut VidfxChain {
+    guard(ptr::null_mut(), || {
+        let chain = EffectChain::from_json(str_arg(json, "json")?)
+            .map_err(|e| format!("invalid effect chain: {}", e))?;
+        Ok(Box::into_raw(Box::new(VidfxChain { chain })))
+    })
+}
+
+/// # Safety
+///
+/// `chain` must come from [`vidfx_chain_from_json`] or be NULL.
+#[no_mangle]
+pub unsafe extern "C" fn vidfx_chain_free(chain: *mut VidfxChain) {
+    if !chain.is_null() {
+        drop(Box::from_raw(chain));
+    }
+}
+
+/// # Safety
+///
+/// `chain` must come from [`vidfx_chain_from_json`] and `frame` must describe
+/// `height` rows of `stride` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn vidfx_chain_process(
+    chain: *const VidfxChain,
+    frame: *mut VidfxFrame,
+    index: u64,
+    time: f64,
+    frame_rate: f64,
+    scale_factor: f64,
+) -> i32 {
+    guard(-1, || {
+        let frame = &mut *frame;
+        let img = read_frame(frame)?;
+        let mut context = FrameContext::new(index as usize, time, frame_rate);
+        context.scale_factor = scale_factor;
+        let processed = (*chain)
+            .chain
+            .apply(DynamicImage::ImageRgba8(img), &context);
+        write_frame(&processed, frame)?;
+        Ok(0)
+    })
+}
+
+/// # Safety
+///
+/// `source` must come from [`vidfx_source_open`], `chain` from
+/// [`vidfx_chain_from_json`] or be NULL, and `frame` must describe `height`
+/// rows of `stride` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn vidfx_source_next_frame(
+    source: *mut VidfxSource,
+    chain: *const VidfxChain,
+    frame: *mut VidfxFrame,
+    scale_factor: f64,
+) -> i32 {
+    guard(-1, || {
+        let source = &mut *source;
+        let Some(img) = source.source.next_frame() else {
+            return Ok(0);
+        };
+
+        let frame_rate = source.source.frame_rate();
+        let mut context =
+            FrameContext::new(source.index, source.index as f64 / frame_rate, frame_rate);
+        context.scale_factor = scale_factor;
+        let processed = match chain.as_ref() {
+            Some(chain) => chain.chain.apply(DynamicImage::ImageRgb8(img), &context),
+            None => DynamicImage::ImageRgb8(img).into_rgba8(),
+        };
+
+        write_frame(&processed, &mut *frame)?;
+        source.index += 1;
+        Ok(1)
+    })
+}
@@ -0,0 +1,65 @@
+use serde::Serialize;
+
+/// Switches effects on and off on a musical grid: `--gate 4:1` or
+/// `--gate-pattern x..x..x.`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Gate {
+    /// One entry per step, true where effects run
+    steps: Vec<bool>,
+    steps_per_beat: f64,
+}
+
+impl Gate {
+    /// `beats:on`, e.g. `4:1` runs effects for the first beat of every four.
+    pub fn parse_ratio(s: &str) -> Result<Gate, String> {
+        let invalid = || format!("invalid gate '{}', expected beats:on, e.g. 4:1", s);
+        let (beats, on) = s.split_once(':').ok_or_else(invalid)?;
+        let beats = beats.trim().parse::<usize>().map_err(|_| invalid())?;
+        let on = on.trim().parse::<usize>().map_err(|_| invalid())?;
+
+        if beats == 0 || on > beats {
+            return Err(invalid());
+        }
+
+        Ok(Gate {
+            steps: (0..beats).map(|beat| beat < on).collect(),
+            steps_per_beat: 1.0,
+        })
+    }
+
+    /// Step sequencer notation, `x` (or `X`, `1`) for on and `.` (or `-`,
+    /// `0`) for off.
+    pub fn parse_pattern(s: &str) -> Result<Gate, String> {
+        let steps = s
+            .chars()
+            .filter(|c| !c.is_whitespace() && *c != '|')
+            .map(|c| match c {
+                'x' | 'X' | '1' => Ok(true),
+                '.' | '-' | '0' => Ok(false),
+                _ => Err(format!(
+                    "invalid gate pattern '{}', use x for on and . for off",
+                    s
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if steps.is_empty() {
+            return Err("gate pattern is empty".to_string());
+        }
+
+        Ok(Gate {
+            steps,
+            steps_per_beat: 1.0,
+        })
+    }
+
+    pub fn with_steps_per_beat(mut self, steps_per_beat: f64) -> Gate {
+        self.steps_per_beat = steps_per_beat;
+        self
+    }
+
+    pub fn is_open(&self, time: f64, bpm: u32) -> bool {
+        let step = (time * bpm as f64 / 60.0 * self.steps_per_beat).floor() as usize;
+        self.steps[step % self.steps.len()]
+    }
+}
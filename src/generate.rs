@@ -0,0 +1,287 @@
+//! Procedural test and calibration patterns, used in place of an input video
+//! via `--input generate:<pattern>`.
+
+use std::f32::consts::PI;
+
+use image::{Rgb, RgbImage};
+
+use crate::Color;
+
+#[derive(Clone, Copy)]
+pub enum Pattern {
+    /// Classic demoscene plasma, slowly evolving
+    Plasma,
+    /// Fresh white noise every frame
+    Noise,
+    /// Hue gradient scrolling to the left
+    Gradient,
+    Solid(Color),
+    /// SMPTE color bars with PLUGE, for checking color conversion and levels
+    SmpteBars,
+    /// Circular zone plate sweeping up to the Nyquist frequency at the edges,
+    /// for spotting scaling and aliasing
+    ZonePlate,
+}
+
+impl Pattern {
+    /// Parses the part after `generate:`, e.g. `plasma` or `solid:#112233`.
+    pub fn parse(s: &str) -> Result<Pattern, String> {
+        match s.split_once(':') {
+            Some(("solid", color)) => Ok(Pattern::Solid(color.parse()?)),
+            None if s == "plasma" => Ok(Pattern::Plasma),
+            None if s == "noise" => Ok(Pattern::Noise),
+            None if s == "gradient" => Ok(Pattern::Gradient),
+            None if s == "smpte-bars" => Ok(Pattern::SmpteBars),
+            None if s == "zone-plate" => Ok(Pattern::ZonePlate),
+            None if s == "solid" => Err("solid needs a color, e.g. solid:#112233".to_string()),
+            _ => Err(format!(
+                "unknown pattern '{}', expected plasma, noise, gradient, smpte-bars, zone-plate or solid:<color>",
+                s
+            )),
+        }
+    }
+}
+
+fn hue(h: f32) -> Rgb<u8> {
+    let channel = |offset: f32| ((0.5 + 0.5 * (2.0 * PI * (h + offset)).cos()) * 255.0) as u8;
+    Rgb([channel(0.0), channel(2.0 / 3.0), channel(1.0 / 3.0)])
+}
+
+fn smpte_bars(width: u32, height: u32) -> RgbImage {
+    const BARS: [[u8; 3]; 7] = [
+        [191, 191, 191],
+        [191, 191, 0],
+        [0, 191, 191],
+        [0, 191, 0],
+        [191, 0, 191],
+        [191, 0, 0],
+        [0, 0, 191],
+    ];
+    const CASTELLATIONS: [[u8; 3]; 7] = [
+        [0, 0, 191],
+        [19, 19, 19],
+        [191, 0, 191],
+        [19, 19, 19],
+        [0, 191, 191],
+        [19, 19, 19],
+        [191, 191, 191],
+    ];
+    // -I, white, +Q and black each a bar and a quarter wide, then the PLUGE
+    // (below black, black, above black) in one bar and black for the last
+    const BOTTOM: [(f32, [u8; 3]); 7] = [
+        (1.25, [0, 33, 76]),
+        (1.25, [255, 255, 255]),
+        (1.25, [50, 0, 106]),
+        (1.25, [19, 19, 19]),
+        (1.0 / 3.0, [9, 9, 9]),
+        (1.0 / 3.0, [19, 19, 19]),
+        (1.0 / 3.0, [29, 29, 29]),
+    ];
+
+    RgbImage::from_fn(width, height, |x, y| {
+        let bar = x as f32 / width as f32 * 7.0;
+        let row = y as f32 / height as f32;
+        let color = if row < 0.67 {
+            BARS[bar as usize]
+        } else if row < 0.75 {
+            CASTELLATIONS[bar as usize]
+        } else {
+            let mut edge = 0.0;
+            BOTTOM
+                .iter()
+                .find(|(span, _)| {
+                    edge += span;
+                    bar < edge
+                })
+                .map_or([19, 19, 19], |(_, color)| *color)
+        };
+        Rgb(color)
+    })
+}
+
+/// 3x5 pixel digits, one row per byte with the top bits unused.
+const DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// The 3x5 glyph for `c`, one row per byte. Letters are drawn in upper case
+/// and anything without a glyph is left blank.
+pub(crate) fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0'..='9' => DIGITS[c as usize - '0' as usize],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '*' => [0b000, 0b101, 0b010, 0b101, 0b000],
+        '@' => [0b111, 0b101, 0b111, 0b100, 0b111],
+        _ => [0; 5],
+    }
+}
+
+/// Draws `lines` in white on a black box with its top left corner at
+/// `(left, top)`, sized relative to the frame so it survives scaling. Returns
+/// the box's size.
+pub fn burn_text(img: &mut RgbImage, (left, top): (u32, u32), lines: &[String]) -> (u32, u32) {
+    let scale = (img.height() / 108).max(1);
+    let columns = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let (box_width, box_height) = (
+        (columns as u32 * 4 + 1) * scale,
+        (lines.len() as u32 * 6 + 1) * scale,
+    );
+    let glyphs: Vec<Vec<[u8; 5]>> = lines
+        .iter()
+        .map(|line| line.chars().map(glyph).collect())
+        .collect();
+
+    for y in 0..box_height.min(img.height().saturating_sub(top)) {
+        for x in 0..box_width.min(img.width().saturating_sub(left)) {
+            let (cx, cy) = (x / scale, y / scale);
+            let lit = cy % 6 != 0 && cx % 4 != 0 && {
+                let row = &glyphs[(cy / 6) as usize];
+                row.get((cx / 4) as usize)
+                    .is_some_and(|glyph| (glyph[(cy % 6 - 1) as usize] >> (3 - cx % 4)) & 1 == 1)
+            };
+            let v = if lit { 255 } else { 0 };
+            img.put_pixel(left + x, top + y, Rgb([v, v, v]));
+        }
+    }
+    (box_width, box_height)
+}
+
+/// Draws `index` in white on a black box in the top left corner. Returns the
+/// box's size.
+pub fn burn_frame_number(img: &mut RgbImage, index: usize) -> (u32, u32) {
+    burn_text(img, (0, 0), &[index.to_string()])
+}
+
+/// Frames of a [`Pattern`] at a fixed size and rate.
+pub struct Generator {
+    pattern: Pattern,
+    width: u32,
+    height: u32,
+    frame_rate: f64,
+    frames: usize,
+    index: usize,
+}
+
+impl Generator {
+    pub fn new(
+        pattern: Pattern,
+        (width, height): (u32, u32),
+        frame_rate: f64,
+        duration: f64,
+    ) -> Self {
+        Generator {
+            pattern,
+            width,
+            height,
+            frame_rate,
+            frames: (duration * frame_rate).round() as usize,
+            index: 0,
+        }
+    }
+
+    /// The frame at `index`, independent of the ones before it.
+    pub fn frame(&self, index: usize) -> RgbImage {
+        let time = (index as f64 / self.frame_rate) as f32;
+        let (width, height) = (self.width as f32, self.height as f32);
+
+        match self.pattern {
+            Pattern::Plasma => RgbImage::from_fn(self.width, self.height, |x, y| {
+                let (u, v) = (x as f32 / width * 8.0, y as f32 / height * 8.0);
+                let value = (u + time).sin()
+                    + (v * 0.7 + time * 1.3).sin()
+                    + ((u + v + time * 0.8) * 0.5).sin()
+                    + ((u * u + v * v).sqrt() * 0.8 - time * 1.7).sin();
+                hue(value / 8.0 + time * 0.05)
+            }),
+            Pattern::Noise => {
+                // xorshift seeded per frame so renders are reproducible
+                let mut state = (index as u32).wrapping_mul(0x9e37_79b9) | 1;
+                RgbImage::from_fn(self.width, self.height, |_, _| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    let v = (state >> 24) as u8;
+                    Rgb([v, v, v])
+                })
+            }
+            Pattern::Gradient => RgbImage::from_fn(self.width, self.height, |x, _| {
+                hue(x as f32 / width + time * 0.1)
+            }),
+            Pattern::SmpteBars => smpte_bars(self.width, self.height),
+            Pattern::ZonePlate => {
+                let (cx, cy) = (width / 2.0, height / 2.0);
+                RgbImage::from_fn(self.width, self.height, |x, y| {
+                    let (dx, dy) = (x as f32 - cx, y as f32 - cy);
+                    let phase = PI * (dx * dx + dy * dy) / width - 2.0 * PI * time;
+                    let v = ((0.5 + 0.5 * phase.cos()) * 255.0).round() as u8;
+                    Rgb([v, v, v])
+                })
+            }
+            Pattern::Solid(Color(r, g, b)) => {
+                RgbImage::from_pixel(self.width, self.height, Rgb([r, g, b]))
+            }
+        }
+    }
+}
+
+impl Iterator for Generator {
+    type Item = RgbImage;
+
+    fn next(&mut self) -> Option<RgbImage> {
+        if self.index >= self.frames {
+            return None;
+        }
+        let frame = self.frame(self.index);
+        self.index += 1;
+        Some(frame)
+    }
+}
@@ -0,0 +1,112 @@
+//! Gradient maps for the `gradient-map`, `duotone` and `falsecolor` effects:
+//! each pixel's luma picks a color along a ramp of stops.
+
+use image::RgbaImage;
+
+use crate::chain::Palette;
+
+/// `img` with its luma mapped through `stops`, sorted by position from 0
+/// at black to 1 at white, held flat past the first and last.
+pub fn apply(mut img: RgbaImage, stops: &[(f32, [u8; 3])]) -> RgbaImage {
+    if stops.is_empty() {
+        return img;
+    }
+    let mut stops = stops.to_vec();
+    stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let ramp: [[u8; 3]; 256] = std::array::from_fn(|level| {
+        let at = level as f32 / 255.0;
+        let after = stops.partition_point(|stop| stop.0 <= at);
+        let ((x0, c0), (x1, c1)) = match after {
+            0 => (stops[0], stops[0]),
+            i if i == stops.len() => (stops[i - 1], stops[i - 1]),
+            i => (stops[i - 1], stops[i]),
+        };
+        let t = if x1 > x0 { (at - x0) / (x1 - x0) } else { 0.0 };
+        std::array::from_fn(|c| (c0[c] as f32 + (c1[c] as f32 - c0[c] as f32) * t).round() as u8)
+    });
+
+    for pixel in img.pixels_mut() {
+        let [r, g, b, _] = pixel.0;
+        let luma = (r as u32 * 77 + g as u32 * 150 + b as u32 * 29) >> 8;
+        let [r, g, b] = ramp[luma as usize];
+        pixel.0[..3].copy_from_slice(&[r, g, b]);
+    }
+    img
+}
+
+/// Matplotlib's inferno, black through purple and orange to pale yellow.
+const INFERNO: &[(f32, u32)] = &[
+    (0.0, 0x000004),
+    (0.125, 0x1b0c41),
+    (0.25, 0x4a0c6b),
+    (0.375, 0x781c6d),
+    (0.5, 0xa52c60),
+    (0.625, 0xcf4446),
+    (0.75, 0xed6925),
+    (0.875, 0xfb9b06),
+    (0.9375, 0xf7d13d),
+    (1.0, 0xfcffa4),
+];
+
+/// Matplotlib's viridis, dark purple through teal to yellow.
+const VIRIDIS: &[(f32, u32)] = &[
+    (0.0, 0x440154),
+    (0.125, 0x482878),
+    (0.25, 0x3e4989),
+    (0.375, 0x31688e),
+    (0.5, 0x26828e),
+    (0.625, 0x1f9e89),
+    (0.75, 0x35b779),
+    (0.875, 0x6ece58),
+    (1.0, 0xfde725),
+];
+
+/// The iron palette of thermal cameras, cold black to white hot.
+const IR: &[(f32, u32)] = &[
+    (0.0, 0x000000),
+    (0.15, 0x1e0064),
+    (0.3, 0x7a008c),
+    (0.45, 0xc8143c),
+    (0.6, 0xf05a00),
+    (0.75, 0xffa000),
+    (0.9, 0xffe060),
+    (1.0, 0xffffff),
+];
+
+/// Camera style exposure bands: purple and blue crushed, green at mid grey,
+/// pink a stop over for skin, yellow and red near and at clipping, grey
+/// between. Stops sharing a position make hard edges.
+const EXPOSURE: &[(f32, u32)] = &[
+    (0.0, 0x7b2cbf),
+    (0.02, 0x7b2cbf),
+    (0.02, 0x1e50ff),
+    (0.1, 0x1e50ff),
+    (0.1, 0x333333),
+    (0.38, 0x606060),
+    (0.38, 0x3ccf3c),
+    (0.48, 0x3ccf3c),
+    (0.48, 0x808080),
+    (0.52, 0x808080),
+    (0.52, 0xff7fbf),
+    (0.56, 0xff7fbf),
+    (0.56, 0xa0a0a0),
+    (0.9, 0xd0d0d0),
+    (0.9, 0xffe000),
+    (0.97, 0xffe000),
+    (0.97, 0xff0000),
+    (1.0, 0xff0000),
+];
+
+/// The stops of a built in `falsecolor` palette.
+pub fn palette(palette: Palette) -> Vec<(f32, [u8; 3])> {
+    let stops = match palette {
+        Palette::Inferno => INFERNO,
+        Palette::Viridis => VIRIDIS,
+        Palette::Ir => IR,
+        Palette::Exposure => EXPOSURE,
+    };
+    stops
+        .iter()
+        .map(|&(at, hex)| (at, [(hex >> 16) as u8, (hex >> 8) as u8, hex as u8]))
+        .collect()
+}
@@ -0,0 +1,66 @@
+//! CMYK print emulation for the `halftone` effect: each ink is laid down as
+//! a screen of dots at its own angle, sized by how much of that ink the area
+//! needs.
+
+use image::RgbaImage;
+
+/// Cyan, magenta, yellow and black coverage of `pixel`, 0 to 1.
+fn cmyk(pixel: [u8; 4]) -> [f32; 4] {
+    let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|v| v as f32 / 255.0);
+    let k = 1.0 - r.max(g).max(b);
+    if k >= 1.0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+    let ink = |v: f32| (1.0 - v - k) / (1.0 - k);
+    [ink(r), ink(g), ink(b), k]
+}
+
+/// `img` printed with dots on a `dot_size` pixel grid, the screens at
+/// `angles` degrees for cyan, magenta, yellow and black.
+pub fn apply(mut img: RgbaImage, dot_size: f32, angles: [f32; 4]) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let size = dot_size.max(1.0);
+    let inks: Vec<[f32; 4]> = img.pixels().map(|pixel| cmyk(pixel.0)).collect();
+    let ink_at = |x: f32, y: f32, c: usize| {
+        let x = (x.round() as i64).clamp(0, width as i64 - 1) as usize;
+        let y = (y.round() as i64).clamp(0, height as i64 - 1) as usize;
+        inks[y * width as usize + x][c]
+    };
+    let rotations = angles.map(|angle| {
+        let radians = angle.to_radians();
+        (radians.sin(), radians.cos())
+    });
+
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let (x, y) = (x as f32 + 0.5, y as f32 + 0.5);
+        let mut printed = [0.0; 4];
+        for (c, &(sin, cos)) in rotations.iter().enumerate() {
+            // The pixel in the screen's rotated grid
+            let (u, v) = (x * cos + y * sin, -x * sin + y * cos);
+            let (cell_u, cell_v) = ((u / size).floor(), (v / size).floor());
+            // Dark areas grow dots past their cell, so neighbours count too
+            for du in -1..=1 {
+                for dv in -1..=1 {
+                    let center_u = (cell_u + du as f32 + 0.5) * size;
+                    let center_v = (cell_v + dv as f32 + 0.5) * size;
+                    let coverage = ink_at(
+                        center_u * cos - center_v * sin,
+                        center_u * sin + center_v * cos,
+                        c,
+                    );
+                    let radius = size * (coverage / std::f32::consts::PI).sqrt();
+                    let distance = ((u - center_u).powi(2) + (v - center_v).powi(2)).sqrt();
+                    // A pixel of antialiasing at the dot's edge
+                    let ink = (radius - distance + 0.5).clamp(0.0, 1.0);
+                    printed[c] = f32::max(printed[c], ink);
+                }
+            }
+        }
+        let [c, m, y, k] = printed;
+        let paper = |ink: f32| ((1.0 - ink) * (1.0 - k) * 255.0).round() as u8;
+        pixel.0[0] = paper(c);
+        pixel.0[1] = paper(m);
+        pixel.0[2] = paper(y);
+    }
+    img
+}
@@ -0,0 +1,61 @@
+use serde_json::Value;
+use vidfx::audio::Audio;
+use vidfx::{EffectChain, FrameContext};
+
+/// What `--debug-overlay` needs to know beyond the frame itself.
+pub struct Hud {
+    /// The input's soundtrack, when it has one
+    pub audio: Option<Audio>,
+}
+
+impl Hud {
+    /// The overlay's lines for `frame`: timing and modulation first, then one
+    /// line per effect actually applied with its parameter values after
+    /// automation, randomization and markers.
+    pub fn lines(&self, frame: &FrameContext, chains: &[EffectChain], gated: bool) -> Vec<String> {
+        let mut lines = vec![
+            format!("frame {} t {:.2}s", frame.index, frame.time),
+            format!("scale {:.3}", frame.scale_factor),
+            match (frame.beat_phase, frame.bpm) {
+                (Some(phase), Some(bpm)) => format!("phase {:.2} bpm {}", phase, bpm),
+                _ => "phase - bpm -".to_string(),
+            },
+            match &self.audio {
+                Some(audio) => format!(
+                    "audio {:.3}",
+                    audio.level(frame.time, 1.0 / frame.frame_rate)
+                ),
+                None => "audio -".to_string(),
+            },
+        ];
+        if gated {
+            lines.push("gate closed".to_string());
+            return lines;
+        }
+
+        for chain in chains {
+            let json = serde_json::to_value(chain).expect("Effect chains serialize");
+            for entry in json["effects"].as_array().into_iter().flatten() {
+                lines.push(params(entry));
+            }
+        }
+        lines
+    }
+}
+
+/// `bloom intensity=1.50 radius=8 ...`, skipping values that aren't a single
+/// number, switch or word.
+fn params(entry: &Value) -> String {
+    let mut line = entry["effect"].as_str().unwrap_or("?").to_string();
+    for (field, value) in entry.as_object().into_iter().flatten() {
+        let value = match value {
+            Value::Number(n) if n.is_f64() => format!("{:.2}", n.as_f64().unwrap_or_default()),
+            Value::Number(n) => n.to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::String(s) if field != "effect" => s.clone(),
+            _ => continue,
+        };
+        line.push_str(&format!(" {}={}", field, value));
+    }
+    line
+}
@@ -0,0 +1,256 @@
+use std::cell::RefCell;
+
+use image::RgbaImage;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::shader::{self, Uniform, UniformValue};
+use crate::FrameContext;
+
+/// One entry of an ISF `INPUTS` array.
+#[derive(Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+struct Input {
+    name: String,
+    #[serde(rename = "TYPE")]
+    kind: String,
+    default: Option<Value>,
+    min: Option<Value>,
+    max: Option<Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "UPPERCASE", default)]
+struct Header {
+    inputs: Vec<Input>,
+    passes: Vec<Value>,
+}
+
+/// A parsed ISF file: its JSON metadata and the GLSL body that follows it.
+struct Isf {
+    path: String,
+    header: Header,
+    body: String,
+}
+
+impl Isf {
+    fn load(path: &str) -> Result<Isf, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+
+        let start = text
+            .find("/*")
+            .ok_or_else(|| format!("{} has no ISF header comment", path))?;
+        let end = text[start..]
+            .find("*/")
+            .map(|end| start + end)
+            .ok_or_else(|| format!("{} has an unterminated ISF header", path))?;
+
+        let header: Header = serde_json::from_str(&text[start + 2..end])
+            .map_err(|e| format!("{}: invalid ISF header: {}", path, e))?;
+
+        if header.passes.len() > 1 {
+            return Err(format!(
+                "{}: multi-pass ISF shaders are not supported",
+                path
+            ));
+        }
+
+        for input in &header.inputs {
+            match input.kind.as_str() {
+                "image" if input.name != "inputImage" => {
+                    return Err(format!(
+                        "{}: only the inputImage image input is supported, not {}",
+                        path, input.name
+                    ));
+                }
+                "audio" | "audioFFT" => {
+                    return Err(format!("{}: audio inputs are not supported", path));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Isf {
+            path: path.to_string(),
+            header,
+            body: text[end + 2..].to_string(),
+        })
+    }
+
+    fn source(&self, uniforms: &[Uniform]) -> String {
+        format!(
+            r#"{}
+#define RENDERSIZE iResolution.xy
+#define TIME iTime
+#define FRAMEINDEX iFrame
+#define TIMEDELTA (iFrame > 0 ? iTime / float(iFrame) : 0.0)
+#define DATE vec4(0.0)
+#define isf_FragNormCoord (vidfx_fragcoord / RENDERSIZE)
+#define vv_FragNormCoord isf_FragNormCoord
+#define IMG_THIS_PIXEL(image) texture(iChannel0, isf_FragNormCoord)
+#define IMG_THIS_NORM_PIXEL(image) texture(iChannel0, isf_FragNormCoord)
+#define IMG_NORM_PIXEL(image, coord) texture(iChannel0, coord)
+#define IMG_PIXEL(image, coord) texture(iChannel0, (coord) / RENDERSIZE)
+#define IMG_SIZE(image) RENDERSIZE
+{}
+"#,
+            shader::prelude(uniforms),
+            self.body.replace("gl_FragColor", "vidfx_out")
+        )
+    }
+}
+
+fn number(value: &Value) -> Option<f32> {
+    value.as_f64().map(|v| v as f32)
+}
+
+fn numbers<const N: usize>(value: &Value) -> Option<[f32; N]> {
+    let array = value.as_array()?;
+    let mut out = [0.0; N];
+    for (o, v) in out.iter_mut().zip(array) {
+        *o = number(v)?;
+    }
+    Some(out)
+}
+
+fn parse_override<const N: usize>(s: &str) -> Option<[f32; N]> {
+    if N == 4 && s.len() == 6 && !s.contains(',') {
+        let channel = |i: usize| u8::from_str_radix(&s[i..i + 2], 16).ok();
+        let mut out = [1.0; N];
+        for (i, o) in out.iter_mut().take(3).enumerate() {
+            *o = channel(i * 2)? as f32 / 255.0;
+        }
+        return Some(out);
+    }
+
+    let parts: Vec<f32> = s
+        .split(',')
+        .map(|p| p.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let mut out = [1.0; N];
+    for (o, p) in out.iter_mut().zip(parts) {
+        *o = p;
+    }
+    Some(out)
+}
+
+/// Resolves each input's value from `--param` overrides, its DEFAULT, or its
+/// MIN, clamped to the published range. Floats named in `modulate` are pulled
+/// from MIN towards their value by the frame's scale factor.
+fn uniforms(
+    isf: &Isf,
+    params: &[(String, String)],
+    modulate: &[String],
+    frame: &FrameContext,
+) -> Result<Vec<Uniform>, String> {
+    let mut uniforms = vec![];
+
+    for input in &isf.header.inputs {
+        let user = params
+            .iter()
+            .find(|(name, _)| *name == input.name)
+            .map(|(_, v)| v.as_str());
+        let invalid = || format!("{}: invalid value for {}", isf.path, input.name);
+
+        let value = match input.kind.as_str() {
+            "image" => continue,
+            "float" => {
+                let min = input.min.as_ref().and_then(number);
+                let max = input.max.as_ref().and_then(number);
+                let mut v = match user {
+                    Some(v) => v.parse::<f32>().map_err(|_| invalid())?,
+                    None => input
+                        .default
+                        .as_ref()
+                        .and_then(number)
+                        .or(min)
+                        .unwrap_or(0.0),
+                };
+                if let Some(min) = min {
+                    v = v.max(min);
+                }
+                if let Some(max) = max {
+                    v = v.min(max);
+                }
+                if modulate.contains(&input.name) {
+                    let floor = min.unwrap_or(0.0);
+                    v = floor + (v - floor) * frame.scale_factor as f32;
+                }
+                UniformValue::Float(v)
+            }
+            "long" => UniformValue::Int(match user {
+                Some(v) => v.parse::<i32>().map_err(|_| invalid())?,
+                None => input.default.as_ref().and_then(Value::as_i64).unwrap_or(0) as i32,
+            }),
+            "bool" | "event" => UniformValue::Bool(match user {
+                Some(v) => matches!(v, "true" | "1" | "on"),
+                None => input
+                    .default
+                    .as_ref()
+                    .map(|d| d.as_bool().unwrap_or(d.as_f64() == Some(1.0)))
+                    .unwrap_or(false),
+            }),
+            "point2D" => UniformValue::Vec2(match user {
+                Some(v) => parse_override(v).ok_or_else(invalid)?,
+                None => input.default.as_ref().and_then(numbers).unwrap_or([0.0; 2]),
+            }),
+            "color" => UniformValue::Vec4(match user {
+                Some(v) => parse_override(v).ok_or_else(invalid)?,
+                None => input
+                    .default
+                    .as_ref()
+                    .and_then(numbers)
+                    .unwrap_or([0.0, 0.0, 0.0, 1.0]),
+            }),
+            other => return Err(format!("{}: unsupported input type {}", isf.path, other)),
+        };
+
+        uniforms.push(Uniform {
+            name: input.name.clone(),
+            value,
+        });
+    }
+
+    Ok(uniforms)
+}
+
+thread_local! {
+    static LOADED: RefCell<Option<Isf>> = const { RefCell::new(None) };
+}
+
+/// Runs the ISF shader in `file` over `img`. `params` are `name=value`
+/// overrides for its inputs.
+pub fn apply(
+    file: &str,
+    params: &[String],
+    modulate: &[String],
+    img: RgbaImage,
+    frame: &FrameContext,
+) -> RgbaImage {
+    let params: Vec<(String, String)> = params
+        .iter()
+        .map(|p| {
+            p.split_once('=')
+                .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                .unwrap_or_else(|| panic!("ISF param '{}' is not name=value", p))
+        })
+        .collect();
+
+    LOADED.with_borrow_mut(|loaded| {
+        if loaded.as_ref().map_or(true, |isf| isf.path != file) {
+            *loaded = Some(Isf::load(file).unwrap_or_else(|e| panic!("{}", e)));
+        }
+        let isf = loaded.as_ref().expect("ISF was just loaded");
+
+        let uniforms = uniforms(isf, &params, modulate, frame).unwrap_or_else(|e| panic!("{}", e));
+        shader::run(
+            &format!("isf:{}", file),
+            || Ok(isf.source(&uniforms)),
+            &uniforms,
+            img,
+            frame,
+        )
+    })
+}
@@ -0,0 +1,301 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use image::{imageops, DynamicImage, RgbImage, Rgba, RgbaImage};
+use serde::{Serialize, Serializer};
+use vidfx::source::Source;
+use vidfx::FrameContext;
+
+/// How a layer combines with what is below it.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum Blend {
+    Normal,
+    Screen,
+    Overlay,
+    Multiply,
+    Add,
+    Difference,
+    Lighten,
+    Darken,
+}
+
+impl Blend {
+    fn apply(self, below: f32, above: f32) -> f32 {
+        match self {
+            Blend::Normal => above,
+            Blend::Screen => 1.0 - (1.0 - below) * (1.0 - above),
+            Blend::Overlay if below < 0.5 => 2.0 * below * above,
+            Blend::Overlay => 1.0 - 2.0 * (1.0 - below) * (1.0 - above),
+            Blend::Multiply => below * above,
+            Blend::Add => (below + above).min(1.0),
+            Blend::Difference => (below - above).abs(),
+            Blend::Lighten => below.max(above),
+            Blend::Darken => below.min(above),
+        }
+    }
+}
+
+/// Whether layers go under the effects or over their result.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum LayerStage {
+    Before,
+    After,
+}
+
+/// A layer setting that can move with the modulation: `a~b` goes from `a` at
+/// a scale factor of 0 to `b` at 1, a plain value stays put.
+#[derive(Clone, Copy, Debug)]
+struct Animated<const N: usize> {
+    from: [f32; N],
+    to: [f32; N],
+}
+
+impl<const N: usize> Animated<N> {
+    fn fixed(value: [f32; N]) -> Self {
+        Animated {
+            from: value,
+            to: value,
+        }
+    }
+
+    /// Values separated by `separator`, e.g. `1600,60` or `320x180~640x360`.
+    fn parse(s: &str, separator: char) -> Option<Self> {
+        let values = |s: &str| -> Option<[f32; N]> {
+            let parts: Vec<f32> = s
+                .split(separator)
+                .map(|part| part.trim().parse::<f32>().ok())
+                .collect::<Option<_>>()?;
+            parts.try_into().ok()
+        };
+        match s.split_once('~') {
+            Some((from, to)) => Some(Animated {
+                from: values(from)?,
+                to: values(to)?,
+            }),
+            None => values(s).map(Animated::fixed),
+        }
+    }
+
+    fn at(&self, scale_factor: f64) -> [f32; N] {
+        let t = scale_factor.clamp(0.0, 1.0) as f32;
+        let mut out = self.from;
+        for (o, to) in out.iter_mut().zip(self.to) {
+            *o += (to - *o) * t;
+        }
+        out
+    }
+}
+
+// serde only implements arrays up to fixed lengths, not for any `N`
+impl<const N: usize> Serialize for Animated<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (&self.from[..], &self.to[..]).serialize(serializer)
+    }
+}
+
+/// One `--layer path[:blend][:opacity][:key=value...]`, e.g.
+/// `top.mp4:screen:0.7` or `small.mp4:pos=1600,60:size=320x180:rotate=0~15`.
+#[derive(Clone, Debug, Serialize)]
+pub struct LayerSpec {
+    path: String,
+    blend: Blend,
+    opacity: Animated<1>,
+    /// Top left corner in pixels, before rotation
+    pos: Option<Animated<2>>,
+    size: Option<Animated<2>>,
+    scale: Animated<1>,
+    /// Degrees clockwise about the layer's center
+    rotate: Animated<1>,
+}
+
+impl LayerSpec {
+    pub fn parse(s: &str) -> Result<LayerSpec, String> {
+        let mut spec = LayerSpec {
+            path: String::new(),
+            blend: Blend::Normal,
+            opacity: Animated::fixed([1.0]),
+            pos: None,
+            size: None,
+            scale: Animated::fixed([1.0]),
+            rotate: Animated::fixed([0.0]),
+        };
+        let invalid =
+            |key: &str, value: &str| format!("invalid {} '{}' in layer '{}'", key, value, s);
+
+        // Options come off the end for as long as parts read as one, so the
+        // colons of urls and Windows paths stay in the file name
+        let (mut blend, mut opacity) = (false, false);
+        let mut path = s;
+        while let Some((rest, part)) = path.rsplit_once(':') {
+            if let Some((key, value)) = part.split_once('=') {
+                match key {
+                    "blend" => {
+                        spec.blend = clap::ValueEnum::from_str(value, true).map_err(|_| {
+                            format!("unknown blend mode '{}' in layer '{}'", value, s)
+                        })?;
+                    }
+                    "opacity" => {
+                        spec.opacity =
+                            Animated::parse(value, ',').ok_or_else(|| invalid(key, value))?;
+                    }
+                    "pos" => {
+                        spec.pos =
+                            Some(Animated::parse(value, ',').ok_or_else(|| invalid(key, value))?);
+                    }
+                    "size" => {
+                        spec.size =
+                            Some(Animated::parse(value, 'x').ok_or_else(|| invalid(key, value))?);
+                    }
+                    "scale" => {
+                        spec.scale =
+                            Animated::parse(value, ',').ok_or_else(|| invalid(key, value))?;
+                    }
+                    "rotate" => {
+                        spec.rotate =
+                            Animated::parse(value, ',').ok_or_else(|| invalid(key, value))?;
+                    }
+                    // A query string or the like, part of the path
+                    _ if !key.chars().all(|c| c.is_ascii_lowercase()) => break,
+                    _ => return Err(format!("unknown layer option '{}' in '{}'", key, s)),
+                }
+            } else if let Some(mode) = (!blend)
+                .then(|| <Blend as clap::ValueEnum>::from_str(part, true).ok())
+                .flatten()
+            {
+                spec.blend = mode;
+                blend = true;
+            } else if let Some(value) = (!opacity)
+                .then(|| Animated::parse(part, ','))
+                .flatten()
+                .filter(|value: &Animated<1>| {
+                    value
+                        .from
+                        .iter()
+                        .chain(&value.to)
+                        .all(|v| (0.0..=1.0).contains(v))
+                })
+            {
+                spec.opacity = value;
+                opacity = true;
+            } else {
+                break;
+            }
+            path = rest;
+        }
+
+        if path.is_empty() {
+            return Err(format!("layer '{}' has no file", s));
+        }
+        spec.path = path.to_string();
+        Ok(spec)
+    }
+}
+
+/// A decoded `--layer`. Frames are looked up by output time, so layers at
+/// other frame rates stay in sync; once a layer runs out it stops showing.
+pub struct Layer {
+    spec: LayerSpec,
+    source: Mutex<Source>,
+}
+
+impl Layer {
+    pub fn open(spec: &LayerSpec) -> Layer {
+        let source = Source::open(Path::new(&spec.path))
+            .unwrap_or_else(|e| panic!("Failed to open layer {}: {}", spec.path, e));
+        Layer {
+            spec: spec.clone(),
+            source: Mutex::new(source),
+        }
+    }
+
+    fn frame(&self, time: f64) -> Option<RgbImage> {
+        self.source
+            .lock()
+            .expect("Layer decoder lock poisoned")
+            .frame_at_time(time)
+    }
+}
+
+/// Blends `layers` over `base` bottom-up. Layers without a position or size
+/// are stretched over the whole frame, others keep their own size unless
+/// given one.
+pub fn composite(base: &mut RgbaImage, layers: &[Layer], frame: &FrameContext) {
+    let (width, height) = base.dimensions();
+    let scale_factor = frame.scale_factor;
+
+    for layer in layers {
+        let Some(source) = layer.frame(frame.time) else {
+            continue;
+        };
+        let spec = &layer.spec;
+
+        let (mut w, mut h) = match (&spec.size, &spec.pos) {
+            (Some(size), _) => {
+                let [w, h] = size.at(scale_factor);
+                (w, h)
+            }
+            (None, Some(_)) => (source.width() as f32, source.height() as f32),
+            (None, None) => (width as f32, height as f32),
+        };
+        let [scale] = spec.scale.at(scale_factor);
+        w *= scale;
+        h *= scale;
+        let [x, y] = spec.pos.map_or([0.0, 0.0], |pos| pos.at(scale_factor));
+        let (w, h) = (w.round().max(1.0) as u32, h.round().max(1.0) as u32);
+
+        let mut img = DynamicImage::ImageRgb8(source).into_rgba8();
+        if img.dimensions() != (w, h) {
+            img = imageops::resize(&img, w, h, imageops::FilterType::Triangle);
+        }
+        let [degrees] = spec.rotate.at(scale_factor);
+        let (img, offset) = rotate(img, degrees);
+
+        let [opacity] = spec.opacity.at(scale_factor);
+        let opacity = opacity.clamp(0.0, 1.0);
+        let left = (x + offset.0).round() as i64;
+        let top = (y + offset.1).round() as i64;
+
+        for (lx, ly, above) in img.enumerate_pixels() {
+            let (bx, by) = (left + lx as i64, top + ly as i64);
+            if bx < 0 || by < 0 || bx >= width as i64 || by >= height as i64 {
+                continue;
+            }
+            let coverage = opacity * above.0[3] as f32 / 255.0;
+            let below = base.get_pixel_mut(bx as u32, by as u32);
+            for c in 0..3 {
+                let a = below.0[c] as f32 / 255.0;
+                let b = above.0[c] as f32 / 255.0;
+                let mixed = a + (spec.blend.apply(a, b) - a) * coverage;
+                below.0[c] = (mixed * 255.0).round() as u8;
+            }
+        }
+    }
+}
+
+/// `img` turned `degrees` clockwise about its center, on a transparent
+/// canvas big enough to hold it, with where the canvas's corner lands
+/// relative to the unrotated corner.
+fn rotate(img: RgbaImage, degrees: f32) -> (RgbaImage, (f32, f32)) {
+    if degrees.rem_euclid(360.0) == 0.0 {
+        return (img, (0.0, 0.0));
+    }
+
+    let (w, h) = (img.width() as f32, img.height() as f32);
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let out_w = (w * cos.abs() + h * sin.abs()).ceil();
+    let out_h = (w * sin.abs() + h * cos.abs()).ceil();
+
+    let out = RgbaImage::from_fn(out_w as u32, out_h as u32, |x, y| {
+        // Back from the canvas into the source, nearest pixel
+        let dx = x as f32 + 0.5 - out_w / 2.0;
+        let dy = y as f32 + 0.5 - out_h / 2.0;
+        let sx = dx * cos + dy * sin + w / 2.0;
+        let sy = -dx * sin + dy * cos + h / 2.0;
+        if sx < 0.0 || sy < 0.0 || sx >= w || sy >= h {
+            Rgba([0, 0, 0, 0])
+        } else {
+            *img.get_pixel(sx as u32, sy as u32)
+        }
+    });
+    (out, ((w - out_w) / 2.0, (h - out_h) / 2.0))
+}
@@ -0,0 +1,72 @@
+//! Level stretching for the `normalize` effect.
+
+use image::{RgbImage, RgbaImage};
+
+/// Every this many pixels are sampled, which is plenty for a histogram.
+const SAMPLE_STEP: usize = 4;
+
+/// Luma histogram of `img` added onto `histogram`.
+fn add_histogram(img: &[u8], channels: usize, histogram: &mut [u64; 256]) {
+    for pixel in img.chunks_exact(channels).step_by(SAMPLE_STEP) {
+        let luma = (pixel[0] as u32 * 77 + pixel[1] as u32 * 150 + pixel[2] as u32 * 29) >> 8;
+        histogram[luma as usize] += 1;
+    }
+}
+
+/// The darkest and brightest levels left once `clip` percent of the
+/// samples are given up at each end.
+fn clip_levels(histogram: &[u64; 256], clip: f32) -> [u8; 2] {
+    let total: u64 = histogram.iter().sum();
+    let limit = (total as f64 * clip.clamp(0.0, 50.0) as f64 / 100.0) as u64;
+
+    let mut seen = 0;
+    let low = histogram
+        .iter()
+        .position(|&count| {
+            seen += count;
+            seen > limit
+        })
+        .unwrap_or(0);
+    seen = 0;
+    let high = 255
+        - histogram
+            .iter()
+            .rev()
+            .position(|&count| {
+                seen += count;
+                seen > limit
+            })
+            .unwrap_or(0);
+    [low as u8, high.max(low) as u8]
+}
+
+/// The levels `img` stretches between at `clip`.
+pub fn frame_levels(img: &RgbaImage, clip: f32) -> [u8; 2] {
+    let mut histogram = [0; 256];
+    add_histogram(img.as_raw(), 4, &mut histogram);
+    clip_levels(&histogram, clip)
+}
+
+/// The levels over all of `frames`, for stretching every frame the same.
+pub fn analyze(frames: impl Iterator<Item = RgbImage>, clip: f32) -> [u8; 2] {
+    let mut histogram = [0; 256];
+    for frame in frames {
+        add_histogram(frame.as_raw(), 3, &mut histogram);
+    }
+    clip_levels(&histogram, clip)
+}
+
+/// Maps `low` to black and `high` to white, clipping beyond.
+pub fn stretch(img: &mut RgbaImage, [low, high]: [u8; 2]) {
+    if high <= low {
+        return;
+    }
+    let scale = 255.0 / (high - low) as f32;
+    let lut: [u8; 256] =
+        std::array::from_fn(|v| ((v as f32 - low as f32) * scale).round().clamp(0.0, 255.0) as u8);
+    for pixel in img.pixels_mut() {
+        for c in 0..3 {
+            pixel.0[c] = lut[pixel.0[c] as usize];
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Effect processing behind the `vidfx` command line tool.
+//!
+//! [`chain::EffectChain`] is the one representation of "what to do to a frame"
+//! used by the CLI, presets and programmatic users alike.
+
+mod ascii;
+pub mod audio;
+mod blur;
+pub mod buffer;
+pub mod chain;
+pub mod color;
+pub mod curves;
+pub mod deinterlace;
+pub mod denoise;
+pub mod displace;
+pub mod encoder;
+#[cfg(feature = "vidfx-ffi")]
+pub mod ffi;
+pub mod generate;
+mod gradient;
+mod halftone;
+pub mod hash;
+mod isf;
+pub mod levels;
+mod linear;
+mod matching;
+mod mixer;
+mod replace;
+mod ripple;
+pub mod schema;
+mod shader;
+mod shatter;
+pub mod source;
+pub mod stabilize;
+pub mod telecine;
+
+pub use chain::{Color, Effect, EffectChain};
+
+/// What an effect gets to know about the frame it is processing. More fields
+/// may come, so start from [`FrameContext::new`] and set the ones you know.
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct FrameContext {
+    pub index: usize,
+    /// Output time in seconds
+    pub time: f64,
+    pub scale_factor: f64,
+    /// Position within the current beat in 0..1, when a bpm is known
+    pub beat_phase: Option<f64>,
+    /// Beats since the start, following any tempo changes, when a bpm is
+    /// known
+    pub beats: Option<f64>,
+    pub bpm: Option<u32>,
+    pub frame_rate: f64,
+}
+
+impl FrameContext {
+    /// Frame `index`, shown at `time` seconds, unmodulated and with no bpm.
+    pub fn new(index: usize, time: f64, frame_rate: f64) -> Self {
+        FrameContext {
+            index,
+            time,
+            scale_factor: 1.0,
+            beat_phase: None,
+            beats: None,
+            bpm: None,
+            frame_rate,
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Float versions of the arithmetic effects that work in linear light, used
+//! by chains with [`EffectChain::linear`](crate::EffectChain) set.
+//!
+//! imgfx only works on 8-bit frames, and 8-bit linear light throws away most
+//! of the shadows, so these go sRGB -> float linear -> sRGB in one step.
+
+use image::{imageops, Rgb, Rgb32FImage, RgbaImage};
+
+use crate::chain::{Effect, Operands, Scaling};
+
+type Op = fn(f32, f32) -> f32;
+
+fn decode(v: u8) -> f32 {
+    let v = v as f32 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn encode(v: f32) -> u8 {
+    let v = v.clamp(0.0, 1.0);
+    let v = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (v * 255.0).round() as u8
+}
+
+/// Source channel for each of red, green and blue, e.g. `["b", "g", "r"]`.
+fn channels(names: &Option<Vec<String>>) -> [usize; 3] {
+    let mut out = [0, 1, 2];
+    for (o, name) in out.iter_mut().zip(names.iter().flatten()) {
+        *o = match name.chars().next() {
+            Some('g') => 1,
+            Some('b') => 2,
+            _ => 0,
+        };
+    }
+    out
+}
+
+/// `effect` on `img` in linear light, `None` for effects that aren't
+/// arithmetic and run as usual.
+pub fn apply(
+    effect: &Effect,
+    img: &RgbaImage,
+    scale_factor: f64,
+    scaling: &Scaling,
+) -> Option<RgbaImage> {
+    let lut: [f32; 256] = std::array::from_fn(|v| decode(v as u8));
+
+    let (color, operands, op): (_, &Operands, Op) = match effect {
+        Effect::Add { color, operands } => (color, operands, |a, c| (a + c).min(1.0)),
+        Effect::Mult { color, operands } => (color, operands, |a, c| a * c),
+        Effect::Average { color, operands } => (color, operands, |a, c| (a + c) / 2.0),
+        Effect::Screen { color, operands } => (color, operands, |a, c| 1.0 - (1.0 - a) * (1.0 - c)),
+        Effect::Bloom {
+            intensity,
+            radius,
+            min_threshold,
+            max_threshold,
+        } => {
+            return Some(bloom(
+                img,
+                *intensity,
+                *radius,
+                (*min_threshold, max_threshold.unwrap_or(255)),
+                &lut,
+            ))
+        }
+        _ => return None,
+    };
+
+    let scaled = color.scaled(scale_factor, scaling);
+    let color = [scaled.0, scaled.1, scaled.2].map(|v| lut[v as usize]);
+    let (lhs, rhs) = (channels(&operands.lhs), channels(&operands.rhs));
+
+    let mut out = img.clone();
+    for (pixel, source) in out.pixels_mut().zip(img.pixels()) {
+        for c in 0..3 {
+            pixel.0[c] = encode(op(lut[source.0[lhs[c]] as usize], color[rhs[c]]));
+        }
+    }
+    Some(out)
+}
+
+/// Pixels with a luma in `threshold` blurred by `radius` and added back on
+/// top, `intensity` times over.
+fn bloom(
+    img: &RgbaImage,
+    intensity: f32,
+    radius: f32,
+    (min, max): (u8, u8),
+    lut: &[f32; 256],
+) -> RgbaImage {
+    let bright = Rgb32FImage::from_fn(img.width(), img.height(), |x, y| {
+        let [r, g, b, _] = img.get_pixel(x, y).0;
+        let luma = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+        if (min as f32..=max as f32).contains(&luma) {
+            Rgb([r, g, b].map(|v| lut[v as usize]))
+        } else {
+            Rgb([0.0; 3])
+        }
+    });
+    let glow = imageops::blur(&bright, radius);
+
+    let mut out = img.clone();
+    for (pixel, glow) in out.pixels_mut().zip(glow.pixels()) {
+        for c in 0..3 {
+            pixel.0[c] = encode(lut[pixel.0[c] as usize] + intensity * glow.0[c]);
+        }
+    }
+    out
+}
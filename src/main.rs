@@ -1,87 +1,1042 @@
-use clap::{builder::styling::RgbColor, ArgAction, Parser, Subcommand};
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueEnum};
 use image::*;
-use std::path::Path;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use ndarray::{self, Array, Array3};
-use video_rs::decode::Decoder;
-use video_rs::encode::{Encoder, Settings};
+use serde::Serialize;
 use video_rs::time::Time;
 
-use imgfx::*;
+use vidfx::chain::{
+    FlashLength, Jitter, MatchMethod, Operands, Palette, Per, ScaleCurve, Scaling, ShatterFill,
+    Stop, DEFAULT_MAX_FLASH_RATE, MAX_SHIFT,
+};
+use vidfx::{Color, Effect, EffectChain, FrameContext};
+
+mod analysis;
+mod autocrop;
+mod cache;
+mod config;
+mod faces;
+mod gate;
+mod grain;
+mod hud;
+mod layer;
+mod markers;
+mod mask;
+mod midi;
+mod modulation;
+mod notify;
+mod onnx;
+mod output;
+mod plugin;
+mod preflight;
+mod project;
+mod quality;
+mod quantize;
+mod randomize;
+mod reframe;
+mod sequence;
+mod smooth;
+mod stats;
+mod style;
+mod sweep;
+mod swing;
+mod tempo;
+mod terminal;
+mod thumbs;
+mod transition;
+mod tui;
+mod units;
+mod upscale;
+mod validate;
+mod verify;
+mod viz;
+mod ytdlp;
+
+use cache::FrameCache;
+use faces::{FaceRegion, Region};
+use gate::Gate;
+use grain::{Grain, Response};
+use hud::Hud;
+use layer::{composite, Layer, LayerSpec, LayerStage};
+use markers::{recolor, Markers};
+use mask::{Banded, Layout, MaskModel};
+use midi::Automation;
+use modulation::Curves;
+use notify::{Completion, Outcome};
+use output::{FrameSink, OutputTarget};
+use plugin::Plugin;
+use preflight::Estimate;
+use project::Project;
+use quantize::Quantize;
+use randomize::Randomize;
+use reframe::ReframeMode;
+use sequence::{load_preset, Sequence};
+use smooth::{Smoother, Smoothing};
+use stats::{Summary, Timings};
+use style::Style;
+use swing::Swing;
+use tempo::TempoMap;
+use terminal::TermProto;
+use transition::{Sweep, Transition};
+use units::{
+    format_size, parse_aspect, parse_duration, parse_matrix, parse_multiplier, parse_origin,
+    parse_percent, parse_range, parse_rect, parse_resolution, parse_rgb, parse_size,
+};
+use upscale::Upscale;
+use vidfx::audio::Audio;
+use vidfx::color::{ColorRange, Correction};
+use vidfx::curves::{self, Curve};
+use vidfx::deinterlace::{deinterlaced, Deinterlace, Fields};
+use vidfx::denoise::denoised;
+use vidfx::encoder::{
+    image_to_ndarray, Attachment, Chapter, Codec, Delivery, EncodeSettings, Provenance,
+};
+use vidfx::generate::{burn_frame_number, burn_text, Generator, Pattern};
+use vidfx::hash::random;
+use vidfx::levels;
+use vidfx::source::{blend_frames, decode_frame, open_decoder, LoopingFrames, Source};
+use vidfx::stabilize;
+use vidfx::telecine::{self, detelecined};
+use viz::{Visualizer, VizStyle};
 
 #[derive(Subcommand)]
 enum SubCommands {
+    /// Bitwise OR each pixel with a color
     Or {
-        color: String,
+        #[command(flatten)]
+        color: ColorArgs,
     },
+    /// Bitwise AND each pixel with a color
     And {
-        color: String,
+        #[command(flatten)]
+        color: ColorArgs,
     },
+    /// Bitwise XOR each pixel with a color
     Xor {
-        color: String,
+        #[command(flatten)]
+        color: ColorArgs,
     },
+    /// Shift each channel's bits to the left
     Left {
-        bits: String,
-        raw: Option<String>,
+        /// How many bits to shift by, 0-8 [default: 1]
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=8))]
+        bits: Option<u8>,
+
+        /// Scale the shift amount with --visualization
+        #[arg(long, action = ArgAction::SetTrue)]
+        modulate: bool,
+
+        /// Run the operation in raw mode, formerly the positional `raw` argument
+        #[arg(long, action = ArgAction::SetTrue)]
+        raw: bool,
+
+        #[arg(hide = true)]
+        legacy: Vec<String>,
     },
+    /// Shift each channel's bits to the right
     Right {
-        bits: String,
-        raw: Option<String>,
+        /// How many bits to shift by, 0-8 [default: 1]
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=8))]
+        bits: Option<u8>,
+
+        /// Scale the shift amount with --visualization
+        #[arg(long, action = ArgAction::SetTrue)]
+        modulate: bool,
+
+        /// Run the operation in raw mode, formerly the positional `raw` argument
+        #[arg(long, action = ArgAction::SetTrue)]
+        raw: bool,
+
+        #[arg(hide = true)]
+        legacy: Vec<String>,
     },
+    /// Add a color to each pixel
     Add {
-        color: String,
+        #[command(flatten)]
+        color: ColorArgs,
     },
+    /// Subtract a color from each pixel
     Sub {
-        color: String,
-        raw: Option<String>,
+        #[command(flatten)]
+        color: ColorArgs,
+
+        /// Run the operation in raw mode, formerly the positional `raw` argument
+        #[arg(long, action = ArgAction::SetTrue)]
+        raw: bool,
     },
+    /// Multiply each pixel by a color
     Mult {
-        color: String,
+        #[command(flatten)]
+        color: ColorArgs,
     },
+    /// Raise each pixel to the power of a color
     Pow {
-        color: String,
+        #[command(flatten)]
+        color: ColorArgs,
     },
+    /// Divide each pixel by a color
     Div {
-        color: String,
+        #[command(flatten)]
+        color: ColorArgs,
     },
+    /// Average each pixel with a color
     Average {
-        color: String,
+        #[command(flatten)]
+        color: ColorArgs,
     },
+    /// Screen blend a color over each pixel
     Screen {
-        color: String,
+        #[command(flatten)]
+        color: ColorArgs,
     },
+    /// Overlay blend a color over each pixel
     Overlay {
-        color: String,
+        #[command(flatten)]
+        color: ColorArgs,
     },
+    /// Make bright areas glow
     Bloom {
-        intensity: f32,
-        radius: f32,
-        min_threshold: u8,
+        /// Strength of the glow [default: 1]
+        #[arg(long)]
+        intensity: Option<f32>,
+
+        /// Blur radius in pixels [default: 8]
+        #[arg(long)]
+        radius: Option<f32>,
+
+        /// Brightness (0-255) from which pixels glow [default: 100]
+        #[arg(long = "min")]
+        min_threshold: Option<u8>,
+
+        /// Brightness (0-255) above which pixels stop glowing
+        #[arg(long = "max")]
         max_threshold: Option<u8>,
+
+        #[arg(hide = true)]
+        legacy: Vec<String>,
     },
+    /// Sort runs of pixels whose brightness falls between --min and --max
     Sort {
-        direction: imgfx::sort::Direction,
-        sort_by: imgfx::sort::SortBy,
-        min_threshold: f32,
-        max_threshold: f32,
+        /// [default: horizontal]
+        #[arg(long, value_enum)]
+        direction: Option<imgfx::sort::Direction>,
+
+        /// [default: luma]
+        #[arg(long, value_enum)]
+        sort_by: Option<imgfx::sort::SortBy>,
+
+        /// Lower threshold, 0-1 [default: 0.2]
+        #[arg(long = "min")]
+        min_threshold: Option<f32>,
+
+        /// Upper threshold, 0-1 [default: 0.8]
+        #[arg(long = "max")]
+        max_threshold: Option<f32>,
+
+        #[arg(hide = true)]
+        legacy: Vec<String>,
+    },
+    /// Run a Shadertoy style GLSL fragment shader (`mainImage`) on each frame.
+    /// Built-in uniforms: iResolution, iTime, iBeat, iAudio, iFrame, iScale,
+    /// with the frame in iChannel0
+    Shader {
+        /// path/to/shader.frag
+        #[arg(long)]
+        file: String,
+
+        /// Extra float uniform. E.g. --uniform amount=0.5
+        #[arg(long)]
+        uniform: Vec<String>,
+    },
+    /// Run an Interactive Shader Format (ISF) filter on each frame, using the
+    /// defaults and ranges published in its header
+    Isf {
+        /// path/to/filter.fs
+        #[arg(long)]
+        file: String,
+
+        /// Override an input. E.g. --param amount=0.5 --param tint=ff8800
+        #[arg(long)]
+        param: Vec<String>,
+
+        /// Float input to scale with --visualization, from its MIN up to its value
+        #[arg(long)]
+        modulate: Vec<String>,
+    },
+    /// Flash a color over the frame, or invert it, on every beat or bar.
+    /// Needs --bpm
+    Strobe {
+        /// Flash color [default: ffffff]
+        #[arg(long)]
+        color: Option<String>,
+
+        /// Invert the frame instead of flashing a color
+        #[arg(long, action = ArgAction::SetTrue)]
+        invert: bool,
+
+        /// How long each flash lasts, in frames (1f) or time (50ms)
+        #[arg(long = "flash-length", default_value = "1f")]
+        flash_length: FlashLength,
+
+        /// Flash at the start of every beat or bar
+        #[arg(long, value_enum, default_value = "beat")]
+        per: Per,
+
+        /// How much of the flash shows over the frame, 0-1
+        #[arg(long, default_value_t = 1.0)]
+        mix: f32,
+
+        /// Skip flashes to stay under this many per second, for
+        /// photosensitive viewers. 0 turns the limit off
+        #[arg(long, default_value_t = DEFAULT_MAX_FLASH_RATE)]
+        max_flash_rate: f64,
+    },
+    /// Stretch levels so the darkest and brightest pixels reach black and
+    /// white, e.g. before sort or bloom on dim footage
+    Normalize {
+        /// Stretch every frame by its own levels (the default)
+        #[arg(long, action = ArgAction::SetTrue, conflicts_with = "global")]
+        per_frame: bool,
+
+        /// Stretch every frame the same, by levels from a first pass over the
+        /// part of the input that is rendered. The pass sees frames
+        /// deinterlaced, denoised and cropped as the render does, but not
+        /// stabilized
+        #[arg(long, action = ArgAction::SetTrue)]
+        global: bool,
+
+        /// How much of the darkest and brightest pixels to clip, up to 50%
+        #[arg(long, default_value = "0.5%", value_parser = parse_clip)]
+        clip: f32,
+    },
+    /// Print the frame as rotated CMYK halftone screens
+    Halftone {
+        /// Distance between dots in pixels
+        #[arg(long, default_value_t = 6.0)]
+        dot_size: f32,
+
+        /// Cyan screen angle in degrees
+        #[arg(long, default_value_t = 15.0)]
+        angle_c: f32,
+
+        /// Magenta screen angle in degrees
+        #[arg(long, default_value_t = 75.0)]
+        angle_m: f32,
+
+        /// Yellow screen angle in degrees
+        #[arg(long, default_value_t = 0.0)]
+        angle_y: f32,
+
+        /// Black screen angle in degrees
+        #[arg(long, default_value_t = 45.0)]
+        angle_k: f32,
+
+        /// Scale the dot size with --visualization, e.g. to pump on the beat
+        #[arg(long, action = ArgAction::SetTrue)]
+        modulate: bool,
+    },
+    /// Redraw the frame as a mosaic of characters
+    Ascii {
+        /// `dense`, `simple`, `digits`, or the characters to use
+        #[arg(long, default_value = "dense")]
+        charset: String,
+
+        /// Size of each character's cell in pixels
+        #[arg(long, value_parser = parse_resolution, default_value = "8x12")]
+        cell: (u32, u32),
+
+        /// Draw each character in the color of its cell rather than white
+        #[arg(long, action = ArgAction::SetTrue)]
+        color: bool,
+    },
+    /// Break the frame into Voronoi cells, for stained glass and shatter
+    /// looks
+    Shatter {
+        /// About how many cells to break the frame into
+        #[arg(long, default_value_t = 400)]
+        cells: u32,
+
+        /// What throws cell centers around. `beat` needs --bpm, `modulate`
+        /// follows --visualization
+        #[arg(long, value_enum, default_value = "none")]
+        jitter: Jitter,
+
+        /// How far --jitter throws centers, in cell sizes
+        #[arg(long, default_value_t = 0.5)]
+        amount: f32,
+
+        /// What fills each cell
+        #[arg(long, value_enum, default_value = "average")]
+        fill: ShatterFill,
+
+        /// Width of the dark lines between cells in pixels, 0 for none
+        #[arg(long, default_value_t = 1.0)]
+        edges: f32,
+    },
+    /// Push pixels around by a displacement map
+    Displace {
+        /// `noise:perlin`, or an image or video whose luma pushes pixels along
+        /// --angle. Prefix with image: or video: if the extension doesn't say
+        #[arg(long, default_value = "noise:perlin")]
+        map: String,
+
+        /// Furthest a pixel is pushed, in pixels
+        #[arg(long, default_value_t = 12.0)]
+        amount: f32,
+
+        /// Size of noise features in pixels
+        #[arg(long, default_value_t = 64.0)]
+        scale: f32,
+
+        /// How many times a second noise changes, 0 to hold it still
+        #[arg(long, default_value_t = 0.5)]
+        evolve: f32,
+
+        /// Direction image and video maps push in, in degrees
+        #[arg(long, default_value_t = 0.0)]
+        angle: f32,
+
+        /// Scale the amount with --visualization
+        #[arg(long, action = ArgAction::SetTrue)]
+        modulate: bool,
+    },
+    /// Distort the frame with sine waves spreading from a point
+    Ripple {
+        /// How far waves push pixels, in pixels
+        #[arg(long, default_value_t = 8.0)]
+        amplitude: f32,
+
+        /// Distance between waves in pixels
+        #[arg(long, default_value_t = 60.0)]
+        wavelength: f32,
+
+        /// Wavelengths the waves travel a second, negative to run inwards
+        #[arg(long, default_value_t = 1.0, allow_negative_numbers = true)]
+        speed: f32,
+
+        /// Where the waves start, `center` or x,y in fractions of the frame
+        #[arg(long, value_parser = parse_origin, default_value = "center")]
+        origin: (f32, f32),
+
+        /// Scale the amplitude with --visualization, e.g. to hit on the bass
+        #[arg(long, action = ArgAction::SetTrue)]
+        modulate: bool,
+    },
+    /// Blur the frame towards a point, as if zooming during the exposure
+    Zoomblur {
+        /// The point to blur towards, `center` or x,y in fractions of the
+        /// frame
+        #[arg(long, value_parser = parse_origin, default_value = "center")]
+        center: (f32, f32),
+
+        /// How much of each pixel's distance to the center to blur over, 0-1
+        #[arg(long, default_value_t = 0.2)]
+        amount: f32,
+
+        /// Scale the amount with --visualization
+        #[arg(long, action = ArgAction::SetTrue)]
+        modulate: bool,
+    },
+    /// Blur the frame along a direction, as if moving during the exposure
+    Motionblur {
+        /// Direction in degrees, 0 being horizontal and 90 vertical
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        angle: f32,
+
+        /// Length of the blur in pixels
+        #[arg(long, default_value_t = 12.0)]
+        length: f32,
+
+        /// Scale the length with --visualization
+        #[arg(long, action = ArgAction::SetTrue)]
+        modulate: bool,
+    },
+    /// Grade with tone curves through control points, over every channel
+    /// and then each on its own. E.g. --master "0,0 0.5,0.7 1,1"
+    Curves {
+        /// Curve for every channel, x,y points from 0 to 1
+        #[arg(long)]
+        master: Option<Curve>,
+
+        /// Curve for red
+        #[arg(long)]
+        r: Option<Curve>,
+
+        /// Curve for green
+        #[arg(long)]
+        g: Option<Curve>,
+
+        /// Curve for blue
+        #[arg(long)]
+        b: Option<Curve>,
+
+        /// Curve file with a `master`, `r`, `g` or `b` curve per line. The
+        /// flags above replace its curves
+        #[arg(long)]
+        file: Option<String>,
+    },
+    /// White balance and mix channels, e.g. before effects that are
+    /// sensitive to channel ratios
+    Mixer {
+        /// Rows for red, green and blue out, columns for red, green and blue
+        /// in. E.g. --matrix "1.1 0 0; 0 1 0; 0 0.1 0.9"
+        #[arg(long, value_parser = parse_matrix, default_value = "1 0 0; 0 1 0; 0 0 1")]
+        matrix: [[f32; 3]; 3],
+
+        /// Color temperature in kelvin the footage was lit at, corrected to
+        /// neutral
+        #[arg(long)]
+        temp: Option<f32>,
+
+        /// Shift towards magenta, or green when negative, -100 to 100
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        tint: f32,
+    },
+    /// Move pixels near one hue to another color, keeping their shading
+    ReplaceColor {
+        /// Hex color whose hue to replace. E.g. --from 00ff00
+        #[arg(long)]
+        from: String,
+
+        /// Hex color to replace it with
+        #[arg(long)]
+        to: String,
+
+        /// How far a hue can be from --from and still be replaced in full, as
+        /// a fraction of half the hue wheel
+        #[arg(long, default_value_t = 0.2)]
+        tolerance: f32,
+
+        /// How far past --tolerance the replacement fades out
+        #[arg(long, default_value_t = 0.05)]
+        feather: f32,
+
+        /// Scale both colors with --visualization on the --scale-channels
+        #[arg(long, action = ArgAction::SetTrue)]
+        modulate: bool,
+    },
+    /// Map shadows to one color and highlights to another
+    Duotone {
+        /// Hex color for black
+        #[arg(long, default_value = "0b1e3f")]
+        shadow: String,
+
+        /// Hex color for white
+        #[arg(long, default_value = "ff7a18")]
+        highlight: String,
+
+        /// Scale both colors with --visualization on the --scale-channels
+        #[arg(long, action = ArgAction::SetTrue)]
+        modulate: bool,
+    },
+    /// Map luma through a ramp of colors. E.g. --stop 0:000000 --stop
+    /// 0.5:ff0055 --stop 1:ffffff
+    GradientMap {
+        /// A color at a luma from 0 to 1, as at:color
+        #[arg(long, required = true)]
+        stop: Vec<Stop>,
+
+        /// Scale every color with --visualization on the --scale-channels
+        #[arg(long, action = ArgAction::SetTrue)]
+        modulate: bool,
+    },
+    /// Map luma through a scientific or thermal palette, or exposure bands
+    /// for checking levels
+    Falsecolor {
+        #[arg(long, value_enum, default_value = "inferno")]
+        palette: Palette,
+    },
+    /// Match each frame's colors to a reference image or clip, so clips
+    /// effected apart cut together
+    MatchColor {
+        /// Image or video to match, e.g. look.png or ref.mp4. Videos are
+        /// sampled across their length
+        #[arg(long)]
+        reference: String,
+
+        #[arg(long, value_enum, default_value = "histogram")]
+        method: MatchMethod,
+
+        /// How far colors move onto the reference's, 0-1
+        #[arg(long, default_value_t = 1.0)]
+        amount: f32,
+
+        /// Scale the amount with --visualization
+        #[arg(long, action = ArgAction::SetTrue)]
+        modulate: bool,
+    },
+    /// Tune effect parameters interactively on frames of the input
+    Tui,
+    /// Render a short sample of every combination of parameter values. E.g.
+    /// --param bloom.intensity=0.5,1,2 --param bloom.radius=4,8
+    Sweep {
+        /// effect.flag=value,value,...
+        #[arg(long, required = true)]
+        param: Vec<String>,
+
+        /// Directory for one output per combination
+        #[arg(long, default_value = "sweep")]
+        dir: String,
+
+        /// Write a single video with every combination tiled in a grid instead
+        #[arg(long)]
+        tile: Option<String>,
+    },
+    /// Export a thumbnail sprite sheet and WebVTT file for web player hover
+    /// previews
+    Thumbs {
+        /// Time between thumbnails
+        #[arg(long, value_parser = parse_duration, default_value = "2s")]
+        interval: f64,
+
+        /// Thumbnail width in pixels, height follows the aspect ratio
+        #[arg(long, default_value_t = 160)]
+        width: u32,
+
+        /// Thumbnails per row of the sprite sheet
+        #[arg(long, default_value_t = 10)]
+        columns: u32,
+
+        /// Sprite sheet image to write
+        #[arg(long, default_value = "thumbs.jpg")]
+        sprite: String,
+
+        /// WebVTT file to write
+        #[arg(long, default_value = "thumbs.vtt")]
+        vtt: String,
+    },
+    /// Estimate depth in every frame with an ONNX model and apply different
+    /// effects to what is near and what is far
+    Depthfx {
+        /// path/to/model.onnx, a MiDaS-style monocular depth model
+        #[arg(long)]
+        model: String,
+
+        /// Side of the square frames the model takes
+        #[arg(long, default_value_t = 256)]
+        model_size: u32,
+
+        /// Effect for the near band as on the command line, e.g. bloom or
+        /// "sort --min 40", or none
+        #[arg(long, default_value = "none")]
+        near_effect: String,
+
+        /// Effect for the far band, as --near-effect
+        #[arg(long, default_value = "none")]
+        far_effect: String,
+
+        /// Nearness from 0 (furthest) to 1 (nearest) where near begins
+        #[arg(long, default_value_t = 0.5)]
+        split: f32,
+
+        /// Width of the blend between the bands, in nearness
+        #[arg(long, default_value_t = 0.1)]
+        softness: f32,
+    },
+    /// Neural style transfer on every frame with an ONNX model. Runs on the
+    /// CPU
+    Style {
+        /// path/to/model.onnx, e.g. the model zoo's mosaic or candy
+        #[arg(long)]
+        model: String,
+
+        /// Side of the square frames the model takes
+        #[arg(long, default_value_t = 224)]
+        model_size: u32,
+
+        /// How much of the stylized frame shows over the original, 0 to 1
+        #[arg(long, default_value_t = 0.7)]
+        strength: f32,
+
+        /// How much of the previous stylized frame to blend in, 0 to 1, to
+        /// calm the flicker between frames
+        #[arg(long, default_value_t = 0.0)]
+        temporal: f32,
+    },
+    /// Find people in every frame with an ONNX segmentation model and apply
+    /// one effect to them and another to the background
+    Segmentfx {
+        /// path/to/model.onnx, e.g. MediaPipe's selfie segmentation
+        #[arg(long)]
+        model: String,
+
+        /// Side of the square frames the model takes
+        #[arg(long, default_value_t = 256)]
+        model_size: u32,
+
+        /// How the model takes its input
+        #[arg(long, value_enum, default_value = "nhwc")]
+        layout: Layout,
+
+        /// Effect for the people as on the command line, e.g. bloom or
+        /// "sort --min 40", or none
+        #[arg(long, default_value = "none")]
+        subject: String,
+
+        /// Effect for everything else, as --subject
+        #[arg(long, default_value = "none")]
+        background: String,
+
+        /// Probability from 0 to 1 above which a pixel is a person
+        #[arg(long, default_value_t = 0.5)]
+        threshold: f32,
+
+        /// Width of the blend at the edge of the mask, in probability
+        #[arg(long, default_value_t = 0.2)]
+        softness: f32,
+    },
+    /// Steady shaky footage in a first pass over the input. Combine with
+    /// --sequence or --markers for effects in the same render
+    Stabilize {
+        /// Frames either side of each one the camera's path is averaged over.
+        /// Higher is steadier
+        #[arg(long, default_value_t = 20)]
+        smoothness: usize,
+    },
+    /// Enlarge frames after every effect, e.g. to deliver low resolution
+    /// sources at 1080p or 4K. Combine with --sequence or --markers for
+    /// effects in the same pass
+    Upscale {
+        /// How many times larger frames come out
+        #[arg(long, value_parser = parse_multiplier, default_value = "2")]
+        factor: f64,
+
+        /// path/to/model.onnx for a Real-ESRGAN-style super resolution
+        /// model, or lanczos to resample
+        #[arg(long, default_value = "lanczos")]
+        model: String,
+    },
+    /// Convert the input to another aspect ratio, e.g. landscape to vertical
+    /// for social. Combine with --sequence or --markers for effects in the
+    /// same pass
+    Reframe {
+        /// Aspect ratio to convert to. E.g. 9:16
+        #[arg(long, value_parser = parse_aspect, default_value = "9:16")]
+        aspect: (u32, u32),
+
+        #[arg(long, value_enum, default_value = "crop")]
+        mode: ReframeMode,
+    },
+    /// Synthesize frames from an audio track instead of reading --input
+    Viz {
+        /// path/to/track.wav, or any format ffmpeg decodes
+        #[arg(long)]
+        audio: String,
+
+        #[arg(long, value_enum, default_value = "bars")]
+        style: VizStyle,
+    },
+    /// Render a .vidfx project file bundling the input, outputs, effect chain
+    /// and settings. Flags given here override the project's
+    Render {
+        /// path/to/project.vidfx
+        file: String,
+    },
+    /// Run a preset over a video, write the result next to it with an _fx
+    /// suffix and open it once done
+    Quick {
+        /// Preset name in the preset dir, or path/to/preset.json
+        preset: String,
+        /// path/to/input/video
+        file: String,
+    },
+    /// Check project and preset files for unknown effects and fields, out of
+    /// range parameters and missing files
+    Validate {
+        /// Project or preset files
+        #[arg(required = true)]
+        files: Vec<String>,
     },
+    /// Manage project and preset files
+    Project {
+        #[command(subcommand)]
+        action: ProjectCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProjectCommands {
+    /// Rewrite a project or preset file to the current format version
+    Upgrade {
+        /// path/to/project.vidfx or preset.json
+        file: String,
+    },
+}
+
+/// Subcommands that run a tool instead of applying an effect to each frame.
+const TOOL_COMMANDS: &[&str] = &[
+    "tui",
+    "sweep",
+    "thumbs",
+    "depthfx",
+    "segmentfx",
+    "style",
+    "stabilize",
+    "reframe",
+    "upscale",
+    "render",
+    "quick",
+    "validate",
+    "project",
+];
+
+/// `--color` for the arithmetic and logic effects.
+#[derive(clap::Args)]
+struct ColorArgs {
+    /// Hex color to combine each pixel with. E.g. --color ff0000
+    #[arg(long)]
+    color: Option<String>,
+
+    /// Positional form from before effect parameters were flags
+    #[arg(hide = true)]
+    legacy: Vec<String>,
 }
 
+/// `--duration` of generated inputs and `sweep` samples when none is given.
+const DEFAULT_DURATION: f64 = 2.0;
+
 #[derive(Parser)]
 #[command(name = "vidfx")]
 #[command(version = "0.0.2")]
 #[command(about = "Implementation of imgfx for videos", long_about = None)]
+// Defaults, project settings and the command line can all give a flag
+#[command(args_override_self = true)]
 struct Args {
     #[command(subcommand)]
     cmd: SubCommands,
 
-    /// path/to/input/image
-    #[arg(short, long)]
-    input: String,
+    /// path/to/input/video, a network url (https://, rtsp://, an .m3u8
+    /// playlist), ytdlp:<url> for anything yt-dlp can find the video in, or a
+    /// test pattern: generate:plasma, generate:noise,
+    /// generate:gradient, generate:smpte-bars, generate:zone-plate or
+    /// generate:solid:#112233
+    #[arg(short, long, global = true)]
+    input: Option<String>,
+
+    /// Length of a generated input, or of each `sweep` sample [default: 2s]
+    #[arg(long, value_parser = parse_duration, global = true)]
+    duration: Option<f64>,
+
+    /// Frame rate of generated frames (`generate:` inputs and `viz`)
+    #[arg(long, default_value_t = 30.0, global = true)]
+    fps: f64,
+
+    /// Size of generated frames (`generate:` inputs and `viz`). E.g. 1280x720
+    #[arg(long, value_parser = parse_resolution, default_value = "1920x1080", global = true)]
+    resolution: (u32, u32),
+
+    /// Where to write the render: a file, an .m3u8 or .mpd playlist of
+    /// segments, a stream url (rtmp://...) or `preview` for a window. Repeat
+    /// to write several at once.
+    /// [default: output.mp4]
+    #[arg(long, value_parser = OutputTarget::parse)]
+    output: Vec<OutputTarget>,
+
+    /// Save the processed frames at these times as PNGs while rendering.
+    /// E.g. --snapshot 0:05,0:37,1:12
+    #[arg(long, value_delimiter = ',', value_parser = parse_duration)]
+    snapshot: Vec<f64>,
+
+    /// Where --snapshot saves its stills
+    #[arg(long, default_value = "snapshots")]
+    snapshot_dir: String,
+
+    /// Show frames while rendering, in a window or in the terminal
+    #[arg(long, value_enum)]
+    preview: Option<PreviewMode>,
+
+    /// Ask before starting renders estimated to come to more than this.
+    /// [default when given: 2GB]
+    #[arg(long, value_parser = parse_size, num_args = 0..=1, default_missing_value = "2GB")]
+    confirm: Option<u64>,
+
+    /// Start even when --target-size says the output disk is too full. Sizes
+    /// guessed from the bit rate only warn
+    #[arg(long, action=ArgAction::SetTrue)]
+    ignore_disk_space: bool,
+
+    /// Count the frames written on stderr while rendering
+    #[arg(long, action=ArgAction::SetTrue)]
+    progress: bool,
+
+    /// Report progress on stderr as one JSON object per line, with the frame,
+    /// total, pct, fps and eta in seconds. Takes over from --progress
+    #[arg(long, action=ArgAction::SetTrue)]
+    progress_json: bool,
+
+    /// Show a desktop notification when the render finishes or fails
+    #[arg(long, action=ArgAction::SetTrue)]
+    notify: bool,
+
+    /// Shell command to run when the render finishes or fails. {output} is
+    /// replaced by the outputs and {status} by `done`, `interrupted`,
+    /// `out-of-space` or `failed`.
+    /// E.g. --on-complete "cp {output} ~/Dropbox"
+    #[arg(long)]
+    on_complete: Option<String>,
+
+    /// How `--preview term` draws frames
+    #[arg(long, value_enum, default_value = "ansi")]
+    term_proto: TermProto,
+
+    /// Video codec. Defaults to h264, vp9 for .webm and dnxhr for .mxf outputs.
+    /// Use ffv1 (.mkv), prores (.mov) or dnxhr to avoid generation loss in an edit
+    #[arg(long, value_enum, env = "VIDFX_DEFAULT_CODEC")]
+    codec: Option<Codec>,
+
+    /// Quantization range of encoded outputs, which are always BT.709
+    #[arg(long, value_enum, default_value = "limited")]
+    color_range: ColorRange,
+
+    /// Encode for a platform: h264 with the bit rate, GOP and flags it
+    /// expects, in limited range so it isn't washed out after upload
+    #[arg(long, value_enum)]
+    target: Option<Delivery>,
+
+    /// Move the mp4/mov index to the front once the render is done, so the
+    /// file plays while still downloading
+    #[arg(long, action = ArgAction::SetTrue, conflicts_with = "fragmented")]
+    faststart: bool,
+
+    /// Write mp4/mov as fragments that play on their own, so an interrupted
+    /// render's .part file still plays up to the last keyframe
+    #[arg(long, action = ArgAction::SetTrue)]
+    fragmented: bool,
+
+    /// Length of each segment of .m3u8 (HLS) and .mpd (DASH) outputs
+    #[arg(long, value_parser = parse_duration, default_value = "4s")]
+    segment_duration: f64,
+
+    /// Detect black letterbox and pillarbox bars across the input and crop
+    /// them off before any effect runs
+    #[arg(long, action=ArgAction::SetTrue)]
+    autocrop: bool,
+
+    /// Measure the whole input in a first pass, so any flag can take a
+    /// statistic of the clip as its value: p<N>_global luma percentiles,
+    /// black_global, white_global, mean_global, red/green/blue_global and
+    /// motion_global, all 0-1, scaled by a trailing *<N>. E.g. sort --min
+    /// black_global or bloom --min p95_global*255
+    #[arg(long, action = ArgAction::SetTrue)]
+    analyze_first_pass: bool,
+
+    /// Smooth noise within each frame before any effect runs, so thresholds
+    /// don't sparkle. Roughly the noise's size in levels, e.g. 2
+    #[arg(long, default_value_t = 0.0)]
+    denoise_spatial: f32,
+
+    /// Smooth noise from frame to frame before any effect runs, as
+    /// --denoise-spatial. Moving parts are left alone
+    #[arg(long, default_value_t = 0.0)]
+    denoise_temporal: f32,
+
+    /// Lay film grain over every frame after the effects, from 0 to 1, e.g.
+    /// 0.3. Follows --seed
+    #[arg(long, default_value_t = 0.0)]
+    grain: f32,
+
+    /// Size of a --grain grain in pixels
+    #[arg(long, default_value_t = 1.5)]
+    grain_size: f32,
+
+    /// How --grain strength follows brightness
+    #[arg(long, value_enum, default_value = "film")]
+    grain_response: Response,
+
+    /// Strength of --grain in red, green and blue
+    #[arg(long, value_parser = parse_rgb, default_value = "1,1,1")]
+    grain_channels: [f32; 3],
+
+    /// Deinterlace the input before any effect runs. `auto` uses yadif on
+    /// sources flagged as interlaced
+    #[arg(long, value_enum, default_value = "auto")]
+    deinterlace: Deinterlace,
 
-    /// path/to/output/image
-    #[arg(long, default_value = ".")]
-    output: Option<String>,
+    /// Undo 3:2 pulldown, turning 29.97fps film transfers back into their
+    /// 23.976 original frames so beats line up with the real cadence
+    #[arg(long, action = ArgAction::SetTrue)]
+    detelecine: bool,
+
+    /// Run add, mult, average, screen and bloom on linear light instead of
+    /// sRGB values, so blends behave physically and bloom doesn't band in the
+    /// shadows
+    #[arg(long, action = ArgAction::SetTrue)]
+    linear: bool,
+
+    /// Only let the scale factor drive these channels of the effects'
+    /// colors, the rest stay at full value. E.g. --scale-channels r to pulse
+    /// only red
+    #[arg(long, value_parser = Scaling::parse_channels)]
+    scale_channels: Option<[bool; 3]>,
+
+    /// Remap the scale factor before it reaches the effects
+    #[arg(long, value_enum)]
+    scale_curve: Option<ScaleCurve>,
+
+    /// Encoder threads [default: chosen by ffmpeg]
+    #[arg(long, env = "VIDFX_THREADS")]
+    threads: Option<usize>,
+
+    /// Directory for relative --output files and the default output.mp4
+    #[arg(long, env = "VIDFX_OUTPUT_DIR")]
+    output_dir: Option<String>,
+
+    /// Two-pass encode file outputs to roughly this size. E.g. --target-size 8MB
+    #[arg(long, value_parser = parse_target_size)]
+    target_size: Option<u64>,
+
+    /// Write the render's timings, fps, peak memory and output sizes to this
+    /// JSON file
+    #[arg(long)]
+    summary_json: Option<String>,
+
+    /// Write per-frame PSNR/SSIM between the frames sent to the encoder and
+    /// the encoded output to this JSON file
+    #[arg(long)]
+    quality_report: Option<String>,
+
+    /// Draw each frame's number in the top left corner of the output, to check
+    /// frame timing end to end
+    #[arg(long, action = ArgAction::SetTrue)]
+    burn_frame_numbers: bool,
+
+    /// Draw the scale factor, beat phase, audio level and every effect's
+    /// current parameter values over each output frame, for tuning modulation
+    #[arg(long, action = ArgAction::SetTrue)]
+    debug_overlay: bool,
+
+    /// Instead of rendering, write every frame's scale factor, beat phase,
+    /// audio level and modulated effect parameters to this CSV file. Needs
+    /// --duration for looped inputs
+    #[arg(long)]
+    dump_modulation: Option<String>,
+
+    /// Also plot the --dump-modulation curves to this PNG
+    #[arg(long, requires = "dump_modulation")]
+    modulation_plot: Option<String>,
+
+    /// Instead of rendering, process the first N frames twice and check that
+    /// both runs produce the same bytes
+    #[arg(long)]
+    verify_deterministic: Option<usize>,
+
+    /// Threads for the second --verify-deterministic run. Can't be combined
+    /// with --plugin
+    #[arg(long, default_value_t = 1, requires = "verify_deterministic")]
+    verify_threads: usize,
+
+    /// Replace the output file if it already exists
+    #[arg(long, action=ArgAction::SetTrue, conflicts_with = "no_overwrite")]
+    overwrite: bool,
+
+    /// Refuse to replace an existing output file (the default)
+    #[arg(long, action=ArgAction::SetTrue)]
+    no_overwrite: bool,
 
     #[arg(short, long, default_value = "default")]
     visualization: String,
@@ -89,6 +1044,199 @@ struct Args {
     #[arg(short, long)]
     bpm: Option<u32>,
 
+    /// Beats per oscillator cycle: 2 runs the visualization at half time,
+    /// 0.5 at double time and 4 once a bar
+    #[arg(long, value_parser = parse_multiplier, default_value = "1")]
+    beat_div: f64,
+
+    /// Where in each cycle the wave turns around, 50% leaves it as it is.
+    /// Low values snap up and decay slowly like a pluck. E.g. --wave-skew 10%
+    #[arg(long, value_parser = parse_skew, default_value = "50%")]
+    wave_skew: f64,
+
+    /// Raise the wave to this power: above 1 narrows its peaks into pulses,
+    /// below 1 widens them. E.g. --wave-exp 3
+    #[arg(long, value_parser = parse_multiplier, default_value = "1")]
+    wave_exp: f64,
+
+    /// Fold the bottom half of the wave up, so it peaks twice a cycle
+    #[arg(long, action=ArgAction::SetTrue)]
+    wave_rectify: bool,
+
+    /// Flip the oscillator upside down, so effects peak on the off-beat
+    /// rather than the downbeat. Invert single --map entries with a - instead
+    #[arg(long, action=ArgAction::SetTrue)]
+    mod_invert: bool,
+
+    /// Squeeze the oscillator into this range rather than all the way from 0
+    /// to 1, for shallower modulation. E.g. --mod-range 0.3..1.0
+    #[arg(long, value_parser = parse_range, default_value = "0..1")]
+    mod_range: (f64, f64),
+
+    /// Added to the oscillator after --mod-range. E.g. --mod-bias 0.1
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    mod_bias: f64,
+
+    /// Loop the input until the output is this long. E.g. --loop-to 3m
+    #[arg(long, value_parser = parse_duration)]
+    loop_to: Option<f64>,
+
+    /// Crossfade length at each loop seam. E.g. --loop-crossfade 500ms
+    #[arg(long, value_parser = parse_duration, requires = "loop_to")]
+    loop_crossfade: Option<f64>,
+
+    /// Smooth every modulation source (oscillator, markers, MIDI, randomize)
+    /// before it drives anything, attack:release or one time for both. E.g.
+    /// --smooth 120ms or --smooth 10ms:300ms
+    #[arg(long, value_parser = Smoothing::parse)]
+    smooth: Option<Smoothing>,
+
+    /// Shuffle the beat grid the oscillators, gates and --quantize follow: how
+    /// much of each beat its first eighth takes. E.g. --swing 56%
+    #[arg(long, value_parser = Swing::parse, default_value = "50%")]
+    swing: Swing,
+
+    /// Sample and hold every modulation source on a beat subdivision, e.g.
+    /// --quantize 1/8, or 1/8t for triplets. Needs --bpm
+    #[arg(long, value_parser = Quantize::parse)]
+    quantize: Option<Quantize>,
+
+    /// Follow a changing tempo from a `time,bpm` CSV, each tempo holding
+    /// until the next row. --bpm, if given, only sets the grid's reference
+    #[arg(long, conflicts_with = "bpm_ramp")]
+    tempo_map: Option<String>,
+
+    /// Glide the tempo between two bpms over a stretch of the render, e.g.
+    /// --bpm-ramp 120..140@0:00-3:00
+    #[arg(long, value_parser = TempoMap::parse_ramp)]
+    bpm_ramp: Option<TempoMap>,
+
+    /// Only process this region of each frame, x,y,width,height in pixels,
+    /// leaving the rest untouched. E.g. --roi 0,540,960,540
+    #[arg(long, value_parser = parse_rect)]
+    roi: Option<(u32, u32, u32, u32)>,
+
+    /// Only process faces, or everything but faces, found in each frame by
+    /// --face-model
+    #[arg(long, value_enum, requires = "face_model")]
+    region: Option<Region>,
+
+    /// path/to/detector.onnx for --region, an UltraFace-style face detector
+    #[arg(long)]
+    face_model: Option<String>,
+
+    /// Pixels over which --region fades between processed and untouched
+    #[arg(long, default_value_t = 20.0)]
+    feather: f32,
+
+    /// Detector score from 0 to 1 above which --region takes it for a face
+    #[arg(long, default_value_t = 0.7)]
+    face_confidence: f32,
+
+    /// Run the effects for some beats out of every few, beats:on. E.g. --gate 4:1
+    /// for the first beat of every four. Needs --bpm
+    #[arg(long, value_parser = Gate::parse_ratio, conflicts_with = "gate_pattern")]
+    gate: Option<Gate>,
+
+    /// Step sequencer gate, a step per beat. E.g. --gate-pattern x..x..x.
+    #[arg(long, value_parser = Gate::parse_pattern)]
+    gate_pattern: Option<Gate>,
+
+    /// Steps per beat for --gate-pattern, e.g. 4 for sixteenth notes
+    #[arg(long, default_value_t = 1.0, requires = "gate_pattern")]
+    steps_per_beat: f64,
+
+    /// Presets to cycle through on the beat, after the subcommand's effect.
+    /// `clean` is no effect. E.g. --sequence acid,clean,clean,invert. Needs --bpm
+    #[arg(long)]
+    sequence: Option<String>,
+
+    /// Give effect parameters a new random value in a range each step,
+    /// effect.field=min..max. E.g. --randomize "bloom.radius=2..16". Needs --bpm
+    #[arg(long)]
+    randomize: Option<String>,
+
+    /// Seed for --randomize, --grain and the sample-hold and drift
+    /// visualizations, the same seed gives the same values
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// How long each --sequence or --randomize step lasts
+    #[arg(long, value_enum, default_value = "beat")]
+    per: Per,
+
+    /// Cue points that switch presets (preset:<name>), recolor the effect
+    /// (color:<hex>) or fire an envelope (hit, hit:<decay>): Audacity labels,
+    /// a DaVinci Resolve EDL or time,label CSV
+    #[arg(long)]
+    markers: Option<String>,
+
+    /// Standard MIDI file to drive effect parameters from, through --map
+    #[arg(long, requires = "map")]
+    midi_file: Option<String>,
+
+    /// What --midi-file drives: cc<n>:effect.field[=min..max] or
+    /// note:<key>=envelope, a - before the target inverting it. E.g. --map
+    /// "cc1:bloom.intensity, note:C1=envelope"
+    #[arg(long, requires = "midi_file")]
+    map: Option<String>,
+
+    /// Where --sequence and --markers look for <name>.json effect chain presets
+    #[arg(long, default_value = "presets", env = "VIDFX_PRESET_DIR")]
+    preset_dir: String,
+
+    /// Video to --transition to from --input
+    #[arg(long, requires = "transition")]
+    input2: Option<String>,
+
+    /// Grayscale matte wiping from --input to --input2, dark areas first: an
+    /// image, or a generated linear, radial or diagonal wipe
+    #[arg(long, requires = "input2")]
+    transition: Option<String>,
+
+    /// When the --transition starts, and --input2 with it
+    #[arg(long, value_parser = parse_duration, default_value = "0")]
+    transition_at: f64,
+
+    /// How long the --transition takes
+    #[arg(long, value_parser = parse_duration, default_value = "1s")]
+    transition_duration: f64,
+
+    /// What moves the --transition along
+    #[arg(long, value_enum, default_value = "timeline")]
+    transition_sweep: Sweep,
+
+    /// Composite another video over the input, path[:blend][:opacity] plus
+    /// pos=x,y, size=WxH, scale=s and rotate=degrees, each animated with
+    /// --visualization when given as a~b. Repeat to stack several, bottom
+    /// first. E.g. --layer top.mp4:screen:0.7 --layer pip.mp4:pos=1600,60~1600,20
+    #[arg(long, value_parser = LayerSpec::parse)]
+    layer: Vec<LayerSpec>,
+
+    /// Whether --layer goes under the effects or over their result
+    #[arg(long, value_enum, default_value = "before")]
+    layer_stage: LayerStage,
+
+    /// Only run the effects on every Nth frame, for heavy effects
+    #[arg(long, default_value_t = 1)]
+    stride: usize,
+
+    /// What the frames in between show with --stride
+    #[arg(long, value_enum, default_value = "hold")]
+    fill: Fill,
+
+    /// Keep processed frames here and reuse them when rendering the same input
+    /// with the same settings again. Not used with --region or style
+    /// --temporal, which carry over from frame to frame and so need every
+    /// frame rendered in order
+    #[arg(long, env = "VIDFX_CACHE_DIR")]
+    cache_dir: Option<String>,
+
+    /// Memory for buffered frames (e.g. a long --loop-crossfade) before they
+    /// spill to a temporary file. E.g. --max-memory 2G
+    #[arg(long, value_parser = parse_size, env = "VIDFX_MAX_MEMORY")]
+    max_memory: Option<u64>,
+
     /// Specify the left hand side operands for the function. E.g. --lhs b g r
     #[arg(long, num_args(1..), global = true)]
     lhs: Option<Vec<String>>,
@@ -97,13 +1245,28 @@ struct Args {
     #[arg(long, num_args(1..), global = true)]
     rhs: Option<Vec<String>>,
 
-    /// If function is 'left' or 'right', how many bits to shift by.
-    #[arg(short, long)]
-    bit_shift: Option<u8>,
+    /// Run an effect plugin (see include/vidfx_plugin.h) after the subcommand.
+    /// Repeat to chain several.
+    #[arg(long, global = true)]
+    plugin: Vec<String>,
+
+    /// key=value parameter passed to every plugin. E.g. --plugin-param amount=0.5
+    #[arg(long, global = true, requires = "plugin")]
+    plugin_param: Vec<String>,
 
     /// Negate the logical operator
     #[arg(short, long, action=ArgAction::SetTrue, global = true)]
     negate: bool,
+
+    /// Deprecated spelling of left and right --bits
+    #[arg(long, hide = true, global = true, value_parser = clap::value_parser!(u8).range(0..=8))]
+    bit_shift: Option<u8>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum PreviewMode {
+    Window,
+    Term,
 }
 
 enum WaveType {
@@ -111,18 +1274,125 @@ enum WaveType {
     Saw,
     Square,
     Triangle,
+    /// A new random level every beat
+    SampleHold {
+        seed: u64,
+    },
+    /// A random walk between 0 and 1, gliding to its next step every beat
+    Drift {
+        seed: u64,
+    },
+}
+
+/// Largest move a `Drift` step makes.
+const DRIFT_STEP: f64 = 0.25;
+
+/// Random stream of the oscillator waves, kept apart from the parameters
+/// `--randomize` draws for with the same seed.
+const WAVE_STREAM: u64 = u64::MAX;
+
+/// Steps between the `Drift` levels kept, the furthest a walk is replayed.
+const DRIFT_CHECKPOINT: u64 = 256;
+
+/// `Drift` levels by seed and step, every `DRIFT_CHECKPOINT` steps.
+static DRIFTS: Mutex<BTreeMap<(u64, u64), f64>> = Mutex::new(BTreeMap::new());
+
+/// Where a `Drift` walk is after `step` steps, bouncing off 0 and 1.
+fn drift(seed: u64, step: u64) -> f64 {
+    let mut drifts = DRIFTS.lock().expect("Drift levels poisoned");
+    let (from, level) = drifts
+        .range((seed, 0)..=(seed, step))
+        .next_back()
+        .map_or((0, 0.5), |(&(_, from), &level)| (from, level));
+    (from..step).fold(level, |level, i| {
+        let level = level + (random(seed, i, WAVE_STREAM) * 2.0 - 1.0) * DRIFT_STEP;
+        let level = if level < 0.0 {
+            -level
+        } else if level > 1.0 {
+            2.0 - level
+        } else {
+            level
+        };
+        if (i + 1) % DRIFT_CHECKPOINT == 0 {
+            drifts.insert((seed, i + 1), level);
+        }
+        level
+    })
+}
+
+/// Bends applied to the stock waves, `--wave-skew`, `--wave-exp` and
+/// `--wave-rectify`, then the `--mod-range` and `--mod-bias` they end up in.
+struct WaveShape {
+    /// Fraction of the cycle the first half of the wave takes
+    skew: f64,
+    exponent: f64,
+    rectify: bool,
+    invert: bool,
+    range: (f64, f64),
+    bias: f64,
+}
+
+impl WaveShape {
+    fn progress(&self, progress: f64) -> f64 {
+        if progress < self.skew {
+            0.5 * progress / self.skew
+        } else {
+            0.5 + 0.5 * (progress - self.skew) / (1.0 - self.skew)
+        }
+    }
+
+    fn level(&self, level: f64) -> f64 {
+        let level = if self.rectify {
+            (2.0 * level - 1.0).abs()
+        } else {
+            level
+        };
+        let level = if self.invert { 1.0 - level } else { level };
+        let (min, max) = self.range;
+        let level = level.clamp(0.0, 1.0).powf(self.exponent);
+        (min + (max - min) * level + self.bias).max(0.0)
+    }
+}
+
+fn parse_target_size(s: &str) -> Result<u64, String> {
+    match parse_size(s)? {
+        0 => Err(format!("target size '{}' leaves no room for any frames", s)),
+        size => Ok(size),
+    }
+}
+
+/// Percent clipped at each end by `normalize`, where past 50% the ends meet.
+fn parse_clip(s: &str) -> Result<f32, String> {
+    let percent = parse_percent(s)?;
+    if percent > 50.0 {
+        return Err(format!("clip '{}' is above 50%", s));
+    }
+    Ok(percent)
+}
+
+fn parse_skew(s: &str) -> Result<f64, String> {
+    let percent = parse_percent(s)?;
+    if !(1.0..=99.0).contains(&percent) {
+        return Err(format!("skew '{}' is outside 1%..99%", s));
+    }
+    Ok(percent as f64 / 100.0)
 }
 
 enum VisualizationMode {
     Default,
-    Osc { bpm: u32, wave_type: WaveType },
+    Osc {
+        bpm: u32,
+        wave_type: WaveType,
+        shape: WaveShape,
+    },
 }
 
-fn bpm_scale_factor(bpm: u32, wave_type: &WaveType, current_time: f64) -> f64 {
+fn bpm_scale_factor(bpm: u32, wave_type: &WaveType, shape: &WaveShape, current_time: f64) -> f64 {
     let beat_duration = 60.0 / bpm as f64;
-    let beat_progress = (current_time % beat_duration) / beat_duration;
+    let beat = (current_time / beat_duration).floor().max(0.0) as u64;
+    let beat_progress = shape.progress((current_time % beat_duration) / beat_duration);
 
-    match wave_type {
+    let level = match wave_type {
         WaveType::Sine => (beat_progress * std::f64::consts::PI * 2.0).sin() * 0.5 + 0.5,
         WaveType::Saw => (1f64 - beat_progress) as f64,
         WaveType::Square => {
@@ -133,289 +1403,1921 @@ fn bpm_scale_factor(bpm: u32, wave_type: &WaveType, current_time: f64) -> f64 {
             }
         }
         WaveType::Triangle => 1.0 - (2.0 * beat_progress - 1.0).abs(),
-    }
-}
+        WaveType::SampleHold { seed } => random(*seed, beat, WAVE_STREAM),
+        WaveType::Drift { seed } => {
+            let (from, to) = (drift(*seed, beat), drift(*seed, beat + 1));
+            from + (to - from) * beat_progress
+        }
+    };
+    shape.level(level)
+}
 
-fn process_video<F>(
-    decoder: &mut Decoder,
-    frame_processor: F,
+/// Where each output frame sits in time and on the beat.
+struct Clock {
     frame_rate: f64,
-    frame_width: u32,
-    frame_height: u32,
     visualization_mode: VisualizationMode,
-) -> Vec<RgbaImage>
+    /// The steady tempo, or the reference the tempo map is measured against
+    bpm: Option<u32>,
+    /// Beats per oscillator cycle
+    beat_div: f64,
+    swing: Swing,
+    tempo: Option<TempoMap>,
+}
+
+impl Clock {
+    /// Where `time` falls on a straight grid at a steady `bpm`, with the
+    /// tempo map's changes and then the swing taken out. Anything that
+    /// counts beats works in this time.
+    fn grid_time(&self, time: f64, bpm: u32) -> f64 {
+        let steady = match &self.tempo {
+            Some(tempo) => tempo.beats(time) * 60.0 / bpm as f64,
+            None => time,
+        };
+        self.swing.straighten(steady, bpm)
+    }
+
+    /// The output time of a point on the grid, undoing [`Clock::grid_time`].
+    fn real_time(&self, grid_time: f64, bpm: u32) -> f64 {
+        let steady = self.swing.swing(grid_time, bpm);
+        match &self.tempo {
+            Some(tempo) => tempo.time_at(steady * bpm as f64 / 60.0),
+            None => steady,
+        }
+    }
+
+    fn context(&self, index: usize) -> FrameContext {
+        let time = index as f64 / self.frame_rate;
+        let scale_factor = match &self.visualization_mode {
+            VisualizationMode::Default => 1.0,
+            VisualizationMode::Osc {
+                bpm,
+                wave_type,
+                shape,
+            } => bpm_scale_factor(
+                *bpm,
+                wave_type,
+                shape,
+                self.grid_time(time, *bpm) / self.beat_div,
+            ),
+        };
+
+        let mut context = FrameContext::new(index, time, self.frame_rate);
+        context.scale_factor = scale_factor;
+        context.beat_phase = self.bpm.map(|bpm| {
+            let beat_duration = 60.0 / bpm as f64;
+            (self.grid_time(time, bpm) % beat_duration) / beat_duration
+        });
+        context.beats = self.bpm.map(|bpm| match &self.tempo {
+            Some(tempo) => tempo.beats(time),
+            None => time * bpm as f64 / 60.0,
+        });
+        context.bpm = match &self.tempo {
+            Some(tempo) => Some(tempo.bpm_at(time).round() as u32),
+            None => self.bpm,
+        };
+        context
+    }
+}
+
+/// What frames skipped by `--stride` show.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+enum Fill {
+    /// Repeat the last processed frame
+    Hold,
+    /// Crossfade between the processed frames on either side
+    Blend,
+}
+
+/// Run the effects on every `every`th frame only.
+struct Stride {
+    every: usize,
+    fill: Fill,
+}
+
+/// Everything that affects processed frames, hashed for `--cache-dir`.
+/// Processed frame indices don't depend on --stride, so it isn't part of it.
+#[derive(Serialize)]
+struct CacheKey<'a> {
+    source: &'a str,
+    chain: &'a EffectChain,
+    linear: bool,
+    scale_channels: Option<[bool; 3]>,
+    scale_curve: Option<ScaleCurve>,
+    deinterlace: Deinterlace,
+    detelecine: bool,
+    autocrop: bool,
+    denoise_spatial: f32,
+    denoise_temporal: f32,
+    plugin: &'a [String],
+    plugin_param: &'a [String],
+    visualization: &'a str,
+    bpm: Option<u32>,
+    beat_div: f64,
+    wave_skew: f64,
+    wave_exp: f64,
+    wave_rectify: bool,
+    mod_invert: bool,
+    mod_range: (f64, f64),
+    mod_bias: f64,
+    seed: u64,
+    fps: f64,
+    resolution: (u32, u32),
+    duration: Option<f64>,
+    loop_to: Option<f64>,
+    loop_crossfade: Option<f64>,
+    burn_frame_numbers: bool,
+    debug_overlay: bool,
+    roi: Option<(u32, u32, u32, u32)>,
+    smooth: Option<Smoothing>,
+    quantize: Option<Quantize>,
+    swing: Swing,
+    tempo: Option<&'a TempoMap>,
+    gate: Option<&'a Gate>,
+    sequence: Option<&'a [EffectChain]>,
+    per: Per,
+    randomize: Option<&'a Randomize>,
+    markers: Option<String>,
+    automation: Option<&'a Automation>,
+    layer: &'a [LayerSpec],
+    layer_stage: LayerStage,
+    input2: Option<&'a str>,
+    transition: Option<&'a str>,
+    transition_at: f64,
+    transition_duration: f64,
+    transition_sweep: Sweep,
+    region: Option<Region>,
+    face_model: Option<String>,
+    feather: f32,
+    face_confidence: f32,
+    grain: f32,
+    grain_size: f32,
+    grain_response: Response,
+    grain_channels: [f32; 3],
+}
+
+/// The file `path` as it stands in a cache key, with its size and
+/// modification time so an input re-exported or a model retrained in place
+/// doesn't hit frames rendered from the old one.
+fn file_key(path: &str) -> String {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return path.to_string();
+    };
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_nanos());
+    format!("{} ({} bytes, modified {})", path, metadata.len(), modified)
+}
+
+/// Runs `effect` on the `roi` part of `img` only and pastes the result back
+/// over the untouched frame. The region is clipped to the frame.
+fn in_region(
+    img: DynamicImage,
+    roi: Option<(u32, u32, u32, u32)>,
+    effect: impl FnOnce(DynamicImage) -> RgbaImage,
+) -> RgbaImage {
+    let Some((x, y, width, height)) = roi else {
+        return effect(img);
+    };
+
+    let mut full = img.into_rgba8();
+    let x = x.min(full.width().saturating_sub(1));
+    let y = y.min(full.height().saturating_sub(1));
+    let width = width.min(full.width() - x);
+    let height = height.min(full.height() - y);
+
+    let region = imageops::crop_imm(&full, x, y, width, height).to_image();
+    let processed = effect(DynamicImage::ImageRgba8(region));
+    imageops::replace(&mut full, &processed, x as i64, y as i64);
+    full
+}
+
+/// `chain` with this frame's `--midi-file` and `--randomize` values, if any.
+fn automated<'a>(
+    chain: &'a EffectChain,
+    automation: &Option<Automation>,
+    randomize: &Option<(Randomize, u32)>,
+    per: Per,
+    frame: &FrameContext,
+) -> Cow<'a, EffectChain> {
+    let chain = match automation {
+        Some(automation) => Cow::Owned(automation.chain(chain, frame.time)),
+        None => Cow::Borrowed(chain),
+    };
+    match randomize {
+        Some((randomize, _)) => {
+            let step = frame.beats.map_or(0, |beats| per.step_at(beats));
+            Cow::Owned(randomize.chain(&chain, step))
+        }
+        None => chain,
+    }
+}
+
+/// `arg` as it would be typed into a POSIX shell.
+fn shell_quoted(arg: &str) -> String {
+    let special = |c: char| c.is_whitespace() || "'\"$\\`*?;&|<>()".contains(c);
+    if arg.is_empty() || arg.contains(special) {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    } else {
+        arg.to_string()
+    }
+}
+
+/// `<stem>_fx.<ext>` next to `input` for `vidfx quick`, numbered on from
+/// `_fx_2` rather than replacing an earlier result.
+fn quick_output(input: &str) -> PathBuf {
+    let input = Path::new(input);
+    let stem = input
+        .file_stem()
+        .expect("Failed to read input file name")
+        .to_string_lossy();
+    let ext = input
+        .extension()
+        .map_or("mp4".into(), |ext| ext.to_string_lossy());
+    (1..)
+        .map(|n| match n {
+            1 => input.with_file_name(format!("{}_fx.{}", stem, ext)),
+            n => input.with_file_name(format!("{}_fx_{}.{}", stem, n, ext)),
+        })
+        .find(|path| !path.exists())
+        .expect("Some name is free")
+}
+
+/// Opens `path` with the system's default application.
+fn open_in_viewer(path: &Path) {
+    let mut command = if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        std::process::Command::new("xdg-open")
+    };
+    if let Err(e) = command.arg(path).spawn() {
+        eprintln!("Failed to open {}: {}", path.display(), e);
+    }
+}
+
+/// The processed frame as the sinks get it, with `--burn-frame-numbers` and
+/// the `--debug-overlay` lines applied last so they stay legible whatever the
+/// effects do.
+fn finish_frame(
+    processed: RgbaImage,
+    frame: &FrameContext,
+    burn_frame_numbers: bool,
+    overlay: Option<Vec<String>>,
+) -> DynamicImage {
+    if !burn_frame_numbers && overlay.is_none() {
+        return DynamicImage::ImageRgba8(processed);
+    }
+
+    let mut rgb = DynamicImage::ImageRgba8(processed).into_rgb8();
+    let (_, top) = if burn_frame_numbers {
+        burn_frame_number(&mut rgb, frame.index)
+    } else {
+        (0, 0)
+    };
+    if let Some(lines) = overlay {
+        burn_text(&mut rgb, (0, top), &lines);
+    }
+    DynamicImage::ImageRgb8(rgb)
+}
+
+/// Decoded `frames` matched up into whole frames, by --detelecine and
+/// --deinterlace, with how many times the frame rate that leaves them at.
+fn progressive<'a>(
+    frames: Box<dyn Iterator<Item = RgbImage> + 'a>,
+    args: &Args,
+    fields: Fields,
+) -> (f64, Box<dyn Iterator<Item = RgbImage> + 'a>) {
+    let (rate, frames) = if args.detelecine {
+        // Telecined sources are flagged interlaced, but there's nothing left
+        // to deinterlace once the fields are matched up
+        let frames = detelecined(frames, fields == Fields::BottomFirst);
+        let frames = match args.deinterlace {
+            Deinterlace::Auto => frames,
+            mode => deinterlaced(frames, mode, fields),
+        };
+        (telecine::RATE, frames)
+    } else {
+        (1.0, deinterlaced(frames, args.deinterlace, fields))
+    };
+    (rate, frames)
+}
+
+/// Processes and encodes frames as they come out of `frames`. Stops early once
+/// `cancelled` is set, so the caller can still finalize whatever was written.
+/// Returns the number of frames encoded.
+fn process_video<F>(
+    mut frames: impl Iterator<Item = RgbImage>,
+    sinks: &mut [Box<dyn FrameSink>],
+    frame_processor: F,
+    clock: &Clock,
+    stride: &Stride,
+    cancelled: &AtomicBool,
+    timings: &mut Timings,
+) -> usize
 where
-    F: Fn(DynamicImage, f64) -> DynamicImage,
+    F: Fn(DynamicImage, &FrameContext) -> DynamicImage,
 {
-    let mut processed = vec![];
-    let mut current_time = 0.0;
-
-    for frame in decoder.decode_iter() {
-        if let Ok((_, frame)) = frame {
-            let scale_factor = match &visualization_mode {
-                VisualizationMode::Default => 1.0,
-                VisualizationMode::Osc { bpm, wave_type } => {
-                    bpm_scale_factor(*bpm, wave_type, current_time)
-                }
-            };
+    let frame_interval = 1.0 / clock.frame_rate;
 
-            let rgb = frame
-                .slice(ndarray::s![.., .., 0..3])
-                .to_slice()
-                .expect("Failed to slice frame into rgb array");
+    let mut frames_written = 0;
+    let mut position = Time::zero();
+    let mut encode_time = std::time::Duration::ZERO;
+    let mut emit = |frame: &RgbImage| {
+        let started = Instant::now();
+        let frame = image_to_ndarray(frame);
+        for sink in sinks.iter_mut() {
+            sink.write(&frame, position);
+        }
+        frames_written += 1;
+        position = Time::from_secs_f64(position.as_secs_f64() + frame_interval);
+        encode_time += started.elapsed();
+    };
 
-            let img = ImageBuffer::from_raw(frame_width, frame_height, rgb.to_vec())
-                .expect("Failed to convert ndarray to ImageBuffer");
+    let mut last: Option<RgbImage> = None;
+    let mut skipped = 0;
 
-            let processed_frame = frame_processor(DynamicImage::ImageRgb8(img), scale_factor);
+    for index in 0.. {
+        if cancelled.load(Ordering::SeqCst) {
+            break;
+        }
+        let started = Instant::now();
+        let Some(img) = frames.next() else {
+            break;
+        };
+        timings.decode += started.elapsed();
 
-            let output = processed_frame.into_rgba8();
+        if index % stride.every != 0 {
+            match (stride.fill, &last) {
+                (Fill::Hold, Some(last)) => emit(last),
+                _ => skipped += 1,
+            }
+            continue;
+        }
 
-            processed.push(output);
+        let started = Instant::now();
+        let processed_frame = frame_processor(DynamicImage::ImageRgb8(img), &clock.context(index));
+        let rgb_image = rgba_to_rgb(&processed_frame.into_rgba8());
+        timings.effects += started.elapsed();
 
-            current_time = current_time + 1.0 / frame_rate as f64;
-        } else {
-            break;
+        if let Some(last) = &last {
+            for i in 0..skipped {
+                let t = (i + 1) as f32 / (skipped + 1) as f32;
+                emit(&blend_frames(last, &rgb_image, t));
+            }
         }
+        skipped = 0;
+
+        emit(&rgb_image);
+        last = Some(rgb_image);
     }
 
-    processed
+    // Frames after the last processed one have nothing to blend towards
+    if let Some(last) = &last {
+        for _ in 0..skipped {
+            emit(last);
+        }
+    }
+
+    timings.encode += encode_time;
+    frames_written
 }
 
-fn scaled_color(rgb: (u8, u8, u8), scale_factor: f64) -> RgbColor {
-    RgbColor(
-        (rgb.0 as f64 * scale_factor) as u8,
-        (rgb.1 as f64 * scale_factor) as u8,
-        (rgb.2 as f64 * scale_factor) as u8,
-    )
+/// Fills in effect parameters that weren't given as flags from the deprecated
+/// positional arguments, in their old order.
+struct Legacy<'a> {
+    command: &'static str,
+    positional: std::slice::Iter<'a, String>,
+    warned: bool,
 }
 
-fn process_subcommand(
-    cmd: &SubCommands,
-    img: DynamicImage,
-    lhs: &Option<Vec<String>>,
-    rhs: &Option<Vec<String>>,
-    negate: bool,
-    scale_factor: f64,
-) -> RgbaImage {
-    match cmd {
-        SubCommands::Or { color } => {
-            let rgb = hex_to_rgb(color).expect("Could not convert color to rgb");
-            or(
-                img,
-                lhs.clone(),
-                rhs.clone(),
-                scaled_color(rgb, scale_factor),
-                negate,
-            )
+impl<'a> Legacy<'a> {
+    fn new(command: &'static str, positional: &'a [String]) -> Self {
+        Legacy {
+            command,
+            positional: positional.iter(),
+            warned: false,
         }
-        SubCommands::And { color } => {
-            let rgb = hex_to_rgb(color).expect("Could not convert color to rgb");
-            and(
-                img,
-                lhs.clone(),
-                rhs.clone(),
-                scaled_color(rgb, scale_factor),
-                negate,
-            )
+    }
+
+    fn param<T: Clone>(
+        &mut self,
+        flag: &Option<T>,
+        name: &str,
+        parse: impl Fn(&str) -> Option<T>,
+    ) -> Option<T> {
+        if flag.is_some() {
+            return flag.clone();
         }
-        SubCommands::Xor { color } => {
-            let rgb = hex_to_rgb(color).expect("Could not convert color to rgb");
-            xor(
-                img,
-                lhs.clone(),
-                rhs.clone(),
-                scaled_color(rgb, scale_factor),
-                negate,
-            )
+
+        let value = self.positional.next()?;
+        if !self.warned {
+            eprintln!(
+                "warning: positional arguments to `{}` are deprecated, see `vidfx {} --help` for the flags",
+                self.command, self.command
+            );
+            self.warned = true;
         }
-        SubCommands::Add { color } => {
-            let rgb = hex_to_rgb(color).expect("Could not convert color to rgb");
-            add(
-                img,
-                lhs.clone(),
-                rhs.clone(),
-                scaled_color(rgb, scale_factor),
+        Some(parse(value).unwrap_or_else(|| {
+            panic!(
+                "Could not parse {} arg '{}' of {}",
+                name, value, self.command
             )
-        }
-        SubCommands::Sub { color, raw } => {
-            let rgb = hex_to_rgb(color).expect("Could not convert color to rgb");
-            let raw_flag = matches!(raw.as_deref(), Some("raw"));
-            sub(
-                img,
-                lhs.clone(),
-                rhs.clone(),
-                scaled_color(rgb, scale_factor),
-                raw_flag,
+        }))
+    }
+
+    fn parsed<T: Clone + std::str::FromStr>(&mut self, flag: &Option<T>, name: &str) -> Option<T> {
+        self.param(flag, name, |value| value.parse().ok())
+    }
+
+    fn color(&mut self, color: &ColorArgs) -> Color {
+        let hex = self
+            .parsed(&color.color, "color")
+            .unwrap_or_else(|| panic!("No --color provided to {}!", self.command));
+        Color::hex(&hex)
+    }
+
+    fn bits(&mut self, flag: &Option<u8>) -> u8 {
+        self.param(flag, "bits", |value| {
+            value.parse().ok().filter(|bits| *bits <= MAX_SHIFT)
+        })
+        .unwrap_or(1)
+    }
+
+    /// `--raw`, or the literal `raw` that followed the old positional arguments.
+    /// Anything else there is an error rather than quietly not raw.
+    fn raw(&mut self, flag: bool) -> bool {
+        let flag = if flag { Some(true) } else { None };
+        self.param(&flag, "raw", |value| (value == "raw").then_some(true))
+            .unwrap_or(false)
+    }
+}
+
+impl SubCommands {
+    /// The chain step for an effect subcommand, `None` for tools like `tui`.
+    fn to_effect(
+        &self,
+        lhs: &Option<Vec<String>>,
+        rhs: &Option<Vec<String>>,
+        negate: bool,
+    ) -> Option<Effect> {
+        let operands = Operands {
+            lhs: lhs.clone(),
+            rhs: rhs.clone(),
+        };
+
+        let effect = match self {
+            SubCommands::Or { color } => Effect::Or {
+                color: Legacy::new("or", &color.legacy).color(color),
+                operands,
+                negate,
+            },
+            SubCommands::And { color } => Effect::And {
+                color: Legacy::new("and", &color.legacy).color(color),
+                operands,
+                negate,
+            },
+            SubCommands::Xor { color } => Effect::Xor {
+                color: Legacy::new("xor", &color.legacy).color(color),
+                operands,
+                negate,
+            },
+            SubCommands::Add { color } => Effect::Add {
+                color: Legacy::new("add", &color.legacy).color(color),
+                operands,
+            },
+            SubCommands::Sub { color, raw } => {
+                let mut legacy = Legacy::new("sub", &color.legacy);
+                Effect::Sub {
+                    color: legacy.color(color),
+                    operands,
+                    raw: legacy.raw(*raw),
+                }
+            }
+            SubCommands::Mult { color } => Effect::Mult {
+                color: Legacy::new("mult", &color.legacy).color(color),
+                operands,
+            },
+            SubCommands::Pow { color } => Effect::Pow {
+                color: Legacy::new("pow", &color.legacy).color(color),
+                operands,
+            },
+            SubCommands::Div { color } => Effect::Div {
+                color: Legacy::new("div", &color.legacy).color(color),
+                operands,
+            },
+            SubCommands::Left {
+                bits,
+                modulate,
+                raw,
+                legacy,
+            } => {
+                let mut legacy = Legacy::new("left", legacy);
+                Effect::Left {
+                    bits: legacy.bits(bits),
+                    lhs: lhs.clone(),
+                    raw: legacy.raw(*raw),
+                    modulate: *modulate,
+                }
+            }
+            SubCommands::Right {
+                bits,
+                modulate,
+                raw,
+                legacy,
+            } => {
+                let mut legacy = Legacy::new("right", legacy);
+                Effect::Right {
+                    bits: legacy.bits(bits),
+                    lhs: lhs.clone(),
+                    raw: legacy.raw(*raw),
+                    modulate: *modulate,
+                }
+            }
+            SubCommands::Average { color } => Effect::Average {
+                color: Legacy::new("average", &color.legacy).color(color),
+                operands,
+            },
+            SubCommands::Screen { color } => Effect::Screen {
+                color: Legacy::new("screen", &color.legacy).color(color),
+                operands,
+            },
+            SubCommands::Overlay { color } => Effect::Overlay {
+                color: Legacy::new("overlay", &color.legacy).color(color),
+                operands,
+            },
+            SubCommands::Bloom {
+                intensity,
+                radius,
+                min_threshold,
+                max_threshold,
+                legacy,
+            } => {
+                let mut legacy = Legacy::new("bloom", legacy);
+                Effect::Bloom {
+                    intensity: legacy.parsed(intensity, "intensity").unwrap_or(1.0),
+                    radius: legacy.parsed(radius, "radius").unwrap_or(8.0),
+                    min_threshold: legacy.parsed(min_threshold, "min").unwrap_or(100),
+                    max_threshold: legacy.parsed(max_threshold, "max"),
+                }
+            }
+            SubCommands::Sort {
+                direction,
+                sort_by,
+                min_threshold,
+                max_threshold,
+                legacy,
+            } => {
+                let mut legacy = Legacy::new("sort", legacy);
+                Effect::Sort {
+                    direction: legacy
+                        .param(direction, "direction", |v| {
+                            imgfx::sort::Direction::from_str(v, true).ok()
+                        })
+                        .unwrap_or_else(|| {
+                            imgfx::sort::Direction::from_str("horizontal", true)
+                                .expect("horizontal is a sort direction")
+                        }),
+                    sort_by: legacy
+                        .param(sort_by, "sort_by", |v| {
+                            imgfx::sort::SortBy::from_str(v, true).ok()
+                        })
+                        .unwrap_or_else(|| {
+                            imgfx::sort::SortBy::from_str("luma", true).expect("luma is a sort key")
+                        }),
+                    min_threshold: legacy.parsed(min_threshold, "min").unwrap_or(0.2),
+                    max_threshold: legacy.parsed(max_threshold, "max").unwrap_or(0.8),
+                }
+            }
+            SubCommands::Shader { file, uniform } => Effect::Shader {
+                file: file.clone(),
+                uniforms: uniform.clone(),
+            },
+            SubCommands::Isf {
+                file,
+                param,
+                modulate,
+            } => Effect::Isf {
+                file: file.clone(),
+                params: param.clone(),
+                modulate: modulate.clone(),
+            },
+            SubCommands::Strobe {
+                color,
+                invert,
+                flash_length,
+                per,
+                mix,
+                max_flash_rate,
+            } => Effect::Strobe {
+                color: color.as_deref().map_or(Color(255, 255, 255), Color::hex),
+                invert: *invert,
+                duration: *flash_length,
+                per: *per,
+                mix: *mix,
+                max_flash_rate: *max_flash_rate,
+            },
+            SubCommands::Normalize { global, clip, .. } => Effect::Normalize {
+                clip: *clip,
+                global: *global,
+                levels: None,
+            },
+            SubCommands::Halftone {
+                dot_size,
+                angle_c,
+                angle_m,
+                angle_y,
+                angle_k,
+                modulate,
+            } => Effect::Halftone {
+                dot_size: *dot_size,
+                angles: [*angle_c, *angle_m, *angle_y, *angle_k],
+                modulate: *modulate,
+            },
+            SubCommands::Ascii {
+                charset,
+                cell,
+                color,
+            } => Effect::Ascii {
+                charset: charset.clone(),
+                cell: [cell.0, cell.1],
+                color: *color,
+            },
+            SubCommands::Shatter {
+                cells,
+                jitter,
+                amount,
+                fill,
+                edges,
+            } => Effect::Shatter {
+                cells: *cells,
+                jitter: *jitter,
+                amount: *amount,
+                fill: *fill,
+                edges: *edges,
+            },
+            SubCommands::Displace {
+                map,
+                amount,
+                scale,
+                evolve,
+                angle,
+                modulate,
+            } => Effect::Displace {
+                map: map.clone(),
+                amount: *amount,
+                scale: *scale,
+                evolve: *evolve,
+                angle: *angle,
+                modulate: *modulate,
+            },
+            SubCommands::Ripple {
+                amplitude,
+                wavelength,
+                speed,
+                origin,
+                modulate,
+            } => Effect::Ripple {
+                amplitude: *amplitude,
+                wavelength: *wavelength,
+                speed: *speed,
+                origin: [origin.0, origin.1],
+                modulate: *modulate,
+            },
+            SubCommands::Zoomblur {
+                center,
+                amount,
+                modulate,
+            } => Effect::Zoomblur {
+                center: [center.0, center.1],
+                amount: *amount,
+                modulate: *modulate,
+            },
+            SubCommands::Motionblur {
+                angle,
+                length,
+                modulate,
+            } => Effect::Motionblur {
+                angle: *angle,
+                length: *length,
+                modulate: *modulate,
+            },
+            SubCommands::Curves {
+                master,
+                r,
+                g,
+                b,
+                file,
+            } => {
+                let [file_master, file_r, file_g, file_b] = match file {
+                    Some(file) => curves::load(Path::new(file)).unwrap_or_else(|e| panic!("{}", e)),
+                    None => Default::default(),
+                };
+                Effect::Curves {
+                    master: master.clone().unwrap_or(file_master),
+                    r: r.clone().unwrap_or(file_r),
+                    g: g.clone().unwrap_or(file_g),
+                    b: b.clone().unwrap_or(file_b),
+                }
+            }
+            SubCommands::Mixer { matrix, temp, tint } => Effect::Mixer {
+                matrix: *matrix,
+                temperature: *temp,
+                tint: *tint,
+            },
+            SubCommands::ReplaceColor {
+                from,
+                to,
+                tolerance,
+                feather,
+                modulate,
+            } => Effect::ReplaceColor {
+                from: Color::hex(from),
+                to: Color::hex(to),
+                tolerance: *tolerance,
+                feather: *feather,
+                modulate: *modulate,
+            },
+            SubCommands::Duotone {
+                shadow,
+                highlight,
+                modulate,
+            } => Effect::GradientMap {
+                stops: vec![
+                    Stop {
+                        at: 0.0,
+                        color: Color::hex(shadow),
+                    },
+                    Stop {
+                        at: 1.0,
+                        color: Color::hex(highlight),
+                    },
+                ],
+                modulate: *modulate,
+            },
+            SubCommands::GradientMap { stop, modulate } => Effect::GradientMap {
+                stops: stop.clone(),
+                modulate: *modulate,
+            },
+            SubCommands::Falsecolor { palette } => Effect::Falsecolor { palette: *palette },
+            SubCommands::MatchColor {
+                reference,
+                method,
+                amount,
+                modulate,
+            } => Effect::MatchColor {
+                reference: reference.clone(),
+                method: *method,
+                amount: *amount,
+                modulate: *modulate,
+            },
+            SubCommands::Tui
+            | SubCommands::Sweep { .. }
+            | SubCommands::Thumbs { .. }
+            | SubCommands::Viz { .. }
+            | SubCommands::Depthfx { .. }
+            | SubCommands::Segmentfx { .. }
+            | SubCommands::Style { .. }
+            | SubCommands::Stabilize { .. }
+            | SubCommands::Reframe { .. }
+            | SubCommands::Upscale { .. }
+            | SubCommands::Render { .. }
+            | SubCommands::Quick { .. }
+            | SubCommands::Validate { .. }
+            | SubCommands::Project { .. } => return None,
+        };
+
+        Some(effect)
+    }
+}
+
+fn main() {
+    let started = Instant::now();
+    // Defaults from .vidfx.toml go first so the command line can override them
+    let defaults = config::default_args();
+    let layers = vec![defaults.clone(), std::env::args().skip(1).collect()];
+    let args = Args::parse_from(analysis::resolve(
+        std::iter::once("vidfx".to_string())
+            .chain(config::layered(Args::command(), layers))
+            .collect(),
+    ));
+
+    // A project's settings go in between
+    let (args, project_chain, project_file) = match &args.cmd {
+        SubCommands::Render { file } => {
+            let project = Project::load(file);
+            let layers = vec![
+                defaults,
+                project.args(file),
+                std::env::args().skip(1).collect(),
+            ];
+            let argv = std::iter::once("vidfx".to_string())
+                .chain(config::layered(Args::command(), layers))
+                .collect();
+            (
+                Args::try_parse_from(analysis::resolve(argv)).unwrap_or_else(|e| e.exit()),
+                Some(project.chain()),
+                Some(file.clone()),
             )
         }
-        SubCommands::Mult { color } => {
-            let rgb = hex_to_rgb(color).expect("Could not convert color to rgb");
-            mult(
-                img,
-                lhs.clone(),
-                rhs.clone(),
-                scaled_color(rgb, scale_factor),
-            )
+        SubCommands::Quick { preset, file } => {
+            let quick = vec![
+                "--input".to_string(),
+                file.clone(),
+                "--output".to_string(),
+                quick_output(file).to_string_lossy().into_owned(),
+                "--overwrite".to_string(),
+                "--progress".to_string(),
+            ];
+            let layers = vec![defaults, quick, std::env::args().skip(1).collect()];
+            let argv = std::iter::once("vidfx".to_string())
+                .chain(config::layered(Args::command(), layers))
+                .collect();
+            let args = Args::try_parse_from(analysis::resolve(argv)).unwrap_or_else(|e| e.exit());
+            let chain = load_preset(preset, &args.preset_dir);
+            (args, Some(chain), None)
         }
-        SubCommands::Pow { color } => {
-            let rgb = hex_to_rgb(color).expect("Could not convert color to rgb");
-            pow(
-                img,
-                lhs.clone(),
-                rhs.clone(),
-                scaled_color(rgb, scale_factor),
-            )
+        _ => (args, None, None),
+    };
+
+    let mut args = args;
+    if let Some(shift) = args.bit_shift {
+        eprintln!("warning: --bit-shift is deprecated, use --bits after left or right");
+        match &mut args.cmd {
+            SubCommands::Left { bits, .. } | SubCommands::Right { bits, .. } => {
+                bits.get_or_insert(shift);
+            }
+            _ => eprintln!("--bit-shift only applies to left and right, ignoring it"),
         }
-        SubCommands::Div { color } => {
-            let rgb = hex_to_rgb(color).expect("Could not convert color to rgb");
-            div(
-                img,
-                lhs.clone(),
-                rhs.clone(),
-                scaled_color(rgb, scale_factor),
-            )
+    }
+
+    let in_path = args
+        .input
+        .clone()
+        .map(|input| match input.strip_prefix(ytdlp::PREFIX) {
+            Some(url) => ytdlp::resolve(url),
+            None => input,
+        });
+    let input = || in_path.as_deref().expect("No --input provided!");
+
+    match &args.cmd {
+        SubCommands::Tui => {
+            video_rs::init().expect("Failed to init video_rs");
+            tui::run(input());
+            return;
         }
-        SubCommands::Left { bits, raw } | SubCommands::Right { bits, raw } => {
-            let raw_flag = matches!(raw.as_deref(), Some("raw"));
-            let direction = if matches!(cmd, SubCommands::Left { .. }) {
-                BitshiftDirection::LEFT
-            } else {
-                BitshiftDirection::RIGHT
-            };
-            let bit_shift = bits.parse::<u8>().expect("Could not parse bits arg to u8");
-            bitshift(img, direction, lhs.clone(), bit_shift, raw_flag)
-        }
-        SubCommands::Average { color } => {
-            let rgb = hex_to_rgb(color).expect("Could not convert color to rgb");
-            average(
-                img,
-                lhs.clone(),
-                rhs.clone(),
-                scaled_color(rgb, scale_factor),
-            )
+        SubCommands::Sweep { param, dir, tile } => {
+            video_rs::init().expect("Failed to init video_rs");
+            sweep::run(
+                input(),
+                param,
+                args.duration.unwrap_or(DEFAULT_DURATION),
+                dir,
+                tile.as_deref(),
+                &args,
+            );
+            return;
         }
-        SubCommands::Screen { color } => {
-            let rgb = hex_to_rgb(color).expect("Could not convert color to rgb");
-            screen(
-                img,
-                lhs.clone(),
-                rhs.clone(),
-                scaled_color(rgb, scale_factor),
-            )
+        SubCommands::Thumbs {
+            interval,
+            width,
+            columns,
+            sprite,
+            vtt,
+        } => {
+            video_rs::init().expect("Failed to init video_rs");
+            thumbs::run(input(), *interval, *width, (*columns).max(1), sprite, vtt);
+            return;
         }
-        SubCommands::Overlay { color } => {
-            let rgb = hex_to_rgb(color).expect("Could not convert color to rgb");
-            overlay(
-                img,
-                lhs.clone(),
-                rhs.clone(),
-                scaled_color(rgb, scale_factor),
-            )
+        SubCommands::Validate { files } => {
+            if !validate::run(files) {
+                std::process::exit(1);
+            }
+            return;
+        }
+        SubCommands::Project {
+            action: ProjectCommands::Upgrade { file },
+        } => {
+            project::upgrade(file);
+            return;
         }
-        SubCommands::Bloom {
-            intensity,
-            radius,
-            min_threshold,
-            max_threshold,
-        } => imgfx::bloom(img, *intensity, *radius, *min_threshold, *max_threshold),
+        _ => {}
+    }
 
-        SubCommands::Sort {
-            direction,
-            sort_by,
-            min_threshold,
-            max_threshold,
-        } => sort(
-            Into::into(img),
-            *direction,
-            *sort_by,
-            *min_threshold * scale_factor as f32,
-            *max_threshold * scale_factor as f32,
-        ),
+    let mut outputs = if args.output.is_empty() {
+        vec![OutputTarget::File("output.mp4".into())]
+    } else {
+        args.output
+    };
+    if let Some(dir) = &args.output_dir {
+        for output in &mut outputs {
+            if let OutputTarget::File(path) = output {
+                if path.is_relative() {
+                    *path = Path::new(dir).join(&*path);
+                }
+            }
+        }
     }
-}
+    match args.preview {
+        Some(PreviewMode::Window) => outputs.push(OutputTarget::Preview),
+        Some(PreviewMode::Term) => outputs.push(OutputTarget::Terminal(args.term_proto)),
+        None => {}
+    }
+    let negate = args.negate;
 
-fn main() {
-    let args = Args::parse();
+    let completion = Completion {
+        notify: args.notify,
+        on_complete: args.on_complete.clone(),
+        outputs: outputs.iter().map(|output| output.to_string()).collect(),
+    };
+    completion.watch_failures();
 
-    let in_path = args.input;
-    let out_path = args.output.unwrap_or("output.mp4".to_string());
-    let negate = args.negate;
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let out_of_space = Arc::new(AtomicBool::new(false));
+    {
+        let cancelled = Arc::clone(&cancelled);
+        ctrlc::set_handler(move || cancelled.store(true, Ordering::SeqCst))
+            .expect("Failed to install Ctrl-C handler");
+    }
+
+    for output in &outputs {
+        if let OutputTarget::File(path) = output {
+            if path.exists() && (args.no_overwrite || !args.overwrite) {
+                panic!(
+                    "{} already exists, pass --overwrite to replace it",
+                    path.display()
+                );
+            }
+        }
+    }
 
     video_rs::init().expect("Failed to init video_rs");
-    let mut decoder =
-        video_rs::Decoder::new(Path::new(&in_path)).expect("Failed to create decoder");
+    let mut decoder = None;
+    let mut source_duration = None;
 
-    let (width, height) = decoder.size();
-    let frame_rate = decoder.frame_rate();
+    let (width, height, frame_rate, frames): (
+        u32,
+        u32,
+        f64,
+        Box<dyn Iterator<Item = RgbImage> + '_>,
+    ) = match &args.cmd {
+        SubCommands::Viz { audio, style } => {
+            let audio = Audio::load(Path::new(audio)).expect("Failed to decode audio");
+            source_duration = Some(audio.duration());
+            let visualizer = Visualizer::new(audio, *style, args.resolution, args.fps);
+            (
+                args.resolution.0,
+                args.resolution.1,
+                args.fps,
+                Box::new(visualizer),
+            )
+        }
+        _ if input().starts_with("generate:") => {
+            let pattern =
+                Pattern::parse(&input()["generate:".len()..]).unwrap_or_else(|e| panic!("{}", e));
+            let duration = args.duration.unwrap_or(DEFAULT_DURATION);
+            source_duration = Some(duration);
+            let generator = Generator::new(pattern, args.resolution, args.fps, duration);
+            (
+                args.resolution.0,
+                args.resolution.1,
+                args.fps,
+                Box::new(generator),
+            )
+        }
+        _ => {
+            let decoder = decoder.insert(open_decoder(input()).expect("Failed to create decoder"));
+            let (width, height) = decoder.size();
+            let frame_rate = decoder.frame_rate() as f64;
+            source_duration = decoder.duration().ok().map(|time| time.as_secs_f64());
 
-    let bpm = args.bpm;
+            let frames: Box<dyn Iterator<Item = RgbImage> + '_> = match args.loop_to {
+                Some(loop_to) => Box::new(LoopingFrames::new(
+                    decoder,
+                    (loop_to * frame_rate).round() as usize,
+                    (args.loop_crossfade.unwrap_or(0.0) * frame_rate).round() as usize,
+                    args.max_memory,
+                )),
+                None => Box::new(std::iter::from_fn(move || decode_frame(decoder))),
+            };
+            let frames = match Correction::probe(Path::new(input())) {
+                Some(correction) => Box::new(frames.map(move |mut frame| {
+                    correction.apply(&mut frame);
+                    frame
+                })),
+                None => frames,
+            };
+            let (rate, frames) = progressive(frames, &args, Fields::probe(Path::new(input())));
+            (width, height, frame_rate * rate, frames)
+        }
+    };
+    let frames = denoised(frames, args.denoise_spatial, args.denoise_temporal);
+    let frames = match &args.cmd {
+        SubCommands::Stabilize { smoothness } => {
+            eprintln!("Analyzing motion of {}", input());
+            let corrections = stabilize::analyze(Path::new(input()), *smoothness)
+                .expect("Failed to analyze input motion");
+            // Detelecined frames each stand for 1/RATE source frames
+            let step = if args.detelecine {
+                1.0 / telecine::RATE
+            } else {
+                1.0
+            };
+            stabilize::stabilized(frames, corrections, step)
+        }
+        _ => frames,
+    };
+    let crop = if !args.autocrop {
+        None
+    } else if matches!(args.cmd, SubCommands::Viz { .. })
+        || input().starts_with("generate:")
+        || input().contains("://")
+    {
+        eprintln!("Only files can be autocropped, keeping the whole frame");
+        None
+    } else {
+        autocrop::detect(Path::new(input()))
+    };
+    let (width, height, frames): (u32, u32, Box<dyn Iterator<Item = RgbImage> + '_>) = match crop {
+        Some((x, y, crop_width, crop_height)) => {
+            eprintln!(
+                "Cropping bars off {}x{} to {}x{} at {},{}",
+                width, height, crop_width, crop_height, x, y
+            );
+            (
+                crop_width,
+                crop_height,
+                Box::new(frames.map(move |frame| {
+                    imageops::crop_imm(&frame, x, y, crop_width, crop_height).to_image()
+                })),
+            )
+        }
+        None => (width, height, frames),
+    };
+    let (width, height, frames) = match &args.cmd {
+        SubCommands::Reframe { aspect, mode } => {
+            let size = reframe::size(width, height, *aspect, *mode);
+            (size.0, size.1, reframe::reframed(frames, size, *mode))
+        }
+        _ => (width, height, frames),
+    };
 
+    let tempo = args
+        .tempo_map
+        .as_deref()
+        .map(TempoMap::load)
+        .or(args.bpm_ramp.clone());
+    let bpm = args.bpm.or_else(|| {
+        tempo
+            .as_ref()
+            .map(|tempo| tempo.initial_bpm().round() as u32)
+    });
+
+    let shape = || WaveShape {
+        skew: args.wave_skew,
+        exponent: args.wave_exp,
+        rectify: args.wave_rectify,
+        invert: args.mod_invert,
+        range: args.mod_range,
+        bias: args.mod_bias,
+    };
     let visualization_mode = match args.visualization.as_str() {
         "default" => VisualizationMode::Default,
         "sine" => VisualizationMode::Osc {
             bpm: bpm.expect("No --bpm provided!"),
             wave_type: WaveType::Sine,
+            shape: shape(),
         },
         "saw" => VisualizationMode::Osc {
             bpm: bpm.expect("No --bpm provided!"),
             wave_type: WaveType::Saw,
+            shape: shape(),
         },
         "square" => VisualizationMode::Osc {
             bpm: bpm.expect("No --bpm provided!"),
             wave_type: WaveType::Square,
+            shape: shape(),
         },
         "triangle" => VisualizationMode::Osc {
             bpm: bpm.expect("No --bpm provided!"),
             wave_type: WaveType::Triangle,
+            shape: shape(),
+        },
+        "sample-hold" => VisualizationMode::Osc {
+            bpm: bpm.expect("No --bpm provided!"),
+            wave_type: WaveType::SampleHold { seed: args.seed },
+            shape: shape(),
+        },
+        "drift" => VisualizationMode::Osc {
+            bpm: bpm.expect("No --bpm provided!"),
+            wave_type: WaveType::Drift { seed: args.seed },
+            shape: shape(),
         },
         _ => panic!("Unknown visualization mode"),
     };
+    let clock = Clock {
+        frame_rate,
+        visualization_mode,
+        bpm,
+        beat_div: args.beat_div,
+        swing: args.swing,
+        tempo,
+    };
 
-    let processed = process_video(
-        &mut decoder,
-        |img, scale_factor| {
-            DynamicImage::ImageRgba8(process_subcommand(
-                &args.cmd,
-                img,
-                &args.lhs,
-                &args.rhs,
-                negate,
-                scale_factor,
-            ))
+    let upscale = match &args.cmd {
+        SubCommands::Upscale { factor, model } => Some(Upscale::load(model, *factor)),
+        _ => None,
+    };
+    // Effects run at the input's size, only the output is enlarged
+    let (output_width, output_height) = match &upscale {
+        Some(upscale) => upscale.size(width, height),
+        None => (width, height),
+    };
+    let upscaled = |img: RgbaImage| match &upscale {
+        Some(upscale) => upscale.apply(img),
+        None => img,
+    };
+    let grain = Grain {
+        amount: args.grain,
+        size: args.grain_size,
+        response: args.grain_response,
+        channels: args.grain_channels,
+        seed: args.seed,
+    };
+    let encode_settings = EncodeSettings {
+        width: output_width,
+        height: output_height,
+        frame_rate,
+        codec: args.codec.or(args.target.map(|_| Codec::H264)),
+        bit_rate: None,
+        threads: args.threads,
+        color_range: match args.target {
+            Some(_) => ColorRange::Limited,
+            None => args.color_range,
         },
-        frame_rate as f64,
-        width,
-        height,
-        visualization_mode,
+        delivery: args.target,
+        faststart: args.faststart,
+        fragmented: args.fragmented,
+        segment_duration: args.segment_duration,
+    };
+
+    let plugins: Vec<Plugin> = args
+        .plugin
+        .iter()
+        .map(|path| Plugin::load(path, &args.plugin_param).unwrap_or_else(|e| panic!("{}", e)))
+        .collect();
+
+    // Sources like `viz` have no effect of their own, only plugins
+    let mut chain = project_chain.unwrap_or_else(|| {
+        args.cmd
+            .to_effect(&args.lhs, &args.rhs, negate)
+            .into_iter()
+            .fold(EffectChain::new(), EffectChain::then)
+    });
+    // `normalize --global` takes its levels from a first pass over the input
+    for effect in &mut chain.effects {
+        if let Effect::Normalize {
+            clip,
+            global: true,
+            levels: levels @ None,
+        } = effect
+        {
+            if matches!(args.cmd, SubCommands::Viz { .. })
+                || input().starts_with("generate:")
+                || input().contains("://")
+            {
+                eprintln!(
+                    "Only files can be analyzed for normalize --global, normalizing per frame"
+                );
+                continue;
+            }
+            eprintln!("Analyzing levels of {}", input());
+            // Over the stretch of the input the render shows and the part of
+            // each frame the effects run on, cleaned up as the render is
+            let mut source =
+                Source::open(Path::new(input())).expect("Failed to analyze input levels");
+            let source_rate = source.frame_rate();
+            let (rate, frames) = progressive(
+                Box::new(std::iter::from_fn(move || source.next_frame())),
+                &args,
+                Fields::probe(Path::new(input())),
+            );
+            let frames = denoised(frames, args.denoise_spatial, args.denoise_temporal);
+            let window = [args.duration, args.loop_to]
+                .into_iter()
+                .flatten()
+                .reduce(f64::min);
+            let count = window.map_or(usize::MAX, |window| {
+                (window * source_rate * rate).ceil() as usize
+            });
+            let frames = frames
+                .take(count)
+                .map(|frame| match crop {
+                    Some((x, y, width, height)) => {
+                        imageops::crop_imm(&frame, x, y, width, height).to_image()
+                    }
+                    None => frame,
+                })
+                .map(|frame| match args.roi {
+                    Some((x, y, width, height)) => {
+                        imageops::crop_imm(&frame, x, y, width, height).to_image()
+                    }
+                    None => frame,
+                });
+            *levels = Some(levels::analyze(frames, *clip));
+        }
+    }
+
+    let sequence = args.sequence.as_ref().map(|spec| {
+        (
+            Sequence::load(spec, &args.preset_dir, args.per),
+            bpm.expect("No --bpm provided!"),
+        )
+    });
+    let markers = args
+        .markers
+        .as_ref()
+        .map(|path| Markers::load(path, &args.preset_dir, frame_rate));
+    // Every chain a frame can go through, checked before the render starts
+    let chains: Vec<&EffectChain> = std::iter::once(&chain)
+        .chain(sequence.iter().flat_map(|(sequence, _)| sequence.steps()))
+        .chain(markers.iter().flat_map(Markers::presets))
+        .collect();
+    let strobes = chains
+        .iter()
+        .flat_map(|chain| &chain.effects)
+        .any(|effect| matches!(effect, Effect::Strobe { .. }));
+    if strobes && bpm.is_none() {
+        panic!("strobe flashes on the beat, pass --bpm");
+    }
+    let randomize = args.randomize.as_ref().map(|spec| {
+        (
+            Randomize::parse(spec, args.seed, &chains).unwrap_or_else(|e| panic!("{}", e)),
+            bpm.expect("No --bpm provided!"),
+        )
+    });
+    let automation = args
+        .midi_file
+        .as_ref()
+        .map(|path| Automation::load(path, args.map.as_deref().expect("No --map provided!")));
+    // Marker and MIDI envelopes scale everything downstream, plugins included
+    let envelopes = |time: f64| {
+        markers
+            .as_ref()
+            .and_then(|markers| markers.state_at(time).envelope)
+            .unwrap_or(1.0)
+            * automation
+                .as_ref()
+                .and_then(|automation| automation.envelope(time))
+                .unwrap_or(1.0)
+    };
+    // With --quantize, the frame whose modulation a frame shows
+    let quantize = args
+        .quantize
+        .map(|quantize| (quantize, bpm.expect("No --bpm provided!")));
+    let held = |index: usize| match quantize {
+        Some((quantize, bpm)) => clock.context(quantize.held(
+            index,
+            frame_rate,
+            bpm,
+            |time| clock.grid_time(time, bpm),
+            |time| clock.real_time(time, bpm),
+        )),
+        None => clock.context(index),
+    };
+    let modulation = |index: usize| {
+        let frame = held(index);
+        frame.scale_factor * envelopes(frame.time)
+    };
+    let smoother = args.smooth.map(Smoother::new);
+    let marked = |frame: &FrameContext| {
+        let mut marked = *frame;
+        marked.scale_factor = match &smoother {
+            Some(smoother) => smoother.value(frame.index, frame.frame_rate, modulation),
+            None => modulation(frame.index),
+        };
+        marked
+    };
+    // Every chain a frame goes through, as adjusted for that frame
+    let active = |frame: &FrameContext| -> Vec<EffectChain> {
+        let state = markers
+            .as_ref()
+            .map(|markers| markers.state_at(frame.time))
+            .unwrap_or_default();
+        let chain = match state.color {
+            Some(color) => Cow::Owned(recolor(&chain, color)),
+            None => Cow::Borrowed(&chain),
+        };
+
+        let presets = sequence
+            .as_ref()
+            .map(|(sequence, bpm)| sequence.chain_at(clock.grid_time(frame.time, *bpm), *bpm))
+            .into_iter()
+            .chain(state.preset);
+        std::iter::once(chain.as_ref())
+            .chain(presets)
+            .map(|chain| {
+                let at = |frame: &FrameContext| {
+                    automated(chain, &automation, &randomize, args.per, frame).into_owned()
+                };
+                match &smoother {
+                    Some(smoother) if automation.is_some() || randomize.is_some() => {
+                        let key = serde_json::to_string(chain).expect("Effect chains serialize");
+                        smoother.chain(&key, frame.index, frame.frame_rate, |index| {
+                            at(&held(index))
+                        })
+                    }
+                    _ => at(&held(frame.index)),
+                }
+                .linear(chain.linear || args.linear)
+                .scaling(Scaling {
+                    channels: args.scale_channels.unwrap_or(chain.scaling.channels),
+                    curve: args.scale_curve.unwrap_or(chain.scaling.curve),
+                })
+            })
+            .collect()
+    };
+    let style = match &args.cmd {
+        SubCommands::Style {
+            model,
+            model_size,
+            strength,
+            temporal,
+        } => Some(Style::load(model, *model_size, *strength, *temporal)),
+        _ => None,
+    };
+    // Style transfer is the effect of `style`, so it goes before any preset
+    let styled = |img: DynamicImage, frame: &FrameContext| match &style {
+        Some(style) => style.apply(img, frame.index),
+        None => img.into_rgba8(),
+    };
+    let sequenced = |img: DynamicImage, frame: &FrameContext| {
+        active(frame).iter().fold(styled(img, frame), |img, chain| {
+            chain.apply(DynamicImage::ImageRgba8(img), frame)
+        })
+    };
+
+    let burn_frame_numbers = args.burn_frame_numbers;
+    let gate = args
+        .gate
+        .clone()
+        .or_else(|| {
+            args.gate_pattern
+                .clone()
+                .map(|gate| gate.with_steps_per_beat(args.steps_per_beat))
+        })
+        .map(|gate| (gate, bpm.expect("No --bpm provided!")));
+    let gated = |frame: &FrameContext| {
+        gate.as_ref()
+            .is_some_and(|(gate, bpm)| !gate.is_open(clock.grid_time(frame.time, *bpm), *bpm))
+    };
+    let transition = args.transition.as_ref().map(|matte| {
+        Transition::open(
+            matte,
+            args.input2.as_deref().expect("No --input2 provided!"),
+            (width, height),
+            args.transition_sweep,
+            args.transition_at,
+            args.transition_duration,
+        )
+    });
+    let transitioned = |img: DynamicImage, frame: &FrameContext| match &transition {
+        Some(transition) => DynamicImage::ImageRgb8(transition.apply(img.into_rgb8(), frame)),
+        None => img,
+    };
+
+    let layers: Vec<Layer> = args.layer.iter().map(Layer::open).collect();
+    let layered = |img: DynamicImage, stage: LayerStage, frame: &FrameContext| {
+        if layers.is_empty() || args.layer_stage != stage {
+            return img;
+        }
+        let mut img = img.into_rgba8();
+        composite(&mut img, &layers, frame);
+        DynamicImage::ImageRgba8(img)
+    };
+
+    let banded = match &args.cmd {
+        SubCommands::Depthfx {
+            model,
+            model_size,
+            near_effect,
+            far_effect,
+            split,
+            softness,
+        } => Some(Banded {
+            model: MaskModel::depth(model, *model_size),
+            inside: mask::effect_chain(near_effect, &args),
+            outside: mask::effect_chain(far_effect, &args),
+            split: *split,
+            softness: *softness,
+        }),
+        SubCommands::Segmentfx {
+            model,
+            model_size,
+            layout,
+            subject,
+            background,
+            threshold,
+            softness,
+        } => Some(Banded {
+            model: MaskModel::segmentation(model, *model_size, *layout),
+            inside: mask::effect_chain(subject, &args),
+            outside: mask::effect_chain(background, &args),
+            split: *threshold,
+            softness: *softness,
+        }),
+        _ => None,
+    };
+    // The mask comes from the frame before any effect has broken it up
+    let masked = |img: DynamicImage, frame: &FrameContext| match &banded {
+        Some(banded) => {
+            let mask = banded.model.mask(&img);
+            banded.apply(sequenced(img, frame), &mask, frame)
+        }
+        None => sequenced(img, frame),
+    };
+
+    // --region requires --face-model
+    let face_region = args
+        .region
+        .zip(args.face_model.as_deref())
+        .map(|(region, model)| FaceRegion::load(model, region, args.feather, args.face_confidence));
+    let targeted =
+        |img: DynamicImage, frame: &FrameContext, effect: &dyn Fn(DynamicImage) -> RgbaImage| {
+            match &face_region {
+                Some(face_region) => face_region.apply(img, frame.index, effect),
+                None => effect(img),
+            }
+        };
+
+    // The audio the render goes with, for the HUD and --dump-modulation
+    let soundtrack = || match &args.cmd {
+        SubCommands::Viz { audio, .. } => Audio::load(Path::new(audio)).ok(),
+        _ => Audio::load(Path::new(input())).ok(),
+    };
+    let hud = args.debug_overlay.then(|| Hud {
+        audio: soundtrack(),
+    });
+    let overlay = |frame: &FrameContext| {
+        hud.as_ref()
+            .map(|hud| hud.lines(frame, &active(frame), gated(frame)))
+    };
+
+    // Plugins are passed in rather than captured, since they can't be shared
+    // across threads and `--verify-threads` renders without them
+    let pipeline = |img: DynamicImage, frame: &FrameContext, plugins: &[Plugin]| {
+        let frame = &marked(frame);
+        let img = layered(transitioned(img, frame), LayerStage::Before, frame);
+        let processed = if gated(frame) {
+            img.into_rgba8()
+        } else {
+            targeted(img, frame, &|img| {
+                in_region(img, args.roi, |img| {
+                    plugins.iter().fold(masked(img, frame), |img, plugin| {
+                        plugin.process(img, frame.scale_factor)
+                    })
+                })
+            })
+        };
+        let processed = layered(
+            DynamicImage::ImageRgba8(processed),
+            LayerStage::After,
+            frame,
+        );
+        finish_frame(
+            grain.apply(upscaled(processed.into_rgba8()), frame.index),
+            frame,
+            burn_frame_numbers,
+            overlay(frame),
+        )
+    };
+    let process = |img: DynamicImage, frame: &FrameContext| pipeline(img, frame, &plugins);
+
+    if let Some(path) = &args.dump_modulation {
+        let duration = args
+            .duration
+            .or(source_duration.filter(|_| args.loop_to.is_none()))
+            .expect("No --duration provided!");
+        let count = (duration * frame_rate).round() as usize;
+
+        let audio = soundtrack();
+        let mut curves = Curves::default();
+        for index in 0..count {
+            let frame = marked(&clock.context(index));
+            let mut extra = vec![];
+            if gate.is_some() {
+                extra.push(("gate", if gated(&frame) { 0.0 } else { 1.0 }));
+            }
+            if let Some(audio) = &audio {
+                let level = audio.level(frame.time, 1.0 / frame.frame_rate);
+                extra.push(("audio", level as f64));
+            }
+            curves.push(&frame, &extra, &active(&frame));
+        }
+        curves.write_csv(path);
+        if let Some(plot) = &args.modulation_plot {
+            curves.plot(plot);
+        }
+        eprintln!("Wrote modulation for {} frames to {}", count, path);
+        return;
+    }
+
+    if let Some(count) = args.verify_deterministic {
+        let frames: Vec<RgbImage> = frames.take(count).collect();
+        let contexts: Vec<FrameContext> = (0..frames.len())
+            .map(|index| clock.context(index))
+            .collect();
+
+        if args.verify_threads > 1 {
+            if !plugins.is_empty() {
+                panic!("--verify-threads can't be used with --plugin");
+            }
+            // Faces are tracked from one frame to the next
+            if args.region.is_some() {
+                panic!("--verify-threads can't be used with --region");
+            }
+            let process = |img: DynamicImage, frame: &FrameContext| pipeline(img, frame, &[]);
+            verify::run_threaded(&frames, &contexts, &process, args.verify_threads);
+        } else {
+            verify::run(&frames, &contexts, &process);
+        }
+        return;
+    }
+
+    // Frames from the cache would leave gaps in the face tracking, so faces
+    // on the frames after them would land elsewhere than in a full render
+    let cache_dir = match (&args.cache_dir, args.region, &args.cmd) {
+        (Some(_), Some(_), _) => {
+            eprintln!("--cache-dir can't be used with --region, skipping");
+            None
+        }
+        // Likewise the blend with the previous stylized frame
+        (Some(_), None, SubCommands::Style { temporal, .. }) if *temporal > 0.0 => {
+            eprintln!("--cache-dir can't be used with style --temporal, skipping");
+            None
+        }
+        (cache_dir, _, _) => cache_dir.as_ref(),
+    };
+    let cache = cache_dir.map(|dir| {
+        let input_key = || file_key(args.input.as_deref().expect("No --input provided!"));
+        let source = match &args.cmd {
+            SubCommands::Viz { audio, style } => format!(
+                "viz {} {:?}",
+                file_key(audio),
+                style.to_possible_value().map(|v| v.get_name().to_string())
+            ),
+            SubCommands::Depthfx {
+                model,
+                model_size,
+                near_effect,
+                far_effect,
+                split,
+                softness,
+            } => format!(
+                "{} depthfx {} {} {:?} {:?} {} {}",
+                input_key(),
+                file_key(model),
+                model_size,
+                near_effect,
+                far_effect,
+                split,
+                softness
+            ),
+            SubCommands::Segmentfx {
+                model,
+                model_size,
+                layout,
+                subject,
+                background,
+                threshold,
+                softness,
+            } => format!(
+                "{} segmentfx {} {} {:?} {:?} {:?} {} {}",
+                input_key(),
+                file_key(model),
+                model_size,
+                layout,
+                subject,
+                background,
+                threshold,
+                softness
+            ),
+            SubCommands::Style {
+                model,
+                model_size,
+                strength,
+                temporal,
+            } => format!(
+                "{} style {} {} {} {}",
+                input_key(),
+                file_key(model),
+                model_size,
+                strength,
+                temporal
+            ),
+            SubCommands::Upscale { factor, model } => {
+                format!("{} upscale {} {}", input_key(), factor, file_key(model))
+            }
+            SubCommands::Stabilize { smoothness } => {
+                format!("{} stabilize {}", input_key(), smoothness)
+            }
+            SubCommands::Reframe { aspect, mode } => {
+                format!("{} reframe {:?} {:?}", input_key(), aspect, mode)
+            }
+            // Resolved ytdlp: urls change between runs, the page doesn't
+            _ => input_key(),
+        };
+        let key = CacheKey {
+            source: &source,
+            chain: &chain,
+            linear: args.linear,
+            scale_channels: args.scale_channels,
+            scale_curve: args.scale_curve,
+            deinterlace: args.deinterlace,
+            detelecine: args.detelecine,
+            autocrop: args.autocrop,
+            denoise_spatial: args.denoise_spatial,
+            denoise_temporal: args.denoise_temporal,
+            plugin: &args.plugin,
+            plugin_param: &args.plugin_param,
+            visualization: &args.visualization,
+            bpm,
+            beat_div: args.beat_div,
+            wave_skew: args.wave_skew,
+            wave_exp: args.wave_exp,
+            wave_rectify: args.wave_rectify,
+            mod_invert: args.mod_invert,
+            mod_range: args.mod_range,
+            mod_bias: args.mod_bias,
+            seed: args.seed,
+            fps: args.fps,
+            resolution: args.resolution,
+            duration: args.duration,
+            loop_to: args.loop_to,
+            loop_crossfade: args.loop_crossfade,
+            burn_frame_numbers,
+            debug_overlay: args.debug_overlay,
+            roi: args.roi,
+            smooth: args.smooth,
+            quantize: args.quantize,
+            swing: args.swing,
+            tempo: clock.tempo.as_ref(),
+            gate: gate.as_ref().map(|(gate, _)| gate),
+            sequence: sequence.as_ref().map(|(sequence, _)| sequence.steps()),
+            per: args.per,
+            randomize: randomize.as_ref().map(|(randomize, _)| randomize),
+            markers: markers.as_ref().map(Markers::key),
+            automation: automation.as_ref(),
+            layer: &args.layer,
+            layer_stage: args.layer_stage,
+            input2: args.input2.as_deref(),
+            transition: args.transition.as_deref(),
+            transition_at: args.transition_at,
+            transition_duration: args.transition_duration,
+            transition_sweep: args.transition_sweep,
+            region: args.region,
+            face_model: args.face_model.as_deref().map(file_key),
+            feather: args.feather,
+            face_confidence: args.face_confidence,
+            grain: args.grain,
+            grain_size: args.grain_size,
+            grain_response: args.grain_response,
+            grain_channels: args.grain_channels,
+        };
+        let key = serde_json::to_string(&key).expect("Cache keys serialize");
+        FrameCache::open(dir, &key)
+    });
+    let process_cached = |img: DynamicImage, frame: &FrameContext| {
+        let Some(cache) = &cache else {
+            return process(img, frame);
+        };
+        if let Some(cached) = cache.get(frame.index) {
+            return DynamicImage::ImageRgb8(cached);
+        }
+        let processed = process(img, frame);
+        cache.put(frame.index, &processed.to_rgb8());
+        processed
+    };
+
+    // Outputs record what made them, the whole chain and project in matroska
+    let invocation = std::env::args()
+        .map(|arg| shell_quoted(&arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let attachments: Vec<Attachment> = std::iter::once(Attachment {
+        filename: "chain.json".to_string(),
+        mime_type: "application/json".to_string(),
+        data: chain.to_json().into_bytes(),
+    })
+    .chain(project_file.as_ref().map(|file| {
+        Attachment {
+            filename: Path::new(file)
+                .file_name()
+                .map_or("project.vidfx".into(), |name| {
+                    name.to_string_lossy().into_owned()
+                }),
+            mime_type: "application/json".to_string(),
+            data: std::fs::read(file).expect("Failed to read project file"),
+        }
+    }))
+    .collect();
+    let provenance = Provenance {
+        comment: Some(format!(
+            "vidfx {}: {}",
+            env!("CARGO_PKG_VERSION"),
+            invocation
+        )),
+        attachments,
+    };
+    let render_duration = args
+        .duration
+        .or(source_duration.filter(|_| args.loop_to.is_none()));
+    let estimate = Estimate::new(
+        &outputs,
+        &encode_settings,
+        args.target_size,
+        args.quality_report.as_deref(),
+        render_duration,
     );
+    if !args.progress_json {
+        estimate.print();
+    }
+    for (dir, needed, free) in estimate.short_of_space() {
+        let message = format!(
+            "{} has {} free but the render needs about {}",
+            dir.display(),
+            format_size(free),
+            format_size(needed)
+        );
+        if args.ignore_disk_space || estimate.is_guess() {
+            eprintln!("Warning: {}", message);
+        } else {
+            panic!("{}, pass --ignore-disk-space to start anyway", message);
+        }
+    }
+    if let Some(limit) = args.confirm {
+        let total = estimate.total_size();
+        if total > limit
+            && !preflight::confirm(&format!(
+                "This render comes to about {}, go ahead?",
+                format_size(total)
+            ))
+        {
+            eprintln!("Cancelled");
+            return;
+        }
+    }
+
+    let mut sinks: Vec<Box<dyn FrameSink>> = outputs
+        .iter()
+        .map(|output| output::open(output, &encode_settings, args.target_size, &provenance))
+        .collect();
+    // The frames exactly as the outputs get them, after any looping, trimming
+    // and cropping, so the report lines up frame for frame
+    let encoded = outputs.iter().find_map(|output| match output {
+        OutputTarget::File(path) => Some(path.clone()),
+        _ => None,
+    });
+    let quality_reference = match (&args.quality_report, &encoded) {
+        (Some(report_path), Some(_)) => {
+            let path = output::reference_path(report_path);
+            sinks.push(output::reference(&path, &encode_settings));
+            Some(path)
+        }
+        (Some(_), None) => {
+            eprintln!("--quality-report needs a file output, skipping");
+            None
+        }
+        (None, _) => None,
+    };
+    if !args.snapshot.is_empty() {
+        sinks.push(output::snapshots(
+            &args.snapshot,
+            Path::new(&args.snapshot_dir),
+            frame_rate,
+        ));
+    }
+    let mut output_dirs: Vec<PathBuf> = outputs
+        .iter()
+        .filter_map(|output| match output {
+            OutputTarget::File(path) => Some(preflight::output_dir(path)),
+            _ => None,
+        })
+        .collect();
+    output_dirs.sort();
+    output_dirs.dedup();
+    if !output_dirs.is_empty() {
+        sinks.push(output::space_watch(
+            output_dirs,
+            Arc::clone(&cancelled),
+            Arc::clone(&out_of_space),
+        ));
+    }
+    if args.progress || args.progress_json {
+        let total = render_duration.map(|duration| (duration * frame_rate).round() as usize);
+        sinks.push(output::progress(total, args.progress_json));
+    }
 
-    let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, false);
-    let mut encoder =
-        Encoder::new(Path::new(&out_path), settings).expect("Failed to create encoder");
+    let mut timings = Timings::default();
+    let frames_written = process_video(
+        frames,
+        &mut sinks,
+        &process_cached,
+        &clock,
+        &Stride {
+            every: args.stride.max(1),
+            fill: args.fill,
+        },
+        &cancelled,
+        &mut timings,
+    );
 
-    let mut position = Time::zero();
+    // Preset changes from --sequence and --markers, as chapters for editors
+    let preset_names = |time: f64| {
+        let sequenced = sequence
+            .as_ref()
+            .map(|(sequence, bpm)| sequence.name_at(clock.grid_time(time, *bpm), *bpm));
+        let marked = markers
+            .as_ref()
+            .and_then(|markers| markers.state_at(time).preset_name);
+        sequenced.into_iter().chain(marked).collect::<Vec<_>>()
+    };
+    if sequence.is_some() || markers.is_some() {
+        let mut chapters: Vec<Chapter> = vec![];
+        for index in 0..frames_written {
+            let (start, end) = (index as f64 / frame_rate, (index + 1) as f64 / frame_rate);
+            let names = preset_names(start);
+            if names.is_empty() {
+                continue;
+            }
+            let title = names.join(" / ");
+            match chapters.last_mut() {
+                Some(chapter) if chapter.title == title => chapter.end = end,
+                _ => chapters.push(Chapter { start, end, title }),
+            }
+        }
+        for sink in &mut sinks {
+            sink.chapters(&chapters);
+        }
+    }
 
-    let frame_interval = (1.0 / frame_rate) as f64;
+    let finishing = Instant::now();
+    for sink in sinks {
+        sink.finish();
+    }
+    timings.encode += finishing.elapsed();
 
-    for frame in processed {
-        let rgb_image = rgba_to_rgb(&frame);
+    let summary = Summary::new(
+        frames_written,
+        started.elapsed(),
+        &timings,
+        &outputs,
+        frame_rate,
+    );
+    if !args.progress_json {
+        summary.print();
+    }
+    if let Some(path) = &args.summary_json {
+        summary.write_json(path);
+    }
 
-        encoder
-            .encode(&image_to_ndarray(&rgb_image), position)
-            .expect("Failed to encode frame");
+    if let (Some(report_path), Some(reference), Some(path)) =
+        (&args.quality_report, &quality_reference, &encoded)
+    {
+        quality::write_report(input(), reference, path, Path::new(report_path));
+        let _ = std::fs::remove_file(reference);
+    }
 
-        position = Time::from_secs_f64(position.as_secs_f64() + frame_interval);
+    let outcome = format!(
+        "wrote {} frames ({:.2}s) to {}",
+        frames_written,
+        frames_written as f64 / frame_rate,
+        completion.outputs.join(", ")
+    );
+    if out_of_space.load(Ordering::SeqCst) {
+        eprintln!("Out of disk space: {}", outcome);
+        completion.report(
+            Outcome::OutOfSpace,
+            &format!("Out of disk space: {}", outcome),
+        );
+        return;
     }
-}
+    if cancelled.load(Ordering::SeqCst) {
+        eprintln!("Interrupted: {}", outcome);
+        completion.report(Outcome::Interrupted, &format!("Interrupted: {}", outcome));
+        return;
+    }
+    completion.report(Outcome::Done, &format!("Done: {}", outcome));
 
-fn image_to_ndarray(image: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Array3<u8> {
-    let (width, height) = image.dimensions();
-    Array::from_shape_vec(
-        (height as usize, width as usize, 3),
-        image.clone().into_raw(),
-    )
-    .expect("Failed to convert image to ndarray")
+    if matches!(args.cmd, SubCommands::Quick { .. }) {
+        if let Some(OutputTarget::File(path)) = outputs.first() {
+            open_in_viewer(path);
+        }
+    }
 }
 
 fn rgba_to_rgb(image: &ImageBuffer<Rgba<u8>, Vec<u8>>) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
@@ -0,0 +1,245 @@
+use std::path::Path;
+
+use serde_json::Value;
+use vidfx::{Color, EffectChain};
+
+use crate::sequence::load_preset;
+use crate::units::parse_duration;
+
+/// What a marker does from its timestamp on, from its label:
+/// `preset:<name>` switches the preset run after the subcommand (`preset:clean`
+/// turns it off), `color:<hex>` recolors the subcommand's effect, and
+/// `hit` or `hit:<decay>` fires an envelope that scales the effects' scale
+/// factor from 1 down to 0.
+enum Event {
+    Preset { name: String, chain: EffectChain },
+    Color(Color),
+    Hit { decay: f64 },
+}
+
+struct Marker {
+    time: f64,
+    event: Event,
+}
+
+/// Cue points from `--markers`: Audacity labels, a DaVinci Resolve EDL or a
+/// `time,label` CSV.
+pub struct Markers {
+    markers: Vec<Marker>,
+}
+
+/// Where the markers leave things at one point in time.
+#[derive(Default)]
+pub struct MarkerState<'a> {
+    pub preset: Option<&'a EffectChain>,
+    pub preset_name: Option<&'a str>,
+    pub color: Option<Color>,
+    /// Envelope level when the file has `hit` markers
+    pub envelope: Option<f64>,
+}
+
+impl Markers {
+    pub fn load(path: &str, preset_dir: &str, frame_rate: f64) -> Markers {
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Failed to read markers {}: {}", path, e));
+
+        let is_edl = Path::new(path)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("edl"));
+        let labels = if is_edl {
+            edl(&text, frame_rate)
+        } else if text.contains('\t') {
+            audacity(&text)
+        } else {
+            csv(&text)
+        }
+        .unwrap_or_else(|e| panic!("Failed to parse markers {}: {}", path, e));
+
+        let mut markers: Vec<Marker> = labels
+            .into_iter()
+            .filter_map(|(time, label)| {
+                let (kind, value) = label.split_once(':').unwrap_or((label.as_str(), ""));
+                let event = match kind.trim().to_lowercase().as_str() {
+                    "preset" => Event::Preset {
+                        name: value.trim().to_string(),
+                        chain: load_preset(value.trim(), preset_dir),
+                    },
+                    "color" => Event::Color(value.parse().unwrap_or_else(|e| panic!("{}", e))),
+                    "hit" => Event::Hit {
+                        decay: if value.trim().is_empty() {
+                            0.5
+                        } else {
+                            parse_duration(value).unwrap_or_else(|e| panic!("{}", e))
+                        },
+                    },
+                    _ => {
+                        eprintln!("Ignoring marker '{}' at {:.3}s", label, time);
+                        return None;
+                    }
+                };
+                Some(Marker { time, event })
+            })
+            .collect();
+        markers.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        Markers { markers }
+    }
+
+    /// Every preset the markers switch to.
+    pub fn presets(&self) -> impl Iterator<Item = &EffectChain> {
+        self.markers
+            .iter()
+            .filter_map(|marker| match &marker.event {
+                Event::Preset { chain, .. } => Some(chain),
+                _ => None,
+            })
+    }
+
+    pub fn state_at(&self, time: f64) -> MarkerState<'_> {
+        let mut state = MarkerState::default();
+        if self
+            .markers
+            .iter()
+            .any(|marker| matches!(marker.event, Event::Hit { .. }))
+        {
+            state.envelope = Some(0.0);
+        }
+
+        for marker in self.markers.iter().take_while(|marker| marker.time <= time) {
+            match &marker.event {
+                Event::Preset { name, chain } => {
+                    state.preset = Some(chain);
+                    state.preset_name = Some(name);
+                }
+                Event::Color(color) => state.color = Some(*color),
+                Event::Hit { decay } => {
+                    let elapsed = time - marker.time;
+                    state.envelope = Some(if *decay > 0.0 {
+                        (1.0 - elapsed / decay).max(0.0)
+                    } else {
+                        0.0
+                    });
+                }
+            }
+        }
+
+        state
+    }
+
+    /// Timestamps and events, for cache keys.
+    pub fn key(&self) -> String {
+        self.markers
+            .iter()
+            .map(|marker| {
+                let event = match &marker.event {
+                    Event::Preset { chain, .. } => {
+                        serde_json::to_string(chain).expect("Effect chains serialize")
+                    }
+                    Event::Color(color) => color.to_string(),
+                    Event::Hit { decay } => format!("hit {}", decay),
+                };
+                format!("{} {}", marker.time, event)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// `chain` with every color operand replaced by `color`.
+pub fn recolor(chain: &EffectChain, color: Color) -> EffectChain {
+    let mut json = serde_json::to_value(chain).expect("Effect chains serialize");
+    for effect in json["effects"]
+        .as_array_mut()
+        .expect("Effect chains serialize their effects as an array")
+    {
+        if effect.get("color").is_some() {
+            effect["color"] = Value::from(color.to_string());
+        }
+    }
+    serde_json::from_value(json).expect("Recolored effect chains deserialize")
+}
+
+/// Audacity label tracks: `start<TAB>end<TAB>label`, with lines starting `\`
+/// holding spectral selections.
+fn audacity(text: &str) -> Result<Vec<(f64, String)>, String> {
+    text.lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('\\'))
+        .map(|line| {
+            let mut fields = line.split('\t');
+            let start = fields.next().unwrap_or_default();
+            let label = fields.nth(1).unwrap_or_default();
+            let time = start
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| format!("invalid label line '{}'", line))?;
+            Ok((time, label.trim().to_string()))
+        })
+        .collect()
+}
+
+/// `time,label` lines with times as `--duration` takes them. A header line
+/// is skipped.
+fn csv(text: &str) -> Result<Vec<(f64, String)>, String> {
+    let mut markers = vec![];
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (time, label) = line
+            .split_once(',')
+            .ok_or_else(|| format!("invalid marker line '{}'", line))?;
+        match parse_duration(time) {
+            Ok(time) => markers.push((time, label.trim().trim_matches('"').to_string())),
+            Err(_) if i == 0 => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(markers)
+}
+
+/// Markers exported from a DaVinci Resolve timeline as an EDL: an event
+/// line with the record in timecode, then a ` |M:<name>` comment. Times are
+/// relative to the hour the timeline starts on, usually 01:00:00:00.
+fn edl(text: &str, frame_rate: f64) -> Result<Vec<(f64, String)>, String> {
+    let timecode = |tc: &str| -> Result<f64, String> {
+        let parts = tc
+            .split([':', ';'])
+            .map(|part| part.parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("invalid timecode '{}'", tc))?;
+        match parts[..] {
+            [h, m, s, f] => Ok(h * 3600.0 + m * 60.0 + s + f / frame_rate.round()),
+            _ => Err(format!("invalid timecode '{}'", tc)),
+        }
+    };
+
+    let mut markers = vec![];
+    let mut record_in = None;
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields
+            .first()
+            .is_some_and(|f| f.chars().all(|c| c.is_ascii_digit()))
+        {
+            let tc = fields
+                .get(6)
+                .ok_or_else(|| format!("invalid event line '{}'", line))?;
+            record_in = Some(timecode(tc)?);
+        } else if let Some(name) = line.split("|M:").nth(1) {
+            let time = record_in
+                .take()
+                .ok_or_else(|| format!("marker '{}' has no event line", line.trim()))?;
+            let name = name.split(" |").next().unwrap_or_default();
+            markers.push((time, name.trim().to_string()));
+        }
+    }
+
+    let start = markers
+        .iter()
+        .map(|(time, _)| (time / 3600.0).floor() * 3600.0)
+        .fold(f64::INFINITY, f64::min);
+    Ok(markers
+        .into_iter()
+        .map(|(time, label)| (time - start, label))
+        .collect())
+}
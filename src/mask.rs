@@ -0,0 +1,173 @@
+//! Effects split by a mask an ONNX model draws on every frame: `depthfx`
+//! with a monocular depth model, `segmentfx` with a person segmentation
+//! model. One chain goes inside the mask and another outside it.
+//!
+//! Models are run with tract and must take a single RGB image and give a
+//! single channel map back.
+
+use clap::Parser;
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, GrayImage, Luma, RgbaImage};
+use vidfx::{EffectChain, FrameContext};
+
+use crate::onnx::Plan;
+use crate::Args;
+
+const MEAN: [f32; 3] = [0.485, 0.456, 0.406];
+const DEVIATION: [f32; 3] = [0.229, 0.224, 0.225];
+
+/// How a model's input tensor is laid out.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    /// Channels first, `[1, 3, h, w]`
+    Nchw,
+    /// Channels last, `[1, h, w, 3]`, as MediaPipe models are
+    Nhwc,
+}
+
+/// What a model takes and gives.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Kind {
+    /// MiDaS-style: ImageNet normalized input, relative inverse depth out,
+    /// larger being nearer
+    Depth,
+    /// 0 to 1 input, the probability of each pixel being a person out
+    Segmentation,
+}
+
+/// A model ready to run on frames resized to `size`.
+pub struct MaskModel {
+    plan: Plan,
+    size: u32,
+    layout: Layout,
+    kind: Kind,
+}
+
+impl MaskModel {
+    fn load(path: &str, size: u32, layout: Layout, kind: Kind) -> MaskModel {
+        let side = size as usize;
+        let shape = match layout {
+            Layout::Nchw => [1, 3, side, side],
+            Layout::Nhwc => [1, side, side, 3],
+        };
+        let plan = Plan::load(path, shape);
+        MaskModel {
+            plan,
+            size,
+            layout,
+            kind,
+        }
+    }
+
+    pub fn depth(path: &str, size: u32) -> MaskModel {
+        MaskModel::load(path, size, Layout::Nchw, Kind::Depth)
+    }
+
+    pub fn segmentation(path: &str, size: u32, layout: Layout) -> MaskModel {
+        MaskModel::load(path, size, layout, Kind::Segmentation)
+    }
+
+    /// The model's map of `img` at its size, from 0 to 255. Depth is spread
+    /// from the furthest point in the frame to the nearest.
+    pub fn mask(&self, img: &DynamicImage) -> GrayImage {
+        let size = self.size;
+        let input = imageops::resize(&img.to_rgb8(), size, size, FilterType::Triangle);
+        let value = |x: usize, y: usize, c: usize| {
+            let value = input.get_pixel(x as u32, y as u32).0[c] as f32 / 255.0;
+            match self.kind {
+                Kind::Depth => (value - MEAN[c]) / DEVIATION[c],
+                Kind::Segmentation => value,
+            }
+        };
+        let outputs = self
+            .plan
+            .run(|[_, i, j, k]| match self.layout {
+                Layout::Nchw => value(k, j, i),
+                Layout::Nhwc => value(j, i, k),
+            })
+            .unwrap_or_else(|e| panic!("Failed to run model: {}", e));
+        let output = outputs.first().expect("Mask models output f32");
+        // [1, h, w], [1, 1, h, w] or [1, h, w, 1]
+        let dims: Vec<usize> = output.shape.iter().copied().filter(|&d| d > 1).collect();
+        let [h, w] = dims[..] else {
+            panic!(
+                "Expected a single channel map from the model, got {:?}",
+                output.shape
+            );
+        };
+        let values = &output.values;
+
+        let (low, range) = match self.kind {
+            Kind::Depth => {
+                let (low, high) = values.iter().fold((f32::MAX, f32::MIN), |(low, high), &v| {
+                    (low.min(v), high.max(v))
+                });
+                (low, (high - low).max(f32::EPSILON))
+            }
+            Kind::Segmentation => (0.0, 1.0),
+        };
+        let mask = GrayImage::from_fn(w as u32, h as u32, |x, y| {
+            let v = (values[y as usize * w + x as usize] - low) / range;
+            Luma([(v.clamp(0.0, 1.0) * 255.0).round() as u8])
+        });
+        imageops::resize(&mask, img.width(), img.height(), FilterType::Triangle)
+    }
+}
+
+/// One chain inside a model's mask and another outside, from the near and
+/// far effects of `depthfx` or the subject and background of `segmentfx`.
+pub struct Banded {
+    pub model: MaskModel,
+    pub inside: EffectChain,
+    pub outside: EffectChain,
+    /// Mask value from 0 to 1 where inside begins
+    pub split: f32,
+    /// Width of the blend between the two, in mask value
+    pub softness: f32,
+}
+
+impl Banded {
+    /// `img` with the inside chain where `mask` is above the split and the
+    /// outside chain below it.
+    pub fn apply(&self, img: RgbaImage, mask: &GrayImage, frame: &FrameContext) -> RgbaImage {
+        let inside = self
+            .inside
+            .apply(DynamicImage::ImageRgba8(img.clone()), frame);
+        let outside = self.outside.apply(DynamicImage::ImageRgba8(img), frame);
+
+        let (low, high) = (
+            self.split - self.softness / 2.0,
+            self.split + self.softness / 2.0,
+        );
+        let mut out = outside;
+        for (x, y, pixel) in out.enumerate_pixels_mut() {
+            let value = mask.get_pixel(x, y).0[0] as f32 / 255.0;
+            let t = if high > low {
+                ((value - low) / (high - low)).clamp(0.0, 1.0)
+            } else {
+                (value >= self.split) as u8 as f32
+            };
+            let t = t * t * (3.0 - 2.0 * t);
+            let inside = inside.get_pixel(x, y).0;
+            for c in 0..4 {
+                pixel.0[c] = (pixel.0[c] as f32 * (1.0 - t) + inside[c] as f32 * t).round() as u8;
+            }
+        }
+        out
+    }
+}
+
+/// An effect given as it would be on the command line, e.g. `bloom` or
+/// `"sort --min 40"`. `none` leaves that part untouched.
+pub fn effect_chain(spec: &str, args: &Args) -> EffectChain {
+    if spec.trim() == "none" {
+        return EffectChain::new();
+    }
+    let argv = std::iter::once("vidfx").chain(spec.split_whitespace());
+    let parsed = Args::try_parse_from(argv).unwrap_or_else(|e| e.exit());
+    let effect = parsed
+        .cmd
+        .to_effect(&args.lhs, &args.rhs, args.negate)
+        .unwrap_or_else(|| panic!("{} is not an effect", spec));
+    EffectChain::new().then(effect).linear(args.linear)
+}
@@ -0,0 +1,188 @@
+//! Color matching for the `match-color` effect: each frame's colors are
+//! moved onto the distribution of a reference image or clip, so clips
+//! graded or effected apart cut together.
+//!
+//! Histogram matching maps every channel's levels so their spread matches
+//! the reference's exactly. Reinhard transfer moves the mean and spread of
+//! each channel in the decorrelated lαβ space, a gentler match that keeps a
+//! frame's own contrast shape.
+
+use std::cell::RefCell;
+use std::path::Path;
+
+use image::RgbaImage;
+
+use crate::chain::MatchMethod;
+use crate::generate::{Generator, Pattern};
+use crate::source::Source;
+
+/// Frames sampled across a reference clip.
+const SAMPLES: usize = 12;
+
+/// Every this many pixels are sampled, which is plenty for statistics.
+const SAMPLE_STEP: usize = 4;
+
+/// Size generated references are drawn at.
+const GENERATED_SIZE: (u32, u32) = (160, 90);
+
+/// Darkest value taken into lαβ, whose logarithm would run off at black.
+const FLOOR: f32 = 1.0 / 255.0;
+
+/// What a frame is matched to.
+struct Reference {
+    /// Cumulative share of samples at or below each level, per channel
+    cdf: [[f64; 256]; 3],
+    /// Mean and standard deviation of l, α and β
+    moments: [[f32; 2]; 3],
+}
+
+thread_local! {
+    static LOADED: RefCell<Option<(String, Reference)>> = const { RefCell::new(None) };
+}
+
+fn to_lab([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let lms = [
+        0.3811 * r + 0.5783 * g + 0.0402 * b,
+        0.1967 * r + 0.7244 * g + 0.0782 * b,
+        0.0241 * r + 0.1288 * g + 0.8444 * b,
+    ]
+    .map(|v| v.max(FLOOR).log10());
+    [
+        (lms[0] + lms[1] + lms[2]) / 3f32.sqrt(),
+        (lms[0] + lms[1] - 2.0 * lms[2]) / 6f32.sqrt(),
+        (lms[0] - lms[1]) / 2f32.sqrt(),
+    ]
+}
+
+fn to_rgb([l, a, b]: [f32; 3]) -> [f32; 3] {
+    let (l, a, b) = (l / 3f32.sqrt(), a / 6f32.sqrt(), b / 2f32.sqrt());
+    let [ls, ms, ss] = [l + a + b, l + a - b, l - 2.0 * a].map(|v| 10f32.powf(v));
+    [
+        4.4679 * ls - 3.5873 * ms + 0.1193 * ss,
+        -1.2186 * ls + 2.3809 * ms - 0.1624 * ss,
+        0.0497 * ls - 0.2439 * ms + 1.2045 * ss,
+    ]
+}
+
+/// The statistics of `frames`, each given with its bytes per pixel.
+fn measure<'a>(frames: impl Iterator<Item = (&'a [u8], usize)>) -> Reference {
+    let mut histograms = [[0u64; 256]; 3];
+    let mut sums = [[0f64; 2]; 3];
+    let mut count = 0u64;
+    for (pixels, channels) in frames {
+        for pixel in pixels.chunks_exact(channels).step_by(SAMPLE_STEP) {
+            for (histogram, &v) in histograms.iter_mut().zip(pixel) {
+                histogram[v as usize] += 1;
+            }
+            let lab = to_lab([pixel[0], pixel[1], pixel[2]].map(|v| v as f32 / 255.0));
+            for (sum, v) in sums.iter_mut().zip(lab) {
+                sum[0] += v as f64;
+                sum[1] += v as f64 * v as f64;
+            }
+            count += 1;
+        }
+    }
+
+    let count = count.max(1) as f64;
+    let cdf = histograms.map(|histogram| {
+        let mut seen = 0;
+        histogram.map(|samples| {
+            seen += samples;
+            seen as f64 / count
+        })
+    });
+    let moments = sums.map(|[sum, squares]| {
+        let mean = sum / count;
+        [
+            mean as f32,
+            (squares / count - mean * mean).max(0.0).sqrt() as f32,
+        ]
+    });
+    Reference { cdf, moments }
+}
+
+/// The statistics of the reference `spec`: an image, a video sampled
+/// across its length, or a `generate:` test pattern.
+fn load(spec: &str) -> Reference {
+    if let Some(pattern) = spec.strip_prefix("generate:") {
+        let pattern = Pattern::parse(pattern).unwrap_or_else(|e| panic!("{}", e));
+        let frame = Generator::new(pattern, GENERATED_SIZE, 30.0, 1.0).frame(0);
+        return measure(std::iter::once((frame.as_raw().as_slice(), 3)));
+    }
+    if image::ImageFormat::from_path(spec).is_ok() {
+        let img = image::open(spec)
+            .unwrap_or_else(|e| panic!("Failed to open color reference {}: {}", spec, e))
+            .into_rgb8();
+        return measure(std::iter::once((img.as_raw().as_slice(), 3)));
+    }
+
+    let mut source = Source::open(Path::new(spec))
+        .unwrap_or_else(|e| panic!("Failed to open color reference {}: {}", spec, e));
+    let duration = source.duration().unwrap_or(0.0);
+    let frames: Vec<_> = (0..SAMPLES)
+        // Away from the very start and end, which are often black
+        .filter_map(|i| source.frame_at_time(duration * (i as f64 + 0.5) / SAMPLES as f64))
+        .collect();
+    if frames.is_empty() {
+        panic!("Color reference {} has no frames", spec);
+    }
+    measure(frames.iter().map(|frame| (frame.as_raw().as_slice(), 3)))
+}
+
+/// For every level the one whose share of `to` matches its share of `from`.
+fn lut(from: &[f64; 256], to: &[f64; 256]) -> [u8; 256] {
+    std::array::from_fn(|level| to.partition_point(|&share| share < from[level]).min(255) as u8)
+}
+
+/// `img` with its colors moved `amount` of the way onto those of the
+/// reference `spec`.
+pub fn apply(mut img: RgbaImage, spec: &str, method: MatchMethod, amount: f32) -> RgbaImage {
+    if amount <= 0.0 {
+        return img;
+    }
+    let frame = measure(std::iter::once((img.as_raw().as_slice(), 4)));
+    LOADED.with_borrow_mut(|loaded| {
+        if loaded.as_ref().map_or(true, |(loaded, _)| loaded != spec) {
+            *loaded = Some((spec.to_string(), load(spec)));
+        }
+        let (_, reference) = loaded.as_ref().expect("Reference was just loaded");
+
+        let mix = |from: u8, to: f32| {
+            (from as f32 + (to - from as f32) * amount)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        };
+        match method {
+            MatchMethod::Histogram => {
+                let luts: [[u8; 256]; 3] =
+                    std::array::from_fn(|c| lut(&frame.cdf[c], &reference.cdf[c]));
+                for pixel in img.pixels_mut() {
+                    for c in 0..3 {
+                        pixel.0[c] = mix(pixel.0[c], luts[c][pixel.0[c] as usize] as f32);
+                    }
+                }
+            }
+            MatchMethod::Reinhard => {
+                for pixel in img.pixels_mut() {
+                    let lab =
+                        to_lab([pixel.0[0], pixel.0[1], pixel.0[2]].map(|v| v as f32 / 255.0));
+                    let moved: [f32; 3] = std::array::from_fn(|c| {
+                        let [mean, spread] = frame.moments[c];
+                        let [to_mean, to_spread] = reference.moments[c];
+                        let scale = if spread > f32::EPSILON {
+                            to_spread / spread
+                        } else {
+                            1.0
+                        };
+                        (lab[c] - mean) * scale + to_mean
+                    });
+                    let rgb = to_rgb(moved);
+                    for c in 0..3 {
+                        pixel.0[c] = mix(pixel.0[c], rgb[c] * 255.0);
+                    }
+                }
+            }
+        }
+    });
+    img
+}
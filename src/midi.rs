@@ -0,0 +1,279 @@
+use midly::{MetaMessage, MidiMessage, Smf, Timing, TrackEventKind};
+use serde::Serialize;
+use vidfx::EffectChain;
+
+use crate::randomize::set_param;
+
+/// What a MIDI source drives.
+#[derive(Debug, Serialize)]
+enum Target {
+    /// `effect.field`, optionally over `min..max`. Without a range the value
+    /// scales the field's own value.
+    Param {
+        effect: String,
+        field: String,
+        range: Option<(f64, f64)>,
+    },
+    /// The effects' scale factor, like a `--visualization` mode
+    Envelope,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+enum Source {
+    Controller(u8),
+    Note(u8),
+}
+
+/// One `--map` entry with the file's values for it in time order, 0..1.
+#[derive(Debug, Serialize)]
+struct Lane {
+    target: Target,
+    /// Whether the entry's target starts with `-`, flipping its values
+    inverted: bool,
+    points: Vec<(f64, f64)>,
+}
+
+/// A standard MIDI file driving effect parameters through `--map`, e.g.
+/// `cc1:bloom.intensity, cc74:sort.min_threshold=0.1..0.6, note:C1=envelope`.
+/// A `-` before the target inverts it, `note:C1=-envelope` ducks on the note.
+#[derive(Debug, Serialize)]
+pub struct Automation {
+    lanes: Vec<Lane>,
+}
+
+impl Automation {
+    pub fn load(path: &str, map: &str) -> Automation {
+        let bytes =
+            std::fs::read(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+        let smf = Smf::parse(&bytes).unwrap_or_else(|e| panic!("Failed to parse {}: {}", path, e));
+        let events = timed_events(&smf);
+
+        let lanes = map
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (source, target, inverted) =
+                    parse_mapping(entry).unwrap_or_else(|e| panic!("{}", e));
+                let points = events
+                    .iter()
+                    .filter_map(|(time, message)| {
+                        let value = match (&source, message) {
+                            (
+                                Source::Controller(cc),
+                                MidiMessage::Controller { controller, value },
+                            ) if controller.as_int() == *cc => value.as_int(),
+                            (Source::Note(note), MidiMessage::NoteOn { key, vel })
+                                if key.as_int() == *note =>
+                            {
+                                vel.as_int()
+                            }
+                            (Source::Note(note), MidiMessage::NoteOff { key, .. })
+                                if key.as_int() == *note =>
+                            {
+                                0
+                            }
+                            _ => return None,
+                        };
+                        let value = value as f64 / 127.0;
+                        Some((*time, if inverted { 1.0 - value } else { value }))
+                    })
+                    .collect::<Vec<_>>();
+
+                if points.is_empty() {
+                    eprintln!("Nothing in {} drives '{}'", path, entry);
+                }
+                Lane {
+                    target,
+                    inverted,
+                    points,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if lanes.is_empty() {
+            panic!("No --map provided!");
+        }
+
+        Automation { lanes }
+    }
+
+    /// `chain` with every mapped parameter at its value for `time`.
+    /// Parameters keep their own value until their first event.
+    pub fn chain(&self, chain: &EffectChain, time: f64) -> EffectChain {
+        let mut json = serde_json::to_value(chain).expect("Effect chains serialize");
+
+        for lane in &self.lanes {
+            let Target::Param {
+                effect,
+                field,
+                range,
+            } = &lane.target
+            else {
+                continue;
+            };
+            if let Some(value) = lane.at(time) {
+                set_param(&mut json, effect, field, |current| match range {
+                    Some((min, max)) => min + (max - min) * value,
+                    None => current.unwrap_or(1.0) * value,
+                });
+            }
+        }
+
+        serde_json::from_value(json)
+            .unwrap_or_else(|e| panic!("Failed to apply --midi-file to the chain: {}", e))
+    }
+
+    /// The product of every envelope lane at `time`, if any are mapped.
+    /// Envelopes are 0 until their first note, or 1 when inverted.
+    pub fn envelope(&self, time: f64) -> Option<f64> {
+        self.lanes
+            .iter()
+            .filter(|lane| matches!(lane.target, Target::Envelope))
+            .map(|lane| {
+                lane.at(time)
+                    .unwrap_or(if lane.inverted { 1.0 } else { 0.0 })
+            })
+            .reduce(|a, b| a * b)
+    }
+}
+
+impl Lane {
+    fn at(&self, time: f64) -> Option<f64> {
+        let next = self.points.partition_point(|(t, _)| *t <= time);
+        next.checked_sub(1).map(|i| self.points[i].1)
+    }
+}
+
+/// `cc<n>:<target>` or `note:<key>=<target>`, where the key is a number or
+/// a name like `C1` or `F#3` with middle C as C4. Also returns whether the
+/// target is inverted.
+fn parse_mapping(entry: &str) -> Result<(Source, Target, bool), String> {
+    let invalid = || {
+        format!(
+            "invalid mapping '{}', expected cc<n>:effect.field or note:<key>=envelope",
+            entry
+        )
+    };
+
+    let (source, target) = if let Some(rest) = entry.strip_prefix("note:") {
+        let (key, target) = rest.split_once('=').ok_or_else(invalid)?;
+        (
+            Source::Note(parse_note(key.trim()).ok_or_else(invalid)?),
+            target,
+        )
+    } else if let Some(rest) = entry.strip_prefix("cc") {
+        let (number, target) = rest.split_once(':').ok_or_else(invalid)?;
+        let number = number.trim().parse::<u8>().map_err(|_| invalid())?;
+        if number > 127 {
+            return Err(invalid());
+        }
+        (Source::Controller(number), target)
+    } else {
+        return Err(invalid());
+    };
+
+    let target = target.trim();
+    let (target, inverted) = match target.strip_prefix('-') {
+        Some(target) => (target.trim(), true),
+        None => (target, false),
+    };
+    if target == "envelope" {
+        return Ok((source, Target::Envelope, inverted));
+    }
+
+    let (param, range) = match target.split_once('=') {
+        Some((param, range)) => {
+            let (min, max) = range.split_once("..").ok_or_else(invalid)?;
+            let min = min.trim().parse::<f64>().map_err(|_| invalid())?;
+            let max = max.trim().parse::<f64>().map_err(|_| invalid())?;
+            (param, Some((min, max)))
+        }
+        None => (target, None),
+    };
+    let (effect, field) = param.split_once('.').ok_or_else(invalid)?;
+
+    Ok((
+        source,
+        Target::Param {
+            effect: effect.trim().to_lowercase(),
+            field: field.trim().replace('-', "_"),
+            range,
+        },
+        inverted,
+    ))
+}
+
+fn parse_note(key: &str) -> Option<u8> {
+    if let Ok(number) = key.parse::<u8>() {
+        return (number <= 127).then_some(number);
+    }
+
+    let mut chars = key.chars();
+    let base = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let (accidental, octave) = if let Some(octave) = rest.strip_prefix('#') {
+        (1, octave)
+    } else if let Some(octave) = rest.strip_prefix('b') {
+        (-1, octave)
+    } else {
+        (0, rest)
+    };
+    let octave = octave.parse::<i32>().ok()?;
+
+    let number = (octave + 1) * 12 + base + accidental;
+    u8::try_from(number).ok().filter(|n| *n <= 127)
+}
+
+/// Every channel message in the file with its time in seconds, following
+/// tempo changes on any track.
+fn timed_events(smf: &Smf) -> Vec<(f64, MidiMessage)> {
+    let mut events = vec![];
+    for track in &smf.tracks {
+        let mut tick = 0u64;
+        for event in track {
+            tick += event.delta.as_int() as u64;
+            events.push((tick, event.kind));
+        }
+    }
+    // Stable, so simultaneous events keep their track order
+    events.sort_by_key(|(tick, _)| *tick);
+
+    let mut timed = vec![];
+    match smf.header.timing {
+        Timing::Metrical(ticks_per_beat) => {
+            let ticks_per_beat = ticks_per_beat.as_int() as f64;
+            let (mut seconds, mut last_tick) = (0.0, 0u64);
+            let mut seconds_per_tick = 0.5 / ticks_per_beat;
+            for (tick, kind) in events {
+                seconds += (tick - last_tick) as f64 * seconds_per_tick;
+                last_tick = tick;
+                match kind {
+                    TrackEventKind::Meta(MetaMessage::Tempo(tempo)) => {
+                        seconds_per_tick = tempo.as_int() as f64 / 1_000_000.0 / ticks_per_beat;
+                    }
+                    TrackEventKind::Midi { message, .. } => timed.push((seconds, message)),
+                    _ => {}
+                }
+            }
+        }
+        Timing::Timecode(fps, subframes) => {
+            let ticks_per_second = fps.as_f32() as f64 * subframes as f64;
+            for (tick, kind) in events {
+                if let TrackEventKind::Midi { message, .. } = kind {
+                    timed.push((tick as f64 / ticks_per_second, message));
+                }
+            }
+        }
+    }
+    timed
+}
@@ -0,0 +1,64 @@
+//! Channel mixing and white balance for the `mixer` effect.
+
+use image::RgbaImage;
+
+/// The color temperature nothing is corrected for.
+const NEUTRAL: f32 = 6500.0;
+
+/// Roughly the sRGB color of a black body at `kelvin`, 0 to 1 per channel,
+/// after Tanner Helland's fit.
+fn black_body(kelvin: f32) -> [f32; 3] {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.699 * (t - 60.0).powf(-0.133_205)
+    };
+    let g = if t <= 66.0 {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_17 * (t - 60.0).powf(-0.075_514_85)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+    [r, g, b].map(|v| (v / 255.0).clamp(0.0, 1.0))
+}
+
+/// Channel gains correcting footage lit at `temperature` kelvin, and
+/// towards magenta by `tint` from -100 to 100, keeping its brightness.
+fn white_balance(temperature: Option<f32>, tint: f32) -> [f32; 3] {
+    let mut gains = match temperature {
+        Some(temperature) => {
+            let (lit, neutral) = (black_body(temperature), black_body(NEUTRAL));
+            std::array::from_fn(|c| neutral[c] / lit[c].max(0.01))
+        }
+        None => [1.0; 3],
+    };
+    gains[1] *= 1.0 - tint.clamp(-100.0, 100.0) / 200.0;
+    let luma = 0.2126 * gains[0] + 0.7152 * gains[1] + 0.0722 * gains[2];
+    gains.map(|gain| gain / luma)
+}
+
+/// `img` white balanced, then mixed so each output channel is its `matrix`
+/// row times the input channels.
+pub fn apply(
+    mut img: RgbaImage,
+    matrix: &[[f32; 3]; 3],
+    temperature: Option<f32>,
+    tint: f32,
+) -> RgbaImage {
+    let gains = white_balance(temperature, tint);
+    for pixel in img.pixels_mut() {
+        let balanced: [f32; 3] = std::array::from_fn(|c| pixel.0[c] as f32 * gains[c]);
+        for (c, row) in matrix.iter().enumerate() {
+            let mixed: f32 = row.iter().zip(&balanced).map(|(w, v)| w * v).sum();
+            pixel.0[c] = mixed.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    img
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use image::{imageops, Rgb, RgbImage};
+use serde_json::Value;
+use vidfx::generate::burn_text;
+use vidfx::{EffectChain, FrameContext};
+
+/// Columns written for every frame, whether or not they move.
+const TIMING: [&str; 4] = ["frame", "time", "scale_factor", "beat_phase"];
+
+const PLOT_WIDTH: u32 = 1200;
+const LANE_HEIGHT: u32 = 80;
+
+/// The evaluated per-frame values behind a render, for `--dump-modulation`.
+/// Effect parameters are named `effect.field`, with `#2` and so on for later
+/// effects of the same kind.
+#[derive(Default)]
+pub struct Curves {
+    columns: Vec<String>,
+    index: HashMap<String, usize>,
+    /// One row per frame, `None` where the column's effect isn't applied
+    rows: Vec<Vec<Option<f64>>>,
+}
+
+impl Curves {
+    fn column(&mut self, name: String) -> usize {
+        if let Some(&i) = self.index.get(&name) {
+            return i;
+        }
+        self.columns.push(name.clone());
+        self.index.insert(name, self.columns.len() - 1);
+        self.columns.len() - 1
+    }
+
+    /// Records `frame` going through `chains`, plus `extra` named values such
+    /// as whether the gate is open.
+    pub fn push(&mut self, frame: &FrameContext, extra: &[(&str, f64)], chains: &[EffectChain]) {
+        let mut values = vec![
+            (TIMING[0].to_string(), frame.index as f64),
+            (TIMING[1].to_string(), frame.time),
+            (TIMING[2].to_string(), frame.scale_factor),
+        ];
+        if let Some(phase) = frame.beat_phase {
+            values.push((TIMING[3].to_string(), phase));
+        }
+        values.extend(extra.iter().map(|(name, value)| (name.to_string(), *value)));
+
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        for chain in chains {
+            let json = serde_json::to_value(chain).expect("Effect chains serialize");
+            for entry in json["effects"].as_array().into_iter().flatten() {
+                let effect = entry["effect"].as_str().unwrap_or("?").to_string();
+                let count = seen.entry(effect.clone()).or_default();
+                *count += 1;
+                let prefix = match *count {
+                    1 => effect,
+                    n => format!("{}#{}", effect, n),
+                };
+                for (field, value) in entry.as_object().into_iter().flatten() {
+                    let value = match value {
+                        Value::Number(n) => n.as_f64(),
+                        Value::Bool(b) => Some(*b as u8 as f64),
+                        _ => None,
+                    };
+                    if let Some(value) = value {
+                        values.push((format!("{}.{}", prefix, field), value));
+                    }
+                }
+            }
+        }
+
+        let mut row = vec![];
+        for (name, value) in values {
+            let i = self.column(name);
+            if row.len() <= i {
+                row.resize(i + 1, None);
+            }
+            row[i] = Some(value);
+        }
+        self.rows.push(row);
+    }
+
+    fn value(&self, row: usize, column: usize) -> Option<f64> {
+        self.rows[row].get(column).copied().flatten()
+    }
+
+    /// The timing columns and every other column that changes at least once.
+    fn modulated(&self) -> Vec<usize> {
+        (0..self.columns.len())
+            .filter(|&i| {
+                if TIMING.contains(&self.columns[i].as_str()) {
+                    return true;
+                }
+                let mut values = (0..self.rows.len()).map(|row| self.value(row, i));
+                let first = values.next().flatten();
+                values.any(|value| value != first)
+            })
+            .collect()
+    }
+
+    pub fn write_csv(&self, path: &str) {
+        let columns = self.modulated();
+        let mut csv = String::new();
+        let header: Vec<&str> = columns.iter().map(|&i| self.columns[i].as_str()).collect();
+        csv.push_str(&header.join(","));
+        csv.push('\n');
+
+        for row in 0..self.rows.len() {
+            let cells: Vec<String> = columns
+                .iter()
+                .map(|&i| {
+                    self.value(row, i)
+                        .map(|v| v.to_string())
+                        .unwrap_or_default()
+                })
+                .collect();
+            let _ = writeln!(csv, "{}", cells.join(","));
+        }
+        std::fs::write(path, csv)
+            .unwrap_or_else(|e| panic!("Failed to write modulation dump {}: {}", path, e));
+    }
+
+    /// One lane per curve except the frame index, each scaled to its own
+    /// range, which is printed in the lane's corner.
+    pub fn plot(&self, path: &str) {
+        let columns: Vec<usize> = self
+            .modulated()
+            .into_iter()
+            .filter(|&i| self.columns[i] != TIMING[0])
+            .collect();
+        let mut img = RgbImage::from_pixel(
+            PLOT_WIDTH,
+            LANE_HEIGHT * columns.len().max(1) as u32,
+            Rgb([16, 16, 16]),
+        );
+        let frames = self.rows.len().max(2) - 1;
+
+        for (lane, &column) in columns.iter().enumerate() {
+            let values: Vec<f64> = (0..self.rows.len())
+                .filter_map(|row| self.value(row, column))
+                .collect();
+            let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+            let span = if max > min { max - min } else { 1.0 };
+
+            // Drawn on its own so the label is sized to the lane
+            let mut strip = RgbImage::from_pixel(PLOT_WIDTH, LANE_HEIGHT, Rgb([16, 16, 16]));
+            let hue = lane as f32 * 0.618;
+            let color = Rgb([0.0f32, 2.0 / 3.0, 1.0 / 3.0].map(|offset| {
+                ((0.6 + 0.4 * (std::f32::consts::TAU * (hue + offset)).cos()) * 255.0) as u8
+            }));
+            for x in 0..PLOT_WIDTH {
+                strip.put_pixel(x, 0, Rgb([48, 48, 48]));
+            }
+
+            let mut last = None;
+            for row in 0..self.rows.len() {
+                let Some(value) = self.value(row, column) else {
+                    last = None;
+                    continue;
+                };
+                let x = (row as f64 / frames as f64 * (PLOT_WIDTH - 1) as f64) as u32;
+                let y = LANE_HEIGHT - 4 - ((value - min) / span * (LANE_HEIGHT - 8) as f64) as u32;
+                // Vertical runs keep steps and fast changes connected
+                let (from, to) = last.map_or((y, y), |last: u32| (last.min(y), last.max(y)));
+                for y in from..=to {
+                    strip.put_pixel(x, y, color);
+                }
+                last = Some(y);
+            }
+
+            let label = format!("{} {:.3}..{:.3}", self.columns[column], min, max);
+            burn_text(&mut strip, (0, 1), &[label]);
+            imageops::replace(&mut img, &strip, 0, (lane as u32 * LANE_HEIGHT) as i64);
+        }
+
+        img.save(path)
+            .unwrap_or_else(|e| panic!("Failed to save modulation plot {}: {}", path, e));
+    }
+}
@@ -0,0 +1,141 @@
+use std::panic::PanicHookInfo;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(feature = "notify")]
+use notify_rust::Notification;
+
+use crate::shell_quoted;
+
+/// Set once the end of the render has been reported, so a panic on several
+/// threads or after `report` doesn't report it again.
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// How a render ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Ran to the end
+    Done,
+    /// Stopped early with Ctrl-C
+    Interrupted,
+    /// Stopped early as a disk being written to filled up
+    OutOfSpace,
+    /// Stopped by a panic
+    Failed,
+}
+
+impl Outcome {
+    /// The notification's title.
+    fn summary(self) -> &'static str {
+        match self {
+            Outcome::Done => "vidfx: render finished",
+            Outcome::Interrupted => "vidfx: render interrupted",
+            Outcome::OutOfSpace => "vidfx: render stopped, disk full",
+            Outcome::Failed => "vidfx: render failed",
+        }
+    }
+
+    /// What `{status}` is replaced by.
+    fn status(self) -> &'static str {
+        match self {
+            Outcome::Done => "done",
+            Outcome::Interrupted => "interrupted",
+            Outcome::OutOfSpace => "out-of-space",
+            Outcome::Failed => "failed",
+        }
+    }
+}
+
+/// What to do when a render ends: `--notify` shows a desktop notification,
+/// `--on-complete` runs a shell command with `{output}` and `{status}`
+/// filled in.
+#[derive(Clone, Debug, Default)]
+pub struct Completion {
+    pub notify: bool,
+    pub on_complete: Option<String>,
+    /// What was rendered to, as shown and passed as `{output}`
+    pub outputs: Vec<String>,
+}
+
+impl Completion {
+    fn is_empty(&self) -> bool {
+        !self.notify && self.on_complete.is_none()
+    }
+
+    /// Reports renders that fail with a panic from here on, after the usual
+    /// message is printed.
+    pub fn watch_failures(&self) {
+        if self.is_empty() {
+            return;
+        }
+        let completion = self.clone();
+        let default = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            default(info);
+            completion.report(Outcome::Failed, &panic_message(info));
+        }));
+    }
+
+    /// Reports a render that ended with `outcome`, with `message` as the
+    /// notification's text.
+    pub fn report(&self, outcome: Outcome, message: &str) {
+        if self.is_empty() || REPORTED.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        #[cfg(feature = "notify")]
+        if self.notify {
+            if let Err(e) = Notification::new()
+                .appname("vidfx")
+                .summary(outcome.summary())
+                .body(message)
+                .show()
+            {
+                eprintln!("Failed to show notification: {}", e);
+            }
+        }
+        #[cfg(not(feature = "notify"))]
+        if self.notify {
+            eprintln!(
+                "Not showing \"{}\", vidfx-cli was built without the notify feature",
+                outcome.summary()
+            );
+        }
+
+        if let Some(hook) = &self.on_complete {
+            let output = self
+                .outputs
+                .iter()
+                .map(|output| shell_quoted(output))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let command = hook
+                .replace("{output}", &output)
+                .replace("{status}", outcome.status());
+            if let Err(e) = shell(&command).status() {
+                eprintln!("Failed to run --on-complete hook: {}", e);
+            }
+        }
+    }
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    }
+}
+
+fn panic_message(info: &PanicHookInfo) -> String {
+    let payload = info.payload();
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "vidfx panicked".to_string())
+}
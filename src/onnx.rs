@@ -0,0 +1,93 @@
+//! The ONNX models behind `depthfx`, `segmentfx`, `style`, `upscale` and
+//! `--region faces`, run with tract. Builds without the `onnx` feature leave
+//! tract out and stop when a model is loaded.
+
+#[cfg(feature = "onnx")]
+use tract_onnx::prelude::*;
+
+/// One output of a model: its shape and its values in row-major order.
+pub struct Output {
+    pub shape: Vec<usize>,
+    pub values: Vec<f32>,
+}
+
+/// A model optimized for a single f32 input of a fixed shape.
+#[cfg(feature = "onnx")]
+pub struct Plan {
+    plan: TypedRunnableModel<TypedModel>,
+    shape: [usize; 4],
+}
+
+#[cfg(not(feature = "onnx"))]
+pub enum Plan {}
+
+impl Plan {
+    /// The ONNX model at `path` optimized for a single f32 input of `shape`.
+    #[cfg(feature = "onnx")]
+    pub fn load(path: &str, shape: [usize; 4]) -> Plan {
+        let load = || -> TractResult<_> {
+            tract_onnx::onnx()
+                .model_for_path(path)?
+                .with_input_fact(0, f32::fact(shape).into())?
+                .into_optimized()?
+                .into_runnable()
+        };
+        let plan = load().unwrap_or_else(|e| panic!("Failed to load model {}: {}", path, e));
+        Plan { plan, shape }
+    }
+
+    #[cfg(not(feature = "onnx"))]
+    pub fn load(path: &str, _shape: [usize; 4]) -> Plan {
+        panic!(
+            "Failed to load model {}: vidfx-cli was built without the onnx feature",
+            path
+        )
+    }
+
+    /// Runs the model on the input whose value at each index is `value`,
+    /// giving back the outputs that are f32.
+    #[cfg(feature = "onnx")]
+    pub fn run(&self, value: impl Fn([usize; 4]) -> f32) -> Result<Vec<Output>, String> {
+        let [n, c, h, w] = self.shape;
+        let tensor: Tensor =
+            tract_ndarray::Array4::from_shape_fn((n, c, h, w), |(n, c, h, w)| value([n, c, h, w]))
+                .into();
+        let outputs = self
+            .plan
+            .run(tvec!(tensor.into()))
+            .map_err(|e| e.to_string())?;
+        Ok(outputs
+            .iter()
+            .filter_map(|output| output.to_array_view::<f32>().ok())
+            .map(|view| Output {
+                shape: view.shape().to_vec(),
+                values: view.iter().copied().collect(),
+            })
+            .collect())
+    }
+
+    #[cfg(not(feature = "onnx"))]
+    pub fn run(&self, _value: impl Fn([usize; 4]) -> f32) -> Result<Vec<Output>, String> {
+        match *self {}
+    }
+}
+
+impl Output {
+    /// The output as one RGB image at `scale` per level, for models whose
+    /// last three dimensions are channels, height and width.
+    pub fn rgb(&self, scale: f32) -> Option<image::RgbImage> {
+        let [.., c, h, w] = self.shape[..] else {
+            return None;
+        };
+        if c != 3 || self.values.len() != 3 * h * w {
+            return None;
+        }
+        Some(image::RgbImage::from_fn(w as u32, h as u32, |x, y| {
+            image::Rgb(std::array::from_fn(|c| {
+                (self.values[(c * h + y as usize) * w + x as usize] * scale)
+                    .round()
+                    .clamp(0.0, 255.0) as u8
+            }))
+        }))
+    }
+}
@@ -0,0 +1,645 @@
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use minifb::{Window, WindowOptions};
+use ndarray::Array3;
+use url::Url;
+use video_rs::time::Time;
+
+use crate::preflight;
+use crate::terminal::{self, TermProto};
+use crate::units::format_size;
+use vidfx::encoder::{
+    container_format, image_to_ndarray, is_segmented, Chapter, Codec, EncodeSettings, Pass,
+    Provenance, VideoEncoder,
+};
+use vidfx::source::Source;
+
+/// Where a render goes. Selected from the `--output` value: `preview` opens a
+/// window, anything with a `scheme://` prefix is streamed, the rest are files.
+#[derive(Clone)]
+pub enum OutputTarget {
+    File(PathBuf),
+    Stream(Url),
+    Preview,
+    Terminal(TermProto),
+}
+
+impl OutputTarget {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if s == "preview" {
+            return Ok(OutputTarget::Preview);
+        }
+
+        if s.contains("://") {
+            return Url::parse(s)
+                .map(OutputTarget::Stream)
+                .map_err(|e| format!("invalid stream url '{}': {}", s, e));
+        }
+
+        Ok(OutputTarget::File(PathBuf::from(s)))
+    }
+}
+
+impl fmt::Display for OutputTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputTarget::File(path) => write!(f, "{}", path.display()),
+            OutputTarget::Stream(url) => write!(f, "{}", url),
+            OutputTarget::Preview => write!(f, "preview window"),
+            OutputTarget::Terminal(_) => write!(f, "terminal preview"),
+        }
+    }
+}
+
+/// One branch of the output fan-out. Every sink receives each processed frame.
+pub trait FrameSink {
+    fn write(&mut self, frame: &Array3<u8>, position: Time);
+
+    /// Marks preset changes and the like in the output, called once before
+    /// `finish`. Only files keep them.
+    fn chapters(&mut self, _chapters: &[Chapter]) {}
+
+    fn finish(self: Box<Self>);
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Where a two-pass encode of `path` keeps its lossless first pass.
+pub fn two_pass_cache_path(path: &Path) -> PathBuf {
+    let mut cache = part_path(path).into_os_string();
+    cache.push(".cache.mkv");
+    PathBuf::from(cache)
+}
+
+/// Where `--quality-report` at `report` keeps the frames the outputs got.
+pub fn reference_path(report: &str) -> PathBuf {
+    PathBuf::from(format!("{}.reference.mkv", report))
+}
+
+/// Encodes to `<path>.part` and moves it over `path` once finalized.
+struct FileSink {
+    encoder: VideoEncoder,
+    part: PathBuf,
+    path: PathBuf,
+}
+
+impl FrameSink for FileSink {
+    fn write(&mut self, frame: &Array3<u8>, position: Time) {
+        self.encoder
+            .encode(frame, position)
+            .expect("Failed to encode frame");
+    }
+
+    fn chapters(&mut self, chapters: &[Chapter]) {
+        self.encoder
+            .add_chapters(chapters)
+            .expect("Failed to add chapters");
+    }
+
+    fn finish(mut self: Box<Self>) {
+        self.encoder.finish().expect("Failed to finalize output");
+        let FileSink {
+            encoder,
+            part,
+            path,
+        } = *self;
+        drop(encoder);
+
+        if part != path {
+            std::fs::rename(&part, &path).expect("Failed to move output into place");
+        }
+    }
+}
+
+const NULL_DEVICE: &str = if cfg!(windows) { "NUL" } else { "/dev/null" };
+
+/// Renders into a lossless cache first, then runs a two-pass encode from the
+/// cache with the bit rate that lands the file at `target_size` bytes.
+struct TwoPassSink {
+    cache: VideoEncoder,
+    cache_path: PathBuf,
+    frames: usize,
+    target_size: u64,
+    settings: EncodeSettings,
+    path: PathBuf,
+    chapters: Vec<Chapter>,
+    provenance: Provenance,
+}
+
+impl FrameSink for TwoPassSink {
+    fn write(&mut self, frame: &Array3<u8>, position: Time) {
+        self.cache
+            .encode(frame, position)
+            .expect("Failed to write frame cache");
+        self.frames += 1;
+    }
+
+    fn chapters(&mut self, chapters: &[Chapter]) {
+        self.chapters = chapters.to_vec();
+    }
+
+    fn finish(mut self: Box<Self>) {
+        self.cache.finish().expect("Failed to finalize frame cache");
+        if self.frames == 0 {
+            let _ = std::fs::remove_file(&self.cache_path);
+            panic!(
+                "No frames were rendered, so {} can't be fit to --target-size",
+                self.path.display()
+            );
+        }
+
+        let duration = self.frames as f64 / self.settings.frame_rate;
+        // Leave a little room for container overhead
+        let bit_rate = (self.target_size as f64 * 8.0 * 0.97 / duration) as usize;
+        let settings = EncodeSettings {
+            bit_rate: Some(bit_rate),
+            ..self.settings
+        };
+
+        let part = part_path(&self.path);
+        let stats = part.with_extension("stats");
+        let passes = [
+            (NULL_DEVICE.to_string(), "null", Pass::First(&stats)),
+            (
+                part.to_string_lossy().into_owned(),
+                container_format(&self.path),
+                Pass::Second(&stats),
+            ),
+        ];
+
+        for (destination, format, pass) in passes {
+            let mut encoder = VideoEncoder::new(
+                &destination,
+                format,
+                Codec::H264,
+                &settings,
+                false,
+                Some(pass),
+                &self.provenance,
+            )
+            .expect("Failed to create encoder");
+            encoder
+                .add_chapters(&self.chapters)
+                .expect("Failed to add chapters");
+
+            // Through Source like any input, so the cache's colors come back
+            // as they were written
+            let mut cache = Source::open(&self.cache_path).expect("Failed to read frame cache");
+            let frame_interval = 1.0 / settings.frame_rate;
+            let mut position = Time::zero();
+            while let Some(frame) = cache.next_frame() {
+                encoder
+                    .encode(&image_to_ndarray(&frame), position)
+                    .expect("Failed to encode frame");
+                position = Time::from_secs_f64(position.as_secs_f64() + frame_interval);
+            }
+            encoder.finish().expect("Failed to finalize output");
+        }
+
+        std::fs::rename(&part, &self.path).expect("Failed to move output into place");
+        let _ = std::fs::remove_file(&self.cache_path);
+        let _ = std::fs::remove_file(&stats);
+        let mut mbtree = stats.into_os_string();
+        mbtree.push(".mbtree");
+        let _ = std::fs::remove_file(mbtree);
+    }
+}
+
+struct StreamSink {
+    encoder: VideoEncoder,
+}
+
+impl FrameSink for StreamSink {
+    fn write(&mut self, frame: &Array3<u8>, position: Time) {
+        self.encoder
+            .encode(frame, position)
+            .expect("Failed to send frame to stream");
+    }
+
+    fn finish(mut self: Box<Self>) {
+        self.encoder.finish().expect("Failed to finalize stream");
+    }
+}
+
+/// Shows frames in a window as they are rendered. Closing the window only stops
+/// the preview, the other outputs keep going.
+struct PreviewSink {
+    window: Option<Window>,
+    buffer: Vec<u32>,
+}
+
+impl FrameSink for PreviewSink {
+    fn write(&mut self, frame: &Array3<u8>, _position: Time) {
+        let Some(window) = self.window.as_mut() else {
+            return;
+        };
+
+        if !window.is_open() {
+            self.window = None;
+            return;
+        }
+
+        let (height, width, _) = frame.dim();
+        self.buffer.clear();
+        self.buffer.extend(
+            frame
+                .rows()
+                .into_iter()
+                .map(|p| (p[0] as u32) << 16 | (p[1] as u32) << 8 | p[2] as u32),
+        );
+
+        window
+            .update_with_buffer(&self.buffer, width, height)
+            .expect("Failed to update preview window");
+    }
+
+    fn finish(self: Box<Self>) {}
+}
+
+/// Draws frames into the terminal, at most every `interval` so slow terminals
+/// don't hold up the render.
+struct TerminalSink {
+    proto: TermProto,
+    interval: Duration,
+    last_draw: Option<Instant>,
+}
+
+impl FrameSink for TerminalSink {
+    fn write(&mut self, frame: &Array3<u8>, _position: Time) {
+        if self
+            .last_draw
+            .is_some_and(|last| last.elapsed() < self.interval)
+        {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+
+        let (height, width, _) = frame.dim();
+        let img =
+            image::RgbImage::from_raw(width as u32, height as u32, frame.iter().copied().collect())
+                .expect("Failed to convert frame for terminal preview");
+
+        let mut stdout = std::io::stdout().lock();
+        let _ = stdout.write_all(terminal::render(&img, self.proto).as_bytes());
+        let _ = stdout.flush();
+    }
+
+    fn finish(self: Box<Self>) {
+        println!();
+    }
+}
+
+/// Reports how far the render has got on stderr, at most every `interval`.
+/// As a line redrawn in place, or with `json` one event per line for
+/// programs wrapping vidfx.
+struct ProgressSink {
+    /// Frames the render will write, when known
+    total: Option<usize>,
+    json: bool,
+    frames: usize,
+    started: Instant,
+    interval: Duration,
+    last_draw: Option<Instant>,
+}
+
+impl ProgressSink {
+    fn fps(&self) -> f64 {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.frames as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    fn json_event(&self, event: &str) {
+        let total = self.total.filter(|&total| total > 0);
+        let fps = self.fps();
+        let line = serde_json::json!({
+            "event": event,
+            "frame": self.frames,
+            "total": total,
+            "pct": total.map(|total| 100.0 * self.frames as f64 / total as f64),
+            "fps": fps,
+            "eta": total
+                .filter(|_| fps > 0.0)
+                .map(|total| total.saturating_sub(self.frames) as f64 / fps),
+            "elapsed": self.started.elapsed().as_secs_f64(),
+        });
+        eprintln!("{}", line);
+    }
+}
+
+impl FrameSink for ProgressSink {
+    fn write(&mut self, _frame: &Array3<u8>, _position: Time) {
+        self.frames += 1;
+        if self
+            .last_draw
+            .is_some_and(|last| last.elapsed() < self.interval)
+        {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+
+        if self.json {
+            self.json_event("progress");
+            return;
+        }
+        let mut stderr = std::io::stderr().lock();
+        let _ = match self.total {
+            Some(total) if total > 0 => write!(
+                stderr,
+                "\r{} / {} frames ({:.0}%)",
+                self.frames,
+                total,
+                100.0 * self.frames as f64 / total as f64
+            ),
+            _ => write!(stderr, "\r{} frames", self.frames),
+        };
+        let _ = stderr.flush();
+    }
+
+    fn finish(self: Box<Self>) {
+        if self.json {
+            self.json_event("done");
+            return;
+        }
+        eprintln!(
+            "\r{} frames in {:.1}s",
+            self.frames,
+            self.started.elapsed().as_secs_f64()
+        );
+    }
+}
+
+/// A sink counting frames out of `total` on stderr, as JSON lines with
+/// `json`.
+pub fn progress(total: Option<usize>, json: bool) -> Box<dyn FrameSink> {
+    Box::new(ProgressSink {
+        total,
+        json,
+        frames: 0,
+        started: Instant::now(),
+        interval: Duration::from_millis(200),
+        last_draw: None,
+    })
+}
+
+/// Stops the render once a disk being written to is nearly full, so files
+/// end cleanly instead of failing mid-write in the encoder.
+struct SpaceWatchSink {
+    dirs: Vec<PathBuf>,
+    cancelled: Arc<AtomicBool>,
+    /// Set along with `cancelled`, so the stop isn't taken for Ctrl-C
+    out_of_space: Arc<AtomicBool>,
+    interval: Duration,
+    last_check: Instant,
+}
+
+/// Free space left for the container to finish its files.
+const MIN_FREE_SPACE: u64 = 64 * 1000 * 1000;
+
+impl FrameSink for SpaceWatchSink {
+    fn write(&mut self, _frame: &Array3<u8>, _position: Time) {
+        if self.last_check.elapsed() < self.interval || self.cancelled.load(Ordering::SeqCst) {
+            return;
+        }
+        self.last_check = Instant::now();
+
+        for dir in &self.dirs {
+            match preflight::available_space(dir) {
+                Some(free) if free < MIN_FREE_SPACE => {
+                    eprintln!(
+                        "\nStopping: only {} left on the disk holding {}",
+                        format_size(free),
+                        dir.display()
+                    );
+                    self.out_of_space.store(true, Ordering::SeqCst);
+                    self.cancelled.store(true, Ordering::SeqCst);
+                    return;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn finish(self: Box<Self>) {}
+}
+
+/// A sink that sets `cancelled` and `out_of_space` when any of `dirs` runs
+/// low on space.
+pub fn space_watch(
+    dirs: Vec<PathBuf>,
+    cancelled: Arc<AtomicBool>,
+    out_of_space: Arc<AtomicBool>,
+) -> Box<dyn FrameSink> {
+    Box::new(SpaceWatchSink {
+        dirs,
+        cancelled,
+        out_of_space,
+        interval: Duration::from_secs(1),
+        last_check: Instant::now(),
+    })
+}
+
+/// Saves the frames showing at each of `times` as PNGs in `dir`, named by
+/// frame number.
+struct SnapshotSink {
+    /// Times still to capture, latest first
+    times: Vec<f64>,
+    frame_rate: f64,
+    dir: PathBuf,
+}
+
+impl FrameSink for SnapshotSink {
+    fn write(&mut self, frame: &Array3<u8>, position: Time) {
+        let start = position.as_secs_f64();
+        let end = start + 1.0 / self.frame_rate;
+        let mut due = false;
+        while self.times.last().is_some_and(|&time| time < end - 1e-9) {
+            due |= self.times.pop().is_some_and(|time| time >= start - 1e-9);
+        }
+        if !due {
+            return;
+        }
+
+        let (height, width, _) = frame.dim();
+        let img =
+            image::RgbImage::from_raw(width as u32, height as u32, frame.iter().copied().collect())
+                .expect("Failed to convert frame for snapshot");
+        let index = (start * self.frame_rate).round() as usize;
+        let path = self.dir.join(format!("still_{:06}.png", index));
+        img.save(&path)
+            .unwrap_or_else(|e| panic!("Failed to save snapshot {}: {}", path.display(), e));
+    }
+
+    fn finish(self: Box<Self>) {
+        for time in self.times.iter().rev() {
+            eprintln!("The render ended before the snapshot at {:.3}s", time);
+        }
+    }
+}
+
+/// A sink saving stills from the render at `times`, in seconds.
+pub fn snapshots(times: &[f64], dir: &Path, frame_rate: f64) -> Box<dyn FrameSink> {
+    std::fs::create_dir_all(dir).expect("Failed to create snapshot directory");
+    let mut times = times.to_vec();
+    times.sort_by(|a, b| b.total_cmp(a));
+    Box::new(SnapshotSink {
+        times,
+        frame_rate,
+        dir: dir.to_path_buf(),
+    })
+}
+
+/// A lossless copy at `path` of exactly the frames the outputs are sent, for
+/// `--quality-report` to compare the encoded output with.
+pub fn reference(path: &Path, settings: &EncodeSettings) -> Box<dyn FrameSink> {
+    let encoder = VideoEncoder::new(
+        &path.to_string_lossy(),
+        "matroska",
+        Codec::Ffv1,
+        settings,
+        false,
+        None,
+        &Provenance::default(),
+    )
+    .expect("Failed to create quality reference");
+    Box::new(FileSink {
+        encoder,
+        part: path.to_path_buf(),
+        path: path.to_path_buf(),
+    })
+}
+
+/// Opens the sink for `target`. A `target_size` in bytes turns file outputs into
+/// two-pass encodes. File outputs record their `provenance`.
+pub fn open(
+    target: &OutputTarget,
+    settings: &EncodeSettings,
+    target_size: Option<u64>,
+    provenance: &Provenance,
+) -> Box<dyn FrameSink> {
+    match target {
+        OutputTarget::File(path) if target_size.is_some() => {
+            if settings.codec.is_some_and(|codec| codec != Codec::H264) {
+                panic!("--target-size only supports h264");
+            }
+            // Checked here, as the second pass only opens its muxer once
+            // every frame has been rendered
+            if !Codec::H264.fits(container_format(path)) {
+                panic!(
+                    "--target-size encodes h264, which can't be stored in {}",
+                    path.display()
+                );
+            }
+
+            let cache_path = two_pass_cache_path(path);
+
+            let cache = VideoEncoder::new(
+                &cache_path.to_string_lossy(),
+                "matroska",
+                Codec::Ffv1,
+                settings,
+                false,
+                None,
+                &Provenance::default(),
+            )
+            .expect("Failed to create frame cache");
+
+            Box::new(TwoPassSink {
+                cache,
+                cache_path,
+                frames: 0,
+                target_size: target_size.unwrap(),
+                settings: *settings,
+                path: path.clone(),
+                chapters: vec![],
+                provenance: provenance.clone(),
+            })
+        }
+        OutputTarget::File(path) => {
+            let format = container_format(path);
+            // Segments are named after the playlist and served as they come,
+            // so playlists are written in place
+            let part = if is_segmented(format) {
+                path.clone()
+            } else {
+                part_path(path)
+            };
+            let codec = settings.codec.unwrap_or_else(|| Codec::default_for(path));
+            if !codec.fits(format) {
+                panic!("The selected codec can't be stored in {}", path.display());
+            }
+
+            let encoder = VideoEncoder::new(
+                &part.to_string_lossy(),
+                format,
+                codec,
+                settings,
+                false,
+                None,
+                provenance,
+            )
+            .expect("Failed to create encoder");
+
+            Box::new(FileSink {
+                encoder,
+                part,
+                path: path.clone(),
+            })
+        }
+        OutputTarget::Stream(url) => {
+            let format = match url.scheme() {
+                "rtmp" | "rtmps" => "flv",
+                "srt" | "udp" => "mpegts",
+                "rtsp" => "rtsp",
+                _ => "flv",
+            };
+
+            let encoder = VideoEncoder::new(
+                url.as_str(),
+                format,
+                settings.codec.unwrap_or(Codec::H264),
+                settings,
+                true,
+                None,
+                &Provenance::default(),
+            )
+            .expect("Failed to open output stream");
+
+            Box::new(StreamSink { encoder })
+        }
+        OutputTarget::Terminal(proto) => {
+            print!("\x1b[2J");
+            Box::new(TerminalSink {
+                proto: *proto,
+                interval: Duration::from_millis(100),
+                last_draw: None,
+            })
+        }
+        OutputTarget::Preview => {
+            let window = Window::new(
+                "vidfx preview",
+                settings.width as usize,
+                settings.height as usize,
+                WindowOptions::default(),
+            )
+            .expect("Failed to open preview window");
+
+            Box::new(PreviewSink {
+                window: Some(window),
+                buffer: Vec::with_capacity((settings.width * settings.height) as usize),
+            })
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::ffi::{c_char, CString};
+
+use image::RgbaImage;
+use libloading::{Library, Symbol};
+
+/// Must match `VIDFX_PLUGIN_ABI_VERSION` in `include/vidfx_plugin.h`.
+const ABI_VERSION: u32 = 1;
+
+#[repr(C)]
+struct VidfxFrame {
+    data: *mut u8,
+    width: u32,
+    height: u32,
+    stride: u32,
+}
+
+#[repr(C)]
+struct VidfxParam {
+    key: *const c_char,
+    value: *const c_char,
+}
+
+#[repr(C)]
+struct VidfxParams {
+    params: *const VidfxParam,
+    len: usize,
+    scale_factor: f64,
+}
+
+type ProcessFn = unsafe extern "C" fn(*mut VidfxFrame, *const VidfxParams) -> i32;
+type AbiVersionFn = unsafe extern "C" fn() -> u32;
+
+/// An effect from a dynamic library, see `include/vidfx_plugin.h`.
+pub struct Plugin {
+    name: String,
+    process: ProcessFn,
+    // Keep the strings alive for as long as `raw_params` points into them
+    _params: Vec<(CString, CString)>,
+    raw_params: Vec<VidfxParam>,
+    // Dropped last so `process` stays valid
+    _library: Library,
+}
+
+impl Plugin {
+    /// Loads `path` and checks its ABI version. `params` are `key=value` pairs
+    /// handed to every call.
+    pub fn load(path: &str, params: &[String]) -> Result<Plugin, String> {
+        // Running the library's initializers is inherent to loading a plugin
+        let library =
+            unsafe { Library::new(path) }.map_err(|e| format!("could not load {}: {}", path, e))?;
+
+        unsafe {
+            if let Ok(abi_version) = library.get::<AbiVersionFn>(b"vidfx_plugin_abi_version") {
+                let version = abi_version();
+                if version != ABI_VERSION {
+                    return Err(format!(
+                        "{} was built for plugin ABI {}, expected {}",
+                        path, version, ABI_VERSION
+                    ));
+                }
+            }
+        }
+
+        let process = unsafe {
+            let symbol: Symbol<ProcessFn> = library
+                .get(b"vidfx_plugin_process")
+                .map_err(|e| format!("{} has no vidfx_plugin_process: {}", path, e))?;
+            *symbol
+        };
+
+        let params = params
+            .iter()
+            .map(|param| {
+                let (key, value) = param
+                    .split_once('=')
+                    .ok_or_else(|| format!("plugin param '{}' is not key=value", param))?;
+                let key = CString::new(key).map_err(|e| e.to_string())?;
+                let value = CString::new(value).map_err(|e| e.to_string())?;
+                Ok((key, value))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let raw_params = params
+            .iter()
+            .map(|(key, value)| VidfxParam {
+                key: key.as_ptr(),
+                value: value.as_ptr(),
+            })
+            .collect();
+
+        Ok(Plugin {
+            name: path.to_string(),
+            process,
+            _params: params,
+            raw_params,
+            _library: library,
+        })
+    }
+
+    pub fn process(&self, mut img: RgbaImage, scale_factor: f64) -> RgbaImage {
+        let (width, height) = img.dimensions();
+        let mut frame = VidfxFrame {
+            data: img.as_mut_ptr(),
+            width,
+            height,
+            stride: width * 4,
+        };
+        let params = VidfxParams {
+            params: self.raw_params.as_ptr(),
+            len: self.raw_params.len(),
+            scale_factor,
+        };
+
+        let status = unsafe { (self.process)(&mut frame, &params) };
+        if status != 0 {
+            panic!("Plugin {} failed with status {}", self.name, status);
+        }
+
+        img
+    }
+}
@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use vidfx::encoder::{Codec, EncodeSettings};
+
+use crate::output::{self, OutputTarget};
+use crate::units::format_size;
+
+/// What a render should come to, worked out before it starts.
+pub struct Estimate {
+    /// Seconds of output, unknown for endless and live inputs
+    duration: Option<f64>,
+    frame_rate: f64,
+    /// Each output with its approximate size, for files when the length is
+    /// known
+    outputs: Vec<(OutputTarget, Option<u64>)>,
+    /// Files written beside the outputs while rendering, the lossless first
+    /// passes of two-pass encodes and the `--quality-report` reference, with
+    /// their approximate sizes
+    scratch: Vec<(PathBuf, u64)>,
+    /// Whether sizes come from a typical bit rate rather than `--target-size`
+    guessed: bool,
+}
+
+impl Estimate {
+    /// `target_size` is the size two-pass files are encoded to,
+    /// `quality_report` where `--quality-report` writes.
+    pub fn new(
+        outputs: &[OutputTarget],
+        settings: &EncodeSettings,
+        target_size: Option<u64>,
+        quality_report: Option<&str>,
+        duration: Option<f64>,
+    ) -> Estimate {
+        let files: Vec<&PathBuf> = outputs
+            .iter()
+            .filter_map(|output| match output {
+                OutputTarget::File(path) => Some(path),
+                _ => None,
+            })
+            .collect();
+        let lossless = duration.map(|duration| {
+            (settings.typical_bit_rate(Codec::Ffv1) as f64 * duration / 8.0) as u64
+        });
+        let caches = files
+            .iter()
+            .filter(|_| target_size.is_some())
+            .map(|path| output::two_pass_cache_path(path));
+        let reference = quality_report
+            .filter(|_| !files.is_empty())
+            .map(output::reference_path);
+        let scratch = match lossless {
+            Some(size) => caches.chain(reference).map(|path| (path, size)).collect(),
+            None => vec![],
+        };
+
+        let outputs = outputs
+            .iter()
+            .map(|output| {
+                let size = match output {
+                    OutputTarget::File(path) => duration.map(|duration| {
+                        target_size.unwrap_or_else(|| {
+                            let codec = settings.codec.unwrap_or_else(|| Codec::default_for(path));
+                            (settings.estimated_bit_rate(codec) as f64 * duration / 8.0) as u64
+                        })
+                    }),
+                    _ => None,
+                };
+                (output.clone(), size)
+            })
+            .collect();
+        Estimate {
+            duration,
+            frame_rate: settings.frame_rate,
+            outputs,
+            scratch,
+            guessed: target_size.is_none(),
+        }
+    }
+
+    /// Whether the sizes are a guess from a typical bit rate, which content
+    /// can easily run well over or under.
+    pub fn is_guess(&self) -> bool {
+        self.guessed
+    }
+
+    /// Bytes written across every file output.
+    pub fn total_size(&self) -> u64 {
+        self.outputs.iter().filter_map(|(_, size)| *size).sum()
+    }
+
+    /// Directories that look too full for the files going into them, with
+    /// the bytes needed and the bytes free.
+    pub fn short_of_space(&self) -> Vec<(PathBuf, u64, u64)> {
+        let mut needed: BTreeMap<PathBuf, u64> = BTreeMap::new();
+        for (output, size) in &self.outputs {
+            if let (OutputTarget::File(path), Some(size)) = (output, size) {
+                *needed.entry(output_dir(path)).or_default() += size;
+            }
+        }
+        for (path, size) in &self.scratch {
+            *needed.entry(output_dir(path)).or_default() += size;
+        }
+        needed
+            .into_iter()
+            .filter_map(|(dir, needed)| {
+                let free = available_space(&dir)?;
+                (needed > free).then_some((dir, needed, free))
+            })
+            .collect()
+    }
+
+    pub fn print(&self) {
+        match self.duration {
+            Some(duration) => eprintln!(
+                "Rendering {:.2}s, {} frames at {}fps",
+                duration,
+                (duration * self.frame_rate).round() as usize,
+                self.frame_rate
+            ),
+            None => eprintln!("Rendering until the input ends"),
+        }
+        for (output, size) in &self.outputs {
+            match size {
+                Some(size) => eprintln!("  {}: about {}", output, format_size(*size)),
+                None => eprintln!("  {}", output),
+            }
+        }
+        for (path, size) in &self.scratch {
+            eprintln!(
+                "  {} while rendering: about {}",
+                path.display(),
+                format_size(*size)
+            );
+        }
+    }
+}
+
+/// The directory `path` is written into, which exists unless the render
+/// fails anyway.
+pub fn output_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Bytes free on the disk holding `dir`, unknown when it can't be read or
+/// vidfx-cli was built without the `disk-space` feature.
+#[cfg(feature = "disk-space")]
+pub fn available_space(dir: &Path) -> Option<u64> {
+    fs2::available_space(dir).ok()
+}
+
+#[cfg(not(feature = "disk-space"))]
+pub fn available_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+/// Asks on the terminal whether to go ahead. Anything but `y` is a no.
+pub fn confirm(question: &str) -> bool {
+    eprint!("{} [y/N] ", question);
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .expect("Failed to read answer");
+    matches!(answer.trim(), "y" | "Y" | "yes")
+}
@@ -0,0 +1,142 @@
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+use vidfx::{schema, Effect, EffectChain};
+
+/// Settings that name files, resolved against the project's directory.
+const PATH_SETTINGS: &[&str] = &[
+    "cache-dir",
+    "markers",
+    "midi-file",
+    "plugin",
+    "preset-dir",
+    "quality-report",
+];
+
+/// A `.vidfx` project: JSON bundling the input, outputs, effect chain and any
+/// other command line settings, rendered with `vidfx render project.vidfx`.
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "input": "clip.mp4",
+///   "output": ["render.mp4"],
+///   "effects": [{ "effect": "bloom", "intensity": 1.5, "radius": 8, "min_threshold": 100 }],
+///   "settings": { "bpm": 120, "visualization": "sine", "markers": "cues.txt" }
+/// }
+/// ```
+///
+/// Settings are long flag names without the dashes. `true` passes a switch,
+/// arrays repeat the flag.
+#[derive(Deserialize)]
+pub struct Project {
+    input: Option<String>,
+    #[serde(default)]
+    output: Vec<String>,
+    #[serde(default)]
+    effects: Vec<Effect>,
+    #[serde(default)]
+    settings: serde_json::Map<String, Value>,
+}
+
+impl Project {
+    pub fn load(path: &str) -> Project {
+        let json = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Failed to read project {}: {}", path, e));
+        serde_json::from_str(&json)
+            .map_err(|e| e.to_string())
+            .and_then(schema::migrate)
+            .and_then(|json| serde_json::from_value(json).map_err(|e| e.to_string()))
+            .unwrap_or_else(|e| panic!("Failed to parse project {}: {}", path, e))
+    }
+
+    pub fn chain(&self) -> EffectChain {
+        self.effects
+            .iter()
+            .cloned()
+            .fold(EffectChain::new(), EffectChain::then)
+    }
+
+    /// The project as command line flags, with paths made relative to the
+    /// directory `path` is in.
+    pub fn args(&self, path: &str) -> Vec<String> {
+        let base = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        let mut args = vec![];
+        if let Some(input) = &self.input {
+            args.extend(["--input".to_string(), resolve(base, input)]);
+        }
+        for output in &self.output {
+            args.extend(["--output".to_string(), resolve(base, output)]);
+        }
+        args.extend(settings_args(&self.settings, base));
+        args
+    }
+}
+
+/// `file` relative to `base`, leaving urls, test patterns and `preview` be.
+fn resolve(base: &Path, file: &str) -> String {
+    if file == "preview" || file.contains("://") || file.starts_with("generate:") {
+        file.to_string()
+    } else {
+        base.join(file).to_string_lossy().into_owned()
+    }
+}
+
+/// Settings keyed by long flag name as command line flags. `true` passes a
+/// switch, arrays repeat the flag and paths are made relative to `base`.
+pub fn settings_args(settings: &serde_json::Map<String, Value>, base: &Path) -> Vec<String> {
+    let mut args = vec![];
+    for (name, value) in settings {
+        let flag = format!("--{}", name);
+        let values = match value {
+            Value::Array(values) => values.clone(),
+            value => vec![value.clone()],
+        };
+        for value in values {
+            let value = match value {
+                Value::Bool(true) => {
+                    args.push(flag.clone());
+                    continue;
+                }
+                Value::Bool(false) | Value::Null => continue,
+                Value::String(s) => s,
+                value => value.to_string(),
+            };
+            let value = if PATH_SETTINGS.contains(&name.as_str()) {
+                resolve(base, &value)
+            } else {
+                value
+            };
+            args.extend([flag.clone(), value]);
+        }
+    }
+    args
+}
+
+/// Rewrites a project or preset file in place at the current format version.
+pub fn upgrade(path: &str) {
+    let text =
+        std::fs::read_to_string(path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+    let json: Value =
+        serde_json::from_str(&text).unwrap_or_else(|e| panic!("Failed to parse {}: {}", path, e));
+
+    let from = schema::version(&json);
+    if from == schema::VERSION {
+        eprintln!("{} is already version {}", path, from);
+        return;
+    }
+
+    let json =
+        schema::migrate(json).unwrap_or_else(|e| panic!("Failed to upgrade {}: {}", path, e));
+    let pretty = serde_json::to_string_pretty(&json).expect("JSON values serialize");
+    std::fs::write(path, pretty + "\n")
+        .unwrap_or_else(|e| panic!("Failed to write {}: {}", path, e));
+    eprintln!(
+        "Upgraded {} from version {} to {}",
+        path,
+        from,
+        schema::VERSION
+    );
+}
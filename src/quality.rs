@@ -0,0 +1,154 @@
+use std::path::Path;
+
+use image::RgbImage;
+use serde::Serialize;
+
+use vidfx::color::Correction;
+use vidfx::source::decode_frame;
+
+#[derive(Serialize)]
+struct FrameQuality {
+    frame: usize,
+    /// `None` when the frames are identical
+    psnr: Option<f64>,
+    ssim: f64,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    frames: usize,
+    psnr_mean: Option<f64>,
+    psnr_min: Option<f64>,
+    ssim_mean: f64,
+    ssim_min: f64,
+}
+
+#[derive(Serialize)]
+struct Report {
+    source: String,
+    output: String,
+    summary: Summary,
+    frames: Vec<FrameQuality>,
+}
+
+fn psnr(a: &RgbImage, b: &RgbImage) -> Option<f64> {
+    let (sum, count) =
+        a.as_raw()
+            .iter()
+            .zip(b.as_raw())
+            .fold((0u64, 0u64), |(sum, count), (&x, &y)| {
+                let d = x as i64 - y as i64;
+                (sum + (d * d) as u64, count + 1)
+            });
+
+    if sum == 0 {
+        return None;
+    }
+
+    let mse = sum as f64 / count as f64;
+    Some(10.0 * (255.0 * 255.0 / mse).log10())
+}
+
+fn luma(img: &RgbImage) -> Vec<f64> {
+    img.pixels()
+        .map(|p| 0.299 * p.0[0] as f64 + 0.587 * p.0[1] as f64 + 0.114 * p.0[2] as f64)
+        .collect()
+}
+
+/// Mean SSIM of the luma plane over non-overlapping 8x8 windows.
+fn ssim(a: &RgbImage, b: &RgbImage) -> f64 {
+    const WINDOW: usize = 8;
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+    let (width, height) = (a.width() as usize, a.height() as usize);
+    let (la, lb) = (luma(a), luma(b));
+
+    let mut total = 0.0;
+    let mut windows = 0;
+
+    for wy in (0..height.saturating_sub(WINDOW - 1)).step_by(WINDOW) {
+        for wx in (0..width.saturating_sub(WINDOW - 1)).step_by(WINDOW) {
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in wy..wy + WINDOW {
+                for x in wx..wx + WINDOW {
+                    let (pa, pb) = (la[y * width + x], lb[y * width + x]);
+                    sa += pa;
+                    sb += pb;
+                    saa += pa * pa;
+                    sbb += pb * pb;
+                    sab += pa * pb;
+                }
+            }
+
+            let n = (WINDOW * WINDOW) as f64;
+            let (ma, mb) = (sa / n, sb / n);
+            let va = saa / n - ma * ma;
+            let vb = sbb / n - mb * mb;
+            let cov = sab / n - ma * mb;
+
+            total += ((2.0 * ma * mb + C1) * (2.0 * cov + C2))
+                / ((ma * ma + mb * mb + C1) * (va + vb + C2));
+            windows += 1;
+        }
+    }
+
+    if windows == 0 {
+        1.0
+    } else {
+        total / windows as f64
+    }
+}
+
+/// Decodes `reference`, the frames as they were sent to the encoder, and the
+/// encoded `output` side by side and writes per-frame PSNR/SSIM with a
+/// summary to `report_path` as JSON. `source` names the render's input.
+pub fn write_report(source: &str, reference: &Path, output: &Path, report_path: &Path) {
+    let mut source_decoder =
+        video_rs::Decoder::new(reference).expect("Failed to open quality reference");
+    let mut output_decoder =
+        video_rs::Decoder::new(output).expect("Failed to open output for quality report");
+
+    // Both sides as the effects saw them, so color handling isn't counted
+    // as error
+    let corrections = [Correction::probe(reference), Correction::probe(output)];
+    let mut frames = vec![];
+    while let (Some(mut a), Some(mut b)) = (
+        decode_frame(&mut source_decoder),
+        decode_frame(&mut output_decoder),
+    ) {
+        for (img, correction) in [&mut a, &mut b].into_iter().zip(&corrections) {
+            if let Some(correction) = correction {
+                correction.apply(img);
+            }
+        }
+        frames.push(FrameQuality {
+            frame: frames.len(),
+            psnr: psnr(&a, &b),
+            ssim: ssim(&a, &b),
+        });
+    }
+
+    let psnrs: Vec<f64> = frames.iter().filter_map(|f| f.psnr).collect();
+    let summary = Summary {
+        frames: frames.len(),
+        psnr_mean: (!psnrs.is_empty()).then(|| psnrs.iter().sum::<f64>() / psnrs.len() as f64),
+        psnr_min: psnrs.iter().copied().reduce(f64::min),
+        ssim_mean: frames.iter().map(|f| f.ssim).sum::<f64>() / frames.len().max(1) as f64,
+        ssim_min: frames
+            .iter()
+            .map(|f| f.ssim)
+            .reduce(f64::min)
+            .unwrap_or(1.0),
+    };
+
+    let report = Report {
+        source: source.to_string(),
+        output: output.display().to_string(),
+        summary,
+        frames,
+    };
+
+    let file = std::fs::File::create(report_path).expect("Failed to create quality report");
+    serde_json::to_writer_pretty(file, &report).expect("Failed to write quality report");
+}
@@ -0,0 +1,51 @@
+use serde::Serialize;
+
+/// Sample and hold on the beat grid: `--quantize 1/8` keeps every modulation
+/// value still for an eighth note at a time, `1/8t` for an eighth triplet.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Quantize {
+    /// Step length in whole notes
+    notes: f64,
+}
+
+impl Quantize {
+    pub fn parse(s: &str) -> Result<Quantize, String> {
+        let invalid = || format!("invalid subdivision '{}', expected e.g. 1/8 or 1/16t", s);
+        let (s, triplet) = match s.trim().strip_suffix('t') {
+            Some(s) => (s, true),
+            None => (s.trim(), false),
+        };
+        let notes = match s.split_once('/') {
+            Some((n, d)) => {
+                let n = n.trim().parse::<f64>().map_err(|_| invalid())?;
+                let d = d.trim().parse::<f64>().map_err(|_| invalid())?;
+                n / d
+            }
+            None => s.parse::<f64>().map_err(|_| invalid())?,
+        };
+        if !(notes > 0.0 && notes.is_finite()) {
+            return Err(invalid());
+        }
+        Ok(Quantize {
+            notes: if triplet { notes * 2.0 / 3.0 } else { notes },
+        })
+    }
+
+    /// The first frame of the step frame `index` falls in, whose modulation
+    /// it shows. Steps are laid out on a straight grid at `bpm`, `to_grid`
+    /// and `from_grid` mapping output time onto it and back.
+    pub fn held(
+        &self,
+        index: usize,
+        frame_rate: f64,
+        bpm: u32,
+        to_grid: impl Fn(f64) -> f64,
+        from_grid: impl Fn(f64) -> f64,
+    ) -> usize {
+        // A whole note is four beats
+        let step = 240.0 / bpm as f64 * self.notes;
+        let time = to_grid(index as f64 / frame_rate);
+        let start = from_grid((time / step + 1e-9).floor() * step);
+        ((start * frame_rate - 1e-9).ceil().max(0.0) as usize).min(index)
+    }
+}
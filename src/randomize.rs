@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use serde_json::Value;
+use vidfx::hash::random;
+use vidfx::{Effect, EffectChain};
+
+/// `effect.field=min..max` ranges that effect parameters jump around in,
+/// e.g. `sort.min_threshold=0.1..0.4, bloom.radius=2..16`.
+#[derive(Serialize)]
+pub struct Randomize {
+    params: Vec<Range>,
+    seed: u64,
+    /// The last step applied to each chain, by the chain's JSON, since
+    /// values only change from one step to the next
+    #[serde(skip)]
+    applied: Mutex<HashMap<String, (usize, EffectChain)>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Range {
+    effect: String,
+    field: String,
+    min: f64,
+    max: f64,
+    /// Largest value an integer field holds, so ranges past it are clamped
+    /// rather than failing to deserialize
+    integer_max: Option<f64>,
+}
+
+/// The largest value `field` of the serialized effect `entry` takes, if it is
+/// an integer field.
+fn integer_max(entry: &Value, field: &str) -> Option<f64> {
+    if !entry[field].is_u64() {
+        return None;
+    }
+    let fits = |value: u64| {
+        let mut entry = entry.clone();
+        entry[field] = Value::from(value);
+        serde_json::from_value::<Effect>(entry).is_ok()
+    };
+    let max = [u8::MAX as u64, u16::MAX as u64, u32::MAX as u64]
+        .into_iter()
+        .find(|&max| !fits(max + 1))
+        .unwrap_or(u64::MAX);
+    Some(max as f64)
+}
+
+impl Randomize {
+    /// Fails for ranges naming an effect that isn't in any of `chains`, or a
+    /// field that isn't a number on it.
+    pub fn parse(spec: &str, seed: u64, chains: &[&EffectChain]) -> Result<Randomize, String> {
+        let params = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|param| !param.is_empty())
+            .map(|param| {
+                let invalid =
+                    || format!("invalid range '{}', expected effect.field=min..max", param);
+                let (key, range) = param.split_once('=').ok_or_else(invalid)?;
+                let (effect, field) = key.trim().split_once('.').ok_or_else(invalid)?;
+                let (min, max) = range.split_once("..").ok_or_else(invalid)?;
+                let min = min.trim().parse::<f64>().map_err(|_| invalid())?;
+                let max = max.trim().parse::<f64>().map_err(|_| invalid())?;
+
+                let effect = effect.trim().to_lowercase();
+                let field = field.trim().replace('-', "_");
+                let entries: Vec<Value> = chains
+                    .iter()
+                    .flat_map(|chain| &chain.effects)
+                    .map(|entry| serde_json::to_value(entry).expect("Effects serialize"))
+                    .filter(|entry| entry["effect"] == effect.as_str())
+                    .collect();
+                let Some(entry) = entries.first() else {
+                    return Err(format!(
+                        "--randomize names {}, which isn't in the chain",
+                        effect
+                    ));
+                };
+                if !entry[&field].is_number() {
+                    return Err(format!(
+                        "--randomize names {}.{}, which isn't a number on {}",
+                        effect, field, effect
+                    ));
+                }
+
+                Ok(Range {
+                    integer_max: integer_max(entry, &field),
+                    effect,
+                    field,
+                    min: min.min(max),
+                    max: min.max(max),
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        if params.is_empty() {
+            return Err("No ranges in --randomize!".to_string());
+        }
+
+        Ok(Randomize {
+            params,
+            seed,
+            applied: Mutex::default(),
+        })
+    }
+
+    /// `chain` with every matching parameter set to its value for `step`.
+    /// The same seed and step always give the same values.
+    pub fn chain(&self, chain: &EffectChain, step: usize) -> EffectChain {
+        let key = serde_json::to_string(chain).expect("Effect chains serialize");
+        let mut applied = self.applied.lock().expect("Randomize cache poisoned");
+        if let Some((applied_step, applied)) = applied.get(&key) {
+            if *applied_step == step {
+                return applied.clone();
+            }
+        }
+
+        let mut json: Value = serde_json::from_str(&key).expect("Effect chains serialize");
+        for (i, range) in self.params.iter().enumerate() {
+            let t = random(self.seed, step as u64, i as u64);
+            let value = range.min + (range.max - range.min) * t;
+            let value = match range.integer_max {
+                Some(max) => value.clamp(0.0, max),
+                None => value,
+            };
+            set_param(&mut json, &range.effect, &range.field, |_| value);
+        }
+
+        let randomized: EffectChain = serde_json::from_value(json)
+            .unwrap_or_else(|e| panic!("Failed to apply --randomize to the chain: {}", e));
+        applied.insert(key, (step, randomized.clone()));
+        randomized
+    }
+}
+
+/// Sets `field` on every `effect` in a serialized chain to `value(current)`,
+/// rounding for integer fields.
+pub fn set_param(json: &mut Value, effect: &str, field: &str, value: impl Fn(Option<f64>) -> f64) {
+    let effects = json["effects"]
+        .as_array_mut()
+        .expect("Effect chains serialize their effects as an array");
+
+    for entry in effects.iter_mut().filter(|entry| entry["effect"] == effect) {
+        let current = &entry[field];
+        let integer = current.is_u64() || current.is_i64();
+        let value = value(current.as_f64());
+        entry[field] = if integer {
+            Value::from(value.round() as i64)
+        } else {
+            Value::from(value)
+        };
+    }
+}
@@ -0,0 +1,142 @@
+//! Reframing footage to another aspect ratio, e.g. landscape to 9:16 for
+//! vertical social video, before any effect sees it.
+
+use image::imageops::{self, FilterType};
+use image::RgbImage;
+
+/// How `reframe` fills the new aspect ratio.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ReframeMode {
+    /// Cut the middle out at the new aspect ratio
+    Crop,
+    /// Fit the whole frame in, over a blurred and enlarged copy of itself
+    Blurpad,
+    /// Crop, following where the movement in the frame is
+    TrackCenter,
+}
+
+/// How much of the way the tracked crop moves towards the movement each
+/// frame, low enough not to jitter.
+const TRACK_SPEED: f64 = 0.05;
+
+/// Sample every this many pixels when looking for movement.
+const TRACK_STEP: usize = 4;
+
+/// How far down background frames are shrunk before blurring, which blurs
+/// the same for a fraction of the work.
+const BLUR_SHRINK: u32 = 8;
+
+/// Even so that 4:2:0 encoders take it.
+fn even(size: f64) -> u32 {
+    ((size / 2.0).round() as u32 * 2).max(2)
+}
+
+/// The frame size `mode` makes of `width`x`height` at `aspect`. Crops are the
+/// largest that fit in the frame, padded frames keep its longer side.
+pub fn size(width: u32, height: u32, (aw, ah): (u32, u32), mode: ReframeMode) -> (u32, u32) {
+    let aspect = aw as f64 / ah as f64;
+    let (w, h) = (width as f64, height as f64);
+    match mode {
+        ReframeMode::Crop | ReframeMode::TrackCenter => {
+            if w / h > aspect {
+                (even(h * aspect).min(width), height)
+            } else {
+                (width, even(w / aspect).min(height))
+            }
+        }
+        ReframeMode::Blurpad => {
+            let long = w.max(h);
+            if aspect < 1.0 {
+                (even(long * aspect), even(long))
+            } else {
+                (even(long), even(long / aspect))
+            }
+        }
+    }
+}
+
+/// `frames` reframed to `width`x`height` with `mode`.
+pub fn reframed<'a>(
+    frames: Box<dyn Iterator<Item = RgbImage> + 'a>,
+    (width, height): (u32, u32),
+    mode: ReframeMode,
+) -> Box<dyn Iterator<Item = RgbImage> + 'a> {
+    match mode {
+        ReframeMode::Crop => Box::new(frames.map(move |frame| {
+            let x = (frame.width().saturating_sub(width)) / 2;
+            let y = (frame.height().saturating_sub(height)) / 2;
+            imageops::crop_imm(&frame, x, y, width, height).to_image()
+        })),
+        ReframeMode::Blurpad => Box::new(frames.map(move |frame| blurpad(&frame, width, height))),
+        ReframeMode::TrackCenter => {
+            let mut previous: Option<RgbImage> = None;
+            let mut center: Option<(f64, f64)> = None;
+            Box::new(frames.map(move |frame| {
+                let target = previous
+                    .as_ref()
+                    .and_then(|previous| movement_center(previous, &frame));
+                let (cx, cy) = center.get_or_insert((0.5, 0.5));
+                if let Some((tx, ty)) = target {
+                    *cx += (tx - *cx) * TRACK_SPEED;
+                    *cy += (ty - *cy) * TRACK_SPEED;
+                }
+
+                let span = |center: f64, size: u32, crop: u32| {
+                    let start = center * size as f64 - crop as f64 / 2.0;
+                    start.clamp(0.0, size.saturating_sub(crop) as f64).round() as u32
+                };
+                let x = span(*cx, frame.width(), width);
+                let y = span(*cy, frame.height(), height);
+                let out = imageops::crop_imm(&frame, x, y, width, height).to_image();
+                previous = Some(frame);
+                out
+            }))
+        }
+    }
+}
+
+fn blurpad(frame: &RgbImage, width: u32, height: u32) -> RgbImage {
+    let (small_w, small_h) = ((width / BLUR_SHRINK).max(1), (height / BLUR_SHRINK).max(1));
+    let background = imageops::resize_to_fill(frame, small_w, small_h, FilterType::Triangle);
+    let background = imageops::blur(&background, 4.0);
+    let mut out = imageops::resize(&background, width, height, FilterType::Triangle);
+
+    let scale = (width as f64 / frame.width() as f64).min(height as f64 / frame.height() as f64);
+    let (fit_w, fit_h) = (
+        ((frame.width() as f64 * scale).round() as u32).clamp(1, width),
+        ((frame.height() as f64 * scale).round() as u32).clamp(1, height),
+    );
+    let foreground = imageops::resize(frame, fit_w, fit_h, FilterType::Lanczos3);
+    imageops::overlay(
+        &mut out,
+        &foreground,
+        ((width - fit_w) / 2) as i64,
+        ((height - fit_h) / 2) as i64,
+    );
+    out
+}
+
+/// Where in the frame things changed since `previous`, as fractions of the
+/// width and height. `None` for a still frame.
+fn movement_center(previous: &RgbImage, frame: &RgbImage) -> Option<(f64, f64)> {
+    if previous.dimensions() != frame.dimensions() {
+        return None;
+    }
+    let (mut total, mut sum_x, mut sum_y) = (0.0, 0.0, 0.0);
+    for y in (0..frame.height()).step_by(TRACK_STEP) {
+        for x in (0..frame.width()).step_by(TRACK_STEP) {
+            let (a, b) = (previous.get_pixel(x, y).0, frame.get_pixel(x, y).0);
+            let change: u32 = (0..3).map(|c| a[c].abs_diff(b[c]) as u32).sum();
+            let weight = change as f64;
+            total += weight;
+            sum_x += weight * x as f64;
+            sum_y += weight * y as f64;
+        }
+    }
+    (total > 0.0).then(|| {
+        (
+            sum_x / total / frame.width() as f64,
+            sum_y / total / frame.height() as f64,
+        )
+    })
+}
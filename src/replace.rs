@@ -0,0 +1,84 @@
+//! Selective color replacement for the `replace-color` effect: pixels near
+//! one hue are moved to another color, keeping their own shading.
+
+use image::RgbaImage;
+
+/// Pixels this grey or greyer have no hue worth matching.
+const GREY: f32 = 0.1;
+
+fn to_hsv([r, g, b]: [f32; 3]) -> [f32; 3] {
+    let max = r.max(g).max(b);
+    let range = max - r.min(g).min(b);
+    let hue = if range <= f32::EPSILON {
+        0.0
+    } else if max == r {
+        ((g - b) / range).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / range + 2.0
+    } else {
+        (r - g) / range + 4.0
+    } / 6.0;
+    let saturation = if max > 0.0 { range / max } else { 0.0 };
+    [hue, saturation, max]
+}
+
+fn to_rgb([h, s, v]: [f32; 3]) -> [f32; 3] {
+    let h = h.rem_euclid(1.0) * 6.0;
+    let c = v * s;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    let [r, g, b] = match h as u32 {
+        0 => [c, x, 0.0],
+        1 => [x, c, 0.0],
+        2 => [0.0, c, x],
+        3 => [0.0, x, c],
+        4 => [x, 0.0, c],
+        _ => [c, 0.0, x],
+    };
+    let m = v - c;
+    [r + m, g + m, b + m]
+}
+
+/// `img` with hues within `tolerance` of `from`'s, as a fraction of half the
+/// hue wheel, moved to `to`, fading out over `feather` past that.
+pub fn apply(
+    mut img: RgbaImage,
+    from: [u8; 3],
+    to: [u8; 3],
+    tolerance: f32,
+    feather: f32,
+) -> RgbaImage {
+    let hsv = |color: [u8; 3]| to_hsv(color.map(|v| v as f32 / 255.0));
+    let (from, to) = (hsv(from), hsv(to));
+    let ratio = |to: f32, from: f32| if from > f32::EPSILON { to / from } else { 1.0 };
+    let (saturation, value) = (ratio(to[1], from[1]), ratio(to[2], from[2]));
+
+    for pixel in img.pixels_mut() {
+        let rgb = [pixel.0[0], pixel.0[1], pixel.0[2]].map(|v| v as f32 / 255.0);
+        let [h, s, v] = to_hsv(rgb);
+        // Distance around the hue wheel, 1 being the opposite side
+        let distance = (h - from[0]).rem_euclid(1.0);
+        let distance = distance.min(1.0 - distance) * 2.0;
+        let near = if distance <= tolerance {
+            1.0
+        } else if feather > 0.0 {
+            (1.0 - (distance - tolerance) / feather).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let weight = near * (s / GREY).min(1.0);
+        if weight <= 0.0 {
+            continue;
+        }
+
+        let replaced = to_rgb([
+            h + to[0] - from[0],
+            (s * saturation).clamp(0.0, 1.0),
+            (v * value).clamp(0.0, 1.0),
+        ]);
+        for c in 0..3 {
+            let mixed = rgb[c] + (replaced[c] - rgb[c]) * weight;
+            pixel.0[c] = (mixed * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    img
+}
@@ -0,0 +1,42 @@
+//! Radial sine waves for the `ripple` effect, spreading out from an origin
+//! like rings on water.
+
+use std::f64::consts::TAU;
+
+use image::RgbaImage;
+
+use crate::displace::sample;
+use crate::FrameContext;
+
+/// `img` pushed towards and away from `origin`, in fractions of the frame,
+/// by waves of `amplitude` pixels every `wavelength` pixels that travel
+/// `speed` wavelengths a second.
+pub fn apply(
+    img: RgbaImage,
+    amplitude: f32,
+    wavelength: f32,
+    speed: f32,
+    origin: [f32; 2],
+    frame: &FrameContext,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let (cx, cy) = (
+        origin[0] as f64 * width as f64,
+        origin[1] as f64 * height as f64,
+    );
+    let wavelength = (wavelength as f64).max(1.0);
+    let phase = frame.time * speed as f64;
+    RgbaImage::from_fn(width, height, |x, y| {
+        let (dx, dy) = (x as f64 - cx, y as f64 - cy);
+        let distance = (dx * dx + dy * dy).sqrt();
+        if distance < f64::EPSILON {
+            return *img.get_pixel(x, y);
+        }
+        let push = amplitude as f64 * (TAU * (distance / wavelength - phase)).sin();
+        sample(
+            &img,
+            x as f64 - dx / distance * push,
+            y as f64 - dy / distance * push,
+        )
+    })
+}
@@ -0,0 +1,39 @@
+//! Versioning for the JSON files vidfx reads, effect chain presets and
+//! `.vidfx` projects. Files carry a `version` field and older ones are
+//! migrated up to [`VERSION`] as they load.
+
+use serde_json::Value;
+
+/// The version files are written with.
+pub const VERSION: u64 = 1;
+
+/// `MIGRATIONS[n]` rewrites a version `n` file as version `n + 1`.
+const MIGRATIONS: [fn(&mut Value); VERSION as usize] = [
+    // Files from before the version field have the same layout as version 1
+    |_| {},
+];
+
+/// A file's version, 0 for files written before versioning.
+pub fn version(json: &Value) -> u64 {
+    json.get("version").and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// Brings `json` up to the current version and stamps it with it. Fails for
+/// files from a newer vidfx.
+pub fn migrate(mut json: Value) -> Result<Value, String> {
+    let from = version(&json);
+    if from > VERSION {
+        return Err(format!(
+            "written by a newer vidfx (version {}, this one reads up to {})",
+            from, VERSION
+        ));
+    }
+
+    for migration in &MIGRATIONS[from as usize..] {
+        migration(&mut json);
+    }
+    if let Some(object) = json.as_object_mut() {
+        object.insert("version".to_string(), Value::from(VERSION));
+    }
+    Ok(json)
+}
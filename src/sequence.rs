@@ -0,0 +1,78 @@
+use std::path::Path;
+
+use vidfx::chain::Per;
+use vidfx::EffectChain;
+
+/// Named presets stepped through on the beat grid, e.g. `acid,clean,invert`.
+/// `clean` is the empty chain; any other name is `<preset dir>/<name>.json`,
+/// or a path to an effect chain JSON file.
+pub struct Sequence {
+    steps: Vec<EffectChain>,
+    names: Vec<String>,
+    per: Per,
+}
+
+impl Sequence {
+    pub fn load(spec: &str, preset_dir: &str, per: Per) -> Sequence {
+        let mut loaded: Vec<(&str, EffectChain)> = vec![];
+        let names = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>();
+        let steps = names
+            .iter()
+            .map(|&name| {
+                if let Some((_, chain)) = loaded.iter().find(|(n, _)| *n == name) {
+                    return chain.clone();
+                }
+                let chain = load_preset(name, preset_dir);
+                loaded.push((name, chain.clone()));
+                chain
+            })
+            .collect::<Vec<_>>();
+
+        if steps.is_empty() {
+            panic!("No presets in --sequence!");
+        }
+
+        Sequence {
+            steps,
+            names: names.into_iter().map(str::to_string).collect(),
+            per,
+        }
+    }
+
+    /// The preset for the step playing at `time`.
+    pub fn chain_at(&self, time: f64, bpm: u32) -> &EffectChain {
+        &self.steps[self.per.step(time, bpm) % self.steps.len()]
+    }
+
+    /// The name of the preset playing at `time`, as given in the sequence.
+    pub fn name_at(&self, time: f64, bpm: u32) -> &str {
+        &self.names[self.per.step(time, bpm) % self.names.len()]
+    }
+
+    /// Every step's chain, for cache keys.
+    pub fn steps(&self) -> &[EffectChain] {
+        &self.steps
+    }
+}
+
+/// `clean` is the empty chain, anything else a JSON file in `preset_dir`
+/// or a path ending in `.json`.
+pub fn load_preset(name: &str, preset_dir: &str) -> EffectChain {
+    if name == "clean" {
+        return EffectChain::new();
+    }
+
+    let path = if name.ends_with(".json") {
+        Path::new(name).to_path_buf()
+    } else {
+        Path::new(preset_dir).join(format!("{}.json", name))
+    };
+    let json = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Failed to read preset {}: {}", path.display(), e));
+    EffectChain::from_json(&json)
+        .unwrap_or_else(|e| panic!("Failed to parse preset {}: {}", path.display(), e))
+}
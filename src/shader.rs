@@ -0,0 +1,501 @@
+#[cfg(feature = "gpu")]
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use image::RgbaImage;
+
+use crate::FrameContext;
+
+#[cfg(feature = "gpu")]
+const VERTEX_SHADER: &str = r#"
+@vertex
+fn main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+/// A user uniform, declared after the built-ins in the uniform block.
+#[derive(Clone)]
+pub enum UniformValue {
+    Float(f32),
+    Int(i32),
+    Bool(bool),
+    Vec2([f32; 2]),
+    Vec4([f32; 4]),
+}
+
+impl UniformValue {
+    fn glsl_type(&self) -> &'static str {
+        match self {
+            UniformValue::Float(_) => "float",
+            UniformValue::Int(_) => "int",
+            UniformValue::Bool(_) => "bool",
+            UniformValue::Vec2(_) => "vec2",
+            UniformValue::Vec4(_) => "vec4",
+        }
+    }
+
+    /// std140 base alignment in bytes
+    fn align(&self) -> usize {
+        match self {
+            UniformValue::Float(_) | UniformValue::Int(_) | UniformValue::Bool(_) => 4,
+            UniformValue::Vec2(_) => 8,
+            UniformValue::Vec4(_) => 16,
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            UniformValue::Float(v) => v.to_le_bytes().to_vec(),
+            UniformValue::Int(v) => v.to_le_bytes().to_vec(),
+            UniformValue::Bool(v) => (*v as u32).to_le_bytes().to_vec(),
+            UniformValue::Vec2(v) => v.iter().flat_map(|c| c.to_le_bytes()).collect(),
+            UniformValue::Vec4(v) => v.iter().flat_map(|c| c.to_le_bytes()).collect(),
+        }
+    }
+}
+
+pub struct Uniform {
+    pub name: String,
+    pub value: UniformValue,
+}
+
+/// GLSL 450 declarations every front end builds on: the uniform block with the
+/// built-ins (iResolution, iTime, iBeat, iAudio, iFrame, iScale) and `uniforms`,
+/// the input frame as iChannel0, the `vidfx_out` color output and
+/// `vidfx_fragcoord`, the fragment position with a bottom left origin. The input
+/// is uploaded bottom row first so sampling it with normalized `vidfx_fragcoord`
+/// behaves as it does in GL.
+pub fn prelude(uniforms: &[Uniform]) -> String {
+    let user_uniforms: String = uniforms
+        .iter()
+        .map(|u| format!("    {} {};\n", u.value.glsl_type(), u.name))
+        .collect();
+
+    format!(
+        r#"#version 450
+layout(set = 0, binding = 0) uniform VidfxUniforms {{
+    vec3 iResolution;
+    float iTime;
+    float iBeat;
+    float iAudio;
+    int iFrame;
+    float iScale;
+{user_uniforms}}};
+layout(set = 0, binding = 1) uniform texture2D vidfx_input;
+layout(set = 0, binding = 2) uniform sampler vidfx_sampler;
+#define iChannel0 sampler2D(vidfx_input, vidfx_sampler)
+#define vidfx_fragcoord vec2(gl_FragCoord.x, iResolution.y - gl_FragCoord.y)
+layout(location = 0) out vec4 vidfx_out;
+"#
+    )
+}
+
+/// Built-ins followed by `uniforms` with std140 offsets, padded to 16 bytes.
+fn pack_uniforms(width: u32, height: u32, uniforms: &[Uniform], frame: &FrameContext) -> Vec<u8> {
+    let builtins: [[u8; 4]; 8] = [
+        (width as f32).to_le_bytes(),
+        (height as f32).to_le_bytes(),
+        1f32.to_le_bytes(),
+        (frame.time as f32).to_le_bytes(),
+        (frame.beat_phase.unwrap_or(0.0) as f32).to_le_bytes(),
+        // No audio analysis yet, shaders see silence
+        0f32.to_le_bytes(),
+        (frame.index as i32).to_le_bytes(),
+        (frame.scale_factor as f32).to_le_bytes(),
+    ];
+
+    let mut bytes = builtins.concat();
+    for uniform in uniforms {
+        bytes.resize(bytes.len().next_multiple_of(uniform.value.align()), 0);
+        bytes.extend(uniform.value.bytes());
+    }
+    bytes.resize(bytes.len().next_multiple_of(16), 0);
+    bytes
+}
+
+/// Shadertoy front end: wraps `mainImage` and turns `--uniform name=value`
+/// into float uniforms.
+fn shadertoy_source(user: &str, uniforms: &[Uniform]) -> String {
+    format!(
+        r#"{}
+{user}
+
+void main() {{
+    vec4 color = vec4(0.0);
+    mainImage(color, vidfx_fragcoord);
+    vidfx_out = vec4(color.rgb, 1.0);
+}}
+"#,
+        prelude(uniforms)
+    )
+}
+
+fn parse_uniforms(uniforms: &[String]) -> Result<Vec<Uniform>, String> {
+    uniforms
+        .iter()
+        .map(|u| {
+            let (name, value) = u
+                .split_once('=')
+                .ok_or_else(|| format!("uniform '{}' is not name=value", u))?;
+            let value = value
+                .parse::<f32>()
+                .map_err(|_| format!("uniform '{}' is not a number", u))?;
+            Ok(Uniform {
+                name: name.trim().to_string(),
+                value: UniformValue::Float(value),
+            })
+        })
+        .collect()
+}
+
+#[cfg(feature = "gpu")]
+struct Renderer {
+    key: String,
+    width: u32,
+    height: u32,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    input: wgpu::Texture,
+    output: wgpu::Texture,
+    readback: wgpu::Buffer,
+    padded_row: u32,
+}
+
+#[cfg(feature = "gpu")]
+impl Renderer {
+    fn new(
+        key: &str,
+        source: String,
+        uniform_size: usize,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, String> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        }))
+        .ok_or("no GPU adapter available for shader")?;
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("vidfx shader"),
+                required_features: wgpu::Features::empty(),
+                required_limits: wgpu::Limits::default(),
+                memory_hints: wgpu::MemoryHints::Performance,
+            },
+            None,
+        ))
+        .map_err(|e| e.to_string())?;
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+
+        let vertex = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("vidfx fullscreen triangle"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(VERTEX_SHADER)),
+        });
+        let fragment = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("vidfx user shader"),
+            source: wgpu::ShaderSource::Glsl {
+                shader: Cow::Owned(source),
+                stage: wgpu::naga::ShaderStage::Fragment,
+                defines: Default::default(),
+            },
+        });
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = |usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: None,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let input = texture(wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST);
+        let output =
+            texture(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC);
+
+        let padded_row = (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("vidfx readback"),
+            size: (padded_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("vidfx uniforms"),
+            size: uniform_size as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let input_view = input.create_view(&Default::default());
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&input_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("vidfx shader"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &vertex,
+                entry_point: "main",
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &fragment,
+                entry_point: "main",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: wgpu::TextureFormat::Rgba8Unorm,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            return Err(format!("{}: {}", key, error));
+        }
+
+        Ok(Self {
+            key: key.to_string(),
+            width,
+            height,
+            device,
+            queue,
+            pipeline,
+            bind_group,
+            uniform_buffer,
+            input,
+            output,
+            readback,
+            padded_row,
+        })
+    }
+
+    fn render(&self, img: &RgbaImage, uniforms: &[u8]) -> RgbaImage {
+        self.queue.write_buffer(&self.uniform_buffer, 0, uniforms);
+
+        let flipped = image::imageops::flip_vertical(img);
+        let size = wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        };
+        self.queue.write_texture(
+            self.input.as_image_copy(),
+            flipped.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.width * 4),
+                rows_per_image: Some(self.height),
+            },
+            size,
+        );
+
+        let view = self.output.create_view(&Default::default());
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        encoder.copy_texture_to_buffer(
+            self.output.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.readback,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            size,
+        );
+        self.queue.submit([encoder.finish()]);
+
+        let slice = self.readback.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("Failed to read back shader output")
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+
+        let mut pixels = Vec::with_capacity((self.width * self.height * 4) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(self.padded_row as usize) {
+                pixels.extend_from_slice(&row[..(self.width * 4) as usize]);
+            }
+        }
+        self.readback.unmap();
+
+        RgbaImage::from_raw(self.width, self.height, pixels).expect("Shader output has wrong size")
+    }
+}
+
+/// Builds without the `gpu` feature leave wgpu out and can't run shaders.
+#[cfg(not(feature = "gpu"))]
+struct Renderer {
+    key: String,
+    width: u32,
+    height: u32,
+    never: std::convert::Infallible,
+}
+
+#[cfg(not(feature = "gpu"))]
+impl Renderer {
+    fn new(_: &str, _: String, _: usize, _: u32, _: u32) -> Result<Self, String> {
+        Err("vidfx was built without the gpu feature".to_string())
+    }
+
+    fn render(&self, _: &RgbaImage, _: &[u8]) -> RgbaImage {
+        match self.never {}
+    }
+}
+
+thread_local! {
+    /// The GPU pipeline is expensive to build, so it is kept around for as long
+    /// as frames keep coming with the same shader and size.
+    static RENDERER: RefCell<Option<Renderer>> = const { RefCell::new(None) };
+}
+
+/// Renders `img` through the shader identified by `key`, only building the
+/// pipeline from `source` when the key or frame size changes.
+pub fn run(
+    key: &str,
+    source: impl FnOnce() -> Result<String, String>,
+    uniforms: &[Uniform],
+    img: RgbaImage,
+    frame: &FrameContext,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let packed = pack_uniforms(width, height, uniforms, frame);
+
+    RENDERER.with_borrow_mut(|renderer| {
+        let stale = renderer.as_ref().map_or(true, |r| {
+            r.key != key || (r.width, r.height) != (width, height)
+        });
+        if stale {
+            let built =
+                source().and_then(|source| Renderer::new(key, source, packed.len(), width, height));
+            *renderer = Some(built.unwrap_or_else(|e| panic!("Failed to build shader: {}", e)));
+        }
+
+        renderer
+            .as_ref()
+            .expect("Renderer was just built")
+            .render(&img, &packed)
+    })
+}
+
+/// Runs the Shadertoy style fragment shader in `file` over `img`.
+pub fn apply(file: &str, uniforms: &[String], img: RgbaImage, frame: &FrameContext) -> RgbaImage {
+    let uniforms = parse_uniforms(uniforms).unwrap_or_else(|e| panic!("{}", e));
+
+    run(
+        file,
+        || {
+            std::fs::read_to_string(file)
+                .map(|user| shadertoy_source(&user, &uniforms))
+                .map_err(|e| format!("could not read {}: {}", file, e))
+        },
+        &uniforms,
+        img,
+        frame,
+    )
+}
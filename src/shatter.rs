@@ -0,0 +1,149 @@
+//! Voronoi cells for the `shatter` effect: the frame is broken into cells
+//! around points on a jittered grid, each filled with its average color for
+//! stained glass or with its own shard of the frame.
+
+use image::{Rgba, RgbaImage};
+
+use crate::chain::{Jitter, ShatterFill};
+use crate::hash::random;
+use crate::FrameContext;
+
+/// Keeps cell centers this far into their grid square, so cells stay even.
+const MARGIN: f64 = 0.15;
+
+/// Which jitter step the frame is on and how far the centers are thrown.
+fn throw(jitter: Jitter, frame: &FrameContext, scale_factor: f64) -> (u64, f64) {
+    match jitter {
+        Jitter::None => (0, 0.0),
+        Jitter::Beat => {
+            let beats = frame
+                .beats
+                .or_else(|| frame.bpm.map(|bpm| frame.time * bpm as f64 / 60.0));
+            match beats {
+                // A kick on every beat that settles before the next
+                Some(beats) => (beats.floor() as u64 + 1, (1.0 - beats.fract()).powi(2)),
+                None => (0, 0.0),
+            }
+        }
+        Jitter::Modulate => (1, scale_factor),
+    }
+}
+
+/// `img` as about `cells` Voronoi cells, their centers thrown up to `amount`
+/// of a cell's size by `jitter`, with `edges` pixel wide dark lines between.
+#[allow(clippy::too_many_arguments)]
+pub fn apply(
+    img: RgbaImage,
+    cells: u32,
+    jitter: Jitter,
+    amount: f32,
+    fill: ShatterFill,
+    edges: f32,
+    frame: &FrameContext,
+    scale_factor: f64,
+) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let spacing = ((width as f64 * height as f64) / cells.max(1) as f64).sqrt();
+    let columns = (width as f64 / spacing).ceil().max(1.0) as usize;
+    let rows = (height as f64 / spacing).ceil().max(1.0) as usize;
+
+    let (step, reach) = throw(jitter, frame, scale_factor);
+    // Up to a cell, so the search below stays small
+    let reach = (reach * amount as f64).clamp(0.0, 1.0) * spacing;
+    // How many squares away a center can be and still be nearer a pixel than
+    // its own square's, which is up to `1 - MARGIN + reach` cells off on
+    // each axis
+    let search =
+        ((1.0 + std::f64::consts::SQRT_2) * (1.0 - MARGIN + reach / spacing)).floor() as i64;
+    // Each center's resting place and how far it has been thrown
+    let centers: Vec<([f64; 2], [f64; 2])> = (0..rows * columns)
+        .map(|cell| {
+            let (i, j) = ((cell % columns) as f64, (cell / columns) as f64);
+            let inset = |param| MARGIN + random(0, 0, param) * (1.0 - 2.0 * MARGIN);
+            let rest = [
+                (i + inset(cell as u64 * 4)) * spacing,
+                (j + inset(cell as u64 * 4 + 1)) * spacing,
+            ];
+            let thrown = |param| (random(0, step, param) * 2.0 - 1.0) * reach;
+            let offset = [thrown(cell as u64 * 4 + 2), thrown(cell as u64 * 4 + 3)];
+            (rest, offset)
+        })
+        .collect();
+
+    // The nearest cell of every pixel and how far it is from the next one
+    let mut nearest = vec![0usize; (width * height) as usize];
+    let mut border = vec![f64::MAX; (width * height) as usize];
+    let mut candidates: Vec<(f64, [f64; 2], usize)> = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            let p = [x as f64 + 0.5, y as f64 + 0.5];
+            let (gi, gj) = ((p[0] / spacing) as i64, (p[1] / spacing) as i64);
+            candidates.clear();
+            for j in (gj - search).max(0)..=(gj + search).min(rows as i64 - 1) {
+                for i in (gi - search).max(0)..=(gi + search).min(columns as i64 - 1) {
+                    let cell = j as usize * columns + i as usize;
+                    let (rest, offset) = centers[cell];
+                    let center = [rest[0] + offset[0], rest[1] + offset[1]];
+                    let distance = (p[0] - center[0]).powi(2) + (p[1] - center[1]).powi(2);
+                    candidates.push((distance, center, cell));
+                }
+            }
+            let &(closest, center, cell) = candidates
+                .iter()
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .expect("Every pixel has a cell nearby");
+            let index = (y * width + x) as usize;
+            nearest[index] = cell;
+            // Distance to the bisector with each other center
+            for &(distance, other, _) in &candidates {
+                let apart =
+                    ((other[0] - center[0]).powi(2) + (other[1] - center[1]).powi(2)).sqrt();
+                if apart > f64::EPSILON {
+                    border[index] = border[index].min((distance - closest) / (2.0 * apart));
+                }
+            }
+        }
+    }
+
+    let averages: Vec<[u8; 4]> = match fill {
+        ShatterFill::Average => {
+            let mut sums = vec![[0u64; 5]; centers.len()];
+            for (pixel, &cell) in img.pixels().zip(&nearest) {
+                for c in 0..4 {
+                    sums[cell][c] += pixel.0[c] as u64;
+                }
+                sums[cell][4] += 1;
+            }
+            sums.iter()
+                .map(|sum| std::array::from_fn(|c| (sum[c] / sum[4].max(1)) as u8))
+                .collect()
+        }
+        ShatterFill::Shards => vec![],
+    };
+
+    RgbaImage::from_fn(width, height, |x, y| {
+        let index = (y * width + x) as usize;
+        let cell = nearest[index];
+        let mut pixel = match fill {
+            ShatterFill::Average => averages[cell],
+            ShatterFill::Shards => {
+                // The shard carries its part of the frame along with its center
+                let offset = centers[cell].1;
+                let sx = (x as f64 - offset[0])
+                    .round()
+                    .clamp(0.0, width as f64 - 1.0);
+                let sy = (y as f64 - offset[1])
+                    .round()
+                    .clamp(0.0, height as f64 - 1.0);
+                img.get_pixel(sx as u32, sy as u32).0
+            }
+        };
+        if edges > 0.0 {
+            let lead = (edges as f64 / 2.0 - border[index] + 0.5).clamp(0.0, 1.0);
+            for channel in pixel.iter_mut().take(3) {
+                *channel = (*channel as f64 * (1.0 - lead)).round() as u8;
+            }
+        }
+        Rgba(pixel)
+    })
+}
@@ -0,0 +1,175 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use serde::Serialize;
+use serde_json::Value;
+use vidfx::EffectChain;
+
+use crate::units::parse_duration;
+
+/// Attack/release smoothing of modulation: `--smooth 120ms`, or
+/// `--smooth 10ms:200ms` to rise faster than it falls. The filter runs with a
+/// [`Smoother`].
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Smoothing {
+    /// Seconds to rise most of the way to a higher value
+    attack: f64,
+    /// Seconds to fall most of the way to a lower value
+    release: f64,
+}
+
+/// Frames between saved filter states, the furthest a frame that arrives out
+/// of order has to replay.
+const CHECKPOINT: usize = 256;
+
+impl Smoothing {
+    pub fn parse(s: &str) -> Result<Smoothing, String> {
+        let (attack, release) = match s.split_once(':') {
+            Some((attack, release)) => (parse_duration(attack)?, parse_duration(release)?),
+            None => {
+                let both = parse_duration(s)?;
+                (both, both)
+            }
+        };
+        Ok(Smoothing { attack, release })
+    }
+
+    /// How far each value moves towards its target over one frame.
+    fn coefficients(&self, frame_rate: f64) -> (f64, f64) {
+        let coefficient = |time: f64| {
+            if time > 0.0 {
+                1.0 - (-1.0 / (time * frame_rate)).exp()
+            } else {
+                1.0
+            }
+        };
+        (coefficient(self.attack), coefficient(self.release))
+    }
+}
+
+/// Moves `values` one frame towards `target`.
+fn step(values: &mut Vec<f64>, target: Vec<f64>, (attack, release): (f64, f64)) {
+    if target.len() != values.len() {
+        *values = target;
+        return;
+    }
+    for (value, target) in values.iter_mut().zip(target) {
+        let coefficient = if target > *value { attack } else { release };
+        *value += (target - *value) * coefficient;
+    }
+}
+
+/// One signal's filter, run from frame 0.
+#[derive(Default)]
+struct Stream {
+    /// The last frame filtered and its values
+    last: Option<(usize, Vec<f64>)>,
+    /// Values at every `CHECKPOINT`th frame
+    checkpoints: BTreeMap<usize, Vec<f64>>,
+}
+
+/// Runs a [`Smoothing`] over signals keyed by name, carrying each from frame
+/// to frame. A frame that arrives out of order, from the frame cache or
+/// parallel rendering, replays from the checkpoint before it, so every frame
+/// gets the same value whatever order frames are rendered in.
+pub struct Smoother {
+    smoothing: Smoothing,
+    streams: Mutex<HashMap<String, Stream>>,
+}
+
+impl Smoother {
+    pub fn new(smoothing: Smoothing) -> Smoother {
+        Smoother {
+            smoothing,
+            streams: Mutex::default(),
+        }
+    }
+
+    /// The signal `key` at frame `index` after smoothing. `signal` gives every
+    /// value for a frame, in the same order each time.
+    pub fn values(
+        &self,
+        key: &str,
+        index: usize,
+        frame_rate: f64,
+        signal: impl Fn(usize) -> Vec<f64>,
+    ) -> Vec<f64> {
+        let coefficients = self.smoothing.coefficients(frame_rate);
+        let mut streams = self.streams.lock().expect("Smoothing state poisoned");
+        let stream = streams.entry(key.to_string()).or_default();
+
+        stream.checkpoints.entry(0).or_insert_with(|| signal(0));
+        let (checkpoint, saved) = stream
+            .checkpoints
+            .range(..=index)
+            .next_back()
+            .expect("Frame 0 is always saved");
+        // Carry on from the last frame unless it is past this one, or further
+        // back than the checkpoint
+        let (mut at, mut values) = match &stream.last {
+            Some((last, values)) if (*checkpoint..=index).contains(last) => (*last, values.clone()),
+            _ => (*checkpoint, saved.clone()),
+        };
+        while at < index {
+            at += 1;
+            step(&mut values, signal(at), coefficients);
+            if at % CHECKPOINT == 0 {
+                stream
+                    .checkpoints
+                    .entry(at)
+                    .or_insert_with(|| values.clone());
+            }
+        }
+        stream.last = Some((index, values.clone()));
+        values
+    }
+
+    /// A single smoothed value, see [`Smoother::values`].
+    pub fn value(&self, index: usize, frame_rate: f64, signal: impl Fn(usize) -> f64) -> f64 {
+        self.values("", index, frame_rate, |i| vec![signal(i)])[0]
+    }
+
+    /// The chain `chain_at` gives for frame `index` with every fractional
+    /// number in it smoothed over the frames before. Integers such as `bits`
+    /// or `seed` keep their value for the frame. `key` tells the chains
+    /// apart, since each needs a filter of its own.
+    pub fn chain(
+        &self,
+        key: &str,
+        index: usize,
+        frame_rate: f64,
+        chain_at: impl Fn(usize) -> EffectChain,
+    ) -> EffectChain {
+        let json = |i: usize| serde_json::to_value(chain_at(i)).expect("Effect chains serialize");
+        let values = self.values(key, index, frame_rate, |i| {
+            let mut numbers = vec![];
+            visit(&mut json(i), &mut |n| numbers.push(*n));
+            numbers
+        });
+
+        let mut current = json(index);
+        let mut values = values.into_iter();
+        visit(&mut current, &mut |n| {
+            if let Some(value) = values.next() {
+                *n = value;
+            }
+        });
+        serde_json::from_value(current).expect("Smoothing keeps the chain's shape")
+    }
+}
+
+/// Calls `f` on every fractional number in `json` in document order,
+/// writing back what it leaves there. Integers are counts, bit depths and
+/// seeds that mean nothing in between, so they are left alone.
+fn visit(json: &mut Value, f: &mut impl FnMut(&mut f64)) {
+    match json {
+        Value::Number(n) if n.is_f64() => {
+            let mut value = n.as_f64().unwrap_or_default();
+            f(&mut value);
+            *json = Value::from(value);
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| visit(value, f)),
+        Value::Object(map) => map.values_mut().for_each(|value| visit(value, f)),
+        _ => {}
+    }
+}
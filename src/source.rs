@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use image::{ImageBuffer, RgbImage};
+use url::Url;
+use video_rs::decode::{Decoder, DecoderBuilder};
+
+use crate::buffer::FrameQueue;
+use crate::color::Correction;
+
+/// A decoder for `input`, a file path or a network url. HTTP(S) and HLS
+/// inputs reconnect after dropouts and RTSP goes over TCP, which IP cameras
+/// handle far better than UDP on a lossy network.
+pub fn open_decoder(input: &str) -> Result<Decoder, video_rs::Error> {
+    let url = match Url::parse(input) {
+        Ok(url) if input.contains("://") => url,
+        _ => return Decoder::new(Path::new(input)),
+    };
+
+    let options: HashMap<String, String> = match url.scheme() {
+        "http" | "https" => [
+            ("reconnect", "1"),
+            ("reconnect_streamed", "1"),
+            ("reconnect_on_network_error", "1"),
+            ("reconnect_delay_max", "10"),
+        ]
+        .as_slice(),
+        "rtsp" | "rtsps" => [("rtsp_transport", "tcp"), ("timeout", "10000000")].as_slice(),
+        _ => [].as_slice(),
+    }
+    .iter()
+    .map(|(key, value)| (key.to_string(), value.to_string()))
+    .collect();
+
+    DecoderBuilder::new(url)
+        .with_options(&options.into())
+        .build()
+}
+
+/// Decodes the next frame into an `RgbImage`, or `None` once the stream ends.
+pub fn decode_frame(decoder: &mut Decoder) -> Option<RgbImage> {
+    decode_timed(decoder).map(|(_, img)| img)
+}
+
+/// The next frame with its timestamp in seconds.
+fn decode_timed(decoder: &mut Decoder) -> Option<(f64, RgbImage)> {
+    let (frame_width, frame_height) = decoder.size();
+    let (time, frame) = decoder.decode().ok()?;
+
+    let rgb = frame
+        .slice(ndarray::s![.., .., 0..3])
+        .to_slice()
+        .expect("Failed to slice frame into rgb array")
+        .to_vec();
+
+    Some((
+        time.as_secs_f64(),
+        ImageBuffer::from_raw(frame_width, frame_height, rgb)
+            .expect("Failed to convert ndarray to ImageBuffer"),
+    ))
+}
+
+/// Decoding forward is cheaper than seeking for gaps up to this many seconds.
+const SEEK_THRESHOLD: f64 = 2.0;
+
+/// A video file that can be read at any frame, for tools that scrub back and
+/// forth rather than play through once.
+///
+/// Seeking lands on the keyframe before the target and decodes up to it, so
+/// every frame is exact whatever the keyframe interval.
+pub struct Source {
+    decoder: Decoder,
+    correction: Option<Correction>,
+    frame_rate: f64,
+    /// Index of the frame the decoder returns next
+    position: usize,
+}
+
+impl Source {
+    pub fn open(path: &Path) -> Result<Source, video_rs::Error> {
+        let decoder = Decoder::new(path)?;
+        let frame_rate = decoder.frame_rate() as f64;
+        Ok(Source {
+            decoder,
+            correction: Correction::probe(path),
+            frame_rate,
+            position: 0,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.decoder.size()
+    }
+
+    pub fn frame_rate(&self) -> f64 {
+        self.frame_rate
+    }
+
+    /// Length in seconds, when the container gives one.
+    pub fn duration(&self) -> Option<f64> {
+        self.decoder.duration().ok().map(|time| time.as_secs_f64())
+    }
+
+    /// The frame after the last one returned, `None` at the end.
+    pub fn next_frame(&mut self) -> Option<RgbImage> {
+        let img = decode_frame(&mut self.decoder)?;
+        self.position += 1;
+        Some(self.corrected(img))
+    }
+
+    fn corrected(&self, mut img: RgbImage) -> RgbImage {
+        if let Some(correction) = &self.correction {
+            correction.apply(&mut img);
+        }
+        img
+    }
+
+    /// Frame `index` counting from 0, `None` past the end.
+    pub fn frame_at(&mut self, index: usize) -> Option<RgbImage> {
+        let ahead = index as f64 - self.position as f64;
+        if !(0.0..=SEEK_THRESHOLD * self.frame_rate).contains(&ahead) {
+            let millis = (index as f64 / self.frame_rate * 1000.0) as i64;
+            self.decoder.seek(millis).ok()?;
+            // Wherever the seek landed, the next frame's timestamp says where
+            // we are
+            let (time, img) = decode_timed(&mut self.decoder)?;
+            self.position = (time * self.frame_rate).round() as usize + 1;
+            if self.position > index {
+                return Some(self.corrected(img));
+            }
+        }
+
+        while self.position < index {
+            decode_frame(&mut self.decoder)?;
+            self.position += 1;
+        }
+        self.next_frame()
+    }
+
+    /// The frame showing at `time` seconds.
+    pub fn frame_at_time(&mut self, time: f64) -> Option<RgbImage> {
+        self.frame_at((time.max(0.0) * self.frame_rate + 1e-6).floor() as usize)
+    }
+}
+
+/// Linear blend between two frames of the same size, `t = 0` yielding `a`.
+pub fn blend_frames(a: &RgbImage, b: &RgbImage, t: f32) -> RgbImage {
+    let mut out = a.clone();
+    for (dst, src) in out.pixels_mut().zip(b.pixels()) {
+        for c in 0..3 {
+            dst.0[c] = (dst.0[c] as f32 * (1.0 - t) + src.0[c] as f32 * t).round() as u8;
+        }
+    }
+    out
+}
+
+/// Plays a clip over and over until `target_frames` have been produced.
+///
+/// With a crossfade, output lags the decoder by `crossfade_frames` so the tail
+/// of each pass is available when the stream runs out; the tail is blended into
+/// the buffered head of the clip and the next pass skips the frames already
+/// shown. Buffered frames beyond `max_memory` bytes are spilled to disk.
+pub struct LoopingFrames<'a> {
+    decoder: &'a mut Decoder,
+    target_frames: usize,
+    crossfade_frames: usize,
+    emitted: usize,
+    first_pass: bool,
+    pass_frames: usize,
+    skip: usize,
+    head: FrameQueue,
+    pending: FrameQueue,
+    ready: FrameQueue,
+}
+
+impl<'a> LoopingFrames<'a> {
+    pub fn new(
+        decoder: &'a mut Decoder,
+        target_frames: usize,
+        crossfade_frames: usize,
+        max_memory: Option<u64>,
+    ) -> Self {
+        // Each of the three queues can hold up to a crossfade's worth of frames
+        let max_memory = max_memory.map(|max| max / 3);
+        Self {
+            decoder,
+            target_frames,
+            crossfade_frames,
+            emitted: 0,
+            first_pass: true,
+            pass_frames: 0,
+            skip: 0,
+            head: FrameQueue::new(max_memory),
+            pending: FrameQueue::new(max_memory),
+            ready: FrameQueue::new(max_memory),
+        }
+    }
+
+    fn rewind(&mut self) -> bool {
+        if self.pass_frames == 0 {
+            return false;
+        }
+
+        let seam = self.pending.len().min(self.head.len());
+        let mut i = 0;
+        while let Some(tail) = self.pending.pop_front() {
+            if i < seam {
+                let t = (i + 1) as f32 / (seam + 1) as f32;
+                let head = self.head.get(i).expect("Seam is within the head");
+                self.ready.push_back(blend_frames(&tail, &head, t));
+            } else {
+                self.ready.push_back(tail);
+            }
+            i += 1;
+        }
+
+        self.first_pass = false;
+        self.pass_frames = 0;
+        self.skip = seam;
+
+        self.decoder
+            .seek_to_start()
+            .expect("Failed to rewind input for looping");
+        true
+    }
+}
+
+impl Iterator for LoopingFrames<'_> {
+    type Item = RgbImage;
+
+    fn next(&mut self) -> Option<RgbImage> {
+        if self.emitted >= self.target_frames {
+            return None;
+        }
+
+        loop {
+            if let Some(frame) = self.ready.pop_front() {
+                self.emitted += 1;
+                return Some(frame);
+            }
+
+            match decode_frame(self.decoder) {
+                Some(frame) => {
+                    self.pass_frames += 1;
+                    if self.skip > 0 {
+                        self.skip -= 1;
+                        continue;
+                    }
+
+                    if self.first_pass && self.head.len() < self.crossfade_frames {
+                        self.head.push_back(frame.clone());
+                    }
+
+                    self.pending.push_back(frame);
+                    if self.pending.len() > self.crossfade_frames {
+                        if let Some(frame) = self.pending.pop_front() {
+                            self.ready.push_back(frame);
+                        }
+                    }
+                }
+                None => {
+                    if !self.rewind() {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+}
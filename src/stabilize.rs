@@ -0,0 +1,160 @@
+//! Steadying shaky footage before effects see it. A first pass measures how
+//! far each frame moved from the one before, the camera's path is smoothed
+//! and each frame is then shifted from where the camera was onto the smooth
+//! path.
+//!
+//! Only translation is corrected, which covers most handheld shake.
+
+use std::path::Path;
+
+use image::imageops::{self, FilterType};
+use image::{GrayImage, RgbImage};
+
+use crate::source::Source;
+
+/// Width frames are shrunk to for measuring motion.
+const ANALYSIS_WIDTH: u32 = 160;
+
+/// Furthest shift searched between frames, in analysis pixels.
+const SEARCH: i32 = 12;
+
+fn analysis_frame(frame: &RgbImage) -> GrayImage {
+    let height = (ANALYSIS_WIDTH as f64 * frame.height() as f64 / frame.width() as f64)
+        .round()
+        .max(1.0) as u32;
+    let small = imageops::resize(frame, ANALYSIS_WIDTH, height, FilterType::Triangle);
+    imageops::grayscale(&small)
+}
+
+/// Mean absolute difference between `current` and `previous` moved by
+/// `(dx, dy)`, over the middle of the frame that stays inside both.
+fn cost(previous: &GrayImage, current: &GrayImage, dx: i32, dy: i32) -> f64 {
+    let (width, height) = (current.width() as i32, current.height() as i32);
+    let (mut total, mut count) = (0u64, 0u64);
+    for y in SEARCH..height - SEARCH {
+        for x in SEARCH..width - SEARCH {
+            let a = previous.get_pixel((x - dx) as u32, (y - dy) as u32).0[0];
+            let b = current.get_pixel(x as u32, y as u32).0[0];
+            total += a.abs_diff(b) as u64;
+            count += 1;
+        }
+    }
+    total as f64 / count.max(1) as f64
+}
+
+/// Where the lowest of three evenly spaced costs would fall between them,
+/// from -0.5 to 0.5.
+fn refine(before: f64, at: f64, after: f64) -> f64 {
+    let curvature = before - 2.0 * at + after;
+    if curvature > 0.0 {
+        (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+    } else {
+        0.0
+    }
+}
+
+/// How far the picture moved from `previous` to `current`, in analysis
+/// pixels.
+fn motion(previous: &GrayImage, current: &GrayImage) -> (f64, f64) {
+    if previous.dimensions() != current.dimensions()
+        || current.width() as i32 <= 2 * SEARCH
+        || current.height() as i32 <= 2 * SEARCH
+    {
+        return (0.0, 0.0);
+    }
+    let (mut best, mut best_cost) = ((0, 0), f64::MAX);
+    for dy in -SEARCH..=SEARCH {
+        for dx in -SEARCH..=SEARCH {
+            let cost = cost(previous, current, dx, dy);
+            if cost < best_cost {
+                (best, best_cost) = ((dx, dy), cost);
+            }
+        }
+    }
+
+    let (dx, dy) = best;
+    let at = |dx: i32, dy: i32| cost(previous, current, dx, dy);
+    let fx = if dx.abs() < SEARCH {
+        refine(at(dx - 1, dy), best_cost, at(dx + 1, dy))
+    } else {
+        0.0
+    };
+    let fy = if dy.abs() < SEARCH {
+        refine(at(dx, dy - 1), best_cost, at(dx, dy + 1))
+    } else {
+        0.0
+    };
+    (dx as f64 + fx, dy as f64 + fy)
+}
+
+/// The shift that lands each frame of `path` on a camera path averaged over
+/// `smoothness` frames either side, in pixels.
+pub fn analyze(path: &Path, smoothness: usize) -> Result<Vec<(f64, f64)>, video_rs::Error> {
+    let mut source = Source::open(path)?;
+    let (width, _) = source.size();
+    let scale = width as f64 / ANALYSIS_WIDTH as f64;
+
+    // Where the camera had moved the picture to by each frame
+    let mut trajectory = vec![];
+    let mut position = (0.0, 0.0);
+    let mut previous: Option<GrayImage> = None;
+    while let Some(frame) = source.next_frame() {
+        let current = analysis_frame(&frame);
+        if let Some(previous) = &previous {
+            let (dx, dy) = motion(previous, &current);
+            position = (position.0 + dx * scale, position.1 + dy * scale);
+        }
+        trajectory.push(position);
+        previous = Some(current);
+    }
+
+    let corrections = (0..trajectory.len())
+        .map(|i| {
+            let end = (i + smoothness + 1).min(trajectory.len());
+            let window = &trajectory[i.saturating_sub(smoothness)..end];
+            let n = window.len() as f64;
+            let smooth = window
+                .iter()
+                .fold((0.0, 0.0), |(x, y), (px, py)| (x + px / n, y + py / n));
+            (smooth.0 - trajectory[i].0, smooth.1 - trajectory[i].1)
+        })
+        .collect();
+    Ok(corrections)
+}
+
+/// `frame` moved by `(dx, dy)`, the uncovered edge filled by stretching the
+/// border.
+fn shifted(frame: &RgbImage, (dx, dy): (f64, f64)) -> RgbImage {
+    let (width, height) = frame.dimensions();
+    let sample = |x: f64, y: f64, c: usize| {
+        let x = x.clamp(0.0, (width - 1) as f64);
+        let y = y.clamp(0.0, (height - 1) as f64);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let (fx, fy) = (x - x0 as f64, y - y0 as f64);
+        let at = |x: u32, y: u32| frame.get_pixel(x, y).0[c] as f64;
+        let top = at(x0, y0) * (1.0 - fx) + at(x1, y0) * fx;
+        let bottom = at(x0, y1) * (1.0 - fx) + at(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    };
+    RgbImage::from_fn(width, height, |x, y| {
+        let (sx, sy) = (x as f64 - dx, y as f64 - dy);
+        image::Rgb(std::array::from_fn(|c| sample(sx, sy, c).round() as u8))
+    })
+}
+
+/// `frames` shifted by `corrections`. Output frame `i` takes the correction
+/// of source frame `i * step`, for stages that change the frame count.
+pub fn stabilized<'a>(
+    frames: Box<dyn Iterator<Item = RgbImage> + 'a>,
+    corrections: Vec<(f64, f64)>,
+    step: f64,
+) -> Box<dyn Iterator<Item = RgbImage> + 'a> {
+    if corrections.is_empty() {
+        return frames;
+    }
+    Box::new(frames.enumerate().map(move |(i, frame)| {
+        let source = (i as f64 * step).round() as usize % corrections.len();
+        shifted(&frame, corrections[source])
+    }))
+}
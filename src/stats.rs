@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::output::OutputTarget;
+use crate::units::format_size;
+
+/// Time spent in each stage of a render. Decoding covers everything that
+/// produces source frames, encoding every sink including previews.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timings {
+    pub decode: Duration,
+    pub effects: Duration,
+    pub encode: Duration,
+}
+
+#[derive(Serialize)]
+struct OutputStats {
+    path: String,
+    bytes: u64,
+    /// Average bits per second over the render
+    bit_rate: Option<f64>,
+}
+
+/// What a render took, printed once it's done and written by
+/// `--summary-json`. Times are in seconds.
+#[derive(Serialize)]
+pub struct Summary {
+    frames: usize,
+    wall_time: f64,
+    decode_time: f64,
+    effect_time: f64,
+    encode_time: f64,
+    fps: f64,
+    /// Peak resident memory in bytes, where the platform reports it
+    peak_memory: Option<u64>,
+    outputs: Vec<OutputStats>,
+}
+
+impl Summary {
+    pub fn new(
+        frames: usize,
+        wall_time: Duration,
+        timings: &Timings,
+        outputs: &[OutputTarget],
+        frame_rate: f64,
+    ) -> Summary {
+        let wall_time = wall_time.as_secs_f64();
+        let duration = frames as f64 / frame_rate;
+        let outputs = outputs
+            .iter()
+            .filter_map(|output| match output {
+                OutputTarget::File(path) => Some(path),
+                _ => None,
+            })
+            .filter_map(|path| {
+                let bytes = std::fs::metadata(path).ok()?.len();
+                Some(OutputStats {
+                    path: path.display().to_string(),
+                    bytes,
+                    bit_rate: (duration > 0.0).then(|| bytes as f64 * 8.0 / duration),
+                })
+            })
+            .collect();
+
+        Summary {
+            frames,
+            wall_time,
+            decode_time: timings.decode.as_secs_f64(),
+            effect_time: timings.effects.as_secs_f64(),
+            encode_time: timings.encode.as_secs_f64(),
+            fps: if wall_time > 0.0 {
+                frames as f64 / wall_time
+            } else {
+                0.0
+            },
+            peak_memory: peak_memory(),
+            outputs,
+        }
+    }
+
+    pub fn print(&self) {
+        eprintln!(
+            "Rendered {} frames in {:.1}s ({:.1} fps)",
+            self.frames, self.wall_time, self.fps
+        );
+        let share = |time: f64| {
+            if self.wall_time > 0.0 {
+                100.0 * time / self.wall_time
+            } else {
+                0.0
+            }
+        };
+        for (stage, time) in [
+            ("decode", self.decode_time),
+            ("effects", self.effect_time),
+            ("encode", self.encode_time),
+        ] {
+            eprintln!("  {:<8} {:>8.1}s {:>4.0}%", stage, time, share(time));
+        }
+        if let Some(peak) = self.peak_memory {
+            eprintln!("  peak memory {}", format_size(peak));
+        }
+        for output in &self.outputs {
+            match output.bit_rate {
+                Some(bit_rate) => eprintln!(
+                    "  {}: {}, {:.1} Mbit/s",
+                    output.path,
+                    format_size(output.bytes),
+                    bit_rate / 1e6
+                ),
+                None => eprintln!("  {}: {}", output.path, format_size(output.bytes)),
+            }
+        }
+    }
+
+    pub fn write_json(&self, path: &str) {
+        let file = std::fs::File::create(path).expect("Failed to create summary file");
+        serde_json::to_writer_pretty(file, self).expect("Failed to write summary file");
+    }
+}
+
+/// The most memory the process has held, from `/proc` on Linux.
+fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kilobytes: u64 = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
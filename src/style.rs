@@ -0,0 +1,83 @@
+//! `style`: neural style transfer with an ONNX model on every frame.
+//!
+//! Models from the ONNX model zoo's fast neural style family are expected:
+//! RGB from 0 to 255 in, channels first, and the stylized image back the
+//! same way.
+
+use std::sync::Mutex;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, RgbImage, RgbaImage};
+
+use crate::onnx::Plan;
+
+pub struct Style {
+    plan: Plan,
+    size: u32,
+    /// How much of the stylized frame shows over the original, 0 to 1
+    strength: f32,
+    /// How much of the previous stylized frame is blended in, 0 to 1
+    temporal: f32,
+    /// The last stylized frame with its index, for `temporal`
+    previous: Mutex<Option<(usize, RgbImage)>>,
+}
+
+impl Style {
+    pub fn load(model: &str, size: u32, strength: f32, temporal: f32) -> Style {
+        let side = size as usize;
+        Style {
+            plan: Plan::load(model, [1, 3, side, side]),
+            size,
+            strength: strength.clamp(0.0, 1.0),
+            temporal: temporal.clamp(0.0, 1.0),
+            previous: Mutex::new(None),
+        }
+    }
+
+    fn stylize(&self, img: &RgbImage) -> RgbImage {
+        let size = self.size;
+        let input = imageops::resize(img, size, size, FilterType::Triangle);
+        let outputs = self
+            .plan
+            .run(|[_, c, y, x]| input.get_pixel(x as u32, y as u32).0[c] as f32)
+            .unwrap_or_else(|e| panic!("Failed to run style model: {}", e));
+        let stylized = outputs
+            .first()
+            .and_then(|output| output.rgb(1.0))
+            .expect("Style models output one RGB image");
+        imageops::resize(&stylized, img.width(), img.height(), FilterType::Triangle)
+    }
+
+    /// Frame `index` of the render stylized. The temporal blend starts over
+    /// when frames come out of order.
+    pub fn apply(&self, img: DynamicImage, index: usize) -> RgbaImage {
+        let original = img.to_rgb8();
+        let mut stylized = self.stylize(&original);
+
+        if self.temporal > 0.0 {
+            let mut previous = self.previous.lock().expect("Style lock poisoned");
+            if let Some((last, frame)) = previous.as_ref() {
+                if last + 1 == index && frame.dimensions() == stylized.dimensions() {
+                    for (pixel, before) in stylized.pixels_mut().zip(frame.pixels()) {
+                        for c in 0..3 {
+                            pixel.0[c] = (pixel.0[c] as f32 * (1.0 - self.temporal)
+                                + before.0[c] as f32 * self.temporal)
+                                .round() as u8;
+                        }
+                    }
+                }
+            }
+            *previous = Some((index, stylized.clone()));
+        }
+
+        let mut out = img.to_rgba8();
+        for (pixel, styled) in out.pixels_mut().zip(stylized.pixels()) {
+            for c in 0..3 {
+                pixel.0[c] = (pixel.0[c] as f32 * (1.0 - self.strength)
+                    + styled.0[c] as f32 * self.strength)
+                    .round() as u8;
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use clap::Parser;
+use image::{imageops, DynamicImage, RgbImage};
+use video_rs::time::Time;
+use vidfx::encoder::{
+    container_format, image_to_ndarray, Codec, EncodeSettings, Provenance, VideoEncoder,
+};
+use vidfx::source::Source;
+use vidfx::{EffectChain, FrameContext};
+
+use crate::Args;
+
+/// One `--param effect.flag=a,b,c`.
+struct Axis {
+    effect: String,
+    flag: String,
+    values: Vec<String>,
+}
+
+fn parse_axes(params: &[String]) -> Vec<Axis> {
+    let mut axes: Vec<Axis> = params
+        .iter()
+        .map(|param| {
+            let (key, values) = param
+                .split_once('=')
+                .unwrap_or_else(|| panic!("Sweep param '{}' is not name=a,b,c", param));
+            let (effect, flag) = match key.split_once('.') {
+                Some((effect, flag)) => (effect.to_string(), flag.to_string()),
+                None => (String::new(), key.to_string()),
+            };
+            // Repeated values would only render the same combination twice
+            let mut seen = BTreeSet::new();
+            Axis {
+                effect,
+                flag,
+                values: values
+                    .split(',')
+                    .map(|v| v.trim().to_string())
+                    .filter(|v| seen.insert(v.clone()))
+                    .collect(),
+            }
+        })
+        .collect();
+
+    // A bare `color=...` belongs to the effect when there is only one
+    let effects: BTreeSet<String> = axes
+        .iter()
+        .filter(|axis| !axis.effect.is_empty())
+        .map(|axis| axis.effect.clone())
+        .collect();
+    let effects: Vec<&String> = effects.iter().collect();
+    for axis in axes.iter_mut().filter(|axis| axis.effect.is_empty()) {
+        match effects.as_slice() {
+            [effect] => axis.effect = effect.to_string(),
+            _ => panic!(
+                "Sweep param '{}' needs an effect prefix, e.g. bloom.{}",
+                axis.flag, axis.flag
+            ),
+        }
+    }
+
+    axes
+}
+
+/// Every combination of one value per axis, as indices into `Axis::values`.
+fn combinations(axes: &[Axis]) -> Vec<Vec<usize>> {
+    axes.iter().fold(vec![vec![]], |combos, axis| {
+        combos
+            .iter()
+            .flat_map(|combo| {
+                (0..axis.values.len()).map(move |i| {
+                    let mut combo = combo.clone();
+                    combo.push(i);
+                    combo
+                })
+            })
+            .collect()
+    })
+}
+
+/// A chain for one combination, with effects in the order they were first
+/// named. Goes through the regular argument parser so values are validated
+/// exactly as on the command line.
+fn chain(axes: &[Axis], combo: &[usize], args: &Args) -> EffectChain {
+    let mut effects: Vec<&str> = vec![];
+    for axis in axes {
+        if !effects.contains(&axis.effect.as_str()) {
+            effects.push(&axis.effect);
+        }
+    }
+
+    effects.iter().fold(EffectChain::new(), |chain, effect| {
+        let mut argv = vec!["vidfx".to_string(), effect.to_string()];
+        for (axis, &i) in axes.iter().zip(combo) {
+            if axis.effect == *effect {
+                argv.push(format!("--{}", axis.flag));
+                argv.push(axis.values[i].clone());
+            }
+        }
+
+        let parsed = Args::try_parse_from(&argv).unwrap_or_else(|e| e.exit());
+        chain.then(
+            parsed
+                .cmd
+                .to_effect(&args.lhs, &args.rhs, args.negate)
+                .unwrap_or_else(|| panic!("{} is not an effect", effect)),
+        )
+    })
+}
+
+fn label(axes: &[Axis], combo: &[usize]) -> String {
+    axes.iter()
+        .zip(combo)
+        .map(|(axis, &i)| format!("{}.{}-{}", axis.effect, axis.flag, axis.values[i]))
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+/// Lays `frames` out in a grid the size of the first one.
+fn tile(frames: &[RgbImage], columns: u32) -> RgbImage {
+    let (width, height) = frames[0].dimensions();
+    let rows = (frames.len() as u32).div_ceil(columns);
+    let (cell_width, cell_height) = ((width / columns).max(1), (height / rows).max(1));
+
+    let mut tiled = RgbImage::new(width, height);
+    for (i, frame) in frames.iter().enumerate() {
+        let cell = imageops::resize(
+            frame,
+            cell_width,
+            cell_height,
+            imageops::FilterType::Triangle,
+        );
+        let (x, y) = (i as u32 % columns, i as u32 / columns);
+        imageops::replace(
+            &mut tiled,
+            &cell,
+            (x * cell_width) as i64,
+            (y * cell_height) as i64,
+        );
+    }
+    tiled
+}
+
+fn open_encoder(path: &Path, settings: &EncodeSettings) -> VideoEncoder {
+    let codec = settings.codec.unwrap_or_else(|| Codec::default_for(path));
+    VideoEncoder::new(
+        path.to_str().expect("Output path is not valid UTF-8"),
+        container_format(path),
+        codec,
+        settings,
+        false,
+        None,
+        &Provenance::default(),
+    )
+    .unwrap_or_else(|e| panic!("Failed to open {}: {}", path.display(), e))
+}
+
+/// Renders the first `duration` seconds of `input` once per combination of
+/// `params`, into `dir` or, with `tiled`, one comparison video.
+pub fn run(
+    input: &str,
+    params: &[String],
+    duration: f64,
+    dir: &str,
+    tiled: Option<&str>,
+    args: &Args,
+) {
+    let axes = parse_axes(params);
+    let combos = combinations(&axes);
+    let chains: Vec<EffectChain> = combos.iter().map(|c| chain(&axes, c, args)).collect();
+
+    let mut source = Source::open(Path::new(input)).expect("Failed to create decoder");
+    let (width, height) = source.size();
+    let frame_rate = source.frame_rate();
+    let settings = EncodeSettings {
+        width,
+        height,
+        frame_rate,
+        codec: args.codec,
+        bit_rate: None,
+        threads: args.threads,
+        color_range: args.color_range,
+        delivery: args.target,
+        faststart: args.faststart,
+        fragmented: args.fragmented,
+        segment_duration: args.segment_duration,
+    };
+
+    let columns = (combos.len() as f64).sqrt().ceil() as u32;
+    let rows = (combos.len() as u32).div_ceil(columns);
+    if tiled.is_some() && (columns > width || rows > height) {
+        panic!(
+            "{} combinations don't fit a {}x{} contact sheet, sweep fewer values or drop --tile",
+            combos.len(),
+            width,
+            height
+        );
+    }
+    let mut encoders: Vec<VideoEncoder> = match tiled {
+        Some(path) => {
+            for (i, combo) in combos.iter().enumerate() {
+                println!(
+                    "tile {},{}: {}",
+                    i as u32 % columns,
+                    i as u32 / columns,
+                    label(&axes, combo)
+                );
+            }
+            vec![open_encoder(Path::new(path), &settings)]
+        }
+        None => {
+            std::fs::create_dir_all(dir).expect("Failed to create sweep directory");
+            combos
+                .iter()
+                .map(|combo| {
+                    let path = PathBuf::from(dir).join(format!("{}.mp4", label(&axes, combo)));
+                    println!("{}", path.display());
+                    open_encoder(&path, &settings)
+                })
+                .collect()
+        }
+    };
+
+    let frame_count = (duration * frame_rate).round() as usize;
+    for index in 0..frame_count {
+        let Some(img) = source.next_frame() else {
+            break;
+        };
+        let time = index as f64 / frame_rate;
+        let mut context = FrameContext::new(index, time, frame_rate);
+        context.bpm = args.bpm;
+
+        let processed: Vec<RgbImage> = chains
+            .iter()
+            .map(|chain| {
+                DynamicImage::ImageRgba8(
+                    chain.apply(DynamicImage::ImageRgb8(img.clone()), &context),
+                )
+                .into_rgb8()
+            })
+            .collect();
+
+        let frames = match tiled {
+            Some(_) => vec![tile(&processed, columns)],
+            None => processed,
+        };
+        for (encoder, frame) in encoders.iter_mut().zip(&frames) {
+            encoder
+                .encode(&image_to_ndarray(frame), Time::from_secs_f64(time))
+                .expect("Failed to encode frame");
+        }
+    }
+
+    for encoder in &mut encoders {
+        encoder.finish().expect("Failed to finish encoding");
+    }
+}
@@ -0,0 +1,46 @@
+use serde::Serialize;
+
+use crate::units::parse_percent;
+
+/// Shuffled timing for the beat grid: `--swing 56%` makes the first eighth
+/// of every beat take 56% of it and the second the rest. 50% is straight.
+///
+/// Beats themselves stay put, only what happens between them moves.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Swing(f64);
+
+impl Swing {
+    pub fn parse(s: &str) -> Result<Swing, String> {
+        let percent = parse_percent(s)?;
+        if !(1.0..=99.0).contains(&percent) {
+            return Err(format!("swing '{}' is outside 1%..99%", s));
+        }
+        Ok(Swing(percent as f64 / 100.0))
+    }
+
+    /// Where `time` would be on a straight grid, for anything that counts
+    /// beats in straight time.
+    pub fn straighten(&self, time: f64, bpm: u32) -> f64 {
+        let beat = 60.0 / bpm as f64;
+        let (start, phase) = ((time / beat).floor() * beat, (time % beat) / beat);
+        let phase = if phase < self.0 {
+            0.5 * phase / self.0
+        } else {
+            0.5 + 0.5 * (phase - self.0) / (1.0 - self.0)
+        };
+        start + phase * beat
+    }
+
+    /// The swung time of a point on the straight grid, undoing
+    /// [`Swing::straighten`].
+    pub fn swing(&self, time: f64, bpm: u32) -> f64 {
+        let beat = 60.0 / bpm as f64;
+        let (start, phase) = ((time / beat).floor() * beat, (time % beat) / beat);
+        let phase = if phase < 0.5 {
+            phase * 2.0 * self.0
+        } else {
+            self.0 + (phase - 0.5) * 2.0 * (1.0 - self.0)
+        };
+        start + phase * beat
+    }
+}
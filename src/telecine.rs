@@ -0,0 +1,149 @@
+//! Inverse telecine: undoing 3:2 pulldown so film shot at 24fps comes back
+//! as the 24 frames it was, rather than 30 with combed and repeated ones.
+//!
+//! Each frame's kept field is matched with whichever neighbouring field
+//! combs least against it, then the most redundant frame of every five is
+//! dropped.
+
+use std::collections::VecDeque;
+
+use image::RgbImage;
+
+/// Frames per pulldown cycle, one of which is dropped.
+const CYCLE: usize = 5;
+
+/// How many frames come out for each one going in.
+pub const RATE: f64 = (CYCLE - 1) as f64 / CYCLE as f64;
+
+fn luma(img: &RgbImage, x: u32, y: u32) -> i32 {
+    let [r, g, b] = img.get_pixel(x, y).0;
+    (r as i32 * 77 + g as i32 * 150 + b as i32 * 29) >> 8
+}
+
+/// Lines with remainder `kept` from `kept_from`, the others from `other`.
+fn weave(kept_from: &RgbImage, other: &RgbImage, kept: u32) -> RgbImage {
+    let mut out = kept_from.clone();
+    for y in (0..out.height()).filter(|y| y % 2 != kept) {
+        for x in 0..out.width() {
+            out.put_pixel(x, y, *other.get_pixel(x, y));
+        }
+    }
+    out
+}
+
+/// How much `img` zigzags between neighbouring lines, which a frame woven
+/// from two different moments does and a whole one mostly doesn't.
+fn combing(img: &RgbImage) -> u64 {
+    let mut total = 0;
+    for y in 1..img.height().saturating_sub(1) {
+        for x in (0..img.width()).step_by(2) {
+            let zigzag = 2 * luma(img, x, y) - luma(img, x, y - 1) - luma(img, x, y + 1);
+            total += zigzag.unsigned_abs() as u64;
+        }
+    }
+    total
+}
+
+fn difference(a: &RgbImage, b: &RgbImage) -> u64 {
+    a.as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&a, &b)| (a as i32 - b as i32).unsigned_abs() as u64)
+        .sum()
+}
+
+/// `frames` with the pulldown removed. `bottom_first` is the source's field
+/// order.
+pub fn detelecined<'a>(
+    frames: Box<dyn Iterator<Item = RgbImage> + 'a>,
+    bottom_first: bool,
+) -> Box<dyn Iterator<Item = RgbImage> + 'a> {
+    Box::new(Detelecine {
+        frames,
+        kept: bottom_first as u32,
+        previous: None,
+        current: None,
+        last: None,
+        cycle: Vec::with_capacity(CYCLE),
+        ready: VecDeque::new(),
+    })
+}
+
+struct Detelecine<'a> {
+    frames: Box<dyn Iterator<Item = RgbImage> + 'a>,
+    kept: u32,
+    previous: Option<RgbImage>,
+    /// The next source frame to match, fetched ahead to see the one after it
+    current: Option<RgbImage>,
+    /// The last matched frame, to measure how much the next one repeats it
+    last: Option<RgbImage>,
+    /// Matched frames of the current cycle with their difference from the
+    /// one before
+    cycle: Vec<(RgbImage, u64)>,
+    ready: VecDeque<RgbImage>,
+}
+
+impl Detelecine<'_> {
+    /// The next source frame with its other field taken from whichever of
+    /// itself, the frame before or the frame after fits best.
+    fn matched(&mut self) -> Option<RgbImage> {
+        let current = match self.current.take() {
+            Some(frame) => frame,
+            None => self.frames.next()?,
+        };
+        self.current = self.frames.next();
+
+        let mut candidates = vec![current.clone()];
+        for other in [self.previous.as_ref(), self.current.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            candidates.push(weave(&current, other, self.kept));
+        }
+        let best = candidates
+            .into_iter()
+            .min_by_key(combing)
+            .expect("The frame itself is always a candidate");
+        self.previous = Some(current);
+        Some(best)
+    }
+
+    /// Drops the most redundant frame of the cycle once it's full, then
+    /// queues the rest.
+    fn flush(&mut self) {
+        if self.cycle.len() == CYCLE {
+            let (duplicate, _) = self
+                .cycle
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, (_, diff))| *diff)
+                .expect("The cycle is full");
+            self.cycle.remove(duplicate);
+        }
+        self.ready
+            .extend(self.cycle.drain(..).map(|(frame, _)| frame));
+    }
+}
+
+impl Iterator for Detelecine<'_> {
+    type Item = RgbImage;
+
+    fn next(&mut self) -> Option<RgbImage> {
+        while self.ready.is_empty() {
+            let Some(frame) = self.matched() else {
+                self.flush();
+                break;
+            };
+            let diff = self
+                .last
+                .as_ref()
+                .map_or(u64::MAX, |last| difference(last, &frame));
+            self.last = Some(frame.clone());
+            self.cycle.push((frame, diff));
+            if self.cycle.len() == CYCLE {
+                self.flush();
+            }
+        }
+        self.ready.pop_front()
+    }
+}
@@ -0,0 +1,169 @@
+use serde::Serialize;
+
+use crate::units::parse_duration;
+
+/// A tempo that changes over the render. `--tempo-map` reads `time,bpm`
+/// rows, each tempo holding until the next row as in a DAW's tempo track;
+/// `--bpm-ramp 120..140@0:00-3:00` glides from one tempo to the other.
+///
+/// The first tempo holds before the map starts and the last one after it
+/// ends.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TempoMap {
+    pieces: Vec<Piece>,
+}
+
+/// A stretch of time over which the tempo moves linearly from `from` to `to`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+struct Piece {
+    start: f64,
+    end: f64,
+    from: f64,
+    to: f64,
+}
+
+impl Piece {
+    fn bpm_at(&self, time: f64) -> f64 {
+        if self.end.is_finite() && self.end > self.start {
+            self.from + (self.to - self.from) * (time - self.start) / (self.end - self.start)
+        } else {
+            self.from
+        }
+    }
+
+    /// Beats from the start of the piece to `time` within it.
+    fn beats(&self, time: f64) -> f64 {
+        let elapsed = time.min(self.end) - self.start;
+        elapsed * (self.from + self.bpm_at(time.min(self.end))) / 120.0
+    }
+
+    /// How far into the piece `beats` beats take.
+    fn elapsed(&self, beats: f64) -> f64 {
+        let slope = if self.end.is_finite() && self.end > self.start {
+            (self.to - self.from) / (self.end - self.start)
+        } else {
+            0.0
+        };
+        // beats * 60 = from * t + slope * t^2 / 2
+        if slope.abs() < 1e-12 {
+            beats * 60.0 / self.from
+        } else {
+            ((self.from * self.from + 2.0 * slope * beats * 60.0)
+                .max(0.0)
+                .sqrt()
+                - self.from)
+                / slope
+        }
+    }
+}
+
+impl TempoMap {
+    fn new(mut points: Vec<(f64, f64)>, ramped: bool) -> Result<TempoMap, String> {
+        if points.is_empty() {
+            return Err("empty tempo map".to_string());
+        }
+        if let Some((_, bpm)) = points
+            .iter()
+            .find(|(_, bpm)| !(*bpm > 0.0 && bpm.is_finite()))
+        {
+            return Err(format!("invalid tempo {}", bpm));
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let first = points[0].1;
+        let mut pieces = vec![Piece {
+            start: 0.0,
+            end: points[0].0.max(0.0),
+            from: first,
+            to: first,
+        }];
+        for (i, &(start, bpm)) in points.iter().enumerate() {
+            let (end, to) = match points.get(i + 1) {
+                Some(&(end, next)) => (end, if ramped { next } else { bpm }),
+                None => (f64::INFINITY, bpm),
+            };
+            pieces.push(Piece {
+                start: start.max(0.0),
+                end: end.max(0.0),
+                from: bpm,
+                to,
+            });
+        }
+        pieces.retain(|piece| piece.end > piece.start);
+        Ok(TempoMap { pieces })
+    }
+
+    /// A `time,bpm` CSV, optionally with a header row.
+    pub fn load(path: &str) -> TempoMap {
+        let text = std::fs::read_to_string(path)
+            .unwrap_or_else(|e| panic!("Failed to read tempo map {}: {}", path, e));
+
+        let mut points = vec![];
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (time, bpm) = line
+                .split_once(',')
+                .unwrap_or_else(|| panic!("Invalid tempo map line '{}'", line));
+            match (parse_duration(time), bpm.trim().parse::<f64>()) {
+                (Ok(time), Ok(bpm)) => points.push((time, bpm)),
+                _ if i == 0 => {}
+                _ => panic!("Invalid tempo map line '{}'", line),
+            }
+        }
+        TempoMap::new(points, false)
+            .unwrap_or_else(|e| panic!("Failed to parse tempo map {}: {}", path, e))
+    }
+
+    /// `<from>..<to>@<start>-<end>`, e.g. `120..140@0:00-3:00`.
+    pub fn parse_ramp(s: &str) -> Result<TempoMap, String> {
+        let invalid = || format!("invalid ramp '{}', expected e.g. 120..140@0:00-3:00", s);
+        let (bpms, times) = s.split_once('@').ok_or_else(invalid)?;
+        let (from, to) = bpms.split_once("..").ok_or_else(invalid)?;
+        let (start, end) = times.split_once('-').ok_or_else(invalid)?;
+
+        let from = from.trim().parse::<f64>().map_err(|_| invalid())?;
+        let to = to.trim().parse::<f64>().map_err(|_| invalid())?;
+        let (start, end) = (parse_duration(start)?, parse_duration(end)?);
+        if end <= start {
+            return Err(format!("ramp '{}' ends before it starts", s));
+        }
+        TempoMap::new(vec![(start, from), (end, to)], true)
+    }
+
+    /// The tempo the render starts at.
+    pub fn initial_bpm(&self) -> f64 {
+        self.pieces[0].from
+    }
+
+    pub fn bpm_at(&self, time: f64) -> f64 {
+        self.pieces
+            .iter()
+            .find(|piece| time < piece.end)
+            .unwrap_or(&self.pieces[self.pieces.len() - 1])
+            .bpm_at(time.max(0.0))
+    }
+
+    /// Beats played from the start up to `time`.
+    pub fn beats(&self, time: f64) -> f64 {
+        self.pieces
+            .iter()
+            .take_while(|piece| piece.start < time)
+            .map(|piece| piece.beats(time))
+            .sum()
+    }
+
+    /// When beat `beats` falls, undoing [`TempoMap::beats`].
+    pub fn time_at(&self, beats: f64) -> f64 {
+        let mut remaining = beats.max(0.0);
+        for piece in &self.pieces {
+            let length = piece.beats(piece.end);
+            if remaining < length || !piece.end.is_finite() {
+                return piece.start + piece.elapsed(remaining);
+            }
+            remaining -= length;
+        }
+        unreachable!("The last piece never ends")
+    }
+}
@@ -0,0 +1,149 @@
+use std::fmt::Write;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{imageops, RgbImage};
+
+/// How frames are drawn into the terminal by `--preview term`.
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum TermProto {
+    /// 24-bit color half blocks, works in any modern terminal
+    Ansi,
+    /// DEC sixel graphics (xterm -ti vt340, foot, wezterm, mlterm)
+    Sixel,
+    /// Kitty graphics protocol (kitty, wezterm, ghostty)
+    Kitty,
+}
+
+/// Width in pixels that image protocols are scaled to before sending.
+const GRAPHICS_WIDTH: u32 = 480;
+
+fn fit(img: &RgbImage, width: u32, height: u32) -> RgbImage {
+    let scale = (width as f32 / img.width() as f32).min(height as f32 / img.height() as f32);
+    let (w, h) = (
+        ((img.width() as f32 * scale) as u32).max(1),
+        ((img.height() as f32 * scale) as u32).max(1),
+    );
+    imageops::resize(img, w, h, imageops::FilterType::Triangle)
+}
+
+/// Escape sequences that draw `img` at the top left of the terminal.
+pub fn render(img: &RgbImage, proto: TermProto) -> String {
+    let mut out = String::from("\x1b[H");
+    match proto {
+        TermProto::Ansi => {
+            let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+            ansi(
+                &fit(img, cols as u32, rows.saturating_sub(1) as u32 * 2),
+                &mut out,
+            );
+        }
+        TermProto::Sixel => sixel(&fit(img, GRAPHICS_WIDTH, GRAPHICS_WIDTH), &mut out),
+        TermProto::Kitty => kitty(&fit(img, GRAPHICS_WIDTH, GRAPHICS_WIDTH), &mut out),
+    }
+    out
+}
+
+fn ansi(img: &RgbImage, out: &mut String) {
+    for y in (0..img.height()).step_by(2) {
+        for x in 0..img.width() {
+            let top = img.get_pixel(x, y).0;
+            let bottom = if y + 1 < img.height() {
+                img.get_pixel(x, y + 1).0
+            } else {
+                [0, 0, 0]
+            };
+            let _ = write!(
+                out,
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m▀",
+                top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+            );
+        }
+        out.push_str("\x1b[0m\r\n");
+    }
+}
+
+/// Sixel output quantized to a 6x6x6 color cube.
+fn sixel(img: &RgbImage, out: &mut String) {
+    let level = |v: u8| (v as u32 * 5 + 127) / 255;
+    let index = |p: &image::Rgb<u8>| level(p.0[0]) * 36 + level(p.0[1]) * 6 + level(p.0[2]);
+
+    out.push_str("\x1bPq");
+    let _ = write!(out, "\"1;1;{};{}", img.width(), img.height());
+    for i in 0..216 {
+        let (r, g, b) = (i / 36, i / 6 % 6, i % 6);
+        let _ = write!(out, "#{};2;{};{};{}", i, r * 20, g * 20, b * 20);
+    }
+
+    let (width, height) = img.dimensions();
+    for band in (0..height).step_by(6) {
+        let mut band_colors = [false; 216];
+        for y in band..(band + 6).min(height) {
+            for x in 0..width {
+                band_colors[index(img.get_pixel(x, y)) as usize] = true;
+            }
+        }
+
+        for color in (0..216u32).filter(|&c| band_colors[c as usize]) {
+            let _ = write!(out, "#{}", color);
+
+            let mut run_char = None;
+            let mut run_len = 0;
+            for x in 0..width {
+                let mut bits = 0u8;
+                for dy in 0..6 {
+                    let y = band + dy;
+                    if y < height && index(img.get_pixel(x, y)) == color {
+                        bits |= 1 << dy;
+                    }
+                }
+                let c = (63 + bits) as char;
+
+                if Some(c) == run_char {
+                    run_len += 1;
+                } else {
+                    push_sixel_run(out, run_char, run_len);
+                    run_char = Some(c);
+                    run_len = 1;
+                }
+            }
+            push_sixel_run(out, run_char, run_len);
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+}
+
+fn push_sixel_run(out: &mut String, c: Option<char>, len: usize) {
+    match c {
+        Some(c) if len > 3 => {
+            let _ = write!(out, "!{}{}", len, c);
+        }
+        Some(c) => out.extend(std::iter::repeat(c).take(len)),
+        None => {}
+    }
+}
+
+/// Kitty graphics protocol, raw RGB in 4096 byte base64 chunks. Reusing the
+/// image id replaces the previous frame instead of stacking them.
+fn kitty(img: &RgbImage, out: &mut String) {
+    let data = STANDARD.encode(img.as_raw());
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(4096).collect();
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        if i == 0 {
+            let _ = write!(
+                out,
+                "\x1b_Ga=T,i=1,q=2,f=24,s={},v={},m={};",
+                img.width(),
+                img.height(),
+                more
+            );
+        } else {
+            let _ = write!(out, "\x1b_Gm={};", more);
+        }
+        out.push_str(std::str::from_utf8(chunk).expect("base64 is ascii"));
+        out.push_str("\x1b\\");
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use image::{imageops, RgbImage};
+use vidfx::source::Source;
+
+/// `hh:mm:ss.mmm` as WebVTT wants it.
+fn vtt_time(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Writes a sprite sheet with a thumbnail every `interval` seconds of `input`,
+/// and a WebVTT file mapping each time range to its tile for web player hover
+/// previews.
+pub fn run(input: &str, interval: f64, width: u32, columns: u32, sprite: &str, vtt: &str) {
+    let mut source = Source::open(Path::new(input)).expect("Failed to create decoder");
+    let frame_rate = source.frame_rate();
+    let (source_width, source_height) = source.size();
+    let height =
+        ((width as f64 * source_height as f64 / source_width as f64).round() as u32).max(1);
+
+    let mut thumbs: Vec<RgbImage> = vec![];
+    let mut index = 0;
+    while let Some(frame) = source.next_frame() {
+        if index as f64 / frame_rate >= thumbs.len() as f64 * interval {
+            thumbs.push(imageops::resize(
+                &frame,
+                width,
+                height,
+                imageops::FilterType::Triangle,
+            ));
+        }
+        index += 1;
+    }
+    let duration = index as f64 / frame_rate;
+
+    if thumbs.is_empty() {
+        panic!("{} has no frames", input);
+    }
+
+    let columns = columns.min(thumbs.len() as u32);
+    let rows = (thumbs.len() as u32).div_ceil(columns);
+    let mut sheet = RgbImage::new(columns * width, rows * height);
+    let sprite_name = Path::new(sprite)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(sprite);
+
+    let mut cues = String::from("WEBVTT\n");
+    for (i, thumb) in thumbs.iter().enumerate() {
+        let (x, y) = (i as u32 % columns * width, i as u32 / columns * height);
+        imageops::replace(&mut sheet, thumb, x as i64, y as i64);
+
+        let start = i as f64 * interval;
+        let end = (start + interval).min(duration);
+        let _ = write!(
+            cues,
+            "\n{} --> {}\n{}#xywh={},{},{},{}\n",
+            vtt_time(start),
+            vtt_time(end),
+            sprite_name,
+            x,
+            y,
+            width,
+            height
+        );
+    }
+
+    sheet.save(sprite).expect("Failed to write sprite sheet");
+    std::fs::write(vtt, cues).expect("Failed to write WebVTT file");
+    println!(
+        "Wrote {} thumbnails to {} and {}",
+        thumbs.len(),
+        sprite,
+        vtt
+    );
+}
@@ -0,0 +1,126 @@
+use std::path::Path;
+use std::sync::Mutex;
+
+use image::{imageops, GrayImage, Luma, RgbImage};
+use serde::Serialize;
+use vidfx::source::Source;
+use vidfx::FrameContext;
+
+/// Edge width of the wipe, as a fraction of the matte's range.
+const SOFTNESS: f32 = 0.05;
+
+/// What moves the wipe threshold.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum Sweep {
+    /// Once, over --transition-duration from --transition-at
+    Timeline,
+    /// Over every beat, starting over at the next. Needs --bpm
+    Beat,
+}
+
+/// A luma matte wipe from `--input` to `--input2`: pixels where the matte is
+/// dark switch first, bright ones last.
+pub struct Transition {
+    matte: GrayImage,
+    second: Mutex<Source>,
+    sweep: Sweep,
+    at: f64,
+    duration: f64,
+}
+
+impl Transition {
+    /// `matte` is a grayscale image file, or a generated `linear`, `radial`
+    /// or `diagonal` wipe.
+    pub fn open(
+        matte: &str,
+        second: &str,
+        size: (u32, u32),
+        sweep: Sweep,
+        at: f64,
+        duration: f64,
+    ) -> Transition {
+        let (width, height) = size;
+        let (w, h) = (width as f32, height as f32);
+        let matte = match matte {
+            "linear" => GrayImage::from_fn(width, height, |x, _| luma(x as f32 / w)),
+            "diagonal" => GrayImage::from_fn(width, height, |x, y| {
+                luma((x as f32 / w + y as f32 / h) / 2.0)
+            }),
+            "radial" => GrayImage::from_fn(width, height, |x, y| {
+                let (dx, dy) = (x as f32 / w - 0.5, y as f32 / h - 0.5);
+                luma((dx * dx + dy * dy).sqrt() / 0.5f32.sqrt())
+            }),
+            path => {
+                let img = image::open(path)
+                    .unwrap_or_else(|e| panic!("Failed to open matte {}: {}", path, e))
+                    .to_luma8();
+                imageops::resize(&img, width, height, imageops::FilterType::Triangle)
+            }
+        };
+
+        let second = Source::open(Path::new(second))
+            .unwrap_or_else(|e| panic!("Failed to open --input2 {}: {}", second, e));
+
+        Transition {
+            matte,
+            second: Mutex::new(second),
+            sweep,
+            at,
+            duration,
+        }
+    }
+
+    /// How far along the wipe is, 0 showing only the first input.
+    fn progress(&self, frame: &FrameContext) -> f32 {
+        match self.sweep {
+            Sweep::Timeline if self.duration <= 0.0 => (frame.time >= self.at) as u8 as f32,
+            Sweep::Timeline => ((frame.time - self.at) / self.duration).clamp(0.0, 1.0) as f32,
+            Sweep::Beat => frame.beat_phase.expect("No --bpm provided!") as f32,
+        }
+    }
+
+    /// Wipes `first` over to the second input's frame for the same moment.
+    /// The second input starts playing when the transition does.
+    pub fn apply(&self, first: RgbImage, frame: &FrameContext) -> RgbImage {
+        let progress = self.progress(frame);
+        if progress <= 0.0 {
+            return first;
+        }
+
+        let time = match self.sweep {
+            Sweep::Timeline => frame.time - self.at,
+            Sweep::Beat => frame.time,
+        };
+        let Some(mut second) = self
+            .second
+            .lock()
+            .expect("Transition decoder lock poisoned")
+            .frame_at_time(time)
+        else {
+            return first;
+        };
+        if second.dimensions() != first.dimensions() {
+            let (width, height) = first.dimensions();
+            second = imageops::resize(&second, width, height, imageops::FilterType::Triangle);
+        }
+
+        let threshold = progress * (1.0 + SOFTNESS);
+        let mut out = first;
+        for ((a, b), m) in out
+            .pixels_mut()
+            .zip(second.pixels())
+            .zip(self.matte.pixels())
+        {
+            let m = m.0[0] as f32 / 255.0;
+            let t = ((threshold - m) / SOFTNESS).clamp(0.0, 1.0);
+            for c in 0..3 {
+                a.0[c] = (a.0[c] as f32 + (b.0[c] as f32 - a.0[c] as f32) * t).round() as u8;
+            }
+        }
+        out
+    }
+}
+
+fn luma(value: f32) -> Luma<u8> {
+    Luma([(value.clamp(0.0, 1.0) * 255.0).round() as u8])
+}
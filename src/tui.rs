@@ -0,0 +1,499 @@
+use std::path::Path;
+use std::time::Duration;
+
+use clap::{ArgAction, CommandFactory, Parser};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use image::{imageops, DynamicImage, RgbImage};
+use minifb::{Window, WindowOptions};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Paragraph, Widget},
+};
+
+use crate::Args;
+use vidfx::source::Source;
+use vidfx::FrameContext;
+
+/// One flag of the effect being tuned.
+enum Param {
+    Number {
+        name: String,
+        value: f64,
+        min: f64,
+        max: f64,
+        step: f64,
+    },
+    Choice {
+        name: String,
+        choices: Vec<String>,
+        selected: usize,
+    },
+    Switch {
+        name: String,
+        on: bool,
+    },
+}
+
+impl Param {
+    fn name(&self) -> &str {
+        match self {
+            Param::Number { name, .. }
+            | Param::Choice { name, .. }
+            | Param::Switch { name, .. } => name,
+        }
+    }
+
+    fn adjust(&mut self, steps: i32) {
+        match self {
+            Param::Number {
+                value,
+                min,
+                max,
+                step,
+                ..
+            } => *value = (*value + *step * steps as f64).clamp(*min, *max),
+            Param::Choice {
+                choices, selected, ..
+            } => {
+                let len = choices.len() as i32;
+                *selected = (*selected as i32 + steps).rem_euclid(len) as usize;
+            }
+            Param::Switch { on, .. } => *on ^= steps % 2 != 0,
+        }
+    }
+
+    fn display(&self) -> String {
+        match self {
+            Param::Number { value, step, .. } if *step >= 1.0 => format!("{}", *value as i64),
+            Param::Number { value, .. } => format!("{:.2}", value),
+            Param::Choice {
+                choices, selected, ..
+            } => choices[*selected].clone(),
+            Param::Switch { on, .. } => if *on { "on" } else { "off" }.to_string(),
+        }
+    }
+}
+
+/// (default, min, max, step) for numeric arguments, keyed by effect and name.
+fn number_range(effect: &str, name: &str) -> (f64, f64, f64, f64) {
+    match (effect, name) {
+        ("bloom", "intensity") => (1.0, 0.0, 10.0, 0.1),
+        ("bloom", "radius") => (8.0, 0.0, 64.0, 1.0),
+        ("bloom", "min_threshold") => (100.0, 0.0, 255.0, 5.0),
+        ("bloom", "max_threshold") => (255.0, 0.0, 255.0, 5.0),
+        ("sort", "min_threshold") => (0.2, 0.0, 1.0, 0.05),
+        ("sort", "max_threshold") => (0.8, 0.0, 1.0, 0.05),
+        ("strobe", "mix") => (1.0, 0.0, 1.0, 0.05),
+        ("strobe", "max_flash_rate") => (3.0, 0.0, 10.0, 0.5),
+        ("normalize", "clip") => (0.5, 0.0, 10.0, 0.1),
+        ("halftone", "dot_size") => (6.0, 1.0, 32.0, 1.0),
+        ("shatter", "cells") => (400.0, 1.0, 4000.0, 50.0),
+        ("shatter", "amount") => (0.5, 0.0, 1.0, 0.05),
+        ("shatter", "edges") => (1.0, 0.0, 8.0, 0.5),
+        ("displace", "amount") => (12.0, 0.0, 100.0, 1.0),
+        ("displace", "scale") => (64.0, 1.0, 512.0, 8.0),
+        ("displace", "evolve") => (0.5, 0.0, 4.0, 0.1),
+        ("displace", "angle") => (0.0, 0.0, 360.0, 15.0),
+        ("ripple", "amplitude") => (8.0, 0.0, 64.0, 1.0),
+        ("ripple", "wavelength") => (60.0, 1.0, 400.0, 5.0),
+        ("ripple", "speed") => (1.0, -4.0, 4.0, 0.1),
+        ("zoomblur", "amount") => (0.2, 0.0, 1.0, 0.05),
+        ("motionblur", "angle") => (0.0, 0.0, 360.0, 15.0),
+        ("motionblur", "length") => (12.0, 0.0, 100.0, 2.0),
+        ("mixer", "temperature") => (6500.0, 2000.0, 12000.0, 100.0),
+        ("mixer", "tint") => (0.0, -100.0, 100.0, 5.0),
+        ("replace-color", "tolerance") => (0.2, 0.0, 1.0, 0.02),
+        ("replace-color", "feather") => (0.05, 0.0, 1.0, 0.01),
+        ("match-color", "amount") => (1.0, 0.0, 1.0, 0.05),
+        (_, "bits") => (1.0, 0.0, 8.0, 1.0),
+        _ => (128.0, 0.0, 255.0, 8.0),
+    }
+}
+
+/// An effect subcommand together with the current values of its flags, built
+/// by introspecting the clap definition so any effect added to `SubCommands`
+/// shows up here.
+struct Effect {
+    name: String,
+    params: Vec<Param>,
+}
+
+impl Effect {
+    fn all() -> Vec<Effect> {
+        Args::command()
+            .get_subcommands()
+            .filter(|cmd| !crate::TOOL_COMMANDS.contains(&cmd.get_name()))
+            // Effects that need a file or similar can't be tuned with arrow keys
+            .filter(|cmd| !cmd.get_arguments().any(|arg| arg.is_required_set()))
+            .map(|cmd| {
+                let name = cmd.get_name().to_string();
+                let mut params = vec![];
+
+                let flags = cmd
+                    .get_arguments()
+                    .filter(|arg| !arg.is_hide_set() && !arg.is_global_set())
+                    .filter(|arg| !matches!(arg.get_id().as_str(), "help" | "version"))
+                    .filter_map(|arg| Some((arg, arg.get_long()?.to_string())));
+
+                for (arg, long) in flags {
+                    let id = arg.get_id().to_string();
+                    let possible: Vec<String> = arg
+                        .get_possible_values()
+                        .iter()
+                        .map(|v| v.get_name().to_string())
+                        .collect();
+
+                    if id == "color" {
+                        for channel in ["r", "g", "b"] {
+                            params.push(Param::Number {
+                                name: format!("color.{}", channel),
+                                value: 255.0,
+                                min: 0.0,
+                                max: 255.0,
+                                step: 8.0,
+                            });
+                        }
+                    } else if matches!(arg.get_action(), ArgAction::SetTrue) {
+                        params.push(Param::Switch {
+                            name: long,
+                            on: false,
+                        });
+                    } else if !possible.is_empty() {
+                        params.push(Param::Choice {
+                            name: long,
+                            choices: possible,
+                            selected: 0,
+                        });
+                    } else {
+                        let (value, min, max, step) = number_range(&name, &id);
+                        params.push(Param::Number {
+                            name: long,
+                            value,
+                            min,
+                            max,
+                            step,
+                        });
+                    }
+                }
+
+                Effect { name, params }
+            })
+            .collect()
+    }
+
+    /// The subcommand and its flags as they'd be typed on the command line.
+    fn cli_args(&self) -> Vec<String> {
+        let mut args = vec![self.name.clone()];
+        let mut color = vec![];
+
+        for param in &self.params {
+            match param {
+                Param::Number { name, value, .. } if name.starts_with("color.") => {
+                    color.push(*value as u8);
+                    if color.len() == 3 {
+                        args.push("--color".into());
+                        args.push(format!("{:02x}{:02x}{:02x}", color[0], color[1], color[2]));
+                    }
+                }
+                Param::Switch { name, on } => {
+                    if *on {
+                        args.push(format!("--{}", name));
+                    }
+                }
+                _ => {
+                    args.push(format!("--{}", param.name()));
+                    args.push(param.display());
+                }
+            }
+        }
+
+        args
+    }
+}
+
+/// Renders an image into the terminal with two pixels per cell using the
+/// upper half block, foreground for the top pixel and background for the bottom.
+struct HalfBlocks<'a>(&'a RgbImage);
+
+impl Widget for HalfBlocks<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.width == 0 || area.height == 0 {
+            return;
+        }
+
+        let scaled = imageops::resize(
+            self.0,
+            area.width as u32,
+            area.height as u32 * 2,
+            imageops::FilterType::Triangle,
+        );
+
+        for y in 0..area.height {
+            for x in 0..area.width {
+                let top = scaled.get_pixel(x as u32, y as u32 * 2).0;
+                let bottom = scaled.get_pixel(x as u32, y as u32 * 2 + 1).0;
+                if let Some(cell) = buf.cell_mut((area.x + x, area.y + y)) {
+                    cell.set_symbol("▀")
+                        .set_fg(Color::Rgb(top[0], top[1], top[2]))
+                        .set_bg(Color::Rgb(bottom[0], bottom[1], bottom[2]));
+                }
+            }
+        }
+    }
+}
+
+struct App {
+    input: String,
+    video: Source,
+    frame_rate: f64,
+    frame_index: i64,
+    source: RgbImage,
+    processed: RgbImage,
+    effects: Vec<Effect>,
+    effect: usize,
+    param: usize,
+    status: String,
+    window: Option<Window>,
+}
+
+impl App {
+    fn invocation(&self) -> String {
+        let mut words = vec![
+            "vidfx".to_string(),
+            "-i".to_string(),
+            self.input.clone(),
+            "--output".to_string(),
+            "output.mp4".to_string(),
+        ];
+        words.extend(self.effects[self.effect].cli_args());
+        words.join(" ")
+    }
+
+    fn seek(&mut self, frame_index: i64) {
+        let frame_index = frame_index.max(0);
+        match self.video.frame_at(frame_index as usize) {
+            Some(frame) => {
+                self.frame_index = frame_index;
+                self.source = frame;
+            }
+            None => self.status = "End of input".into(),
+        }
+    }
+
+    /// Runs the effect through the regular argument parser so the preview uses
+    /// exactly what the exported command line would.
+    fn reprocess(&mut self) {
+        let mut argv = vec!["vidfx".to_string(), "-i".to_string(), self.input.clone()];
+        argv.extend(self.effects[self.effect].cli_args());
+
+        match Args::try_parse_from(&argv) {
+            Ok(args) => {
+                let effect = args
+                    .cmd
+                    .to_effect(&args.lhs, &args.rhs, args.negate)
+                    .expect("Tool commands are filtered out of the effect list");
+                let processed = effect.apply(
+                    DynamicImage::ImageRgb8(self.source.clone()),
+                    &FrameContext::new(
+                        self.frame_index as usize,
+                        self.frame_index as f64 / self.frame_rate,
+                        self.frame_rate,
+                    ),
+                );
+                self.processed = DynamicImage::ImageRgba8(processed).into_rgb8();
+            }
+            Err(e) => {
+                self.status = e.to_string().lines().next().unwrap_or_default().to_string();
+            }
+        }
+
+        self.update_window();
+    }
+
+    fn update_window(&mut self) {
+        let Some(window) = self.window.as_mut() else {
+            return;
+        };
+
+        if !window.is_open() {
+            self.window = None;
+            return;
+        }
+
+        let buffer: Vec<u32> = self
+            .processed
+            .pixels()
+            .map(|p| (p.0[0] as u32) << 16 | (p.0[1] as u32) << 8 | p.0[2] as u32)
+            .collect();
+        let (width, height) = self.processed.dimensions();
+        let _ = window.update_with_buffer(&buffer, width as usize, height as usize);
+    }
+
+    fn toggle_window(&mut self) {
+        if self.window.take().is_some() {
+            return;
+        }
+
+        let (width, height) = self.processed.dimensions();
+        match Window::new(
+            "vidfx tui preview",
+            width as usize,
+            height as usize,
+            WindowOptions::default(),
+        ) {
+            Ok(window) => {
+                self.window = Some(window);
+                self.update_window();
+            }
+            Err(e) => self.status = format!("Could not open window: {}", e),
+        }
+    }
+
+    fn draw(&self, frame: &mut ratatui::Frame) {
+        let [preview, sidebar] =
+            Layout::horizontal([Constraint::Min(20), Constraint::Length(36)]).areas(frame.area());
+        let [params, help] =
+            Layout::vertical([Constraint::Min(5), Constraint::Length(9)]).areas(sidebar);
+
+        let title = format!(
+            " frame {} ({:.2}s) ",
+            self.frame_index,
+            self.frame_index as f64 / self.frame_rate
+        );
+        let block = Block::bordered().title(title);
+        let inner = block.inner(preview);
+        frame.render_widget(block, preview);
+        frame.render_widget(HalfBlocks(&self.processed), inner);
+
+        let effect = &self.effects[self.effect];
+        let mut lines = vec![Line::from(Span::styled(
+            effect.name.clone(),
+            Style::default().add_modifier(Modifier::BOLD),
+        ))];
+        for (i, param) in effect.params.iter().enumerate() {
+            let style = if i == self.param {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            lines.push(Line::from(Span::styled(
+                format!("{:<16}{}", param.name(), param.display()),
+                style,
+            )));
+        }
+        lines.push(Line::from(""));
+        lines.push(Line::from(self.status.clone()));
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(" effect ")),
+            params,
+        );
+
+        let keys = [
+            "tab/S-tab  effect",
+            "up/down    parameter",
+            "+/-        adjust (shift: x10)",
+            "left/right frame, pgup/pgdn 1s",
+            "w          toggle window",
+            "e          show command line",
+            "q          quit and print it",
+        ];
+        frame.render_widget(
+            Paragraph::new(keys.iter().map(|k| Line::from(*k)).collect::<Vec<_>>())
+                .block(Block::bordered().title(" keys ")),
+            help,
+        );
+    }
+
+    /// Returns false when the user asked to quit.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        let params = self.effects[self.effect].params.len();
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Tab | KeyCode::BackTab => {
+                let step = if code == KeyCode::Tab { 1 } else { -1 };
+                self.effect =
+                    (self.effect as i32 + step).rem_euclid(self.effects.len() as i32) as usize;
+                self.param = 0;
+                self.reprocess();
+            }
+            KeyCode::Up if params > 0 => self.param = (self.param + params - 1) % params,
+            KeyCode::Down if params > 0 => self.param = (self.param + 1) % params,
+            KeyCode::Char(c @ ('+' | '=' | '-' | '_')) if params > 0 => {
+                let steps = match c {
+                    '+' => 10,
+                    '=' => 1,
+                    '-' => -1,
+                    _ => -10,
+                };
+                self.effects[self.effect].params[self.param].adjust(steps);
+                self.reprocess();
+            }
+            KeyCode::Left => {
+                self.seek(self.frame_index - 1);
+                self.reprocess();
+            }
+            KeyCode::Right => {
+                self.seek(self.frame_index + 1);
+                self.reprocess();
+            }
+            KeyCode::PageUp => {
+                self.seek(self.frame_index - self.frame_rate.round() as i64);
+                self.reprocess();
+            }
+            KeyCode::PageDown => {
+                self.seek(self.frame_index + self.frame_rate.round() as i64);
+                self.reprocess();
+            }
+            KeyCode::Char('w') => self.toggle_window(),
+            KeyCode::Char('e') => self.status = self.invocation(),
+            _ => {}
+        }
+        true
+    }
+}
+
+/// Interactive parameter tuning on a single frame of `input`. Prints the
+/// resulting command line when the user quits.
+pub fn run(input: &str) {
+    let mut video = Source::open(Path::new(input)).expect("Failed to create decoder");
+    let frame_rate = video.frame_rate();
+    let source = video.next_frame().expect("Input has no frames");
+
+    let mut app = App {
+        input: input.to_string(),
+        video,
+        frame_rate,
+        frame_index: 0,
+        processed: source.clone(),
+        source,
+        effects: Effect::all(),
+        effect: 0,
+        param: 0,
+        status: String::new(),
+        window: None,
+    };
+    app.reprocess();
+
+    let mut terminal = ratatui::init();
+    loop {
+        terminal
+            .draw(|frame| app.draw(frame))
+            .expect("Failed to draw tui");
+
+        if event::poll(Duration::from_millis(50)).expect("Failed to poll terminal events") {
+            if let Event::Key(key) = event::read().expect("Failed to read terminal event") {
+                if key.kind == KeyEventKind::Press && !app.handle_key(key.code) {
+                    break;
+                }
+            }
+        }
+
+        app.update_window();
+    }
+    ratatui::restore();
+
+    println!("{}", app.invocation());
+}
@@ -0,0 +1,210 @@
+/// Parses a duration into seconds. Accepts plain seconds (`90`, `1.5`), unit
+/// suffixes (`120ms`, `4s`, `3m`, `1h`) and clock notation (`1:30`, `0:01:30.5`).
+pub fn parse_duration(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+
+    if s.contains(':') {
+        return s.split(':').try_fold(0.0, |total, part| {
+            part.parse::<f64>()
+                .map(|value| total * 60.0 + value)
+                .map_err(|_| format!("invalid timestamp '{}'", s))
+        });
+    }
+
+    let (number, scale) = if let Some(n) = s.strip_suffix("ms") {
+        (n, 0.001)
+    } else if let Some(n) = s.strip_suffix('s') {
+        (n, 1.0)
+    } else if let Some(n) = s.strip_suffix('m') {
+        (n, 60.0)
+    } else if let Some(n) = s.strip_suffix('h') {
+        (n, 3600.0)
+    } else {
+        (s, 1.0)
+    };
+
+    let value = number
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| format!("invalid duration '{}'", s))?;
+
+    if value < 0.0 {
+        return Err(format!("duration '{}' is negative", s));
+    }
+
+    Ok(value * scale)
+}
+
+/// Parses a byte size such as `8MB`, `500k` or `1.5GiB`. Decimal suffixes
+/// (`KB`, `MB`, `GB`) are powers of 1000, binary ones (`KiB`, `K`, ...) of 1024.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+
+    let scale: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "kb" => 1000,
+        "mb" => 1000 * 1000,
+        "gb" => 1000 * 1000 * 1000,
+        "k" | "kib" => 1 << 10,
+        "m" | "mib" => 1 << 20,
+        "g" | "gib" => 1 << 30,
+        _ => return Err(format!("invalid size '{}'", s)),
+    };
+
+    let value = number
+        .parse::<f64>()
+        .map_err(|_| format!("invalid size '{}'", s))?;
+
+    Ok((value * scale as f64) as u64)
+}
+
+/// `bytes` in the largest decimal unit that keeps it above one, e.g. `85.3 MB`.
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1000.0 && unit + 1 < units.len() {
+        value /= 1000.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
+/// Parses an aspect ratio `W:H`, e.g. `9:16`.
+pub fn parse_aspect(s: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid aspect ratio '{}', expected e.g. 9:16", s);
+    let (w, h) = s.split_once(':').ok_or_else(invalid)?;
+    let w = w.trim().parse::<u32>().map_err(|_| invalid())?;
+    let h = h.trim().parse::<u32>().map_err(|_| invalid())?;
+    if w == 0 || h == 0 {
+        return Err(invalid());
+    }
+    Ok((w, h))
+}
+
+/// Parses `WIDTHxHEIGHT`, e.g. `1920x1080`.
+pub fn parse_resolution(s: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid resolution '{}', expected e.g. 1920x1080", s);
+    let (width, height) = s.trim().split_once(['x', 'X']).ok_or_else(invalid)?;
+    let width = width.trim().parse::<u32>().map_err(|_| invalid())?;
+    let height = height.trim().parse::<u32>().map_err(|_| invalid())?;
+
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+
+    Ok((width, height))
+}
+
+/// Parses a rectangle as `x,y,width,height` in pixels, e.g. `100,50,640,360`.
+pub fn parse_rect(s: &str) -> Result<(u32, u32, u32, u32), String> {
+    let invalid = || format!("invalid region '{}', expected x,y,width,height", s);
+    let parts: Vec<u32> = s
+        .split(',')
+        .map(|part| part.trim().parse::<u32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| invalid())?;
+
+    match parts[..] {
+        [x, y, width, height] if width > 0 && height > 0 => Ok((x, y, width, height)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses a percentage such as `0.5%` or `2`, as the number of percent.
+pub fn parse_percent(s: &str) -> Result<f32, String> {
+    let value = s
+        .trim()
+        .trim_end_matches('%')
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| format!("invalid percentage '{}'", s))?;
+    if !(0.0..=100.0).contains(&value) {
+        return Err(format!("percentage '{}' is outside 0..100", s));
+    }
+    Ok(value)
+}
+
+/// Parses a positive multiplier such as `2`, `0.5` or `1/4`.
+pub fn parse_multiplier(s: &str) -> Result<f64, String> {
+    let invalid = || format!("invalid multiplier '{}', expected e.g. 2, 0.5 or 1/4", s);
+    let value = match s.split_once('/') {
+        Some((n, d)) => {
+            let n = n.trim().parse::<f64>().map_err(|_| invalid())?;
+            let d = d.trim().parse::<f64>().map_err(|_| invalid())?;
+            n / d
+        }
+        None => s.trim().parse::<f64>().map_err(|_| invalid())?,
+    };
+    if !(value > 0.0 && value.is_finite()) {
+        return Err(invalid());
+    }
+    Ok(value)
+}
+
+/// Parses a `min..max` range such as `0.3..1.0`.
+pub fn parse_range(s: &str) -> Result<(f64, f64), String> {
+    let invalid = || format!("invalid range '{}', expected e.g. 0.3..1.0", s);
+    let (min, max) = s.split_once("..").ok_or_else(invalid)?;
+    let min = min.trim().parse::<f64>().map_err(|_| invalid())?;
+    let max = max.trim().parse::<f64>().map_err(|_| invalid())?;
+    Ok((min, max))
+}
+
+/// Parses a point in fractions of the frame, `center` or `x,y` such as
+/// `0.25,0.5`.
+pub fn parse_origin(s: &str) -> Result<(f32, f32), String> {
+    if s.trim() == "center" {
+        return Ok((0.5, 0.5));
+    }
+    let invalid = || format!("invalid origin '{}', expected center or e.g. 0.25,0.5", s);
+    let (x, y) = s.split_once(',').ok_or_else(invalid)?;
+    let x = x.trim().parse::<f32>().map_err(|_| invalid())?;
+    let y = y.trim().parse::<f32>().map_err(|_| invalid())?;
+    Ok((x, y))
+}
+
+/// Parses a value for each of red, green and blue, e.g. `1,0.5,1`.
+pub fn parse_rgb(s: &str) -> Result<[f32; 3], String> {
+    let invalid = || {
+        format!(
+            "invalid channels '{}', expected three values e.g. 1,0.5,1",
+            s
+        )
+    };
+    let values: Vec<f32> = s
+        .split(',')
+        .map(|value| value.trim().parse::<f32>().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    values.try_into().map_err(|_| invalid())
+}
+
+/// Parses a 3x3 matrix written row by row, e.g. `1 0 0; 0 1 0; 0 0 1`.
+pub fn parse_matrix(s: &str) -> Result<[[f32; 3]; 3], String> {
+    let invalid = || {
+        format!(
+            "invalid matrix '{}', expected e.g. \"1 0 0; 0 1 0; 0 0 1\"",
+            s
+        )
+    };
+    let rows: Vec<[f32; 3]> = s
+        .split(';')
+        .map(|row| {
+            let values: Vec<f32> = row
+                .split([' ', ','])
+                .filter(|value| !value.is_empty())
+                .map(|value| value.parse::<f32>().map_err(|_| invalid()))
+                .collect::<Result<_, _>>()?;
+            values.try_into().map_err(|_| invalid())
+        })
+        .collect::<Result<_, _>>()?;
+    rows.try_into().map_err(|_| invalid())
+}
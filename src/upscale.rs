@@ -0,0 +1,103 @@
+//! `upscale`: enlarging frames after every effect, so low resolution sources
+//! can be delivered at 1080p or 4K.
+//!
+//! With a model, Real-ESRGAN-style ONNX networks are expected: RGB from 0 to
+//! 1 in, channels first, and the image at a fixed multiple of the size back.
+//! Frames are run through in overlapping tiles to keep memory in check, then
+//! resized to the exact `--factor`.
+
+use std::sync::OnceLock;
+
+use image::imageops::{self, FilterType};
+use image::{RgbImage, RgbaImage};
+
+use crate::onnx::Plan;
+
+/// Side of the tiles given to the model, without their padding.
+const TILE: u32 = 128;
+
+/// Pixels of the neighbouring tiles each tile sees on every side, so the
+/// seams don't show.
+const PAD: u32 = 8;
+
+pub struct Upscale {
+    factor: f64,
+    plan: Option<Plan>,
+    /// How much the model enlarges by, known once it has run
+    model_scale: OnceLock<u32>,
+}
+
+impl Upscale {
+    /// `model` is an ONNX file, or `lanczos` for plain resampling.
+    pub fn load(model: &str, factor: f64) -> Upscale {
+        let side = (TILE + 2 * PAD) as usize;
+        let plan = (model != "lanczos").then(|| Plan::load(model, [1, 3, side, side]));
+        Upscale {
+            factor,
+            plan,
+            model_scale: OnceLock::new(),
+        }
+    }
+
+    /// The size `width`x`height` frames come out at, kept even for 4:2:0
+    /// encoders.
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        let even = |size: u32| ((size as f64 * self.factor / 2.0).round() as u32 * 2).max(2);
+        (even(width), even(height))
+    }
+
+    fn run_tile(&self, plan: &Plan, tile: &RgbImage) -> RgbImage {
+        let outputs = plan
+            .run(|[_, c, y, x]| tile.get_pixel(x as u32, y as u32).0[c] as f32 / 255.0)
+            .unwrap_or_else(|e| panic!("Failed to run upscale model: {}", e));
+        outputs
+            .first()
+            .and_then(|output| output.rgb(255.0))
+            .expect("Upscale models output one RGB image")
+    }
+
+    /// `img` enlarged by the model at its own scale.
+    fn model_upscale(&self, plan: &Plan, img: &RgbImage) -> RgbImage {
+        let (width, height) = img.dimensions();
+        let side = TILE + 2 * PAD;
+        let mut out: Option<RgbImage> = None;
+        for ty in (0..height).step_by(TILE as usize) {
+            for tx in (0..width).step_by(TILE as usize) {
+                // The tile with its padding, edges stretched past the frame
+                let tile = RgbImage::from_fn(side, side, |x, y| {
+                    let sx = (tx as i64 + x as i64 - PAD as i64).clamp(0, width as i64 - 1);
+                    let sy = (ty as i64 + y as i64 - PAD as i64).clamp(0, height as i64 - 1);
+                    *img.get_pixel(sx as u32, sy as u32)
+                });
+                let upscaled = self.run_tile(plan, &tile);
+                let scale = *self
+                    .model_scale
+                    .get_or_init(|| (upscaled.width() / side).max(1));
+                let out = out.get_or_insert_with(|| RgbImage::new(width * scale, height * scale));
+
+                let (w, h) = (TILE.min(width - tx), TILE.min(height - ty));
+                let inner =
+                    imageops::crop_imm(&upscaled, PAD * scale, PAD * scale, w * scale, h * scale)
+                        .to_image();
+                imageops::replace(out, &inner, (tx * scale) as i64, (ty * scale) as i64);
+            }
+        }
+        out.expect("Frames have at least one tile")
+    }
+
+    pub fn apply(&self, img: RgbaImage) -> RgbaImage {
+        let (width, height) = self.size(img.width(), img.height());
+        let Some(plan) = &self.plan else {
+            return imageops::resize(&img, width, height, FilterType::Lanczos3);
+        };
+
+        let rgb = image::DynamicImage::ImageRgba8(img).into_rgb8();
+        let upscaled = self.model_upscale(plan, &rgb);
+        let rgba = image::DynamicImage::ImageRgb8(upscaled).into_rgba8();
+        if rgba.dimensions() == (width, height) {
+            rgba
+        } else {
+            imageops::resize(&rgba, width, height, FilterType::Lanczos3)
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use std::path::Path;
+
+use clap::Parser;
+use serde_json::Value;
+use vidfx::chain::MAX_SHIFT;
+use vidfx::displace::MapSpec;
+use vidfx::{schema, Effect};
+
+use crate::project::Project;
+use crate::Args;
+
+/// Problems found in one file, each with the line or field it is about.
+struct Report<'a> {
+    path: &'a str,
+    errors: usize,
+}
+
+impl Report<'_> {
+    fn error(&mut self, at: &str, message: impl std::fmt::Display) {
+        eprintln!("{}: {}: {}", self.path, at, message);
+        self.errors += 1;
+    }
+
+    fn exists(&mut self, at: &str, file: &str) {
+        if !Path::new(file).exists() {
+            self.error(at, format!("{} does not exist", file));
+        }
+    }
+}
+
+/// Checks project and preset files for syntax errors, unknown effects and
+/// fields, out of range parameters, bad settings and missing files. Returns
+/// whether every file is valid.
+pub fn run(files: &[String]) -> bool {
+    let mut valid = true;
+    for path in files {
+        let mut report = Report { path, errors: 0 };
+        validate(&mut report);
+        if report.errors == 0 {
+            eprintln!("{}: ok", path);
+        }
+        valid &= report.errors == 0;
+    }
+    valid
+}
+
+fn validate(report: &mut Report) {
+    let path = report.path;
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => return report.error("file", e),
+    };
+    let json: Value = match serde_json::from_str(&text) {
+        Ok(json) => json,
+        Err(e) => {
+            let at = format!("line {} column {}", e.line(), e.column());
+            return report.error(&at, e);
+        }
+    };
+    if !json.is_object() {
+        return report.error("file", "expected a JSON object");
+    }
+    let json = match schema::migrate(json) {
+        Ok(json) => json,
+        Err(e) => return report.error("version", e),
+    };
+
+    match json.get("effects") {
+        Some(Value::Array(effects)) => {
+            for (i, effect) in effects.iter().enumerate() {
+                validate_effect(report, &format!("effects[{}]", i), effect);
+            }
+        }
+        Some(_) => report.error("effects", "expected an array of effects"),
+        None => {}
+    }
+
+    let is_project = ["input", "output", "settings"]
+        .iter()
+        .any(|key| json.get(key).is_some());
+    if is_project {
+        validate_project(report, json);
+    } else if json.get("effects").is_none() {
+        report.error("effects", "missing, a preset needs an effects array");
+    }
+}
+
+fn validate_effect(report: &mut Report, at: &str, json: &Value) {
+    let effect: Effect = match serde_json::from_value(json.clone()) {
+        Ok(effect) => effect,
+        Err(e) => return report.error(at, e),
+    };
+
+    // Whatever didn't survive a round trip wasn't a field of the effect
+    let known = serde_json::to_value(&effect).expect("Effects serialize");
+    if let Some(fields) = json.as_object() {
+        for (field, value) in fields {
+            if !value.is_null() && known.get(field).is_none() {
+                report.error(&format!("{}.{}", at, field), "unknown field");
+            }
+        }
+    }
+
+    let mut missing = None;
+    let mut invalid = None;
+    let mut empty = None;
+    let mut range = |field: &str, value: f64, min: f64, max: f64| {
+        if !(min..=max).contains(&value) {
+            report.error(
+                &format!("{}.{}", at, field),
+                format!("{} is outside {}..{}", value, min, max),
+            );
+        }
+    };
+    match &effect {
+        Effect::Left { bits, .. } | Effect::Right { bits, .. } => {
+            range("bits", *bits as f64, 0.0, MAX_SHIFT as f64);
+        }
+        Effect::Normalize { clip, .. } => {
+            range("clip", *clip as f64, 0.0, 50.0);
+        }
+        Effect::Halftone { dot_size, .. } => {
+            range("dot_size", *dot_size as f64, 1.0, f64::INFINITY);
+        }
+        Effect::Ascii { charset, cell, .. } => {
+            if charset.is_empty() {
+                empty = Some("charset");
+            }
+            range("cell[0]", cell[0] as f64, 1.0, f64::INFINITY);
+            range("cell[1]", cell[1] as f64, 1.0, f64::INFINITY);
+        }
+        Effect::Shatter {
+            cells,
+            amount,
+            edges,
+            ..
+        } => {
+            range("cells", *cells as f64, 1.0, f64::INFINITY);
+            range("amount", *amount as f64, 0.0, 1.0);
+            range("edges", *edges as f64, 0.0, f64::INFINITY);
+        }
+        Effect::Displace {
+            map,
+            amount,
+            scale,
+            evolve,
+            ..
+        } => {
+            range("amount", *amount as f64, 0.0, f64::INFINITY);
+            range("scale", *scale as f64, 1.0, f64::INFINITY);
+            range("evolve", *evolve as f64, 0.0, f64::INFINITY);
+            match MapSpec::parse(map) {
+                Ok(MapSpec::Noise) => {}
+                // Video maps can be streams, which ffmpeg opens itself
+                Ok(MapSpec::Video(path)) if path.contains("://") => {}
+                Ok(MapSpec::Image(path) | MapSpec::Video(path)) => {
+                    missing = Some(("map", path.to_string()));
+                }
+                Err(e) => invalid = Some(("map", e)),
+            }
+        }
+        Effect::Ripple {
+            amplitude,
+            wavelength,
+            ..
+        } => {
+            range("amplitude", *amplitude as f64, 0.0, f64::INFINITY);
+            range("wavelength", *wavelength as f64, 1.0, f64::INFINITY);
+        }
+        Effect::Zoomblur { amount, .. } => {
+            range("amount", *amount as f64, 0.0, 1.0);
+        }
+        Effect::Motionblur { length, .. } => {
+            range("length", *length as f64, 0.0, f64::INFINITY);
+        }
+        Effect::Mixer {
+            temperature, tint, ..
+        } => {
+            if let Some(temperature) = temperature {
+                range("temperature", *temperature as f64, 1000.0, 40000.0);
+            }
+            range("tint", *tint as f64, -100.0, 100.0);
+        }
+        Effect::ReplaceColor {
+            tolerance, feather, ..
+        } => {
+            range("tolerance", *tolerance as f64, 0.0, 1.0);
+            range("feather", *feather as f64, 0.0, 1.0);
+        }
+        Effect::MatchColor {
+            reference, amount, ..
+        } => {
+            if reference.is_empty() {
+                empty = Some("reference");
+            }
+            range("amount", *amount as f64, 0.0, 1.0);
+        }
+        Effect::GradientMap { stops, .. } => {
+            if stops.is_empty() {
+                empty = Some("stops");
+            }
+            for (i, stop) in stops.iter().enumerate() {
+                range(&format!("stops[{}].at", i), stop.at as f64, 0.0, 1.0);
+            }
+        }
+        Effect::Bloom {
+            intensity,
+            radius,
+            min_threshold,
+            max_threshold,
+        } => {
+            range("intensity", *intensity as f64, 0.0, f64::INFINITY);
+            range("radius", *radius as f64, 0.0, f64::INFINITY);
+            if let Some(max) = max_threshold {
+                range("max_threshold", *max as f64, *min_threshold as f64, 255.0);
+            }
+        }
+        Effect::Sort {
+            min_threshold,
+            max_threshold,
+            ..
+        } => {
+            range("min_threshold", *min_threshold as f64, 0.0, 1.0);
+            range(
+                "max_threshold",
+                *max_threshold as f64,
+                *min_threshold as f64,
+                1.0,
+            );
+        }
+        Effect::Shader { file, .. } | Effect::Isf { file, .. } => {
+            missing = Some(("file", file.clone()));
+        }
+        _ => {}
+    }
+    if let Some((field, file)) = missing {
+        report.exists(&format!("{}.{}", at, field), &file);
+    }
+    if let Some((field, message)) = invalid {
+        report.error(&format!("{}.{}", at, field), message);
+    }
+    if let Some(field) = empty {
+        report.error(&format!("{}.{}", at, field), "can't be empty");
+    }
+}
+
+fn validate_project(report: &mut Report, json: Value) {
+    let path = report.path;
+    let project: Project = match serde_json::from_value(json) {
+        Ok(project) => project,
+        Err(e) => return report.error("project", e),
+    };
+
+    // The settings are flags, so clap knows which are valid
+    let argv = std::iter::once("vidfx".to_string())
+        .chain(project.args(path))
+        .chain(["render".to_string(), path.to_string()]);
+    let args = match Args::try_parse_from(argv) {
+        Ok(args) => args,
+        Err(e) => {
+            let message = e.to_string();
+            let first = message.lines().next().unwrap_or_default();
+            return report.error("settings", first.trim_start_matches("error: "));
+        }
+    };
+
+    match args.input.as_deref() {
+        Some(input) if input.starts_with("generate:") || input.contains("://") => {}
+        Some(input) => report.exists("input", input),
+        None => report.error("input", "missing"),
+    }
+    if let Some(markers) = &args.markers {
+        report.exists("settings.markers", markers);
+    }
+    if let Some(midi) = &args.midi_file {
+        report.exists("settings.midi-file", midi);
+    }
+    for plugin in &args.plugin {
+        report.exists("settings.plugin", plugin);
+    }
+    if let Some(sequence) = &args.sequence {
+        for name in sequence.split(',').map(str::trim) {
+            if name.is_empty() || name == "clean" {
+                continue;
+            }
+            let preset = if name.ends_with(".json") {
+                Path::new(name).to_path_buf()
+            } else {
+                Path::new(&args.preset_dir).join(format!("{}.json", name))
+            };
+            report.exists("settings.sequence", &preset.to_string_lossy());
+        }
+    }
+}
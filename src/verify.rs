@@ -0,0 +1,71 @@
+use image::{DynamicImage, RgbImage};
+use vidfx::FrameContext;
+
+fn render<F>(frames: &[RgbImage], contexts: &[FrameContext], process: &F) -> Vec<Vec<u8>>
+where
+    F: Fn(DynamicImage, &FrameContext) -> DynamicImage,
+{
+    frames
+        .iter()
+        .zip(contexts)
+        .map(|(img, context)| {
+            process(DynamicImage::ImageRgb8(img.clone()), context)
+                .into_rgba8()
+                .into_raw()
+        })
+        .collect()
+}
+
+/// Renders `frames` twice and panics at the first one whose bytes differ.
+pub fn run<F>(frames: &[RgbImage], contexts: &[FrameContext], process: &F)
+where
+    F: Fn(DynamicImage, &FrameContext) -> DynamicImage,
+{
+    let first = render(frames, contexts, process);
+    let second = render(frames, contexts, process);
+    compare(&first, &second);
+    println!("{} frames rendered identically twice", first.len());
+}
+
+/// Like [`run`], with the second render split across `threads` threads, each
+/// starting with cold shader and ISF caches.
+pub fn run_threaded<F>(frames: &[RgbImage], contexts: &[FrameContext], process: &F, threads: usize)
+where
+    F: Fn(DynamicImage, &FrameContext) -> DynamicImage + Sync,
+{
+    let first = render(frames, contexts, process);
+
+    let chunk = frames.len().div_ceil(threads).max(1);
+    let second: Vec<Vec<u8>> = std::thread::scope(|scope| {
+        let workers: Vec<_> = frames
+            .chunks(chunk)
+            .zip(contexts.chunks(chunk))
+            .map(|(frames, contexts)| scope.spawn(move || render(frames, contexts, process)))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Render thread panicked"))
+            .collect()
+    });
+
+    compare(&first, &second);
+    println!(
+        "{} frames rendered identically twice, the second time on {} threads",
+        first.len(),
+        threads
+    );
+}
+
+fn compare(first: &[Vec<u8>], second: &[Vec<u8>]) {
+    for (index, (a, b)) in first.iter().zip(second).enumerate() {
+        if a != b {
+            let differing = a.iter().zip(b).filter(|(a, b)| a != b).count();
+            panic!(
+                "Frame {} is not deterministic: {} of {} bytes differ between renders",
+                index,
+                differing,
+                a.len()
+            );
+        }
+    }
+}
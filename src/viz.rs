@@ -0,0 +1,148 @@
+use image::{Rgb, RgbImage};
+use vidfx::audio::{self, Audio};
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum VizStyle {
+    /// Spectrum analyzer bars
+    Bars,
+    /// Oscilloscope trace of the waveform
+    Scope,
+    /// Scrolling spectrogram, low frequencies at the bottom
+    Spectrogram,
+}
+
+const FFT_SIZE: usize = 2048;
+const BAR_COUNT: usize = 64;
+/// Seconds of history visible in the spectrogram.
+const SPECTROGRAM_SECONDS: f64 = 8.0;
+
+/// Black to purple to orange to yellow.
+fn heat(v: f32) -> Rgb<u8> {
+    let stops = [
+        [0.0, 0.0, 0.0],
+        [90.0, 20.0, 120.0],
+        [230.0, 90.0, 40.0],
+        [255.0, 240.0, 120.0],
+    ];
+    let scaled = v.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+    let i = (scaled as usize).min(stops.len() - 2);
+    let t = scaled - i as f32;
+    Rgb(std::array::from_fn(|c| {
+        (stops[i][c] + (stops[i + 1][c] - stops[i][c]) * t) as u8
+    }))
+}
+
+/// Frames synthesized from an audio track, one per `1 / fps` seconds of audio.
+pub struct Visualizer {
+    audio: Audio,
+    style: VizStyle,
+    width: u32,
+    height: u32,
+    fps: f64,
+    index: usize,
+    bars: Vec<f32>,
+    spectrogram: RgbImage,
+}
+
+impl Visualizer {
+    pub fn new(audio: Audio, style: VizStyle, (width, height): (u32, u32), fps: f64) -> Self {
+        Visualizer {
+            audio,
+            style,
+            width,
+            height,
+            fps,
+            index: 0,
+            bars: vec![0.0; BAR_COUNT],
+            spectrogram: RgbImage::new(width, height),
+        }
+    }
+
+    fn bars(&mut self, time: f64) -> RgbImage {
+        let spectrum = audio::spectrum(&self.audio.window(time, FFT_SIZE));
+        let bands = audio::bands(&spectrum, self.audio.rate, BAR_COUNT);
+
+        // Fast attack, slow release so bars don't flicker
+        for (bar, band) in self.bars.iter_mut().zip(bands) {
+            *bar = if band > *bar {
+                band
+            } else {
+                *bar * 0.85 + band * 0.15
+            };
+        }
+
+        let mut img = RgbImage::new(self.width, self.height);
+        let bar_width = self.width as f32 / BAR_COUNT as f32;
+        for (i, bar) in self.bars.iter().enumerate() {
+            let top = self.height - (bar * self.height as f32) as u32;
+            let from = (i as f32 * bar_width) as u32;
+            let to = (((i + 1) as f32 * bar_width) as u32)
+                .saturating_sub(1)
+                .max(from + 1);
+            for x in from..to.min(self.width) {
+                for y in top..self.height {
+                    img.put_pixel(x, y, heat(1.0 - y as f32 / self.height as f32));
+                }
+            }
+        }
+        img
+    }
+
+    fn scope(&self, time: f64) -> RgbImage {
+        let samples = self.audio.window(time, FFT_SIZE);
+        let mut img = RgbImage::new(self.width, self.height);
+        let mid = self.height as f32 / 2.0;
+        let y_at = |x: u32| {
+            let sample = samples[x as usize * (samples.len() - 1) / self.width.max(2) as usize];
+            (mid - sample * mid).clamp(0.0, self.height as f32 - 1.0) as u32
+        };
+
+        let mut previous = y_at(0);
+        for x in 0..self.width {
+            let y = y_at(x);
+            for y in previous.min(y)..=previous.max(y) {
+                img.put_pixel(x, y, Rgb([120, 255, 160]));
+            }
+            previous = y;
+        }
+        img
+    }
+
+    fn spectrogram(&mut self, time: f64) -> RgbImage {
+        let spectrum = audio::spectrum(&self.audio.window(time, FFT_SIZE));
+        let rows = audio::bands(&spectrum, self.audio.rate, self.height as usize);
+
+        let step = ((self.width as f64 / (self.fps * SPECTROGRAM_SECONDS)).round() as u32)
+            .clamp(1, self.width);
+        let kept = self.width - step;
+        for y in 0..self.height {
+            for x in 0..kept {
+                let pixel = *self.spectrogram.get_pixel(x + step, y);
+                self.spectrogram.put_pixel(x, y, pixel);
+            }
+            let color = heat(rows[(self.height - 1 - y) as usize]);
+            for x in kept..self.width {
+                self.spectrogram.put_pixel(x, y, color);
+            }
+        }
+        self.spectrogram.clone()
+    }
+}
+
+impl Iterator for Visualizer {
+    type Item = RgbImage;
+
+    fn next(&mut self) -> Option<RgbImage> {
+        let time = self.index as f64 / self.fps;
+        if time >= self.audio.duration() {
+            return None;
+        }
+        self.index += 1;
+
+        Some(match self.style {
+            VizStyle::Bars => self.bars(time),
+            VizStyle::Scope => self.scope(time),
+            VizStyle::Spectrogram => self.spectrogram(time),
+        })
+    }
+}
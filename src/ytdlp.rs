@@ -0,0 +1,34 @@
+use std::io::ErrorKind;
+use std::process::Command;
+
+/// Prefix of inputs resolved through yt-dlp, `ytdlp:https://...`.
+pub const PREFIX: &str = "ytdlp:";
+
+/// The media url to decode for a `ytdlp:` page url, as yt-dlp resolves it.
+/// Without yt-dlp on the PATH the url is taken to already be a stream.
+pub fn resolve(url: &str) -> String {
+    let output = Command::new("yt-dlp")
+        .args(["--no-playlist", "--format", "best[vcodec!=none]/bestvideo"])
+        .arg("--get-url")
+        .arg(url)
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .unwrap_or_else(|| panic!("yt-dlp found no video for {}", url))
+            .to_string(),
+        Ok(output) => panic!(
+            "yt-dlp failed to resolve {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            eprintln!("yt-dlp not found, reading {} as a stream url", url);
+            url.to_string()
+        }
+        Err(e) => panic!("Failed to run yt-dlp: {}", e),
+    }
+}
@@ -0,0 +1,34 @@
+//! Runs the `vidfx-cli` binary on argument lists that need no video.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+fn vidfx() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_vidfx-cli"))
+}
+
+/// A minimal preset written to the temp dir for `validate`.
+fn preset() -> PathBuf {
+    let path = std::env::temp_dir().join(format!("vidfx-cli-{}.json", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"{"effects": [{"effect": "or", "color": "ff0000"}]}"#,
+    )
+    .expect("Failed to write preset");
+    path
+}
+
+/// Project settings and `.vidfx.toml` defaults come before the command line's
+/// own flags, so giving a flag twice must override rather than fail.
+#[test]
+fn repeated_flags_override() {
+    let preset = preset();
+    let status = vidfx()
+        .args(["--seed", "1", "--seed", "2", "--overwrite", "--overwrite"])
+        .arg("validate")
+        .arg(&preset)
+        .status()
+        .expect("Failed to run vidfx-cli");
+    let _ = std::fs::remove_file(&preset);
+    assert!(status.success());
+}
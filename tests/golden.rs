@@ -0,0 +1,188 @@
+//! Renders small generated sources through every effect and compares a hash
+//! of each frame against `tests/golden/hashes.txt`.
+//!
+//! Run with `cargo test --features golden`. After an intentional change to an
+//! effect, regenerate the hashes with `VIDFX_BLESS=1 cargo test --features
+//! golden` and review which lines changed. Mismatching frames are written to
+//! `target/golden/` as PNGs for a look. Frames with no recorded hash yet, such
+//! as those of a newly added case, are listed but don't fail until blessed.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use image::DynamicImage;
+use vidfx::generate::{Generator, Pattern};
+use vidfx::hash::fnv1a;
+use vidfx::{Effect, FrameContext};
+
+const HASHES: &str = "tests/golden/hashes.txt";
+const SIZE: (u32, u32) = (64, 36);
+const FRAMES: usize = 3;
+
+/// One chain step per effect subcommand, in the JSON form presets use. GPU
+/// effects (shader, isf) are left out since drivers differ in their output.
+const CASES: &[(&str, &str)] = &[
+    ("or", r#"{"effect": "or", "color": "ff0000"}"#),
+    ("and", r#"{"effect": "and", "color": "00ff00"}"#),
+    ("xor", r#"{"effect": "xor", "color": "0000ff"}"#),
+    ("left", r#"{"effect": "left", "bits": 2}"#),
+    ("right", r#"{"effect": "right", "bits": 2}"#),
+    ("add", r#"{"effect": "add", "color": "202020"}"#),
+    ("sub", r#"{"effect": "sub", "color": "202020"}"#),
+    ("mult", r#"{"effect": "mult", "color": "808080"}"#),
+    ("pow", r#"{"effect": "pow", "color": "020202"}"#),
+    ("div", r#"{"effect": "div", "color": "020202"}"#),
+    ("average", r#"{"effect": "average", "color": "ff8800"}"#),
+    ("screen", r#"{"effect": "screen", "color": "ff8800"}"#),
+    ("overlay", r#"{"effect": "overlay", "color": "ff8800"}"#),
+    (
+        "bloom",
+        r#"{"effect": "bloom", "intensity": 1.0, "radius": 4.0, "min_threshold": 100}"#,
+    ),
+    (
+        "sort",
+        r#"{"effect": "sort", "direction": "horizontal", "sort_by": "luma", "min_threshold": 0.2, "max_threshold": 0.8}"#,
+    ),
+    (
+        "strobe",
+        r#"{"effect": "strobe", "color": "ffffff", "duration": "1f", "per": "beat", "mix": 0.8}"#,
+    ),
+    ("normalize", r#"{"effect": "normalize", "clip": 0.5}"#),
+    (
+        "halftone",
+        r#"{"effect": "halftone", "dot_size": 6.0, "angles": [15.0, 75.0, 0.0, 45.0]}"#,
+    ),
+    (
+        "ascii",
+        r#"{"effect": "ascii", "charset": "dense", "cell": [8, 12], "color": true}"#,
+    ),
+    (
+        "shatter",
+        r#"{"effect": "shatter", "cells": 400, "jitter": "beat", "amount": 0.5, "fill": "shards", "edges": 1.0}"#,
+    ),
+    (
+        "displace",
+        r#"{"effect": "displace", "map": "noise:perlin", "amount": 12.0, "scale": 64.0, "evolve": 0.5}"#,
+    ),
+    (
+        "ripple",
+        r#"{"effect": "ripple", "amplitude": 8.0, "wavelength": 60.0, "speed": 1.0, "origin": [0.5, 0.5]}"#,
+    ),
+    (
+        "zoomblur",
+        r#"{"effect": "zoomblur", "center": [0.5, 0.5], "amount": 0.2}"#,
+    ),
+    (
+        "motionblur",
+        r#"{"effect": "motionblur", "angle": 90.0, "length": 12.0}"#,
+    ),
+    (
+        "curves",
+        r#"{"effect": "curves", "master": "0,0 0.5,0.7 1,1", "b": "0,0.1 1,0.9"}"#,
+    ),
+    (
+        "mixer",
+        r#"{"effect": "mixer", "matrix": [[1.1, 0, 0], [0, 1, 0], [0, 0.1, 0.9]], "temperature": 5600, "tint": -5}"#,
+    ),
+    (
+        "replace-color",
+        r#"{"effect": "replace-color", "from": "00ff00", "to": "ff00ff", "tolerance": 0.2, "feather": 0.05}"#,
+    ),
+    (
+        "gradient-map",
+        r#"{"effect": "gradient-map", "stops": [{"at": 0.0, "color": "0b1e3f"}, {"at": 0.6, "color": "d4145a"}, {"at": 1.0, "color": "ff7a18"}]}"#,
+    ),
+    (
+        "falsecolor",
+        r#"{"effect": "falsecolor", "palette": "exposure"}"#,
+    ),
+    (
+        "match-color",
+        r#"{"effect": "match-color", "reference": "generate:smpte-bars", "method": "reinhard", "amount": 1.0}"#,
+    ),
+];
+
+const PATTERNS: &[&str] = &["smpte-bars", "zone-plate", "plasma"];
+
+fn read_hashes() -> BTreeMap<String, String> {
+    std::fs::read_to_string(HASHES)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (name, hash) = line.split_once(' ')?;
+            Some((name.to_string(), hash.trim().to_string()))
+        })
+        .collect()
+}
+
+fn write_hashes(hashes: &BTreeMap<String, String>) {
+    let mut out = String::from("# Regenerate with VIDFX_BLESS=1 cargo test --features golden\n");
+    for (name, hash) in hashes {
+        out.push_str(&format!("{} {}\n", name, hash));
+    }
+    std::fs::write(HASHES, out).expect("Failed to write golden hashes");
+}
+
+#[test]
+fn effects_match_golden_frames() {
+    let bless = std::env::var_os("VIDFX_BLESS").is_some();
+    let mut hashes = read_hashes();
+    let mut failures = vec![];
+    let diff_dir = PathBuf::from("target/golden");
+
+    for pattern_name in PATTERNS {
+        let pattern = Pattern::parse(pattern_name).expect("Golden pattern is valid");
+        let source = Generator::new(pattern, SIZE, 30.0, FRAMES as f64 / 30.0);
+
+        for (effect_name, json) in CASES {
+            let effect: Effect = serde_json::from_str(json).expect("Golden case is valid");
+
+            for index in 0..FRAMES {
+                let mut context = FrameContext::new(index, index as f64 / 30.0, 30.0);
+                context.beat_phase = Some((index as f64 / 30.0 * 2.0).fract());
+                context.bpm = Some(120);
+                let frame = effect.apply(DynamicImage::ImageRgb8(source.frame(index)), &context);
+                let name = format!("{}/{}/{}", effect_name, pattern_name, index);
+                let hash = format!("{:016x}", fnv1a(frame.as_raw()));
+
+                if bless {
+                    hashes.insert(name, hash);
+                    continue;
+                }
+
+                match hashes.get(&name) {
+                    Some(expected) if *expected == hash => {}
+                    None => failures.push(format!("{}: no golden hash recorded", name)),
+                    Some(expected) => {
+                        let path = diff_dir.join(format!("{}.png", name.replace('/', "-")));
+                        std::fs::create_dir_all(&diff_dir).expect("Failed to create target/golden");
+                        frame
+                            .save(&path)
+                            .expect("Failed to write mismatching frame");
+                        failures.push(format!(
+                            "{}: expected {}, got {} (see {})",
+                            name,
+                            expected,
+                            hash,
+                            path.display()
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    if bless {
+        write_hashes(&hashes);
+        return;
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} frames differ from or are missing in {}:\n{}\nIf the change is intended, rerun with VIDFX_BLESS=1",
+        failures.len(),
+        Path::new(HASHES).display(),
+        failures.join("\n")
+    );
+}
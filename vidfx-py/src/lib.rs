@@ -0,0 +1,226 @@
+//! Python bindings for the vidfx effect chain and render pipeline.
+//!
+//! ```python
+//! import math
+//! import vidfx
+//!
+//! chain = vidfx.EffectChain().add("xor", color="ff0000").add("bloom", intensity=1.5, radius=8.0, min_threshold=100)
+//! vidfx.render("in.mp4", "out.mp4", chain, scale_factor=lambda index, time: abs(math.sin(time)))
+//! ```
+
+use std::path::Path;
+
+use image::{DynamicImage, RgbImage};
+use numpy::{PyArray3, PyArrayMethods, PyReadonlyArray3, PyUntypedArrayMethods};
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use video_rs::time::Time;
+use vidfx_core::color::{ColorRange, Correction};
+use vidfx_core::encoder::{
+    container_format, image_to_ndarray, Codec, EncodeSettings, Provenance, VideoEncoder,
+};
+use vidfx_core::source::decode_frame;
+use vidfx_core::{Effect, FrameContext};
+
+/// A list of effects applied in order, the same ones the CLI subcommands run.
+#[pyclass]
+#[derive(Clone, Default)]
+struct EffectChain {
+    inner: vidfx_core::EffectChain,
+}
+
+#[pymethods]
+impl EffectChain {
+    #[new]
+    fn new() -> Self {
+        EffectChain::default()
+    }
+
+    /// Parses a chain saved with `to_json`.
+    #[staticmethod]
+    fn from_json(json: &str) -> PyResult<Self> {
+        let inner = vidfx_core::EffectChain::from_json(json).map_err(PyValueError::new_err)?;
+        Ok(EffectChain { inner })
+    }
+
+    fn to_json(&self) -> String {
+        self.inner.to_json()
+    }
+
+    /// Appends `effect` (a subcommand name like `"xor"`) configured by its
+    /// keyword arguments, and returns the chain so calls can be strung along.
+    #[pyo3(signature = (effect, **params))]
+    fn add<'py>(
+        mut slf: PyRefMut<'py, Self>,
+        effect: &str,
+        params: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<PyRefMut<'py, Self>> {
+        let py = slf.py();
+        let params = match params {
+            Some(params) => params.copy()?,
+            None => PyDict::new_bound(py),
+        };
+        params.set_item("effect", effect)?;
+
+        let json: String = py
+            .import_bound("json")?
+            .call_method1("dumps", (params,))?
+            .extract()?;
+        let effect: Effect =
+            serde_json::from_str(&json).map_err(|e| PyValueError::new_err(e.to_string()))?;
+
+        slf.inner = std::mem::take(&mut slf.inner).then(effect);
+        Ok(slf)
+    }
+
+    /// Runs the chain over one height x width x 3 RGB frame.
+    #[pyo3(signature = (
+        frame,
+        index = 0,
+        time = 0.0,
+        scale_factor = 1.0,
+        beat_phase = None,
+        bpm = None,
+        frame_rate = 30.0,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn apply<'py>(
+        &self,
+        py: Python<'py>,
+        frame: PyReadonlyArray3<'py, u8>,
+        index: usize,
+        time: f64,
+        scale_factor: f64,
+        beat_phase: Option<f64>,
+        bpm: Option<u32>,
+        frame_rate: f64,
+    ) -> PyResult<Bound<'py, PyArray3<u8>>> {
+        let img = array_to_image(&frame)?;
+        let mut context = FrameContext::new(index, time, frame_rate);
+        context.scale_factor = scale_factor;
+        context.beat_phase = beat_phase;
+        context.bpm = bpm;
+
+        let processed = py.allow_threads(|| {
+            DynamicImage::ImageRgba8(self.inner.apply(DynamicImage::ImageRgb8(img), &context))
+                .into_rgb8()
+        });
+        Ok(PyArray3::from_owned_array_bound(
+            py,
+            image_to_ndarray(&processed),
+        ))
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.effects.len()
+    }
+}
+
+fn array_to_image(frame: &PyReadonlyArray3<u8>) -> PyResult<RgbImage> {
+    let shape = frame.shape();
+    if shape[2] != 3 {
+        return Err(PyValueError::new_err(
+            "frames must be height x width x 3 RGB",
+        ));
+    }
+
+    let data = frame.as_array().iter().copied().collect();
+    RgbImage::from_raw(shape[1] as u32, shape[0] as u32, data)
+        .ok_or_else(|| PyValueError::new_err("frame data does not match its shape"))
+}
+
+/// Decodes `input`, runs `chain` over every frame and encodes to `output`.
+///
+/// `scale_factor` is either a number or a `(index, time) -> float` callable,
+/// which is how parameter sweeps are scripted. Returns the number of frames
+/// written.
+#[pyfunction]
+#[pyo3(signature = (input, output, chain, codec = None, scale_factor = None))]
+fn render(
+    py: Python<'_>,
+    input: &str,
+    output: &str,
+    chain: &EffectChain,
+    codec: Option<&str>,
+    scale_factor: Option<PyObject>,
+) -> PyResult<usize> {
+    let codec = codec
+        .map(|name| <Codec as clap::ValueEnum>::from_str(name, true).map_err(PyValueError::new_err))
+        .transpose()?;
+
+    video_rs::init().map_err(|e| PyIOError::new_err(e.to_string()))?;
+    let mut decoder =
+        video_rs::Decoder::new(Path::new(input)).map_err(|e| PyIOError::new_err(e.to_string()))?;
+
+    let (width, height) = decoder.size();
+    let settings = EncodeSettings {
+        width,
+        height,
+        frame_rate: decoder.frame_rate() as f64,
+        codec,
+        bit_rate: None,
+        threads: None,
+        color_range: ColorRange::Limited,
+        delivery: None,
+        faststart: false,
+        fragmented: false,
+        segment_duration: 4.0,
+    };
+    let output_path = Path::new(output);
+    let codec = codec.unwrap_or_else(|| Codec::default_for(output_path));
+    let mut encoder = VideoEncoder::new(
+        output,
+        container_format(output_path),
+        codec,
+        &settings,
+        false,
+        None,
+        &Provenance::default(),
+    )
+    .map_err(|e| PyIOError::new_err(e.to_string()))?;
+
+    let correction = Correction::probe(Path::new(input));
+    let frame_interval = 1.0 / settings.frame_rate;
+    let mut index = 0;
+    while let Some(mut img) = decode_frame(&mut decoder) {
+        if let Some(correction) = &correction {
+            correction.apply(&mut img);
+        }
+        let time = index as f64 * frame_interval;
+        let scale_factor = match &scale_factor {
+            Some(f) if f.bind(py).is_callable() => f.call1(py, (index, time))?.extract(py)?,
+            Some(f) => f.extract(py)?,
+            None => 1.0,
+        };
+        let mut context = FrameContext::new(index, time, settings.frame_rate);
+        context.scale_factor = scale_factor;
+
+        let frame = py.allow_threads(|| {
+            let processed =
+                DynamicImage::ImageRgba8(chain.inner.apply(DynamicImage::ImageRgb8(img), &context))
+                    .into_rgb8();
+            image_to_ndarray(&processed)
+        });
+        encoder
+            .encode(&frame, Time::from_secs_f64(time))
+            .map_err(|e| PyIOError::new_err(e.to_string()))?;
+
+        // Lets Ctrl-C in a notebook interrupt long renders.
+        py.check_signals()?;
+        index += 1;
+    }
+
+    encoder
+        .finish()
+        .map_err(|e| PyIOError::new_err(e.to_string()))?;
+    Ok(index)
+}
+
+#[pymodule]
+#[pyo3(name = "vidfx")]
+fn vidfx_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<EffectChain>()?;
+    m.add_function(wrap_pyfunction!(render, m)?)?;
+    Ok(())
+}